@@ -0,0 +1,70 @@
+// Lane cfg aliases. The lane lists are spelled out once here instead of in
+// every `#[cfg(any(...))]` that needs them:
+//
+// - `m3`: the lanes that run the M3 user-mode threads or the Go image loader.
+// - `r4_ipc`: the IPC/SHM/quota test lanes, which run R4 without the Go
+//   services.
+// - `r4`: every R4 lane, `r4_ipc` plus `go_test` and the lanes built on it.
+// - `user_mode`: every lane that enters ring 3, `m3` plus `r4_ipc`.
+
+const M3_FEATURES: &[&str] = &[
+    "user_hello_test",
+    "syscall_test",
+    "thread_exit_test",
+    "thread_spawn_test",
+    "vm_map_test",
+    "syscall_invalid_test",
+    "stress_syscall_test",
+    "yield_test",
+    "user_fault_test",
+    "blk_test",
+    "fs_test",
+    "go_test",
+    "go_std_test",
+    "sec_rights_test",
+    "sec_filter_test",
+];
+
+const R4_IPC_FEATURES: &[&str] = &[
+    "ipc_test",
+    "shm_test",
+    "ipc_badptr_send_test",
+    "ipc_badptr_recv_test",
+    "ipc_badptr_svc_test",
+    "ipc_buffer_full_test",
+    "ipc_waiter_busy_test",
+    "svc_overwrite_test",
+    "svc_full_test",
+    "svc_bad_endpoint_test",
+    "stress_ipc_test",
+    "quota_endpoints_test",
+    "quota_shm_test",
+    "quota_threads_test",
+];
+
+fn feature_enabled(name: &str) -> bool {
+    let var = format!("CARGO_FEATURE_{}", name.to_uppercase());
+    std::env::var_os(var).is_some()
+}
+
+fn main() {
+    println!("cargo::rustc-check-cfg=cfg(m3)");
+    println!("cargo::rustc-check-cfg=cfg(r4)");
+    println!("cargo::rustc-check-cfg=cfg(r4_ipc)");
+    println!("cargo::rustc-check-cfg=cfg(user_mode)");
+
+    let m3 = M3_FEATURES.iter().any(|name| feature_enabled(name));
+    if m3 {
+        println!("cargo::rustc-cfg=m3");
+    }
+    let r4_ipc = R4_IPC_FEATURES.iter().any(|name| feature_enabled(name));
+    if r4_ipc {
+        println!("cargo::rustc-cfg=r4_ipc");
+    }
+    if r4_ipc || feature_enabled("go_test") {
+        println!("cargo::rustc-cfg=r4");
+    }
+    if m3 || r4_ipc {
+        println!("cargo::rustc-cfg=user_mode");
+    }
+}
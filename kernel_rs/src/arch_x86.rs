@@ -52,15 +52,20 @@ struct DtPtr {
     base: u64,
 }
 
-static mut GDT: [u64; 7] = [
-    0x0000_0000_0000_0000,
-    0x00AF_9A00_0000_FFFF,
-    0x00CF_9200_0000_FFFF,
-    0x00CF_F200_0000_FFFF,
-    0x00AF_FA00_0000_FFFF,
-    0,
-    0,
-];
+/// CPUs the GDT has a TSS slot for.
+pub(crate) const MAX_CPUS: usize = 8;
+// Null, kernel code and data, user data and code, then one 16-byte TSS
+// descriptor per CPU.
+const GDT_TSS_BASE: usize = 5;
+
+static mut GDT: [u64; GDT_TSS_BASE + 2 * MAX_CPUS] = {
+    let mut gdt = [0u64; GDT_TSS_BASE + 2 * MAX_CPUS];
+    gdt[1] = 0x00AF_9A00_0000_FFFF;
+    gdt[2] = 0x00CF_9200_0000_FFFF;
+    gdt[3] = 0x00CF_F200_0000_FFFF;
+    gdt[4] = 0x00AF_FA00_0000_FFFF;
+    gdt
+};
 
 pub(crate) unsafe fn gdt_init() {
     let limit = (core::mem::size_of_val(&GDT) - 1) as u16;
@@ -83,6 +88,34 @@ pub(crate) unsafe fn gdt_init() {
     );
 }
 
+#[cfg(r4)]
+const MSR_EFER: u32 = 0xC000_0080;
+#[cfg(r4)]
+const EFER_NXE: u64 = 1 << 11;
+
+/// Turn on no-execute page protection (EFER.NXE) if the CPU has it.
+/// Until it is on, bit 63 of a page table entry is reserved and faults.
+#[cfg(r4)]
+pub(crate) unsafe fn nx_enable() -> bool {
+    // CPUID.80000001H:EDX bit 20 advertises the NX bit.
+    if core::arch::x86_64::__cpuid(0x8000_0000).eax < 0x8000_0001
+        || core::arch::x86_64::__cpuid(0x8000_0001).edx & (1 << 20) == 0
+    {
+        return false;
+    }
+    let (lo, hi): (u32, u32);
+    core::arch::asm!("rdmsr", in("ecx") MSR_EFER, out("eax") lo, out("edx") hi, options(nomem, nostack));
+    let efer = ((hi as u64) << 32 | lo as u64) | EFER_NXE;
+    core::arch::asm!(
+        "wrmsr",
+        in("ecx") MSR_EFER,
+        in("eax") efer as u32,
+        in("edx") (efer >> 32) as u32,
+        options(nostack),
+    );
+    true
+}
+
 cfg_user! {
     #[repr(C, packed)]
     struct Tss {
@@ -97,7 +130,7 @@ cfg_user! {
         iopb_offset: u16,
     }
 
-    static mut TSS: Tss = Tss {
+    const TSS_EMPTY: Tss = Tss {
         reserved0: 0,
         rsp0: 0, rsp1: 0, rsp2: 0,
         reserved1: 0,
@@ -107,37 +140,68 @@ cfg_user! {
         iopb_offset: 104,
     };
 
+    static mut TSS: [Tss; MAX_CPUS] = [TSS_EMPTY; MAX_CPUS];
+
     pub(crate) unsafe fn tss_init(kernel_stack_top: u64) {
-        TSS.rsp0 = kernel_stack_top;
-        let tss_addr = &TSS as *const Tss as u64;
-        GDT[5] = (103u64)
+        tss_load(0, kernel_stack_top);
+    }
+
+    /// Load `cpu`'s own TSS, with `kernel_stack_top` as its ring-0 stack.
+    /// Each CPU's descriptor has its own selector, which is how
+    /// `cpu_index` tells the CPUs apart.
+    pub(crate) unsafe fn tss_load(cpu: usize, kernel_stack_top: u64) {
+        TSS[cpu].rsp0 = kernel_stack_top;
+        let tss_addr = core::ptr::addr_of!(TSS[cpu]) as u64;
+        let slot = GDT_TSS_BASE + 2 * cpu;
+        GDT[slot] = (103u64)
                 | ((tss_addr & 0xFFFF) << 16)
                 | (((tss_addr >> 16) & 0xFF) << 32)
                 | (0x89u64 << 40)
                 | (((tss_addr >> 24) & 0xFF) << 56);
-        GDT[6] = tss_addr >> 32;
+        GDT[slot + 1] = tss_addr >> 32;
         let limit = (core::mem::size_of_val(&GDT) - 1) as u16;
         let base = GDT.as_ptr() as u64;
         let gdt_ptr = DtPtr { limit, base };
         core::arch::asm!("lgdt [{}]", in(reg) &gdt_ptr);
         core::arch::asm!(
-            "mov ax, 0x28",
-            "ltr ax",
-            out("ax") _,
+            "ltr {0:x}",
+            in(reg) (slot * 8) as u16,
             options(nostack),
         );
     }
 
+    /// This CPU's index, from the TSS selector it loaded: 0 for the boot
+    /// CPU, and before any TSS is loaded.
+    #[cfg(feature = "go_test")]
+    #[inline(always)]
+    pub(crate) fn cpu_index() -> usize {
+        let tr: u16;
+        unsafe {
+            core::arch::asm!("str {0:x}", out(reg) tr, options(nomem, nostack, preserves_flags));
+        }
+        (tr as usize).saturating_sub(GDT_TSS_BASE * 8) / 16
+    }
+
+    /// RFLAGS for user mode. The Go lane runs user code with interrupts
+    /// enabled so the timer can preempt it.
+    #[cfg(feature = "go_test")]
+    pub(crate) const USER_RFLAGS: u64 = 0x202;
+    #[cfg(not(feature = "go_test"))]
+    pub(crate) const USER_RFLAGS: u64 = 0x002;
+
     pub(crate) unsafe fn enter_ring3_at(code_va: u64, user_sp: u64) -> ! {
         core::arch::asm!(
             "push 0x1B",
             "push {stack}",
-            "push 0x002",
+            "push {rflags}",
             "push 0x23",
             "push {code}",
             "iretq",
             stack = in(reg) user_sp,
+            rflags = in(reg) USER_RFLAGS,
             code = in(reg) code_va,
+            // System V: rdx holds an exit hook for the runtime; there is none.
+            in("rdx") 0u64,
             options(noreturn),
         );
     }
@@ -194,6 +258,10 @@ pub(crate) unsafe fn idt_init() {
         #[cfg(any(feature = "blk_test", feature = "fs_test", feature = "go_test"))]
         fn isr_stub_65();
         fn isr_stub_128();
+        #[cfg(feature = "go_test")]
+        fn isr_stub_240();
+        #[cfg(feature = "go_test")]
+        fn isr_stub_255();
     }
 
     idt_set_gate(0, isr_stub_0 as *const () as u64);
@@ -207,6 +275,11 @@ pub(crate) unsafe fn idt_init() {
         idt_set_gate(64, isr_stub_64 as *const () as u64);
         idt_set_gate(65, isr_stub_65 as *const () as u64);
     }
+    #[cfg(feature = "go_test")]
+    {
+        idt_set_gate(240, isr_stub_240 as *const () as u64);
+        idt_set_gate(255, isr_stub_255 as *const () as u64);
+    }
 
     let handler = isr_stub_128 as *const () as u64;
     IDT[128] = IdtEntry {
@@ -218,7 +291,11 @@ pub(crate) unsafe fn idt_init() {
         offset_high: (handler >> 32) as u32,
         reserved: 0,
     };
+    idt_load();
+}
 
+/// Load the IDT `idt_init` built; the APs share it.
+pub(crate) unsafe fn idt_load() {
     let ptr = DtPtr {
         limit: (256 * core::mem::size_of::<IdtEntry>() - 1) as u16,
         base: IDT.as_ptr() as u64,
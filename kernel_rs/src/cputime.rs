@@ -0,0 +1,169 @@
+// CPU time accounting for the Go lane. Every kernel entry and exit and every
+// task switch closes the interval since the previous one and charges it, by
+// what the CPU was doing, to the running task's user or kernel time or to
+// the idle counter.
+//
+// Intervals are TSC cycles. They are converted to microseconds against the
+// PIT, which has ticked `R4_TICKS` times since the accounting started. Each
+// CPU keeps its own mode and mark, and the totals add up every CPU; an idle
+// AP's time is charged when it next wakes. The totals are atomic, since a
+// scheduler IPI charges them without the kernel lock.
+//
+// Load averages are fixed-point EWMAs of the runnable task count, sampled
+// every five seconds with the usual 1, 5 and 15 minute decay factors.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::*;
+
+#[derive(Clone, Copy, PartialEq)]
+enum CpuMode {
+    User,
+    Kernel,
+    Idle,
+}
+
+// Fraction bits of the load averages, and the per-sample decay factors
+// exp(-5s/1min), exp(-5s/5min) and exp(-5s/15min) in that format.
+const LOAD_SHIFT: u32 = 11;
+const LOAD_ONE: u64 = 1 << LOAD_SHIFT;
+const LOAD_DECAY: [u64; 3] = [1884, 2014, 2037];
+const LOAD_SAMPLE_TICKS: u64 = 5 * R4_TIMER_HZ as u64;
+// Fraction bits of the load averages as user space sees them.
+const LOAD_REPORT_SHIFT: u32 = 16;
+
+static mut CPU_MODE: [CpuMode; arch_x86::MAX_CPUS] = [CpuMode::Kernel; arch_x86::MAX_CPUS];
+// TSC at each CPU's last charge; 0 until the accounting starts there.
+static mut CPU_MARK: [u64; arch_x86::MAX_CPUS] = [0; arch_x86::MAX_CPUS];
+// TSC at the first tick, the base for converting cycles.
+static mut CPU_TSC_BASE: u64 = 0;
+static CPU_USER_CYCLES: AtomicU64 = AtomicU64::new(0);
+static CPU_KERNEL_CYCLES: AtomicU64 = AtomicU64::new(0);
+static CPU_IDLE_CYCLES: AtomicU64 = AtomicU64::new(0);
+static mut CPU_LOAD: [u64; 3] = [0; 3];
+
+#[inline(always)]
+fn rdtsc() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+/// Start counting, with the boot CPU in the kernel and the APs idle.
+/// Called with the PIT.
+pub(crate) unsafe fn cpu_start() {
+    CPU_TSC_BASE = rdtsc();
+    for cpu in 0..arch_x86::MAX_CPUS {
+        CPU_MARK[cpu] = CPU_TSC_BASE;
+        CPU_MODE[cpu] = if cpu == 0 { CpuMode::Kernel } else { CpuMode::Idle };
+    }
+}
+
+/// An AP came online: it idles until the scheduler gives it a task.
+pub(crate) unsafe fn cpu_join() {
+    let cpu = smp::cpu_id();
+    CPU_MODE[cpu] = CpuMode::Idle;
+    if CPU_TSC_BASE != 0 {
+        CPU_MARK[cpu] = rdtsc();
+    }
+}
+
+/// Charge the interval since this CPU's last mark to its current task, or
+/// to the idle counter.
+unsafe fn cpu_charge() {
+    let cpu = smp::cpu_id();
+    if CPU_MARK[cpu] == 0 {
+        return;
+    }
+    let now = rdtsc();
+    let cycles = now.wrapping_sub(CPU_MARK[cpu]);
+    CPU_MARK[cpu] = now;
+    match CPU_MODE[cpu] {
+        CpuMode::User => {
+            R4_TASKS[r4_current()].user_cycles += cycles;
+            CPU_USER_CYCLES.fetch_add(cycles, Ordering::Relaxed);
+        }
+        CpuMode::Kernel => {
+            R4_TASKS[r4_current()].kernel_cycles += cycles;
+            CPU_KERNEL_CYCLES.fetch_add(cycles, Ordering::Relaxed);
+        }
+        CpuMode::Idle => {
+            CPU_IDLE_CYCLES.fetch_add(cycles, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Kernel entry: whatever ran since the last exit is charged.
+pub(crate) unsafe fn cpu_enter() {
+    cpu_charge();
+    CPU_MODE[smp::cpu_id()] = CpuMode::Kernel;
+}
+
+/// Kernel exit through `frame`: the handler's time is charged to the task
+/// current now, and the mode follows where the frame returns to.
+pub(crate) unsafe fn cpu_exit(frame: *mut u64) {
+    cpu_charge();
+    let cpu = smp::cpu_id();
+    CPU_MODE[cpu] = if *frame.add(18) & 3 == 3 {
+        CpuMode::User
+    } else if R4_IDLE[cpu] {
+        CpuMode::Idle
+    } else {
+        CpuMode::Kernel
+    };
+}
+
+/// Task switch: close the outgoing task's kernel time before
+/// this CPU's `R4_CURRENT` changes.
+pub(crate) unsafe fn cpu_switch() {
+    cpu_charge();
+}
+
+/// Tasks ready or running, kernel workers included.
+pub(crate) unsafe fn cpu_runnable() -> u64 {
+    (0..r4_sched_span())
+        .filter(|&tid| matches!(R4_TASKS[tid].state, R4State::Ready | R4State::Running))
+        .count() as u64
+}
+
+/// Per-tick hook: fold the runnable count into the load averages every
+/// `LOAD_SAMPLE_TICKS`.
+pub(crate) unsafe fn cpu_tick() {
+    if !R4_TICKS.is_multiple_of(LOAD_SAMPLE_TICKS) {
+        return;
+    }
+    let active = cpu_runnable() * LOAD_ONE;
+    for (load, decay) in CPU_LOAD.iter_mut().zip(LOAD_DECAY) {
+        *load = (*load * decay + active * (LOAD_ONE - decay) + LOAD_ONE / 2) >> LOAD_SHIFT;
+    }
+}
+
+/// Microseconds in `cycles`, at the rate the TSC has run against the PIT.
+/// Zero before the first tick.
+pub(crate) unsafe fn cycles_to_us(cycles: u64) -> u64 {
+    let elapsed_us = R4_TICKS * R4_TICK_US;
+    if elapsed_us == 0 {
+        return 0;
+    }
+    let rate = rdtsc().wrapping_sub(CPU_TSC_BASE) / elapsed_us;
+    if rate == 0 {
+        return 0;
+    }
+    cycles / rate
+}
+
+pub(crate) unsafe fn cpu_user_us() -> u64 {
+    cycles_to_us(CPU_USER_CYCLES.load(Ordering::Relaxed))
+}
+
+pub(crate) unsafe fn cpu_kernel_us() -> u64 {
+    cycles_to_us(CPU_KERNEL_CYCLES.load(Ordering::Relaxed))
+}
+
+pub(crate) unsafe fn cpu_idle_us() -> u64 {
+    cycles_to_us(CPU_IDLE_CYCLES.load(Ordering::Relaxed))
+}
+
+/// The 1, 5 and 15 minute load averages with `LOAD_REPORT_SHIFT` fraction
+/// bits.
+pub(crate) unsafe fn cpu_loads() -> [u64; 3] {
+    CPU_LOAD.map(|load| load << (LOAD_REPORT_SHIFT - LOAD_SHIFT))
+}
@@ -0,0 +1,173 @@
+// Kernel-object storage: the task, address space, timer and M3 thread
+// tables, sized from the boot limits instead of at build time.
+//
+// Their frames come from a pool of their own, carved from the memory map
+// beside the user frame pool, so user memory pressure never takes them and
+// they never take user frames. A table is sized when its lane (re)starts
+// and keeps its frames until the next restart; it reaches its slots through
+// a directory frame, so a slot's address never moves.
+
+use core::marker::PhantomData;
+use core::ops::{Index, IndexMut};
+
+use crate::*;
+
+// Pool size when the command line sets none (`rugo.kobj_frames=N`).
+pub(crate) const KOBJ_DEFAULT_FRAMES: usize = 64;
+// Slot pages one directory frame points to.
+const KOBJ_DIR_ENTRIES: usize = 512;
+
+static mut KOBJ_BASE: u64 = 0;
+static mut KOBJ_FRAME_COUNT: usize = 0;
+// One byte per frame, nonzero while the frame is in use.
+static mut KOBJ_USED: *mut u8 = core::ptr::dangling_mut();
+
+#[inline(always)]
+unsafe fn kobj_ptr(phys: u64) -> *mut u8 {
+    (phys + HHDM_OFFSET) as *mut u8
+}
+
+unsafe fn kobj_pool_carve() -> bool {
+    let count = limits::kobj_frames();
+    let used = match pmm::pmm_carve(count.div_ceil(4096), 4096) {
+        Some(phys) => phys,
+        None => return false,
+    };
+    let base = match pmm::pmm_carve(count, 4096) {
+        Some(phys) => phys,
+        None => return false,
+    };
+    KOBJ_USED = kobj_ptr(used);
+    core::ptr::write_bytes(KOBJ_USED, 0, count);
+    KOBJ_FRAME_COUNT = count;
+    KOBJ_BASE = base;
+    true
+}
+
+/// Allocate one zeroed kernel-object frame and return its physical address.
+unsafe fn kobj_frame_alloc() -> Option<u64> {
+    if KOBJ_BASE == 0 && !kobj_pool_carve() {
+        serial_write(b"KOBJ: no memory for the kernel-object pool\n");
+        return None;
+    }
+    for i in 0..KOBJ_FRAME_COUNT {
+        if *KOBJ_USED.add(i) == 0 {
+            *KOBJ_USED.add(i) = 1;
+            let phys = KOBJ_BASE + (i as u64) * 4096;
+            core::ptr::write_bytes(kobj_ptr(phys), 0, 4096);
+            return Some(phys);
+        }
+    }
+    None
+}
+
+unsafe fn kobj_frame_free(phys: u64) {
+    if phys < KOBJ_BASE {
+        return;
+    }
+    let idx = ((phys - KOBJ_BASE) / 4096) as usize;
+    if idx < KOBJ_FRAME_COUNT {
+        *KOBJ_USED.add(idx) = 0;
+    }
+}
+
+/// A table of `T` slots in kernel-object frames. Indexing past `len` is a
+/// kernel bug, like indexing past the end of an array.
+pub(crate) struct KTable<T> {
+    // Frame holding the physical address of each page of slots, or 0.
+    dir: u64,
+    len: usize,
+    _slots: PhantomData<T>,
+}
+
+impl<T: Copy> KTable<T> {
+    const PER_PAGE: usize = 4096 / core::mem::size_of::<T>();
+    /// Most slots a table of `T` can have.
+    pub(crate) const CAPACITY: usize = KOBJ_DIR_ENTRIES * Self::PER_PAGE;
+
+    pub(crate) const EMPTY: Self = Self { dir: 0, len: 0, _slots: PhantomData };
+
+    /// Give the table `len` slots set to `fill`, returning its old frames to
+    /// the pool first. False if the pool cannot hold them, leaving the table
+    /// empty.
+    pub(crate) unsafe fn init(&mut self, len: usize, fill: T) -> bool {
+        const { assert!(core::mem::size_of::<T>() <= 4096, "a slot must fit in one frame") };
+        self.release();
+        if len > Self::CAPACITY {
+            return false;
+        }
+        let dir = match kobj_frame_alloc() {
+            Some(phys) => phys,
+            None => return false,
+        };
+        self.dir = dir;
+        let pages = kobj_ptr(dir) as *mut u64;
+        for page in 0..len.div_ceil(Self::PER_PAGE) {
+            match kobj_frame_alloc() {
+                Some(phys) => *pages.add(page) = phys,
+                None => {
+                    self.release();
+                    return false;
+                }
+            }
+        }
+        self.len = len;
+        self.fill(fill);
+        true
+    }
+
+    /// Set every slot to `value`.
+    pub(crate) unsafe fn fill(&mut self, value: T) {
+        for i in 0..self.len {
+            core::ptr::write(self.slot(i), value);
+        }
+    }
+
+    unsafe fn release(&mut self) {
+        if self.dir == 0 {
+            return;
+        }
+        let pages = kobj_ptr(self.dir) as *const u64;
+        for page in 0..KOBJ_DIR_ENTRIES {
+            let phys = *pages.add(page);
+            if phys == 0 {
+                break;
+            }
+            kobj_frame_free(phys);
+        }
+        kobj_frame_free(self.dir);
+        self.dir = 0;
+        self.len = 0;
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    #[cfg(feature = "go_test")]
+    pub(crate) fn swap(&mut self, a: usize, b: usize) {
+        unsafe { core::ptr::swap(self.slot(a), self.slot(b)) }
+    }
+
+    fn slot(&self, i: usize) -> *mut T {
+        assert!(i < self.len, "kernel-object table index out of range");
+        unsafe {
+            let page = *(kobj_ptr(self.dir) as *const u64).add(i / Self::PER_PAGE);
+            (kobj_ptr(page) as *mut T).add(i % Self::PER_PAGE)
+        }
+    }
+}
+
+impl<T: Copy> Index<usize> for KTable<T> {
+    type Output = T;
+
+    fn index(&self, i: usize) -> &T {
+        unsafe { &*self.slot(i) }
+    }
+}
+
+impl<T: Copy> IndexMut<usize> for KTable<T> {
+    fn index_mut(&mut self, i: usize) -> &mut T {
+        unsafe { &mut *self.slot(i) }
+    }
+}
@@ -9,7 +9,7 @@ mod runtime;
 macro_rules! cfg_m3 {
     ($($item:item)*) => {
         $(
-            #[cfg(any(feature = "user_hello_test", feature = "syscall_test", feature = "thread_exit_test", feature = "thread_spawn_test", feature = "vm_map_test", feature = "syscall_invalid_test", feature = "stress_syscall_test", feature = "yield_test", feature = "user_fault_test", feature = "blk_test", feature = "fs_test", feature = "go_test", feature = "go_std_test", feature = "sec_rights_test", feature = "sec_filter_test"))]
+            #[cfg(m3)]
             $item
         )*
     };
@@ -19,11 +19,7 @@ macro_rules! cfg_m3 {
 macro_rules! cfg_user {
     ($($item:item)*) => {
         $(
-            #[cfg(any(
-                feature = "user_hello_test", feature = "syscall_test", feature = "thread_exit_test", feature = "thread_spawn_test", feature = "vm_map_test", feature = "syscall_invalid_test", feature = "stress_syscall_test", feature = "yield_test", feature = "user_fault_test",
-                feature = "ipc_test", feature = "shm_test", feature = "ipc_badptr_send_test", feature = "ipc_badptr_recv_test", feature = "ipc_badptr_svc_test", feature = "ipc_buffer_full_test", feature = "ipc_waiter_busy_test", feature = "svc_overwrite_test", feature = "svc_full_test", feature = "svc_bad_endpoint_test", feature = "stress_ipc_test", feature = "quota_endpoints_test", feature = "quota_shm_test", feature = "quota_threads_test", feature = "blk_test", feature = "fs_test",
-                feature = "go_test", feature = "go_std_test", feature = "sec_rights_test", feature = "sec_filter_test",
-            ))]
+            #[cfg(user_mode)]
             $item
         )*
     };
@@ -33,7 +29,18 @@ macro_rules! cfg_user {
 macro_rules! cfg_r4 {
     ($($item:item)*) => {
         $(
-            #[cfg(any(feature = "ipc_test", feature = "shm_test", feature = "ipc_badptr_send_test", feature = "ipc_badptr_recv_test", feature = "ipc_badptr_svc_test", feature = "ipc_buffer_full_test", feature = "ipc_waiter_busy_test", feature = "svc_overwrite_test", feature = "svc_full_test", feature = "svc_bad_endpoint_test", feature = "stress_ipc_test", feature = "quota_endpoints_test", feature = "quota_shm_test", feature = "quota_threads_test", feature = "go_test"))]
+            #[cfg(r4)]
+            $item
+        )*
+    };
+}
+
+// Shorthand for "a lane that loads ELF images at run time" (M6 fs_test
+// packages, G1 go_test services and spawned programs)
+macro_rules! cfg_elf_loader {
+    ($($item:item)*) => {
+        $(
+            #[cfg(any(feature = "fs_test", feature = "go_test"))]
             $item
         )*
     };
@@ -47,6 +54,27 @@ mod sched;
 mod storage;
 mod syscall;
 mod trap;
+cfg_elf_loader! {
+    mod sign;
+}
+cfg_user! {
+    mod pmm;
+}
+cfg_r4! {
+    mod vm;
+}
+#[cfg(feature = "go_test")]
+mod timer;
+#[cfg(feature = "go_test")]
+mod workq;
+cfg_user! {
+    mod kobj;
+    mod limits;
+}
+#[cfg(feature = "go_test")]
+mod cputime;
+#[cfg(feature = "go_test")]
+mod smp;
 
 use arch_x86::{gdt_init, idt_init, inb, outb, qemu_exit};
 #[cfg(any(
@@ -57,42 +85,24 @@ use arch_x86::{gdt_init, idt_init, inb, outb, qemu_exit};
     feature = "go_test",
 ))]
 use arch_x86::{inl, inw, outl, outw};
-#[cfg(any(
-    feature = "user_hello_test",
-    feature = "syscall_test",
-    feature = "thread_exit_test",
-    feature = "thread_spawn_test",
-    feature = "vm_map_test",
-    feature = "syscall_invalid_test",
-    feature = "stress_syscall_test",
-    feature = "yield_test",
-    feature = "user_fault_test",
-    feature = "ipc_test",
-    feature = "shm_test",
-    feature = "ipc_badptr_send_test",
-    feature = "ipc_badptr_recv_test",
-    feature = "ipc_badptr_svc_test",
-    feature = "ipc_buffer_full_test",
-    feature = "ipc_waiter_busy_test",
-    feature = "svc_overwrite_test",
-    feature = "svc_full_test",
-    feature = "svc_bad_endpoint_test",
-    feature = "stress_ipc_test",
-    feature = "quota_endpoints_test",
-    feature = "quota_shm_test",
-    feature = "quota_threads_test",
-    feature = "blk_test",
-    feature = "fs_test",
-    feature = "go_test",
-    feature = "go_std_test",
-    feature = "sec_rights_test",
-    feature = "sec_filter_test",
-))]
+#[cfg(user_mode)]
 use arch_x86::{enter_ring3_at, tss_init};
-use memory::{
-    check_page_user_perms, copyin_user, copyinstr_user, copyout_user, user_pages_ok, user_range_ok,
-    USER_PERM_READ, USER_PERM_WRITE, USER_VA_LIMIT,
-};
+use memory::copyin_user;
+#[cfg(any(user_mode, feature = "net_test"))]
+use memory::user_range_ok;
+#[cfg(m3)]
+use memory::USER_PERM_READ;
+#[cfg(user_mode)]
+use memory::{USER_PERM_WRITE, USER_VA_LIMIT};
+#[cfg(any(user_mode, feature = "net_test"))]
+use memory::copyout_user;
+#[cfg(user_mode)]
+use memory::user_pages_ok;
+#[cfg(m3)]
+use memory::copyinstr_user;
+cfg_m3! {
+    use memory::user_leaf_walk;
+}
 #[cfg(feature = "sched_test")]
 use sched::{pic_init, pit_init, sched_init, thread_create};
 
@@ -104,6 +114,52 @@ const ELF_V1_PT_LOAD: u32 = 1;
 const ELF_V1_ET_EXEC: u16 = 2;
 const ELF_V1_USER_LIMIT: u64 = 0x0000_8000_0000_0000;
 
+// ET_DYN (PIE) images: the kernel applies `R_X86_64_RELATIVE` fixups from the
+// dynamic section itself, so only self-contained images without `PT_INTERP`
+// are loaded.
+cfg_elf_loader! {
+    const ELF_V1_ET_DYN: u16 = 3;
+    const ELF_V1_PT_DYNAMIC: u32 = 2;
+    const ELF_V1_PT_INTERP: u32 = 3;
+    const ELF_V1_DT_NULL: u64 = 0;
+    const ELF_V1_DT_PLTRELSZ: u64 = 2;
+    const ELF_V1_DT_RELA: u64 = 7;
+    const ELF_V1_DT_RELASZ: u64 = 8;
+    const ELF_V1_DT_RELAENT: u64 = 9;
+    const ELF_V1_DT_REL: u64 = 17;
+    const ELF_V1_DT_PLTREL: u64 = 20;
+    const ELF_V1_DT_TEXTREL: u64 = 22;
+    const ELF_V1_DT_JMPREL: u64 = 23;
+    const ELF_V1_DT_RELR: u64 = 36;
+    const ELF_V1_DYN_SIZE: usize = 16;
+    const ELF_V1_RELA_SIZE: u64 = 24;
+    const ELF_V1_R_X86_64_RELATIVE: u32 = 8;
+    const ELF_V1_MAX_RELOCS: u64 = 4096;
+}
+#[cfg(feature = "go_test")]
+const ELF_V1_PT_TLS: u32 = 7;
+#[cfg(feature = "go_test")]
+const ELF_V1_PT_PHDR: u32 = 6;
+
+cfg_elf_loader! {
+    /// Why a PIE image's relocations were refused; mirrors the loader
+    /// contract's failure classes.
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum ElfV1RelocError {
+        /// Malformed dynamic section or relocation table (`E_INVAL`).
+        Invalid,
+        /// More relocations than `ELF_V1_MAX_RELOCS` (`E_RANGE`).
+        Range,
+        /// A relocation target outside the loaded segments (`E_FAULT`).
+        Fault,
+        /// An interpreter, text relocations, or a `DT_REL`/`DT_RELR` table
+        /// (`E_UNSUP`).
+        Unsupported,
+        /// A relocation type other than `R_X86_64_RELATIVE` (`E_UNSUP`).
+        UnsupportedType(u32),
+    }
+}
+
 #[allow(dead_code)]
 const AUXV_V1_AT_NULL: u64 = 0;
 #[allow(dead_code)]
@@ -116,6 +172,27 @@ const AUXV_V1_AT_PHNUM: u64 = 5;
 const AUXV_V1_AT_PAGESZ: u64 = 6;
 #[allow(dead_code)]
 const AUXV_V1_AT_ENTRY: u64 = 9;
+#[cfg(feature = "go_test")]
+const AUXV_V1_AT_UID: u64 = 11;
+#[cfg(feature = "go_test")]
+const AUXV_V1_AT_EUID: u64 = 12;
+#[cfg(feature = "go_test")]
+const AUXV_V1_AT_GID: u64 = 13;
+#[cfg(feature = "go_test")]
+const AUXV_V1_AT_EGID: u64 = 14;
+#[cfg(feature = "go_test")]
+const AUXV_V1_AT_HWCAP: u64 = 16;
+#[cfg(feature = "go_test")]
+const AUXV_V1_AT_CLKTCK: u64 = 17;
+#[cfg(feature = "go_test")]
+const AUXV_V1_AT_RANDOM: u64 = 25;
+#[cfg(feature = "go_test")]
+const AUXV_V1_AT_EXECFN: u64 = 31;
+#[cfg(feature = "go_test")]
+const AUXV_V1_COUNT: usize = 14;
+// Clock ticks per second reported through `AT_CLKTCK`.
+#[cfg(feature = "go_test")]
+const AUXV_V1_CLKTCK: u64 = 100;
 
 #[allow(dead_code)]
 fn elf_v1_read_u16(buf: &[u8], off: usize) -> Option<u16> {
@@ -276,14 +353,304 @@ fn elf_v1_validate_image(image: &[u8]) -> bool {
     load_count > 0
 }
 
-#[allow(dead_code)]
-fn elf_v1_build_auxv(entry: u64, phdr: u64, phent: u64, phnum: u64) -> [(u64, u64); 6] {
+cfg_elf_loader! {
+    /// Page-aligned `[low, high)` virtual span covered by the `PT_LOAD` segments.
+    fn elf_v1_load_span(image: &[u8]) -> Option<(u64, u64)> {
+        let e_phoff = elf_v1_read_u64(image, 32)? as usize;
+        let e_phentsize = elf_v1_read_u16(image, 54)? as usize;
+        let e_phnum = elf_v1_read_u16(image, 56)? as usize;
+        let mut low = u64::MAX;
+        let mut high = 0u64;
+        for idx in 0..e_phnum {
+            let off = e_phoff.checked_add(idx.checked_mul(e_phentsize)?)?;
+            if elf_v1_read_u32(image, off)? != ELF_V1_PT_LOAD {
+                continue;
+            }
+            let p_vaddr = elf_v1_read_u64(image, off + 16)?;
+            let p_memsz = elf_v1_read_u64(image, off + 40)?;
+            low = core::cmp::min(low, p_vaddr & !0xFFF);
+            high = core::cmp::max(high, p_vaddr.checked_add(p_memsz)?.checked_add(0xFFF)? & !0xFFF);
+        }
+        if low >= high {
+            return None;
+        }
+        Some((low, high))
+    }
+
+    /// File offset of `len` bytes at `vaddr`, which must be file-backed by one
+    /// `PT_LOAD` segment.
+    fn elf_v1_vaddr_to_offset(image: &[u8], vaddr: u64, len: u64) -> Option<usize> {
+        let e_phoff = elf_v1_read_u64(image, 32)? as usize;
+        let e_phentsize = elf_v1_read_u16(image, 54)? as usize;
+        let e_phnum = elf_v1_read_u16(image, 56)? as usize;
+        let end = vaddr.checked_add(len)?;
+        for idx in 0..e_phnum {
+            let off = e_phoff.checked_add(idx.checked_mul(e_phentsize)?)?;
+            if elf_v1_read_u32(image, off)? != ELF_V1_PT_LOAD {
+                continue;
+            }
+            let p_offset = elf_v1_read_u64(image, off + 8)?;
+            let p_vaddr = elf_v1_read_u64(image, off + 16)?;
+            let p_filesz = elf_v1_read_u64(image, off + 32)?;
+            if vaddr >= p_vaddr && end <= p_vaddr.checked_add(p_filesz)? {
+                let file_off = p_offset.checked_add(vaddr - p_vaddr)?;
+                if file_off.checked_add(len)? > image.len() as u64 {
+                    return None;
+                }
+                return Some(file_off as usize);
+            }
+        }
+        None
+    }
+
+    /// Walk the RELA and PLT relocation tables named by the dynamic section in
+    /// table order, passing each target's link-time address and addend to
+    /// `apply`. Targets must land in `[span_low, span_high)`. Returns the
+    /// number of relocations; nothing is applied unless the caller's `apply`
+    /// writes, so a first pass with a no-op validates the whole image.
+    fn elf_v1_for_each_relative(
+        image: &[u8],
+        span_low: u64,
+        span_high: u64,
+        mut apply: impl FnMut(u64, u64) -> bool,
+    ) -> Result<u64, ElfV1RelocError> {
+        use ElfV1RelocError::*;
+        let e_phoff = elf_v1_read_u64(image, 32).ok_or(Invalid)? as usize;
+        let e_phentsize = elf_v1_read_u16(image, 54).ok_or(Invalid)? as usize;
+        let e_phnum = elf_v1_read_u16(image, 56).ok_or(Invalid)? as usize;
+        let mut dynamic = None;
+        for idx in 0..e_phnum {
+            let off = e_phoff + idx * e_phentsize;
+            match elf_v1_read_u32(image, off).ok_or(Invalid)? {
+                ELF_V1_PT_INTERP => return Err(Unsupported),
+                ELF_V1_PT_DYNAMIC => {
+                    let p_offset = elf_v1_read_u64(image, off + 8).ok_or(Invalid)?;
+                    let p_filesz = elf_v1_read_u64(image, off + 32).ok_or(Invalid)?;
+                    dynamic = Some((p_offset as usize, p_filesz as usize));
+                }
+                _ => {}
+            }
+        }
+        let (dyn_off, dyn_len) = match dynamic {
+            Some(v) => v,
+            None => return Ok(0),
+        };
+        if dyn_off.checked_add(dyn_len).ok_or(Invalid)? > image.len() {
+            return Err(Invalid);
+        }
+
+        let (mut rela, mut relasz, mut relaent) = (0u64, 0u64, ELF_V1_RELA_SIZE);
+        let (mut jmprel, mut pltrelsz, mut pltrel) = (0u64, 0u64, ELF_V1_DT_RELA);
+        let mut entry = dyn_off;
+        while entry + ELF_V1_DYN_SIZE <= dyn_off + dyn_len {
+            let tag = elf_v1_read_u64(image, entry).ok_or(Invalid)?;
+            let val = elf_v1_read_u64(image, entry + 8).ok_or(Invalid)?;
+            match tag {
+                ELF_V1_DT_NULL => break,
+                ELF_V1_DT_RELA => rela = val,
+                ELF_V1_DT_RELASZ => relasz = val,
+                ELF_V1_DT_RELAENT => relaent = val,
+                ELF_V1_DT_JMPREL => jmprel = val,
+                ELF_V1_DT_PLTRELSZ => pltrelsz = val,
+                ELF_V1_DT_PLTREL => pltrel = val,
+                ELF_V1_DT_REL | ELF_V1_DT_RELR | ELF_V1_DT_TEXTREL => return Err(Unsupported),
+                _ => {}
+            }
+            entry += ELF_V1_DYN_SIZE;
+        }
+        if relaent != ELF_V1_RELA_SIZE || (pltrelsz != 0 && pltrel != ELF_V1_DT_RELA) {
+            return Err(Invalid);
+        }
+        if relasz % ELF_V1_RELA_SIZE != 0 || pltrelsz % ELF_V1_RELA_SIZE != 0 {
+            return Err(Invalid);
+        }
+        if (relasz + pltrelsz) / ELF_V1_RELA_SIZE > ELF_V1_MAX_RELOCS {
+            return Err(Range);
+        }
+
+        let mut count = 0u64;
+        for (table, size) in [(rela, relasz), (jmprel, pltrelsz)] {
+            if size == 0 {
+                continue;
+            }
+            let base = elf_v1_vaddr_to_offset(image, table, size).ok_or(Invalid)?;
+            for idx in 0..(size / ELF_V1_RELA_SIZE) as usize {
+                let off = base + idx * ELF_V1_RELA_SIZE as usize;
+                let r_offset = elf_v1_read_u64(image, off).ok_or(Invalid)?;
+                let r_info = elf_v1_read_u64(image, off + 8).ok_or(Invalid)?;
+                let r_addend = elf_v1_read_u64(image, off + 16).ok_or(Invalid)?;
+                let r_type = r_info as u32;
+                if r_type != ELF_V1_R_X86_64_RELATIVE || r_info >> 32 != 0 {
+                    return Err(UnsupportedType(r_type));
+                }
+                if r_offset < span_low || r_offset.checked_add(8).ok_or(Fault)? > span_high {
+                    return Err(Fault);
+                }
+                if !apply(r_offset, r_addend) {
+                    return Err(Fault);
+                }
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+}
+
+/// `PT_TLS` template: `filesz` initialized bytes at `vaddr`, zero-filled up
+/// to `memsz`.
+#[cfg(feature = "go_test")]
+#[derive(Clone, Copy)]
+struct ElfV1Tls {
+    vaddr: u64,
+    filesz: u64,
+    memsz: u64,
+    align: u64,
+}
+
+/// The image's `PT_TLS` segment, if any. Its initialized bytes must be
+/// file-backed by a `PT_LOAD` segment and its alignment at most a page.
+#[cfg(feature = "go_test")]
+fn elf_v1_tls_segment(image: &[u8]) -> Result<Option<ElfV1Tls>, ()> {
+    let e_phoff = elf_v1_read_u64(image, 32).ok_or(())? as usize;
+    let e_phentsize = elf_v1_read_u16(image, 54).ok_or(())? as usize;
+    let e_phnum = elf_v1_read_u16(image, 56).ok_or(())? as usize;
+    for idx in 0..e_phnum {
+        let off = e_phoff + idx * e_phentsize;
+        if elf_v1_read_u32(image, off).ok_or(())? != ELF_V1_PT_TLS {
+            continue;
+        }
+        let tls = ElfV1Tls {
+            vaddr: elf_v1_read_u64(image, off + 16).ok_or(())?,
+            filesz: elf_v1_read_u64(image, off + 32).ok_or(())?,
+            memsz: elf_v1_read_u64(image, off + 40).ok_or(())?,
+            align: elf_v1_read_u64(image, off + 48).ok_or(())?,
+        };
+        if tls.filesz > tls.memsz || tls.align > 4096 || (tls.align != 0 && !elf_v1_is_pow2(tls.align)) {
+            return Err(());
+        }
+        if tls.filesz != 0 && elf_v1_vaddr_to_offset(image, tls.vaddr, tls.filesz).is_none() {
+            return Err(());
+        }
+        return Ok(Some(tls));
+    }
+    Ok(None)
+}
+
+/// What the startup stack needs from a loaded image, at loaded addresses.
+#[cfg(feature = "go_test")]
+#[derive(Clone, Copy)]
+struct ElfV1LoadInfo {
+    entry: u64,
+    phdr: u64,
+    phent: u64,
+    phnum: u64,
+    tls: Option<ElfV1Tls>,
+}
+
+/// Link-time address of the program headers: `PT_PHDR` when present,
+/// otherwise the `PT_LOAD` segment that holds them in the file, or 0.
+#[cfg(feature = "go_test")]
+fn elf_v1_phdr_vaddr(image: &[u8]) -> u64 {
+    let (e_phoff, e_phentsize, e_phnum) = match (
+        elf_v1_read_u64(image, 32),
+        elf_v1_read_u16(image, 54),
+        elf_v1_read_u16(image, 56),
+    ) {
+        (Some(off), Some(ent), Some(num)) => (off, ent as usize, num as usize),
+        _ => return 0,
+    };
+    let mut from_load = 0;
+    for idx in 0..e_phnum {
+        let off = e_phoff as usize + idx * e_phentsize;
+        let (p_type, p_offset, p_vaddr, p_filesz) = match (
+            elf_v1_read_u32(image, off),
+            elf_v1_read_u64(image, off + 8),
+            elf_v1_read_u64(image, off + 16),
+            elf_v1_read_u64(image, off + 32),
+        ) {
+            (Some(t), Some(o), Some(v), Some(f)) => (t, o, v, f),
+            _ => return 0,
+        };
+        if p_type == ELF_V1_PT_PHDR {
+            return p_vaddr;
+        }
+        if p_type == ELF_V1_PT_LOAD && e_phoff >= p_offset && e_phoff - p_offset < p_filesz {
+            from_load = p_vaddr + (e_phoff - p_offset);
+        }
+    }
+    from_load
+}
+
+cfg_elf_loader! {
+    /// Hardware entropy for load-base randomization, when the CPU has RDRAND.
+    fn elf_v1_entropy() -> Option<u64> {
+        // CPUID.01H:ECX bit 30 advertises RDRAND.
+        let leaf1 = core::arch::x86_64::__cpuid(1);
+        if leaf1.ecx & (1 << 30) == 0 {
+            return None;
+        }
+        for _ in 0..10 {
+            let value: u64;
+            let ok: u8;
+            unsafe {
+                core::arch::asm!(
+                    "rdrand {v}",
+                    "setc {ok}",
+                    v = out(reg) value,
+                    ok = out(reg_byte) ok,
+                    options(nomem, nostack),
+                );
+            }
+            if ok != 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+/// Sixteen bytes for `AT_RANDOM`: RDRAND when present, otherwise a mix of
+/// the time-stamp counter.
+#[cfg(feature = "go_test")]
+fn elf_v1_random_bytes() -> [u8; 16] {
+    let (lo, hi) = match (elf_v1_entropy(), elf_v1_entropy()) {
+        (Some(lo), Some(hi)) => (lo, hi),
+        _ => {
+            let tsc = unsafe { core::arch::x86_64::_rdtsc() };
+            let mix = |mut z: u64| {
+                z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+                z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+                z ^ (z >> 31)
+            };
+            (mix(tsc), mix(tsc.wrapping_add(0x9E37_79B9_7F4A_7C15)))
+        }
+    };
+    let mut out = [0u8; 16];
+    out[..8].copy_from_slice(&lo.to_le_bytes());
+    out[8..].copy_from_slice(&hi.to_le_bytes());
+    out
+}
+
+/// The aux vector, `AT_NULL` last. Tasks have no user ids yet, so the id
+/// entries are 0.
+#[cfg(feature = "go_test")]
+fn elf_v1_build_auxv(info: &ElfV1LoadInfo, random: u64, execfn: u64) -> [(u64, u64); AUXV_V1_COUNT] {
+    // CPUID.01H:EDX, as Linux reports it for x86-64.
+    let hwcap = core::arch::x86_64::__cpuid(1).edx as u64;
     [
-        (AUXV_V1_AT_PHDR, phdr),
-        (AUXV_V1_AT_PHENT, phent),
-        (AUXV_V1_AT_PHNUM, phnum),
+        (AUXV_V1_AT_PHDR, info.phdr),
+        (AUXV_V1_AT_PHENT, info.phent),
+        (AUXV_V1_AT_PHNUM, info.phnum),
         (AUXV_V1_AT_PAGESZ, 4096),
-        (AUXV_V1_AT_ENTRY, entry),
+        (AUXV_V1_AT_ENTRY, info.entry),
+        (AUXV_V1_AT_HWCAP, hwcap),
+        (AUXV_V1_AT_CLKTCK, AUXV_V1_CLKTCK),
+        (AUXV_V1_AT_UID, 0),
+        (AUXV_V1_AT_EUID, 0),
+        (AUXV_V1_AT_GID, 0),
+        (AUXV_V1_AT_EGID, 0),
+        (AUXV_V1_AT_RANDOM, random),
+        (AUXV_V1_AT_EXECFN, execfn),
         (AUXV_V1_AT_NULL, 0),
     ]
 }
@@ -414,6 +781,190 @@ static mut KADDR_REQUEST: LimineKaddrRequest = LimineKaddrRequest {
     response: core::ptr::null(),
 };
 
+// --------------- Limine memory map request ---------------
+
+#[cfg(user_mode)]
+const LIMINE_MEMMAP_USABLE: u64 = 0;
+
+#[cfg(user_mode)]
+#[repr(C)]
+struct LimineMemmapEntry {
+    base: u64,
+    length: u64,
+    kind: u64,
+}
+
+#[cfg(user_mode)]
+#[repr(C)]
+struct LimineMemmapResponse {
+    revision: u64,
+    entry_count: u64,
+    entries: *const *const LimineMemmapEntry,
+}
+
+#[cfg(user_mode)]
+#[repr(C)]
+struct LimineMemmapRequest {
+    id: [u64; 4],
+    revision: u64,
+    response: *const LimineMemmapResponse,
+}
+
+#[cfg(user_mode)]
+unsafe impl Sync for LimineMemmapRequest {}
+
+// The frame pools are carved from the usable entries (`pmm`).
+#[cfg(user_mode)]
+#[used]
+#[link_section = ".limine_requests"]
+static mut MEMMAP_REQUEST: LimineMemmapRequest = LimineMemmapRequest {
+    id: [0xc7b1dd30df4c8b88, 0x0a82e883a194f07b,
+         0x67cf3d9d378a806f, 0xe304acdfc50c3c62],
+    revision: 0,
+    response: core::ptr::null(),
+};
+
+// --------------- Limine executable file and module requests ---------------
+
+#[cfg(user_mode)]
+#[repr(C)]
+struct LimineFile {
+    revision: u64,
+    address: *const u8,
+    size: u64,
+    path: *const u8,
+    string: *const u8,
+}
+
+#[cfg(user_mode)]
+#[repr(C)]
+struct LimineExecutableFileResponse {
+    revision: u64,
+    executable_file: *const LimineFile,
+}
+
+#[cfg(user_mode)]
+#[repr(C)]
+struct LimineExecutableFileRequest {
+    id: [u64; 4],
+    revision: u64,
+    response: *const LimineExecutableFileResponse,
+}
+
+#[cfg(user_mode)]
+unsafe impl Sync for LimineExecutableFileRequest {}
+
+// The kernel command line arrives as the executable file's string.
+#[cfg(user_mode)]
+#[used]
+#[link_section = ".limine_requests"]
+static mut EXECUTABLE_FILE_REQUEST: LimineExecutableFileRequest = LimineExecutableFileRequest {
+    id: [0xc7b1dd30df4c8b88, 0x0a82e883a194f07b,
+         0xad97e90e83f1ed67, 0x31eb5d1c5ff23b69],
+    revision: 0,
+    response: core::ptr::null(),
+};
+
+/// A NUL-terminated bootloader string, at most one page long.
+#[cfg(user_mode)]
+unsafe fn c_str<'a>(ptr: *const u8) -> &'a [u8] {
+    if ptr.is_null() {
+        return &[];
+    }
+    let mut len = 0usize;
+    while len < 4096 && *ptr.add(len) != 0 {
+        len += 1;
+    }
+    core::slice::from_raw_parts(ptr, len)
+}
+
+/// The kernel command line, or nothing if the bootloader gave none.
+#[cfg(user_mode)]
+unsafe fn boot_cmdline() -> &'static [u8] {
+    let exec_resp = core::ptr::read_volatile(core::ptr::addr_of!(EXECUTABLE_FILE_REQUEST.response));
+    if exec_resp.is_null() || (*exec_resp).executable_file.is_null() {
+        &[]
+    } else {
+        c_str((*(*exec_resp).executable_file).string)
+    }
+}
+
+#[cfg(any(feature = "fs_test", feature = "go_test"))]
+#[repr(C)]
+struct LimineModuleResponse {
+    revision: u64,
+    module_count: u64,
+    modules: *const *const LimineFile,
+}
+
+#[cfg(any(feature = "fs_test", feature = "go_test"))]
+#[repr(C)]
+struct LimineModuleRequest {
+    id: [u64; 4],
+    revision: u64,
+    response: *const LimineModuleResponse,
+}
+
+#[cfg(any(feature = "fs_test", feature = "go_test"))]
+unsafe impl Sync for LimineModuleRequest {}
+
+#[cfg(any(feature = "fs_test", feature = "go_test"))]
+#[used]
+#[link_section = ".limine_requests"]
+static mut MODULE_REQUEST: LimineModuleRequest = LimineModuleRequest {
+    id: [0xc7b1dd30df4c8b88, 0x0a82e883a194f07b,
+         0x3e7e279702be32af, 0xca1c4f3bd1280cee],
+    revision: 0,
+    response: core::ptr::null(),
+};
+
+// --------------- Limine MP request ---------------
+
+#[cfg(feature = "go_test")]
+#[repr(C)]
+struct LimineMpInfo {
+    processor_id: u32,
+    lapic_id: u32,
+    reserved: u64,
+    // Written last: the AP polls it and jumps there with `rdi` = this info.
+    goto_address: u64,
+    extra_argument: u64,
+}
+
+#[cfg(feature = "go_test")]
+#[repr(C)]
+struct LimineMpResponse {
+    revision: u64,
+    flags: u32,
+    bsp_lapic_id: u32,
+    cpu_count: u64,
+    cpus: *const *mut LimineMpInfo,
+}
+
+#[cfg(feature = "go_test")]
+#[repr(C)]
+struct LimineMpRequest {
+    id: [u64; 4],
+    revision: u64,
+    response: *const LimineMpResponse,
+    flags: u64,
+}
+
+#[cfg(feature = "go_test")]
+unsafe impl Sync for LimineMpRequest {}
+
+#[cfg(feature = "go_test")]
+#[used]
+#[link_section = ".limine_requests"]
+static mut MP_REQUEST: LimineMpRequest = LimineMpRequest {
+    id: [0xc7b1dd30df4c8b88, 0x0a82e883a194f07b,
+         0x95a67b819a1b857e, 0xa0b61b723b6a73e0],
+    revision: 0,
+    response: core::ptr::null(),
+    flags: 0,
+};
+
+#[cfg(user_mode)]
 static mut HHDM_OFFSET: u64 = 0;
 
 extern "C" {
@@ -445,6 +996,7 @@ cfg_m3! {
         0
     }
 
+    #[cfg(not(feature = "go_test"))]
     unsafe fn sys_thread_spawn_m3(frame: *mut u64, entry: u64) -> u64 {
         if entry >= USER_VA_LIMIT { return 0xFFFF_FFFF_FFFF_FFFF; }
         if !user_pages_ok(entry, 1, USER_PERM_READ) {
@@ -453,10 +1005,13 @@ cfg_m3! {
 
         m3_bootstrap_main_thread(frame);
 
-        for tid in 1..M3_MAX_THREADS {
+        for tid in 1..M3_THREADS.len() {
             if M3_THREADS[tid].state != M3ThreadState::Dead {
                 continue;
             }
+            if !m3_map_thread_stack(tid) {
+                break;
+            }
             M3_THREADS[tid].saved_frame = [0u64; 22];
             M3_THREADS[tid].saved_frame[17] = entry;                    // RIP
             M3_THREADS[tid].saved_frame[18] = 0x23;                     // CS
@@ -476,8 +1031,8 @@ cfg_m3! {
         if !user_range_ok(vaddr, size as usize) { return 0xFFFF_FFFF_FFFF_FFFF; }
 
         let pte = match m3_user_pte_ptr(vaddr) {
-            Some(p) => p,
-            None => return 0xFFFF_FFFF_FFFF_FFFF,
+            Some((p, 4096)) => p,
+            _ => return 0xFFFF_FFFF_FFFF_FFFF,
         };
         if *pte & 1 != 0 { return 0xFFFF_FFFF_FFFF_FFFF; }
 
@@ -514,8 +1069,8 @@ cfg_m3! {
         };
 
         let pte = match m3_user_pte_ptr(vaddr) {
-            Some(p) => p,
-            None => return 0xFFFF_FFFF_FFFF_FFFF,
+            Some((p, 4096)) => p,
+            _ => return 0xFFFF_FFFF_FFFF_FFFF,
         };
         if *pte & 1 == 0 { return 0xFFFF_FFFF_FFFF_FFFF; }
 
@@ -814,6 +1369,17 @@ cfg_m3! {
         }
     }
 
+    /// Whether fsync on `fd` is a journal commit the storage flusher can
+    /// take: a writable journal descriptor the caller holds.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_fsync_defers(fd: u64) -> bool {
+        let idx = fd as usize;
+        idx < M8_FD_MAX
+            && r4_fd_owner_ok(idx)
+            && matches!(M8_FD_TABLE[idx].kind, M8FdKind::JournalFile)
+            && M8_FD_TABLE[idx].rights & M10_RIGHT_WRITE != 0
+    }
+
     unsafe fn sys_fsync_v1(fd: u64) -> u64 {
         let idx = fd as usize;
         if idx >= M8_FD_MAX { return 0xFFFF_FFFF_FFFF_FFFF; }
@@ -855,9 +1421,11 @@ cfg_m3! {
             if !r4_fd_owner_ok(idx) {
                 return 0xFFFF_FFFF_FFFF_FFFF;
             }
-            let owner_tid = M8_FD_TABLE[idx].owner_tid;
-            if owner_tid < R4_NUM_TASKS && R4_TASKS[owner_tid].fd_count != 0 {
-                R4_TASKS[owner_tid].fd_count -= 1;
+            if R4_TASKS[r4_current()].fd_count != 0 {
+                R4_TASKS[r4_current()].fd_count -= 1;
+            }
+            if r4_fd_drop_holder(&mut M8_FD_TABLE[idx], r4_current()) {
+                return 0;
             }
         }
         M8_FD_TABLE[idx] = M8FdEntry::EMPTY;
@@ -1005,10 +1573,14 @@ cfg_m3! {
         ready
     }
 
+    // R4 lanes dispatch 43 to `sys_fork_r4`.
+    #[cfg(not(feature = "go_test"))]
     unsafe fn sys_fork_deferred_v1() -> u64 {
         0xFFFF_FFFF_FFFF_FFFF
     }
 
+    // R4 lanes dispatch 44 to `sys_clone_r4`.
+    #[cfg(not(feature = "go_test"))]
     unsafe fn sys_clone_deferred_v1() -> u64 {
         0xFFFF_FFFF_FFFF_FFFF
     }
@@ -1018,15 +1590,15 @@ cfg_m3! {
     }
 }
 
-#[cfg(not(any(feature = "ipc_test", feature = "shm_test", feature = "ipc_badptr_send_test", feature = "ipc_badptr_recv_test", feature = "ipc_badptr_svc_test", feature = "ipc_buffer_full_test", feature = "ipc_waiter_busy_test", feature = "svc_overwrite_test", feature = "svc_full_test", feature = "svc_bad_endpoint_test", feature = "stress_ipc_test", feature = "quota_endpoints_test", feature = "quota_shm_test", feature = "quota_threads_test")))]
+#[cfg(not(r4_ipc))]
 unsafe fn sys_thread_exit_m3(frame: *mut u64) {
-    #[cfg(any(feature = "user_hello_test", feature = "syscall_test", feature = "thread_exit_test", feature = "thread_spawn_test", feature = "vm_map_test", feature = "syscall_invalid_test", feature = "stress_syscall_test", feature = "yield_test", feature = "user_fault_test", feature = "blk_test", feature = "fs_test", feature = "go_test", feature = "go_std_test", feature = "sec_rights_test", feature = "sec_filter_test"))]
+    #[cfg(m3)]
     {
         // M8 PR-2: expose deterministic child-exit observation for wait semantics.
         M8_WAIT_HAS_EXIT = true;
         M8_WAIT_EXIT_STATUS = 0;
     }
-    #[cfg(any(feature = "user_hello_test", feature = "syscall_test", feature = "thread_exit_test", feature = "thread_spawn_test", feature = "vm_map_test", feature = "syscall_invalid_test", feature = "stress_syscall_test", feature = "yield_test", feature = "user_fault_test", feature = "blk_test", feature = "fs_test", feature = "go_test", feature = "go_std_test", feature = "sec_rights_test", feature = "sec_filter_test"))]
+    #[cfg(m3)]
     if M3_THREADING_ACTIVE {
         M3_THREADS[M3_CURRENT].state = M3ThreadState::Dead;
         if let Some(next) = m3_find_ready(M3_CURRENT) {
@@ -1038,13 +1610,13 @@ unsafe fn sys_thread_exit_m3(frame: *mut u64) {
     trap::m3_return_to_kernel_halt(frame);
 }
 
-#[cfg(feature = "fs_test")]
+#[cfg(any(feature = "fs_test", feature = "go_test"))]
 #[inline(always)]
 fn sha256_rotr(x: u32, n: u32) -> u32 {
-    (x >> n) | (x << (32 - n))
+    x.rotate_right(n)
 }
 
-#[cfg(feature = "fs_test")]
+#[cfg(any(feature = "fs_test", feature = "go_test"))]
 fn sha256_compress(state: &mut [u32; 8], block: &[u8; 64]) {
     const K: [u32; 64] = [
         0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5,
@@ -1125,7 +1697,7 @@ fn sha256_compress(state: &mut [u32; 8], block: &[u8; 64]) {
     state[7] = state[7].wrapping_add(h);
 }
 
-#[cfg(feature = "fs_test")]
+#[cfg(any(feature = "fs_test", feature = "go_test"))]
 fn sha256_digest(data: &[u8]) -> [u8; 32] {
     let mut state = [
         0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a,
@@ -1168,27 +1740,43 @@ fn sha256_digest(data: &[u8]) -> [u8; 32] {
 // --------------- User page table infrastructure (shared M3+R4) ---------------
 
 cfg_user! {
+    #[allow(dead_code)]
     const USER_CODE_VA: u64   = 0x40_0000;
+    #[cfg(not(feature = "go_test"))]
     const USER_STACK_TOP: u64 = 0x80_0000;
 
     #[derive(Clone, Copy)]
     #[repr(C, align(4096))]
     struct Page([u8; 4096]);
 
+    #[cfg(not(feature = "go_test"))]
+    static mut USER_CODE_PAGE: Page = Page([0; 4096]);
+
+}
+
+// Only the M3 page setup uses these tables. The Go lane runs every process in
+// its own `vm` space, so it does not build them.
+cfg_m3! {
+    #[cfg(not(feature = "go_test"))]
     static mut USER_PML4:      Page = Page([0; 4096]);
+    #[cfg(not(feature = "go_test"))]
     static mut USER_PDPT:      Page = Page([0; 4096]);
+    #[cfg(not(feature = "go_test"))]
     static mut USER_PD:        Page = Page([0; 4096]);
+    #[cfg(not(feature = "go_test"))]
     static mut USER_PT_CODE:   Page = Page([0; 4096]);
+    #[cfg(not(feature = "go_test"))]
     static mut USER_PT_STACK:  Page = Page([0; 4096]);
-    static mut USER_CODE_PAGE: Page = Page([0; 4096]);
+    #[cfg(not(feature = "go_test"))]
     static mut USER_STACK_PAGE: Page = Page([0; 4096]);
-
 }
 
 // --------------- M3: User thread + vm model ----------------------------------
 
 cfg_m3! {
-    const M3_MAX_THREADS: usize = 4;
+    // Most threads `rugo.m3_threads=` may ask for: one stack page each in the
+    // stack page table, below the main thread's.
+    const M3_MAX_THREADS: usize = 511;
     const M3_MAX_VM_MAPS: usize = 8;
     const M3_VM_PAGE_SIZE: u64 = 4096;
     const M8_FD_MAX: usize = 16;
@@ -1218,6 +1806,7 @@ cfg_m3! {
         rights: u64,
         offset: usize,
         owner_tid: usize,
+        holders: u64,
     }
 
     impl M8FdEntry {
@@ -1226,7 +1815,16 @@ cfg_m3! {
             rights: 0,
             offset: 0,
             owner_tid: 0,
+            holders: 0,
         };
+
+        #[cfg(feature = "go_test")]
+        #[inline(always)]
+        fn held_by(&self, tid: usize) -> bool {
+            self.kind != M8FdKind::Free
+                && (self.owner_tid == tid
+                    || self.holders & runtime::isolation::holder_bit(tid) != 0)
+        }
     }
 
     static mut M8_FD_TABLE: [M8FdEntry; M8_FD_MAX] = [M8FdEntry::EMPTY; M8_FD_MAX];
@@ -1267,31 +1865,61 @@ cfg_m3! {
         const EMPTY: Self = Self { active: false, va: 0 };
     }
 
-    static mut M3_THREADS: [M3Thread; M3_MAX_THREADS] =
-        [M3Thread::EMPTY; M3_MAX_THREADS];
+    // Sized from the boot limit on every reset.
+    static mut M3_THREADS: kobj::KTable<M3Thread> = kobj::KTable::EMPTY;
     static mut M3_CURRENT: usize = 0;
     static mut M3_THREADING_ACTIVE: bool = false;
 
-    static mut M3_STACK_PAGE_1: Page = Page([0; 4096]);
-    static mut M3_STACK_PAGE_2: Page = Page([0; 4096]);
-    static mut M3_STACK_PAGE_3: Page = Page([0; 4096]);
+    // Stack frame of each spawned thread's slot, carved on its first spawn
+    // and kept across resets; slot 0 runs on `USER_STACK_PAGE`.
+    #[cfg(not(feature = "go_test"))]
+    static mut M3_STACK_FRAMES: kobj::KTable<u64> = kobj::KTable::EMPTY;
 
     static mut M3_VM_PAGES: [Page; M3_MAX_VM_MAPS] = [Page([0; 4096]); M3_MAX_VM_MAPS];
     static mut M3_VM_MAPS: [M3VmMap; M3_MAX_VM_MAPS] = [M3VmMap::EMPTY; M3_MAX_VM_MAPS];
 
+    #[cfg(not(feature = "go_test"))]
     #[inline(always)]
     unsafe fn m3_stack_top_for_slot(slot: usize) -> u64 {
         USER_STACK_TOP - (slot as u64) * 0x1000
     }
 
-    unsafe fn m3_reset_state() {
-        M3_CURRENT = 0;
-        M3_THREADING_ACTIVE = false;
-        M8_WAIT_HAS_EXIT = false;
-        M8_WAIT_EXIT_STATUS = 0;
+    /// Back and map slot `tid`'s stack page, zeroed. False if no memory is
+    /// left for it.
+    #[cfg(not(feature = "go_test"))]
+    unsafe fn m3_map_thread_stack(tid: usize) -> bool {
+        if M3_STACK_FRAMES[tid] == 0 {
+            match pmm::pmm_carve(1, 4096) {
+                Some(phys) => M3_STACK_FRAMES[tid] = phys,
+                None => return false,
+            }
+        }
+        let phys = M3_STACK_FRAMES[tid];
+        core::ptr::write_bytes((phys + HHDM_OFFSET) as *mut u8, 0, 4096);
+        let pt_stack = USER_PT_STACK.0.as_mut_ptr() as *mut u64;
+        *pt_stack.add(511 - tid) = phys | 0x07;
+        true
+    }
+
+    // The Go lanes run no M3 threads; only the compat apps reset the M3
+    // state before they launch.
+    #[cfg(any(not(feature = "go_test"), feature = "compat_real_test"))]
+    unsafe fn m3_reset_state() {
+        M3_CURRENT = 0;
+        M3_THREADING_ACTIVE = false;
+        M8_WAIT_HAS_EXIT = false;
+        M8_WAIT_EXIT_STATUS = 0;
         M10_SEC_PROFILE = M10SecProfile::Default;
-        for i in 0..M3_MAX_THREADS {
-            M3_THREADS[i] = M3Thread::EMPTY;
+        let threads = limits::m3_threads();
+        let tables_ok = M3_THREADS.init(threads, M3Thread::EMPTY);
+        // Only the M3 lanes spawn threads onto their own stack frames.
+        #[cfg(not(feature = "go_test"))]
+        let tables_ok = tables_ok
+            && (M3_STACK_FRAMES.len() != 0 || M3_STACK_FRAMES.init(threads, 0));
+        if !tables_ok {
+            serial_write(b"M3: thread table alloc fail\n");
+            qemu_exit(0x33);
+            loop { core::arch::asm!("cli; hlt", options(nomem, nostack)); }
         }
         for i in 0..M3_MAX_VM_MAPS {
             M3_VM_MAPS[i] = M3VmMap::EMPTY;
@@ -1306,18 +1934,21 @@ cfg_m3! {
             rights: console_rights,
             offset: 0,
             owner_tid: 0,
+            holders: 0,
         };
         M8_FD_TABLE[1] = M8FdEntry {
             kind: M8FdKind::Console,
             rights: console_rights,
             offset: 0,
             owner_tid: 0,
+            holders: 0,
         };
         M8_FD_TABLE[2] = M8FdEntry {
             kind: M8FdKind::Console,
             rights: console_rights,
             offset: 0,
             owner_tid: 0,
+            holders: 0,
         };
     }
 
@@ -1346,7 +1977,7 @@ cfg_m3! {
     }
 
     unsafe fn m3_find_ready(exclude: usize) -> Option<usize> {
-        for tid in 0..M3_MAX_THREADS {
+        for tid in 0..M3_THREADS.len() {
             if tid != exclude && M3_THREADS[tid].state == M3ThreadState::Ready {
                 return Some(tid);
             }
@@ -1354,29 +1985,13 @@ cfg_m3! {
         None
     }
 
-    unsafe fn m3_user_pte_ptr(addr: u64) -> Option<*mut u64> {
+    /// The leaf slot covering `addr` and the size it maps. A 2 MiB leaf is
+    /// returned as such; callers that edit single pages must check the size.
+    unsafe fn m3_user_pte_ptr(addr: u64) -> Option<(*mut u64, u64)> {
         if addr >= USER_VA_LIMIT { return None; }
         let hhdm = HHDM_OFFSET;
         if hhdm == 0 { return None; }
-
-        let cr3: u64;
-        core::arch::asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack));
-        let pml4_phys = cr3 & 0x000F_FFFF_FFFF_F000;
-        let pml4 = (pml4_phys + hhdm) as *const u64;
-        let pml4e = *pml4.add(((addr >> 39) & 0x1FF) as usize);
-        if pml4e & 1 == 0 || pml4e & 4 == 0 { return None; }
-
-        let pdpt = ((pml4e & 0x000F_FFFF_FFFF_F000) + hhdm) as *const u64;
-        let pdpte = *pdpt.add(((addr >> 30) & 0x1FF) as usize);
-        if pdpte & 1 == 0 || pdpte & 4 == 0 || pdpte & 0x80 != 0 { return None; }
-
-        let pd = ((pdpte & 0x000F_FFFF_FFFF_F000) + hhdm) as *const u64;
-        let pde = *pd.add(((addr >> 21) & 0x1FF) as usize);
-        if pde & 1 == 0 || pde & 4 == 0 || pde & 0x80 != 0 { return None; }
-
-        let pt = ((pde & 0x000F_FFFF_FFFF_F000) + hhdm) as *mut u64;
-        let pt_idx = ((addr >> 12) & 0x1FF) as usize;
-        Some(pt.add(pt_idx))
+        user_leaf_walk(addr, hhdm, 0x05)
     }
 
     unsafe fn m3_kv2p(va: u64) -> u64 {
@@ -1514,6 +2129,7 @@ cfg_m3! {
                     rights: req,
                     offset: src.offset,
                     owner_tid: src.owner_tid,
+                    holders: src.holders,
                 };
                 M8_FD_TABLE[idx] = M8FdEntry::EMPTY;
                 return i as u64;
@@ -1525,18 +2141,49 @@ cfg_m3! {
     #[cfg(feature = "go_test")]
     #[inline(always)]
     unsafe fn r4_fd_owner_ok(idx: usize) -> bool {
-        idx < 3
-            || (M8_FD_TABLE[idx].kind != M8FdKind::Free
-                && M8_FD_TABLE[idx].owner_tid == R4_CURRENT)
+        idx < 3 || r4_fd_held_by(idx, r4_current())
+    }
+
+    #[cfg(feature = "go_test")]
+    #[inline(always)]
+    unsafe fn r4_fd_held_by(idx: usize, tid: usize) -> bool {
+        M8_FD_TABLE[idx].held_by(tid)
+    }
+
+    /// Drop `tid`'s hold on a descriptor, handing ownership to another holder
+    /// if it was the owner. Returns false once nobody holds the entry.
+    #[cfg(feature = "go_test")]
+    fn r4_fd_drop_holder(entry: &mut M8FdEntry, tid: usize) -> bool {
+        entry.holders &= !runtime::isolation::holder_bit(tid);
+        if entry.owner_tid == tid {
+            match runtime::isolation::next_holder(entry.holders) {
+                Some(next) => {
+                    entry.owner_tid = next;
+                    entry.holders &= !runtime::isolation::holder_bit(next);
+                }
+                None => return false,
+            }
+        }
+        true
+    }
+
+    /// Fork: the child holds every descriptor the parent holds, with the
+    /// same rights and a shared offset.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_share_fds(parent: usize, child: usize) {
+        for entry in M8_FD_TABLE.iter_mut().skip(3) {
+            if entry.held_by(parent) {
+                entry.holders |= runtime::isolation::holder_bit(child);
+            }
+        }
+        R4_TASKS[child].fd_count = R4_TASKS[parent].fd_count;
     }
 
     #[cfg(feature = "go_test")]
     unsafe fn r4_release_owned_fds(owner_tid: usize) {
-        for idx in 3..M8_FD_MAX {
-            if M8_FD_TABLE[idx].kind != M8FdKind::Free
-                && M8_FD_TABLE[idx].owner_tid == owner_tid
-            {
-                M8_FD_TABLE[idx] = M8FdEntry::EMPTY;
+        for entry in M8_FD_TABLE.iter_mut().skip(3) {
+            if entry.held_by(owner_tid) && !r4_fd_drop_holder(entry, owner_tid) {
+                *entry = M8FdEntry::EMPTY;
             }
         }
         if owner_tid < R4_NUM_TASKS {
@@ -1552,12 +2199,12 @@ cfg_m3! {
         #[cfg(feature = "go_test")]
         {
             if !runtime::isolation::under_quota(
-                R4_TASKS[R4_CURRENT].fd_count,
-                R4_TASKS[R4_CURRENT].fd_limit as usize,
+                R4_TASKS[r4_current()].fd_count,
+                R4_TASKS[r4_current()].fd_limit as usize,
             ) {
                 return 0xFFFF_FFFF_FFFF_FFFF;
             }
-            owner_tid = R4_CURRENT;
+            owner_tid = r4_current();
         }
         for i in 3..M8_FD_MAX {
             if M8_FD_TABLE[i].kind == M8FdKind::Free {
@@ -1565,9 +2212,10 @@ cfg_m3! {
                 M8_FD_TABLE[i].rights = m10_rights_for_kind(kind);
                 M8_FD_TABLE[i].offset = 0;
                 M8_FD_TABLE[i].owner_tid = owner_tid;
+                M8_FD_TABLE[i].holders = 0;
                 #[cfg(feature = "go_test")]
                 {
-                    R4_TASKS[R4_CURRENT].fd_count += 1;
+                    R4_TASKS[r4_current()].fd_count += 1;
                 }
                 return i as u64;
             }
@@ -1579,6 +2227,7 @@ cfg_m3! {
 // --------------- M3: User page setup -----------------------------------------
 
 cfg_m3! {
+    #[cfg(not(feature = "go_test"))]
     unsafe fn setup_user_pages(user_code: &[u8]) {
         let hhdm_resp_ptr = core::ptr::read_volatile(
             core::ptr::addr_of!(HHDM_REQUEST.response));
@@ -1609,9 +2258,6 @@ cfg_m3! {
 
         let pt_stack = USER_PT_STACK.0.as_mut_ptr() as *mut u64;
         *pt_stack.add(511) = kv2p(USER_STACK_PAGE.0.as_ptr() as u64) | 0x07;
-        *pt_stack.add(510) = kv2p(M3_STACK_PAGE_1.0.as_ptr() as u64) | 0x07;
-        *pt_stack.add(509) = kv2p(M3_STACK_PAGE_2.0.as_ptr() as u64) | 0x07;
-        *pt_stack.add(508) = kv2p(M3_STACK_PAGE_3.0.as_ptr() as u64) | 0x07;
 
         *new_pml4 = kv2p(USER_PDPT.0.as_ptr() as u64) | 0x07;
 
@@ -1624,55 +2270,63 @@ cfg_m3! {
         core::arch::asm!("mov cr3, {}", in(reg) new_pml4_phys, options(nostack));
     }
 
-    unsafe fn m3_user_code_page_ptr(page_idx: usize) -> Option<*mut u8> {
-        match page_idx {
-            0 => Some(USER_CODE_PAGE.0.as_mut_ptr()),
-            #[cfg(feature = "go_test")]
-            1 => Some(USER_CODE_PAGE_2.0.as_mut_ptr()),
-            #[cfg(feature = "go_test")]
-            2 => Some(USER_CODE_PAGE_3.0.as_mut_ptr()),
-            #[cfg(feature = "go_test")]
-            3 => Some(USER_CODE_PAGE_4.0.as_mut_ptr()),
-            #[cfg(feature = "go_test")]
-            4 => Some(USER_CODE_PAGE_5.0.as_mut_ptr()),
-            #[cfg(feature = "go_test")]
-            5 => Some(USER_CODE_PAGE_6.0.as_mut_ptr()),
-            _ => None,
-        }
-    }
+    // The M3 ELF loader only runs on fs_test, which loads ELF packages from
+    // disk. Its code window starts with the static code page and extends
+    // over the 2 MiB covered by `USER_PT_CODE` with frames carved from the
+    // memory map the first time an image reaches them, kept for later
+    // images. R4 lanes load images into their own process space instead
+    // (`r4_load_elf`).
+    #[cfg(feature = "fs_test")]
+    const M3_USER_CODE_PAGES: usize = 512;
 
-    fn m3_user_code_page_count() -> usize {
-        #[cfg(feature = "go_test")]
-        {
-            runtime::process::GO_IMAGE_MAX_PAGES
-        }
-        #[cfg(not(feature = "go_test"))]
-        {
-            1
+    #[cfg(feature = "fs_test")]
+    static mut M3_USER_CODE_FRAMES: [u64; M3_USER_CODE_PAGES] = [0; M3_USER_CODE_PAGES];
+
+    #[cfg(feature = "fs_test")]
+    unsafe fn m3_user_code_page_ptr(page_idx: usize) -> Option<*mut u8> {
+        if page_idx == 0 {
+            return Some(USER_CODE_PAGE.0.as_mut_ptr());
+        }
+        if page_idx < M3_USER_CODE_PAGES {
+            if M3_USER_CODE_FRAMES[page_idx] == 0 {
+                let phys = pmm::pmm_carve(1, 4096)?;
+                core::ptr::write_bytes((phys + HHDM_OFFSET) as *mut u8, 0, 4096);
+                M3_USER_CODE_FRAMES[page_idx] = phys;
+            }
+            let phys = M3_USER_CODE_FRAMES[page_idx];
+            let pt_code = USER_PT_CODE.0.as_mut_ptr() as *mut u64;
+            // User read/execute, like the first code page.
+            *pt_code.add(page_idx) = phys | 0x05;
+            return Some((phys + HHDM_OFFSET) as *mut u8);
         }
+        None
     }
 
+    /// Clear every code page an earlier image used.
+    #[cfg(feature = "fs_test")]
     unsafe fn m3_zero_user_code_pages() {
-        for page_idx in 0..m3_user_code_page_count() {
-            if let Some(ptr) = m3_user_code_page_ptr(page_idx) {
-                core::ptr::write_bytes(ptr, 0, runtime::process::GO_IMAGE_PAGE_SIZE);
+        core::ptr::write_bytes(USER_CODE_PAGE.0.as_mut_ptr(), 0, 4096);
+        for &phys in M3_USER_CODE_FRAMES.iter() {
+            if phys != 0 {
+                core::ptr::write_bytes((phys + HHDM_OFFSET) as *mut u8, 0, 4096);
             }
         }
     }
 
+    #[cfg(feature = "fs_test")]
     unsafe fn m3_copy_user_code(offset: usize, src: &[u8]) -> bool {
         let mut copied = 0usize;
         while copied < src.len() {
             let dst_off = offset + copied;
-            let page_idx = dst_off / runtime::process::GO_IMAGE_PAGE_SIZE;
-            let page_off = dst_off % runtime::process::GO_IMAGE_PAGE_SIZE;
+            let page_idx = dst_off / 4096;
+            let page_off = dst_off % 4096;
             let page_ptr = match m3_user_code_page_ptr(page_idx) {
                 Some(ptr) => ptr,
                 None => return false,
             };
             let chunk = core::cmp::min(
                 src.len() - copied,
-                runtime::process::GO_IMAGE_PAGE_SIZE - page_off,
+                4096 - page_off,
             );
             core::ptr::copy_nonoverlapping(
                 src.as_ptr().add(copied),
@@ -1684,19 +2338,20 @@ cfg_m3! {
         true
     }
 
+    #[cfg(feature = "fs_test")]
     unsafe fn m3_zero_user_code(offset: usize, len: usize) -> bool {
         let mut cleared = 0usize;
         while cleared < len {
             let dst_off = offset + cleared;
-            let page_idx = dst_off / runtime::process::GO_IMAGE_PAGE_SIZE;
-            let page_off = dst_off % runtime::process::GO_IMAGE_PAGE_SIZE;
+            let page_idx = dst_off / 4096;
+            let page_off = dst_off % 4096;
             let page_ptr = match m3_user_code_page_ptr(page_idx) {
                 Some(ptr) => ptr,
                 None => return false,
             };
             let chunk = core::cmp::min(
                 len - cleared,
-                runtime::process::GO_IMAGE_PAGE_SIZE - page_off,
+                4096 - page_off,
             );
             core::ptr::write_bytes(page_ptr.add(page_off), 0, chunk);
             cleared += chunk;
@@ -1704,13 +2359,34 @@ cfg_m3! {
         true
     }
 
+    /// Load base for an image spanning `[low, high)`: ET_EXEC images stay at
+    /// their link address; PIE images slide by whole pages inside the code
+    /// window, at random when the CPU offers entropy.
+    #[cfg(feature = "fs_test")]
+    unsafe fn m3_elf_load_bias(e_type: u16, low: u64, high: u64, code_end: u64) -> Option<u64> {
+        if e_type == ELF_V1_ET_EXEC {
+            return Some(0);
+        }
+        let window_pages = (code_end - USER_CODE_VA) / 4096;
+        let image_pages = (high - low) / 4096;
+        if image_pages > window_pages {
+            return None;
+        }
+        let slide = match elf_v1_entropy() {
+            Some(v) => v % (window_pages - image_pages + 1),
+            None => 0,
+        };
+        Some(USER_CODE_VA + slide * 4096 - low)
+    }
+
+    #[cfg(feature = "fs_test")]
     unsafe fn m3_load_user_elf_image(image: &[u8]) -> Option<u64> {
         if !elf_v1_validate_image(image) || image.len() < 64 {
             return None;
         }
 
         let e_type = elf_v1_read_u16(image, 16)?;
-        if e_type != ELF_V1_ET_EXEC {
+        if e_type != ELF_V1_ET_EXEC && e_type != ELF_V1_ET_DYN {
             return None;
         }
 
@@ -1718,9 +2394,17 @@ cfg_m3! {
         let e_phoff = elf_v1_read_u64(image, 32)? as usize;
         let e_phentsize = elf_v1_read_u16(image, 54)? as usize;
         let e_phnum = elf_v1_read_u16(image, 56)? as usize;
-        let code_span = runtime::process::GO_IMAGE_PAGE_SIZE
-            .checked_mul(m3_user_code_page_count())?;
-        let code_end = USER_CODE_VA.checked_add(code_span as u64)?;
+        let code_end = USER_CODE_VA + (M3_USER_CODE_PAGES * 4096) as u64;
+        let (span_low, span_high) = elf_v1_load_span(image)?;
+        let bias = m3_elf_load_bias(e_type, span_low, span_high, code_end)?;
+
+        // Check every relocation before anything is copied.
+        if e_type == ELF_V1_ET_DYN {
+            if let Err(err) = elf_v1_for_each_relative(image, span_low, span_high, |_, _| true) {
+                m3_log_elf_reloc_error(err);
+                return None;
+            }
+        }
 
         for idx in 0..e_phnum {
             let off = e_phoff.checked_add(idx.checked_mul(e_phentsize)?)?;
@@ -1730,7 +2414,7 @@ cfg_m3! {
             }
 
             let p_offset = elf_v1_read_u64(image, off + 8)? as usize;
-            let p_vaddr = elf_v1_read_u64(image, off + 16)?;
+            let p_vaddr = elf_v1_read_u64(image, off + 16)?.checked_add(bias)?;
             let p_filesz = elf_v1_read_u64(image, off + 32)? as usize;
             let p_memsz = elf_v1_read_u64(image, off + 40)? as usize;
             if p_vaddr < USER_CODE_VA || p_vaddr.checked_add(p_memsz as u64)? > code_end {
@@ -1751,9 +2435,36 @@ cfg_m3! {
             }
         }
 
-        Some(e_entry)
+        if e_type == ELF_V1_ET_DYN {
+            let applied = elf_v1_for_each_relative(image, span_low, span_high, |target, addend| {
+                let value = addend.wrapping_add(bias).to_le_bytes();
+                m3_copy_user_code((target + bias - USER_CODE_VA) as usize, &value)
+            });
+            if let Err(err) = applied {
+                m3_log_elf_reloc_error(err);
+                return None;
+            }
+        }
+
+        e_entry.checked_add(bias)
+    }
+
+    #[cfg(any(feature = "fs_test", feature = "go_test"))]
+    fn m3_log_elf_reloc_error(err: ElfV1RelocError) {
+        match err {
+            ElfV1RelocError::Invalid => serial_write(b"ELF: bad dynamic section\n"),
+            ElfV1RelocError::Range => serial_write(b"ELF: too many relocations\n"),
+            ElfV1RelocError::Fault => serial_write(b"ELF: relocation out of range\n"),
+            ElfV1RelocError::Unsupported => serial_write(b"ELF: unsupported dynamic image\n"),
+            ElfV1RelocError::UnsupportedType(r_type) => {
+                serial_write(b"ELF: unsupported relocation type=");
+                serial_write_u64_dec(r_type as u64);
+                serial_write(b"\n");
+            }
+        }
     }
 
+    #[cfg(feature = "fs_test")]
     unsafe fn setup_user_elf_pages(image: &[u8]) -> Option<u64> {
         let hhdm_resp_ptr = core::ptr::read_volatile(
             core::ptr::addr_of!(HHDM_REQUEST.response));
@@ -1772,6 +2483,13 @@ cfg_m3! {
         let new_pml4 = USER_PML4.0.as_mut_ptr() as *mut u64;
         for i in 0..512 { *new_pml4.add(i) = *old_pml4.add(i); }
 
+        // Drop entries left by the previous image; pool frames they point to
+        // were recycled when the frame pool was reset.
+        core::ptr::write_bytes(USER_PDPT.0.as_mut_ptr(), 0, 4096);
+        core::ptr::write_bytes(USER_PD.0.as_mut_ptr(), 0, 4096);
+        core::ptr::write_bytes(USER_PT_CODE.0.as_mut_ptr(), 0, 4096);
+        core::ptr::write_bytes(USER_PT_STACK.0.as_mut_ptr(), 0, 4096);
+
         let pdpt = USER_PDPT.0.as_mut_ptr() as *mut u64;
         *pdpt = kv2p(USER_PD.0.as_ptr() as u64) | 0x07;
 
@@ -1779,37 +2497,11 @@ cfg_m3! {
         *pd.add(2) = kv2p(USER_PT_CODE.0.as_ptr() as u64) | 0x07;
         *pd.add(3) = kv2p(USER_PT_STACK.0.as_ptr() as u64) | 0x07;
 
-        let code_flags = if cfg!(feature = "go_test") { 0x07 } else { 0x05 };
         let pt_code = USER_PT_CODE.0.as_mut_ptr() as *mut u64;
-        *pt_code.add(0) = kv2p(USER_CODE_PAGE.0.as_ptr() as u64) | code_flags;
-        #[cfg(feature = "go_test")]
-        {
-            *pt_code.add(1) = kv2p(USER_CODE_PAGE_2.0.as_ptr() as u64) | code_flags;
-            *pt_code.add(2) = kv2p(USER_CODE_PAGE_3.0.as_ptr() as u64) | code_flags;
-            *pt_code.add(3) = kv2p(USER_CODE_PAGE_4.0.as_ptr() as u64) | code_flags;
-            *pt_code.add(4) = kv2p(USER_CODE_PAGE_5.0.as_ptr() as u64) | code_flags;
-            *pt_code.add(5) = kv2p(USER_CODE_PAGE_6.0.as_ptr() as u64) | code_flags;
-        }
+        *pt_code.add(0) = kv2p(USER_CODE_PAGE.0.as_ptr() as u64) | 0x05;
 
         let pt_stack = USER_PT_STACK.0.as_mut_ptr() as *mut u64;
         *pt_stack.add(511) = kv2p(USER_STACK_PAGE.0.as_ptr() as u64) | 0x07;
-        *pt_stack.add(510) = kv2p(M3_STACK_PAGE_1.0.as_ptr() as u64) | 0x07;
-        *pt_stack.add(509) = kv2p(M3_STACK_PAGE_2.0.as_ptr() as u64) | 0x07;
-        *pt_stack.add(508) = kv2p(M3_STACK_PAGE_3.0.as_ptr() as u64) | 0x07;
-        #[cfg(feature = "go_test")]
-        {
-            *pt_stack.add(510) = kv2p(USER_STACK_PAGE_2.0.as_ptr() as u64) | 0x07;
-            *pt_stack.add(509) = kv2p(USER_STACK_PAGE_3.0.as_ptr() as u64) | 0x07;
-            *pt_stack.add(508) = kv2p(USER_STACK_PAGE_4.0.as_ptr() as u64) | 0x07;
-            *pt_stack.add(507) = kv2p(USER_STACK_PAGE_5.0.as_ptr() as u64) | 0x07;
-            *pt_stack.add(506) = kv2p(USER_STACK_PAGE_6.0.as_ptr() as u64) | 0x07;
-            *pt_stack.add(505) = kv2p(USER_STACK_PAGE_7.0.as_ptr() as u64) | 0x07;
-            *pt_stack.add(504) = kv2p(USER_STACK_PAGE_8.0.as_ptr() as u64) | 0x07;
-            *pt_stack.add(503) = kv2p(USER_HEAP_PAGE_1.0.as_ptr() as u64) | 0x07;
-            *pt_stack.add(502) = kv2p(USER_HEAP_PAGE_2.0.as_ptr() as u64) | 0x07;
-            *pt_stack.add(501) = kv2p(USER_HEAP_PAGE_3.0.as_ptr() as u64) | 0x07;
-            *pt_stack.add(500) = kv2p(USER_HEAP_PAGE_4.0.as_ptr() as u64) | 0x07;
-        }
 
         *new_pml4 = kv2p(USER_PDPT.0.as_ptr() as u64) | 0x07;
         let new_pml4_phys = kv2p(new_pml4 as u64);
@@ -2005,6 +2697,19 @@ static GO_USER_BIN: &[u8] = include_bytes!("../../out/gousr.bin");
 #[cfg(feature = "go_desktop_test")]
 static GO_DESKTOP_BIN: &[u8] = include_bytes!("../../out/gousr-desktop.bin");
 
+// Programs `sys_spawn` can start on every Go lane, by `/bin/<name>`.
+#[cfg(feature = "go_test")]
+struct R4Program {
+    name: &'static [u8],
+    image: &'static [u8],
+}
+
+#[cfg(feature = "go_test")]
+static R4_PROGRAMS: [R4Program; 1] = [R4Program {
+    name: b"hello",
+    image: include_bytes!("../../out/bin-hello.signed.elf"),
+}];
+
 // --------------- X1 runtime-backed compatibility ELF corpus ------------------
 
 #[cfg(feature = "compat_real_test")]
@@ -2012,18 +2717,51 @@ static GO_DESKTOP_BIN: &[u8] = include_bytes!("../../out/gousr-desktop.bin");
 struct CompatRealApp {
     name: &'static [u8],
     image: &'static [u8],
+    argv: &'static [&'static [u8]],
+    envp: &'static [&'static [u8]],
 }
 
 #[cfg(feature = "compat_real_test")]
-static X1_CLI_FILE_ELF: &[u8] = include_bytes!("../../out/x1-cli-file.elf");
+static COMPAT_REAL_ENVP: [&[u8]; 2] = [b"PATH=/bin", b"RUGO_LANE=compat_real"];
+
+#[cfg(feature = "compat_real_test")]
+static X1_CLI_FILE_ELF: &[u8] = include_bytes!("../../out/x1-cli-file.signed.elf");
+
+#[cfg(feature = "compat_real_test")]
+static X1_PROC_SOCK_ELF: &[u8] = include_bytes!("../../out/x1-proc-sock.signed.elf");
+
+#[cfg(feature = "compat_real_test")]
+static X1_MEM_PROBE_ELF: &[u8] = include_bytes!("../../out/x1-mem-probe.signed.elf");
 
 #[cfg(feature = "compat_real_test")]
-static X1_PROC_SOCK_ELF: &[u8] = include_bytes!("../../out/x1-proc-sock.elf");
+static X1_SCHED_PROBE_ELF: &[u8] = include_bytes!("../../out/x1-sched-probe.signed.elf");
 
 #[cfg(feature = "compat_real_test")]
-static COMPAT_REAL_APPS: [CompatRealApp; 2] = [
-    CompatRealApp { name: b"x1-cli-file", image: X1_CLI_FILE_ELF },
-    CompatRealApp { name: b"x1-proc-sock", image: X1_PROC_SOCK_ELF },
+static COMPAT_REAL_APPS: [CompatRealApp; 4] = [
+    CompatRealApp {
+        name: b"x1-cli-file",
+        image: X1_CLI_FILE_ELF,
+        argv: &[b"x1-cli-file"],
+        envp: &COMPAT_REAL_ENVP,
+    },
+    CompatRealApp {
+        name: b"x1-proc-sock",
+        image: X1_PROC_SOCK_ELF,
+        argv: &[b"x1-proc-sock"],
+        envp: &COMPAT_REAL_ENVP,
+    },
+    CompatRealApp {
+        name: b"x1-mem-probe",
+        image: X1_MEM_PROBE_ELF,
+        argv: &[b"x1-mem-probe"],
+        envp: &COMPAT_REAL_ENVP,
+    },
+    CompatRealApp {
+        name: b"x1-sched-probe",
+        image: X1_SCHED_PROBE_ELF,
+        argv: &[b"x1-sched-probe"],
+        envp: &COMPAT_REAL_ENVP,
+    },
 ];
 
 #[cfg(feature = "compat_real_test")]
@@ -2060,39 +2798,19 @@ cfg_r4! {
     #[cfg(any(feature = "stress_ipc_test", feature = "go_test"))]
     const USER_STACK4_TOP: u64 = 0x7F_D000;
 
+    #[cfg(r4_ipc)]
     static mut USER_CODE_PAGE_2:  Page = Page([0; 4096]);
-    static mut USER_STACK_PAGE_2: Page = Page([0; 4096]);
-    #[cfg(any(feature = "stress_ipc_test", feature = "go_test"))]
+    #[cfg(feature = "stress_ipc_test")]
     static mut USER_CODE_PAGE_3:  Page = Page([0; 4096]);
-    #[cfg(any(feature = "stress_ipc_test", feature = "go_test"))]
-    static mut USER_STACK_PAGE_3: Page = Page([0; 4096]);
-    #[cfg(any(feature = "stress_ipc_test", feature = "go_test"))]
+    #[cfg(feature = "stress_ipc_test")]
     static mut USER_CODE_PAGE_4:  Page = Page([0; 4096]);
-    #[cfg(any(feature = "stress_ipc_test", feature = "go_test"))]
-    static mut USER_STACK_PAGE_4: Page = Page([0; 4096]);
-    #[cfg(feature = "go_test")]
-    static mut USER_CODE_PAGE_5:  Page = Page([0; 4096]);
-    #[cfg(feature = "go_test")]
-    static mut USER_CODE_PAGE_6:  Page = Page([0; 4096]);
-    #[cfg(feature = "go_test")]
-    static mut USER_STACK_PAGE_5: Page = Page([0; 4096]);
-    #[cfg(feature = "go_test")]
-    static mut USER_STACK_PAGE_6: Page = Page([0; 4096]);
-    #[cfg(feature = "go_test")]
-    static mut USER_STACK_PAGE_7: Page = Page([0; 4096]);
-    #[cfg(feature = "go_test")]
-    static mut USER_STACK_PAGE_8: Page = Page([0; 4096]);
-    #[cfg(feature = "go_test")]
-    static mut USER_HEAP_PAGE_1: Page = Page([0; 4096]);
-    #[cfg(feature = "go_test")]
-    static mut USER_HEAP_PAGE_2: Page = Page([0; 4096]);
-    #[cfg(feature = "go_test")]
-    static mut USER_HEAP_PAGE_3: Page = Page([0; 4096]);
-    #[cfg(feature = "go_test")]
-    static mut USER_HEAP_PAGE_4: Page = Page([0; 4096]);
+    #[cfg(all(feature = "go_test", not(feature = "compat_real_test")))]
+    const GO_USER_HEAP_BASE: u64 = 0x7F_4000;
+    #[cfg(all(feature = "go_test", not(feature = "compat_real_test")))]
+    const GO_USER_HEAP_TOP: u64 = 0x7F_8000;
 }
 
-// --------------- R4: SHM backing pages ---------------------------------------
+// --------------- R4: SHM objects ---------------------------------------------
 
 #[cfg(feature = "pressure_shm_test")]
 const R4_MAX_SHM: usize = 64;
@@ -2103,18 +2821,83 @@ const R4_MAX_SHM: usize = 64;
 #[cfg(all(feature = "shm_test", not(feature = "pressure_shm_test"), not(feature = "quota_shm_test")))]
 const R4_MAX_SHM: usize = 2;
 
-#[cfg(any(feature = "shm_test", feature = "quota_shm_test"))]
+#[cfg(all(feature = "go_test", not(any(feature = "shm_test", feature = "quota_shm_test"))))]
+const R4_MAX_SHM: usize = 8;
+
+#[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+const R4_SHM_MAX_PAGES: usize = 16;
+#[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+const R4_MAX_SHM_HANDLES: usize = R4_MAX_SHM * 2;
+
+#[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+const R4_SHM_RIGHT_READ: u8 = 1 << 0;
+#[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+const R4_SHM_RIGHT_WRITE: u8 = 1 << 1;
+#[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+const R4_SHM_RIGHT_GRANT: u8 = 1 << 2;
+#[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+const R4_SHM_RIGHT_MASK: u8 = R4_SHM_RIGHT_READ | R4_SHM_RIGHT_WRITE | R4_SHM_RIGHT_GRANT;
+#[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+const R4_SHM_MAP_READONLY: u64 = 1 << 0;
+
+/// A shared memory object: pool frames plus a count of the handles and
+/// in-flight grants that name it. Mappings hold their own frame references.
+/// A `large` object's pages are 2 MiB frames from the large pool.
+#[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
 #[derive(Clone, Copy)]
 struct ShmObject {
     active: bool,
-    size: usize,
+    large: bool,
+    pages: usize,
+    frames: [u64; R4_SHM_MAX_PAGES],
+    refs: u32,
+}
+
+#[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+impl ShmObject {
+    const EMPTY: Self = Self {
+        active: false,
+        large: false,
+        pages: 0,
+        frames: [0; R4_SHM_MAX_PAGES],
+        refs: 0,
+    };
+
+    #[inline(always)]
+    fn page_size(&self) -> u64 {
+        if self.large { vm::VM_LARGE_PAGE } else { 4096 }
+    }
+}
+
+/// A task's handle to an SHM object. Handle ids index this table and are
+/// only valid for the tasks that hold them.
+#[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+#[derive(Clone, Copy)]
+struct ShmHandle {
+    active: bool,
+    object: usize,
+    rights: u8,
+    owner_tid: usize,
+    holders: u64,
+}
+
+#[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+impl ShmHandle {
+    const EMPTY: Self = Self { active: false, object: 0, rights: 0, owner_tid: 0, holders: 0 };
+
+    #[inline(always)]
+    fn held_by(&self, tid: usize) -> bool {
+        self.active
+            && (self.owner_tid == tid
+                || self.holders & runtime::isolation::holder_bit(tid) != 0)
+    }
 }
 
-#[cfg(any(feature = "shm_test", feature = "quota_shm_test"))]
-static mut R4_SHM_PAGES: [Page; R4_MAX_SHM] = [Page([0; 4096]); R4_MAX_SHM];
+#[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+static mut R4_SHM_OBJECTS: [ShmObject; R4_MAX_SHM] = [ShmObject::EMPTY; R4_MAX_SHM];
 
-#[cfg(any(feature = "shm_test", feature = "quota_shm_test"))]
-static mut R4_SHM_OBJECTS: [ShmObject; R4_MAX_SHM] = [ShmObject { active: false, size: 0 }; R4_MAX_SHM];
+#[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+static mut R4_SHM_HANDLES: [ShmHandle; R4_MAX_SHM_HANDLES] = [ShmHandle::EMPTY; R4_MAX_SHM_HANDLES];
 
 // --------------- R4: Task model ----------------------------------------------
 
@@ -2126,25 +2909,202 @@ cfg_r4! {
     const R4_WAIT_NONE: i32 = -2;
     const R4_SCHED_CLASS_BEST_EFFORT: u8 = 0;
     const R4_SCHED_CLASS_CRITICAL: u8 = 1;
+    const R4_SCHED_CLASS_DEADLINE: u8 = 2;
+    // Timer ticks a task runs before the tick handler preempts it. Critical
+    // tasks get the longer slice.
+    const R4_QUANTUM_TICKS_BEST_EFFORT: u32 = 4;
+    const R4_QUANTUM_TICKS_CRITICAL: u32 = 8;
+    const R4_TIMER_HZ: u32 = 100;
+    const R4_TICK_US: u64 = 1_000_000 / R4_TIMER_HZ as u64;
+    #[cfg(feature = "go_test")]
+    const R4_TICK_NS: u64 = R4_TICK_US * 1000;
+    #[cfg(feature = "go_test")]
+    const R4_MAX_TIMER_HANDLES: usize = 16;
+    // sys_nanosleep flag: `rdi` is a deadline on the monotonic clock rather
+    // than a duration.
+    #[cfg(feature = "go_test")]
+    const R4_SLEEP_ABSOLUTE: u64 = 1;
+    // A deadline of u64::MAX means "no deadline" to the timed syscalls.
+    #[cfg(feature = "go_test")]
+    const R4_DEADLINE_NONE: u64 = u64::MAX;
+    const R4_NICE_MIN: i64 = -20;
+    const R4_NICE_MAX: i64 = 19;
+    const R4_NICE_0_WEIGHT: u64 = 1024;
+    // Weight per nice level, -20 first; each step is about 1.25x CPU share.
+    const R4_NICE_WEIGHTS: [u64; 40] = [
+        88761, 71755, 56483, 46273, 36291,
+        29154, 23254, 18705, 14949, 11916,
+        9548, 7620, 6100, 4904, 3906,
+        3121, 2501, 1991, 1586, 1277,
+        1024, 820, 655, 526, 423,
+        335, 272, 215, 172, 137,
+        110, 87, 70, 56, 45,
+        36, 29, 23, 18, 15,
+    ];
+    // A task returning from a block may run at most this far behind the
+    // slowest runnable best-effort task, so sleeping earns no large burst.
+    const R4_VRUNTIME_SLACK_US: u64 = R4_QUANTUM_TICKS_BEST_EFFORT as u64 * R4_TICK_US;
+    // Ready best-effort work held off this many ticks by critical tasks gets
+    // the next slice.
+    #[cfg(feature = "go_test")]
+    const R4_STARVATION_TICKS: u32 = 20;
+    // Deadline reservations: `runtime_us` of CPU every `period_us`, both whole
+    // ticks. The summed runtime/period of live reservations stays at or under
+    // the capacity, in parts per million, so the other classes keep a share.
+    const R4_DEADLINE_PARAMS_SIZE: usize = 16;
+    const R4_DEADLINE_PERIOD_MAX_US: u64 = 1_000_000;
+    const R4_DEADLINE_CAPACITY_PPM: u64 = 900_000;
+    // CPU affinity masks, one bit per CPU. The Go lane brings up the
+    // application processors at boot (`smp`); an accepted mask includes at
+    // least one CPU that came online.
+    const R4_CPU_BOOT: usize = 0;
+    const R4_AFFINITY_ALL: u64 = u64::MAX;
+    // `sys_sched_set` selector: `rdx` is an affinity mask rather than a class
+    // parameter block.
+    const R4_SCHED_SET_AFFINITY: u64 = 0x80;
     const R4_PROC_INFO_BASE_WORDS: usize = 13;
     const R4_PROC_INFO_BASE_SIZE: usize = R4_PROC_INFO_BASE_WORDS * 8;
     const R4_PROC_INFO_EXT_WORDS: usize = 17;
     const R4_PROC_INFO_EXT_SIZE: usize = R4_PROC_INFO_EXT_WORDS * 8;
+    const R4_PROC_INFO_MEM_WORDS: usize = 21;
+    const R4_PROC_INFO_MEM_SIZE: usize = R4_PROC_INFO_MEM_WORDS * 8;
+    const R4_PROC_INFO_SCHED_WORDS: usize = 24;
+    const R4_PROC_INFO_SCHED_SIZE: usize = R4_PROC_INFO_SCHED_WORDS * 8;
+    const R4_PROC_INFO_DEADLINE_WORDS: usize = 28;
+    const R4_PROC_INFO_DEADLINE_SIZE: usize = R4_PROC_INFO_DEADLINE_WORDS * 8;
+    const R4_PROC_INFO_CPU_WORDS: usize = 30;
+    const R4_PROC_INFO_CPU_SIZE: usize = R4_PROC_INFO_CPU_WORDS * 8;
+    const R4_PROC_INFO_AFFINITY_WORDS: usize = 31;
+    const R4_PROC_INFO_AFFINITY_SIZE: usize = R4_PROC_INFO_AFFINITY_WORDS * 8;
+    #[cfg(feature = "go_test")]
+    const R4_SYSINFO_WORDS: usize = 8;
+    #[cfg(feature = "go_test")]
+    const R4_SYSINFO_SIZE: usize = R4_SYSINFO_WORDS * 8;
+    const R4_ISOLATION_CONFIG_SIZE: usize = 24;
+    const R4_ISOLATION_CONFIG_MEM_SIZE: usize = 40;
+    /// Returned instead of -1 when a reservation would pass the caller's hard
+    /// memory limit, so a supervisor can tell quota exhaustion apart.
+    const R4_ERR_MEM_LIMIT: u64 = 0xFFFF_FFFF_FFFF_FFFE;
+    /// Returned instead of -1 when a deadline reservation would push the
+    /// admitted bandwidth past `R4_DEADLINE_CAPACITY_PPM`.
+    const R4_ERR_SCHED_CAPACITY: u64 = 0xFFFF_FFFF_FFFF_FFFE;
+    /// Returned instead of -1 when a timed receive or wait reaches its
+    /// deadline before anything arrives.
+    const R4_ERR_TIMED_OUT: u64 = 0xFFFF_FFFF_FFFF_FFFD;
     const R4_TASK_CAP_STORAGE: u8 = 1 << 0;
     const R4_TASK_CAP_NETWORK: u8 = 1 << 1;
-    const R4_TASK_CAP_MASK: u8 = R4_TASK_CAP_STORAGE | R4_TASK_CAP_NETWORK;
+    /// Create tasks and control ones it did not create. Held by Go init and
+    /// handed on only through an isolation config.
+    const R4_TASK_CAP_SPAWN: u8 = 1 << 2;
+    const R4_TASK_CAP_MASK: u8 = R4_TASK_CAP_STORAGE | R4_TASK_CAP_NETWORK | R4_TASK_CAP_SPAWN;
     const R4_TASK_DEFAULT_FD_LIMIT: u8 = 8;
     const R4_TASK_DEFAULT_SOCKET_LIMIT: u8 = 4;
     const R4_TASK_DEFAULT_ENDPOINT_LIMIT: u8 = 4;
+    #[cfg(feature = "go_test")]
+    const R4_CLONE_ARGS_SIZE: usize = 48;
+    #[cfg(feature = "go_test")]
+    const R4_CLONE_VM: u64 = 1 << 0;
+    #[cfg(feature = "go_test")]
+    const R4_CLONE_FILES: u64 = 1 << 1;
+    #[cfg(feature = "go_test")]
+    const R4_CLONE_SETTLS: u64 = 1 << 2;
+    #[cfg(feature = "go_test")]
+    const R4_CLONE_DETACHED: u64 = 1 << 3;
+    #[cfg(feature = "go_test")]
+    const R4_CLONE_MASK: u64 =
+        R4_CLONE_VM | R4_CLONE_FILES | R4_CLONE_SETTLS | R4_CLONE_DETACHED;
+    const R4_MSR_FS_BASE: u32 = 0xC000_0100;
+    const R4_MSR_GS_BASE: u32 = 0xC000_0101;
+    // `sys_arch_prctl` codes, numbered as on Linux.
+    const R4_ARCH_SET_GS: u64 = 0x1001;
+    const R4_ARCH_SET_FS: u64 = 0x1002;
+    const R4_ARCH_GET_FS: u64 = 0x1003;
+    const R4_ARCH_GET_GS: u64 = 0x1004;
+    // Thread control block above the static TLS block: the self pointer
+    // plus one spare word.
+    #[cfg(feature = "go_test")]
+    const R4_TLS_TCB_SIZE: u64 = 16;
+    // Initial stack budget: strings, pointer arrays and the aux vector.
+    #[cfg(feature = "go_test")]
+    const R4_STARTUP_MAX_BYTES: usize = 4096;
+    // ET_DYN images load at a page-granular slide above this base, clear of
+    // the mmap region and the thread stack slots.
+    #[cfg(feature = "go_test")]
+    const R4_ELF_DYN_BASE: u64 = 0x20_0000_0000;
+    #[cfg(feature = "go_test")]
+    const R4_ELF_DYN_SLIDE_PAGES: u64 = 0x1_0000;
+    #[cfg(feature = "go_test")]
+    const R4_STARTUP_MAX_ARGS: usize = 32;
+    // Spawn argument block: six words of path and vectors, then an
+    // isolation config.
+    #[cfg(feature = "go_test")]
+    const R4_SPAWN_HEADER_SIZE: usize = 48;
+    #[cfg(feature = "go_test")]
+    const R4_SPAWN_ARGS_SIZE: usize = R4_SPAWN_HEADER_SIZE + R4_ISOLATION_CONFIG_MEM_SIZE;
+    #[cfg(feature = "go_test")]
+    const R4_SPAWN_PATH_MAX: usize = 64;
+    #[cfg(feature = "go_test")]
+    const R4_SPAWN_STRING_MAX: usize = 128;
 
-    #[cfg(any(feature = "stress_ipc_test", feature = "go_test"))]
-    const R4_MAX_TASKS: usize = 6;
-    #[cfg(not(any(feature = "stress_ipc_test", feature = "go_test")))]
-    const R4_MAX_TASKS: usize = 2;
+    // Kernel worker threads take the task slots above the user tasks.
+    #[cfg(feature = "go_test")]
+    const R4_MAX_KWORKERS: usize = 2;
+    #[cfg(not(feature = "go_test"))]
+    const R4_MAX_KWORKERS: usize = 0;
+    // Task ids carry the slot in the low bits and the slot's generation
+    // above it, masked so an id stays a positive i32.
+    const R4_TASK_ID_SLOT_BITS: u32 = 16;
+    const R4_TASK_GEN_MASK: u16 = 0x7FFF;
+
+    // Largest size a task stack may grow to; the fault handler backs it one
+    // page at a time below the stack top.
+    const R4_USER_STACK_MAX: u64 = 0x4_0000;
+    // Thread stack slots of the shared Go space stack down from here, each
+    // `R4_USER_STACK_MAX` plus its guard gap.
+    #[cfg(feature = "go_test")]
+    const GO_USER_STACK_REGION_TOP: u64 = 0x4000_0000;
+    // Most user tasks `rugo.max_tasks=` may ask for: what the task and space
+    // tables can index, the slots a fd/endpoint/SHM/socket holder mask can
+    // name and, on the Go lane, the stack slots a space's area table holds
+    // and that fit between the mmap base and the top of the stack region.
+    const R4_TASK_TABLE_MAX: usize = r4_const_min(
+        r4_const_min(kobj::KTable::<R4Task>::CAPACITY - R4_MAX_KWORKERS, vm::VM_MAX_SPACES),
+        runtime::isolation::HOLDER_SLOTS,
+    );
+    #[cfg(feature = "go_test")]
+    const R4_TASK_LIMIT_MAX: usize = r4_const_min(
+        r4_const_min(R4_TASK_TABLE_MAX, vm::VM_STACK_SLOTS),
+        ((GO_USER_STACK_REGION_TOP - vm::VM_MMAP_BASE) / (R4_USER_STACK_MAX + vm::VM_STACK_GUARD))
+            as usize,
+    );
+    #[cfg(not(feature = "go_test"))]
+    const R4_TASK_LIMIT_MAX: usize = R4_TASK_TABLE_MAX;
+
+    const fn r4_const_min(a: usize, b: usize) -> usize {
+        if a < b { a } else { b }
+    }
 
     #[derive(Clone, Copy, PartialEq)]
     enum R4State { Ready, Running, Blocked, Exited, Dead }
 
+    /// FXSAVE image of a task's x87/SSE registers. User code may be
+    /// preempted mid-computation, so the registers travel with the task.
+    #[derive(Clone, Copy)]
+    #[repr(C, align(16))]
+    struct R4FpuState([u8; 512]);
+
+    impl R4FpuState {
+        // FCW 0x037F and MXCSR 0x1F80: the reset state, exceptions masked.
+        const INIT: Self = {
+            let mut image = [0u8; 512];
+            image[0] = 0x7F;
+            image[1] = 0x03;
+            image[24] = 0x80;
+            image[25] = 0x1F;
+            Self(image)
+        };
+    }
+
     #[derive(Clone, Copy)]
     struct R4Task {
         saved_frame: [u64; 22],
@@ -2155,7 +3115,6 @@ cfg_r4! {
         endpoint_count: usize,
         shm_count: usize,
         thread_count: usize,
-        can_spawn: bool,
         parent_tid: usize,
         exit_status: u64,
         wait_target: i32,
@@ -2173,6 +3132,50 @@ cfg_r4! {
         block_count: u64,
         ipc_send_count: u64,
         ipc_recv_count: u64,
+        space: usize,
+        fs_base: u64,
+        gs_base: u64,
+        detached: bool,
+        join_ptr: u64,
+        fpu: R4FpuState,
+        quantum_left: u32,
+        nice: i8,
+        // CPUs the task may run on; inherited like the nice level.
+        affinity: u64,
+        // The CPU whose run queue holds the task: where it last ran.
+        cpu: usize,
+        // Neighbours on that run queue while the task is ready.
+        rq_prev: usize,
+        rq_next: usize,
+        vruntime: u64,
+        cpu_time_us: u64,
+        dl_runtime_us: u64,
+        dl_period_us: u64,
+        dl_budget_us: u64,
+        dl_deadline: u64,
+        dl_throttled: bool,
+        dl_overruns: u64,
+        dl_misses: u64,
+        timeout_ret: u64,
+        poll_fds: u64,
+        poll_nfds: u64,
+        polling: bool,
+        // A kernel worker thread: runs in ring 0 on its own stack.
+        kernel: bool,
+        // Killed while running on another CPU; it exits with `exit_status`
+        // on its next entry to the kernel.
+        kill_pending: bool,
+        // Blocked until a kernel worker finishes a job on the task's behalf.
+        kwork_wait: bool,
+        // Bumped each time the slot is reused; part of the task id.
+        generation: u16,
+        // Created by `sys_thread_spawn_r4` or `sys_clone_r4` into the
+        // creator's space; counts against the thread limit.
+        thread: bool,
+        // TSC cycles spent in user mode and in the kernel on the task's
+        // behalf, charged at every kernel entry, exit and switch.
+        user_cycles: u64,
+        kernel_cycles: u64,
     }
 
     impl R4Task {
@@ -2183,7 +3186,6 @@ cfg_r4! {
             endpoint_count: 0,
             shm_count: 0,
             thread_count: 0,
-            can_spawn: false,
             parent_tid: 0,
             exit_status: 0,
             wait_target: R4_WAIT_NONE,
@@ -2201,19 +3203,117 @@ cfg_r4! {
             block_count: 0,
             ipc_send_count: 0,
             ipc_recv_count: 0,
+            space: vm::VM_NO_SPACE,
+            fs_base: 0,
+            gs_base: 0,
+            detached: false,
+            join_ptr: 0,
+            fpu: R4FpuState::INIT,
+            quantum_left: 0,
+            nice: 0,
+            affinity: R4_AFFINITY_ALL,
+            cpu: R4_CPU_BOOT,
+            rq_prev: R4_RQ_NONE,
+            rq_next: R4_RQ_NONE,
+            vruntime: 0,
+            cpu_time_us: 0,
+            dl_runtime_us: 0,
+            dl_period_us: 0,
+            dl_budget_us: 0,
+            dl_deadline: 0,
+            dl_throttled: false,
+            dl_overruns: 0,
+            dl_misses: 0,
+            timeout_ret: 0,
+            poll_fds: 0,
+            poll_nfds: 0,
+            polling: false,
+            kernel: false,
+            kill_pending: false,
+            kwork_wait: false,
+            generation: 0,
+            thread: false,
+            user_cycles: 0,
+            kernel_cycles: 0,
         };
     }
 
-    static mut R4_TASKS: [R4Task; R4_MAX_TASKS] = [R4Task::EMPTY; R4_MAX_TASKS];
-    static mut R4_CURRENT: usize = 0;
+    // One slot per user task up to the boot limit, then the kernel workers;
+    // sized by `r4_pages_init`.
+    static mut R4_TASKS: kobj::KTable<R4Task> = kobj::KTable::EMPTY;
+    // The task each CPU runs, or last ran if it is idle.
+    static mut R4_CURRENT: [usize; arch_x86::MAX_CPUS] = [0; arch_x86::MAX_CPUS];
     static mut R4_NUM_TASKS: usize = 0;
-    static mut R4_THREADS_CREATED: usize = 0;
+    // Never decreases: the lowest virtual runtime among runnable best-effort
+    // tasks, as of the last tick.
+    static mut R4_MIN_VRUNTIME: u64 = 0;
+    #[cfg(feature = "go_test")]
+    static mut R4_BEST_EFFORT_WAIT_TICKS: u32 = 0;
+    // Timer ticks since the R4 lane started; deadlines are absolute ticks.
+    static mut R4_TICKS: u64 = 0;
+    // Set while a CPU waits in `r4_idle` for a timer or another CPU to wake
+    // a task.
+    #[cfg(feature = "go_test")]
+    static mut R4_IDLE: [bool; arch_x86::MAX_CPUS] = [false; arch_x86::MAX_CPUS];
+
+    // Ready tasks of one CPU in the order they became ready, linked through
+    // their slots. A task sits on the queue of `R4_TASKS[tid].cpu` exactly
+    // while it is ready; `r4_set_state` keeps it that way.
+    #[derive(Clone, Copy)]
+    struct R4RunQueue {
+        head: usize,
+        tail: usize,
+    }
+
+    impl R4RunQueue {
+        const EMPTY: Self = Self { head: R4_RQ_NONE, tail: R4_RQ_NONE };
+    }
+
+    const R4_RQ_NONE: usize = usize::MAX;
+    static mut R4_RUNQ: [R4RunQueue; arch_x86::MAX_CPUS] = [R4RunQueue::EMPTY; arch_x86::MAX_CPUS];
+
+    /// The CPU this runs on.
+    #[inline(always)]
+    fn r4_cpu() -> usize {
+        #[cfg(feature = "go_test")]
+        {
+            smp::cpu_id()
+        }
+        #[cfg(not(feature = "go_test"))]
+        {
+            R4_CPU_BOOT
+        }
+    }
+
+    /// Bit per CPU that can run tasks.
+    #[inline(always)]
+    fn r4_cpus_online() -> u64 {
+        #[cfg(feature = "go_test")]
+        {
+            smp::online_mask()
+        }
+        #[cfg(not(feature = "go_test"))]
+        {
+            1 << R4_CPU_BOOT
+        }
+    }
+
+    /// The task running on this CPU.
+    #[inline(always)]
+    unsafe fn r4_current() -> usize {
+        R4_CURRENT[r4_cpu()]
+    }
+
+    #[inline(always)]
+    unsafe fn r4_set_current(tid: usize) {
+        R4_CURRENT[r4_cpu()] = tid;
+    }
 
     #[inline(always)]
     unsafe fn r4_stack_top_for_slot(slot: usize) -> u64 {
         #[cfg(feature = "go_test")]
         {
-            USER_STACK_TOP - (slot as u64) * 0x2000
+            GO_USER_STACK_REGION_TOP - (slot as u64) * (R4_USER_STACK_MAX + vm::VM_STACK_GUARD)
         }
         #[cfg(not(feature = "go_test"))]
         {
@@ -2221,11 +3321,22 @@ cfg_r4! {
         }
     }
 
+    /// Reserve a growable stack for every thread slot of a shared space.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_reserve_slot_stacks(space: usize) -> bool {
+        for slot in 0..r4_user_task_limit() {
+            if !vm::vm_stack_reserve(space, r4_stack_top_for_slot(slot), R4_USER_STACK_MAX) {
+                return false;
+            }
+        }
+        true
+    }
+
     unsafe fn r4_init_task(tid: usize, code_va: u64, stk_top: u64, parent_tid: usize) {
         R4_TASKS[tid].saved_frame = [0u64; 22];
         R4_TASKS[tid].saved_frame[17] = code_va;  // RIP
         R4_TASKS[tid].saved_frame[18] = 0x23;     // CS (user code RPL=3)
-        R4_TASKS[tid].saved_frame[19] = 0x02;     // RFLAGS
+        R4_TASKS[tid].saved_frame[19] = arch_x86::USER_RFLAGS; // RFLAGS
         R4_TASKS[tid].saved_frame[20] = stk_top;  // RSP
         R4_TASKS[tid].saved_frame[21] = 0x1B;     // SS (user data RPL=3)
         R4_TASKS[tid].recv_ep = 0;
@@ -2241,7 +3352,11 @@ cfg_r4! {
         R4_TASKS[tid].sched_class = R4_SCHED_CLASS_BEST_EFFORT;
         if tid == parent_tid {
             R4_TASKS[tid].isolation_domain = 0;
-            R4_TASKS[tid].cap_flags = R4_TASK_CAP_MASK;
+            R4_TASKS[tid].cap_flags = if cfg!(feature = "go_test") && tid == 0 {
+                R4_TASK_CAP_MASK
+            } else {
+                R4_TASK_CAP_MASK & !R4_TASK_CAP_SPAWN
+            };
             R4_TASKS[tid].fd_limit = R4_TASK_DEFAULT_FD_LIMIT;
             R4_TASKS[tid].socket_limit = R4_TASK_DEFAULT_SOCKET_LIMIT;
             R4_TASKS[tid].endpoint_limit = R4_TASK_DEFAULT_ENDPOINT_LIMIT;
@@ -2259,71 +3374,609 @@ cfg_r4! {
         R4_TASKS[tid].block_count = 0;
         R4_TASKS[tid].ipc_send_count = 0;
         R4_TASKS[tid].ipc_recv_count = 0;
-        #[cfg(feature = "go_test")]
-        {
-            R4_TASKS[tid].can_spawn = tid == 0;
-        }
-        #[cfg(not(feature = "go_test"))]
-        {
-            R4_TASKS[tid].can_spawn = false;
+        R4_TASKS[tid].fs_base = 0;
+        R4_TASKS[tid].gs_base = 0;
+        R4_TASKS[tid].detached = false;
+        R4_TASKS[tid].join_ptr = 0;
+        R4_TASKS[tid].fpu = R4FpuState::INIT;
+        R4_TASKS[tid].quantum_left = r4_quantum_ticks(R4_SCHED_CLASS_BEST_EFFORT);
+        R4_TASKS[tid].nice = 0;
+        R4_TASKS[tid].affinity = R4_AFFINITY_ALL;
+        R4_TASKS[tid].cpu = r4_cpu();
+        R4_TASKS[tid].vruntime = R4_MIN_VRUNTIME;
+        R4_TASKS[tid].cpu_time_us = 0;
+        R4_TASKS[tid].user_cycles = 0;
+        R4_TASKS[tid].kernel_cycles = 0;
+        r4_deadline_reset(tid);
+        R4_TASKS[tid].timeout_ret = 0;
+        R4_TASKS[tid].polling = false;
+        R4_TASKS[tid].kernel = false;
+        R4_TASKS[tid].kill_pending = false;
+        R4_TASKS[tid].kwork_wait = false;
+        R4_TASKS[tid].thread = false;
+        // A task that is its own parent is a process: it keeps the space the
+        // page setup built for it, or gets a fresh one. Forked children arrive
+        // with their copy-on-write space already assigned. Spawned threads
+        // share their creator's space.
+        if R4_TASKS[tid].space != vm::VM_NO_SPACE {
+            // Pre-assigned by the page setup or by fork.
+        } else if tid == parent_tid {
+            R4_TASKS[tid].space = vm::vm_space_create().unwrap_or(vm::VM_NO_SPACE);
+        } else {
+            vm::vm_space_retain(R4_TASKS[parent_tid].space);
+            R4_TASKS[tid].space = R4_TASKS[parent_tid].space;
         }
-        R4_TASKS[tid].state = R4State::Ready;
+        // Held back until the creator has filled in the rest and makes it
+        // ready, so no other CPU picks up a half-built task.
+        r4_set_state(tid, R4State::Blocked);
     }
 
-    unsafe fn r4_find_ready(exclude: usize) -> Option<usize> {
-        if R4_NUM_TASKS == 0 { return None; }
-        let mut best: Option<usize> = None;
-        let mut best_class = R4_SCHED_CLASS_BEST_EFFORT;
-        let mut i = (exclude + 1) % R4_NUM_TASKS;
-        for _ in 0..R4_NUM_TASKS {
-            if i != exclude && R4_TASKS[i].state == R4State::Ready {
-                let class = R4_TASKS[i].sched_class;
-                if best.is_none() || class > best_class {
-                    best = Some(i);
-                    best_class = class;
-                }
-            }
-            i = (i + 1) % R4_NUM_TASKS;
+    #[inline(always)]
+    fn r4_nice_weight(nice: i8) -> u64 {
+        R4_NICE_WEIGHTS[(nice as i64 - R4_NICE_MIN) as usize]
+    }
+
+    /// Drop any deadline reservation and its counters.
+    #[inline(always)]
+    unsafe fn r4_deadline_reset(tid: usize) {
+        R4_TASKS[tid].dl_runtime_us = 0;
+        R4_TASKS[tid].dl_period_us = 0;
+        R4_TASKS[tid].dl_budget_us = 0;
+        R4_TASKS[tid].dl_deadline = 0;
+        R4_TASKS[tid].dl_throttled = false;
+        R4_TASKS[tid].dl_overruns = 0;
+        R4_TASKS[tid].dl_misses = 0;
+    }
+
+    /// The class a new task takes from its creator. A reservation is never
+    /// copied, since that would bypass admission, so children of a deadline
+    /// task start best-effort.
+    #[inline(always)]
+    unsafe fn r4_inherited_class(parent: usize) -> u8 {
+        if R4_TASKS[parent].sched_class == R4_SCHED_CLASS_DEADLINE {
+            R4_SCHED_CLASS_BEST_EFFORT
+        } else {
+            R4_TASKS[parent].sched_class
         }
-        best
     }
 
-    unsafe fn r4_switch_to(frame: *mut u64, tid: usize) {
-        for i in 0..22 { *frame.add(i) = R4_TASKS[tid].saved_frame[i]; }
-        R4_TASKS[tid].state = R4State::Running;
-        R4_TASKS[tid].dispatch_count += 1;
-        R4_CURRENT = tid;
+    /// Slots the scheduler scans: the user tasks, then the kernel workers
+    /// above them. Unused slots in between are dead and never picked.
+    #[inline(always)]
+    unsafe fn r4_sched_span() -> usize {
+        if R4_MAX_KWORKERS == 0 { R4_NUM_TASKS } else { R4_TASKS.len() }
     }
 
-    unsafe fn r4_save_frame(frame: *mut u64, tid: usize) {
-        for i in 0..22 { R4_TASKS[tid].saved_frame[i] = *frame.add(i); }
+    #[inline(always)]
+    unsafe fn r4_cpu_allowed(tid: usize, cpu: usize) -> bool {
+        R4_TASKS[tid].affinity & (1 << cpu) != 0
     }
 
+    /// Take the scheduler lock: the run queues, task states and scheduling
+    /// fields sit under it. Taken inside the kernel lock, or alone by the
+    /// scheduler IPI.
     #[inline(always)]
-    unsafe fn r4_wait_matches(target: i32, child_tid: usize) -> bool {
-        target == -1 || target == child_tid as i32
+    unsafe fn r4_sched_lock() {
+        #[cfg(feature = "go_test")]
+        smp::smp_sched_lock();
     }
 
     #[inline(always)]
-    unsafe fn r4_copy_wait_status(status_ptr: u64, status: u64) -> bool {
-        if status_ptr == 0 {
-            return true;
-        }
-        let st = status.to_le_bytes();
-        copyout_user(status_ptr, &st, st.len()).is_ok()
+    unsafe fn r4_sched_unlock() {
+        #[cfg(feature = "go_test")]
+        smp::smp_sched_unlock();
     }
 
-    unsafe fn r4_has_waitable_child(parent_tid: usize, target: i32) -> bool {
-        for tid in 0..R4_NUM_TASKS {
-            if tid == parent_tid || R4_TASKS[tid].parent_tid != parent_tid {
-                continue;
-            }
-            if !r4_wait_matches(target, tid) {
-                continue;
-            }
-            if R4_TASKS[tid].state != R4State::Dead {
-                return true;
-            }
+    unsafe fn r4_rq_push(tid: usize) {
+        let cpu = R4_TASKS[tid].cpu;
+        let tail = R4_RUNQ[cpu].tail;
+        R4_TASKS[tid].rq_prev = tail;
+        R4_TASKS[tid].rq_next = R4_RQ_NONE;
+        if tail == R4_RQ_NONE {
+            R4_RUNQ[cpu].head = tid;
+        } else {
+            R4_TASKS[tail].rq_next = tid;
+        }
+        R4_RUNQ[cpu].tail = tid;
+    }
+
+    unsafe fn r4_rq_remove(tid: usize) {
+        let cpu = R4_TASKS[tid].cpu;
+        let (prev, next) = (R4_TASKS[tid].rq_prev, R4_TASKS[tid].rq_next);
+        if prev == R4_RQ_NONE {
+            R4_RUNQ[cpu].head = next;
+        } else {
+            R4_TASKS[prev].rq_next = next;
+        }
+        if next == R4_RQ_NONE {
+            R4_RUNQ[cpu].tail = prev;
+        } else {
+            R4_TASKS[next].rq_prev = prev;
+        }
+        R4_TASKS[tid].rq_prev = R4_RQ_NONE;
+        R4_TASKS[tid].rq_next = R4_RQ_NONE;
+    }
+
+    /// Move `tid` to `state`. A task that becomes ready goes to the back of
+    /// its CPU's queue; one that stops being ready leaves it.
+    unsafe fn r4_set_state(tid: usize, state: R4State) {
+        r4_sched_lock();
+        if R4_TASKS[tid].state == R4State::Ready {
+            r4_rq_remove(tid);
+        }
+        R4_TASKS[tid].state = state;
+        if state == R4State::Ready {
+            r4_rq_push(tid);
+        }
+        r4_sched_unlock();
+    }
+
+    /// Make `cpu` the task's CPU, moving it between queues if it is ready.
+    unsafe fn r4_set_cpu(tid: usize, cpu: usize) {
+        r4_sched_lock();
+        let ready = R4_TASKS[tid].state == R4State::Ready;
+        if ready {
+            r4_rq_remove(tid);
+        }
+        R4_TASKS[tid].cpu = cpu;
+        if ready {
+            r4_rq_push(tid);
+        }
+        r4_sched_unlock();
+    }
+
+    /// Pick the next task for this CPU to run, `exclude` aside. Its own run
+    /// queue comes first; with nothing ready there it pulls a ready task
+    /// from another CPU's queue. Called with the scheduler lock held.
+    unsafe fn r4_find_ready(exclude: usize) -> Option<usize> {
+        if R4_NUM_TASKS == 0 { return None; }
+        let cpu = r4_cpu();
+        r4_pick_ready(exclude, 1 << cpu, |tid| r4_cpu_allowed(tid, cpu))
+            .or_else(|| r4_pick_ready(exclude, !(1 << cpu), |tid| r4_cpu_allowed(tid, cpu)))
+    }
+
+    /// Pick among the tasks on the run queues in `queues` that `eligible`
+    /// accepts, `exclude` aside. A deadline task with budget left wins
+    /// first, earliest deadline first. Ready critical tasks come next, in
+    /// queue order, unless best-effort work has waited out the starvation
+    /// bound. Best-effort tasks go in order of weighted virtual runtime,
+    /// ties in queue order. A throttled deadline task runs only when
+    /// nothing else is ready.
+    unsafe fn r4_pick_ready(exclude: usize, queues: u64, eligible: impl Fn(usize) -> bool) -> Option<usize> {
+        let mut deadline: Option<usize> = None;
+        let mut throttled: Option<usize> = None;
+        let mut critical: Option<usize> = None;
+        let mut best_effort: Option<usize> = None;
+        for cpu in (0..arch_x86::MAX_CPUS).filter(|cpu| queues & (1 << cpu) != 0) {
+            let mut i = R4_RUNQ[cpu].head;
+            while i != R4_RQ_NONE {
+                if i != exclude && eligible(i) {
+                    if R4_TASKS[i].sched_class == R4_SCHED_CLASS_DEADLINE {
+                        if R4_TASKS[i].dl_throttled {
+                            if throttled.is_none() {
+                                throttled = Some(i);
+                            }
+                        } else if deadline.is_none_or(|d| R4_TASKS[i].dl_deadline < R4_TASKS[d].dl_deadline) {
+                            deadline = Some(i);
+                        }
+                    } else if R4_TASKS[i].sched_class == R4_SCHED_CLASS_CRITICAL {
+                        if critical.is_none() {
+                            critical = Some(i);
+                        }
+                    } else if best_effort.is_none_or(|b| R4_TASKS[i].vruntime < R4_TASKS[b].vruntime) {
+                        best_effort = Some(i);
+                    }
+                }
+                i = R4_TASKS[i].rq_next;
+            }
+        }
+        if deadline.is_some() {
+            return deadline;
+        }
+        #[cfg(feature = "go_test")]
+        {
+            if best_effort.is_some() && R4_BEST_EFFORT_WAIT_TICKS >= R4_STARVATION_TICKS {
+                return best_effort;
+            }
+        }
+        critical.or(best_effort).or(throttled)
+    }
+
+    #[inline(always)]
+    unsafe fn r4_wrmsr(msr: u32, value: u64) {
+        core::arch::asm!(
+            "wrmsr",
+            in("ecx") msr,
+            in("eax") value as u32,
+            in("edx") (value >> 32) as u32,
+            options(nostack),
+        );
+    }
+
+    #[inline(always)]
+    unsafe fn r4_load_fs_base(base: u64) {
+        r4_wrmsr(R4_MSR_FS_BASE, base);
+    }
+
+    // The kernel never uses GS, so the user GS base lives in IA32_GS_BASE.
+    #[inline(always)]
+    unsafe fn r4_load_gs_base(base: u64) {
+        r4_wrmsr(R4_MSR_GS_BASE, base);
+    }
+
+    // The kernel is built without SSE, so the live x87/SSE registers always
+    // belong to the user task that last ran.
+    #[inline(always)]
+    unsafe fn r4_fpu_save(tid: usize) {
+        core::arch::asm!(
+            "fxsave64 [{}]",
+            in(reg) R4_TASKS[tid].fpu.0.as_mut_ptr(),
+            options(nostack),
+        );
+    }
+
+    #[inline(always)]
+    unsafe fn r4_fpu_restore(tid: usize) {
+        core::arch::asm!(
+            "fxrstor64 [{}]",
+            in(reg) R4_TASKS[tid].fpu.0.as_ptr(),
+            options(nostack),
+        );
+    }
+
+    #[inline(always)]
+    fn r4_quantum_ticks(class: u8) -> u32 {
+        if class == R4_SCHED_CLASS_CRITICAL {
+            R4_QUANTUM_TICKS_CRITICAL
+        } else {
+            R4_QUANTUM_TICKS_BEST_EFFORT
+        }
+    }
+
+    unsafe fn r4_switch_to(frame: *mut u64, tid: usize) {
+        #[cfg(feature = "go_test")]
+        cputime::cpu_switch();
+        for i in 0..22 { *frame.add(i) = R4_TASKS[tid].saved_frame[i]; }
+        r4_fpu_restore(tid);
+        vm::vm_activate(R4_TASKS[tid].space);
+        r4_load_fs_base(R4_TASKS[tid].fs_base);
+        r4_load_gs_base(R4_TASKS[tid].gs_base);
+        r4_set_state(tid, R4State::Running);
+        R4_TASKS[tid].cpu = r4_cpu();
+        R4_TASKS[tid].dispatch_count += 1;
+        // Whatever woke the task, its timeout no longer applies.
+        #[cfg(feature = "go_test")]
+        timer::timer_cancel(timer::TimerEvent::Task(tid));
+        R4_TASKS[tid].quantum_left = r4_quantum_ticks(R4_TASKS[tid].sched_class);
+        if R4_TASKS[tid].sched_class == R4_SCHED_CLASS_BEST_EFFORT {
+            let floor = R4_MIN_VRUNTIME.saturating_sub(R4_VRUNTIME_SLACK_US);
+            if R4_TASKS[tid].vruntime < floor {
+                R4_TASKS[tid].vruntime = floor;
+            }
+            #[cfg(feature = "go_test")]
+            {
+                R4_BEST_EFFORT_WAIT_TICKS = 0;
+            }
+        }
+        r4_set_current(tid);
+    }
+
+    unsafe fn r4_save_frame(frame: *mut u64, tid: usize) {
+        for i in 0..22 { R4_TASKS[tid].saved_frame[i] = *frame.add(i); }
+        r4_fpu_save(tid);
+    }
+
+    /// Program the PIT and unmask IRQ0. User code runs with IF set, so the
+    /// first tick arrives once a task is in ring 3.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_timer_start() {
+        cputime::cpu_start();
+        sched::pic_init();
+        sched::pit_init(R4_TIMER_HZ);
+    }
+
+    /// Charge the running task one tick of CPU time. Best-effort tasks also
+    /// advance their virtual runtime by the tick scaled to their nice weight.
+    /// Critical tasks instead count how long ready best-effort work waits.
+    /// Deadline tasks spend budget, and are throttled when it runs out.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_charge_tick(cur: usize) {
+        R4_TASKS[cur].cpu_time_us += R4_TICK_US;
+        let mut best_effort_ready = false;
+        let mut min_vruntime: Option<u64> = None;
+        for tid in 0..r4_sched_span() {
+            let task = &R4_TASKS[tid];
+            if task.sched_class != R4_SCHED_CLASS_BEST_EFFORT {
+                continue;
+            }
+            if task.state == R4State::Ready {
+                best_effort_ready = true;
+            }
+            if task.state == R4State::Ready || task.state == R4State::Running {
+                min_vruntime = Some(min_vruntime.map_or(task.vruntime, |v| v.min(task.vruntime)));
+            }
+        }
+        if R4_TASKS[cur].sched_class == R4_SCHED_CLASS_CRITICAL {
+            if best_effort_ready {
+                R4_BEST_EFFORT_WAIT_TICKS += 1;
+            } else {
+                R4_BEST_EFFORT_WAIT_TICKS = 0;
+            }
+        } else if R4_TASKS[cur].sched_class == R4_SCHED_CLASS_DEADLINE {
+            // Still running when the last of the budget goes: an overrun.
+            if !R4_TASKS[cur].dl_throttled {
+                R4_TASKS[cur].dl_budget_us = R4_TASKS[cur].dl_budget_us.saturating_sub(R4_TICK_US);
+                if R4_TASKS[cur].dl_budget_us == 0 {
+                    R4_TASKS[cur].dl_throttled = true;
+                    R4_TASKS[cur].dl_overruns += 1;
+                }
+            }
+        } else {
+            R4_TASKS[cur].vruntime +=
+                R4_TICK_US * R4_NICE_0_WEIGHT / r4_nice_weight(R4_TASKS[cur].nice);
+        }
+        if let Some(v) = min_vruntime {
+            if v > R4_MIN_VRUNTIME {
+                R4_MIN_VRUNTIME = v;
+            }
+        }
+    }
+
+    /// Start a new period for every deadline task whose deadline has come.
+    /// A task that was still ready with budget left missed its deadline.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_deadline_replenish() {
+        for tid in 0..r4_sched_span() {
+            // Checked before borrowing mutably: unused slots in the span may
+            // have no storage behind them.
+            if R4_TASKS[tid].sched_class != R4_SCHED_CLASS_DEADLINE
+                || R4_TICKS < R4_TASKS[tid].dl_deadline
+            {
+                continue;
+            }
+            let task = &mut R4_TASKS[tid];
+            if !task.dl_throttled
+                && task.dl_budget_us > 0
+                && (task.state == R4State::Ready || task.state == R4State::Running)
+            {
+                task.dl_misses += 1;
+            }
+            task.dl_deadline = R4_TICKS + task.dl_period_us / R4_TICK_US;
+            task.dl_budget_us = task.dl_runtime_us;
+            task.dl_throttled = false;
+        }
+    }
+
+    /// Whether `next` should take the CPU from `cur` on this tick. A
+    /// throttled `cur` gives way to anything, and a throttled `next` never
+    /// preempts. A higher class preempts at once. A critical task yields to
+    /// best-effort work only past the starvation bound; a deadline task
+    /// never yields to a lower class. Deadline tasks preempt each other by
+    /// earlier deadline. Otherwise the switch waits for the quantum, and
+    /// best-effort also needs `next` not to be ahead.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_should_preempt(cur: usize, next: usize, quantum_spent: bool) -> bool {
+        if R4_TASKS[next].dl_throttled {
+            return R4_TASKS[cur].dl_throttled && quantum_spent;
+        }
+        if R4_TASKS[cur].dl_throttled {
+            return true;
+        }
+        let cur_class = R4_TASKS[cur].sched_class;
+        let next_class = R4_TASKS[next].sched_class;
+        if next_class > cur_class {
+            return true;
+        }
+        if next_class < cur_class {
+            return cur_class == R4_SCHED_CLASS_CRITICAL
+                && R4_BEST_EFFORT_WAIT_TICKS >= R4_STARVATION_TICKS;
+        }
+        if cur_class == R4_SCHED_CLASS_DEADLINE {
+            return R4_TASKS[next].dl_deadline < R4_TASKS[cur].dl_deadline;
+        }
+        if !quantum_spent {
+            return false;
+        }
+        cur_class == R4_SCHED_CLASS_CRITICAL || R4_TASKS[next].vruntime <= R4_TASKS[cur].vruntime
+    }
+
+    /// A tick that lands in `r4_idle`: fire due timers and hand the CPU to
+    /// the first task they make ready.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_idle_tick(frame: *mut u64) {
+        r4_deadline_replenish();
+        r4_timers_expire();
+        r4_idle_pick(frame);
+    }
+
+    /// Leave `r4_idle` for a ready task, if this CPU may run one.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_idle_pick(frame: *mut u64) {
+        // The table length is never a tid, so no task is excluded.
+        if let Some(tid) = r4_find_ready(R4_TASKS.len()) {
+            R4_IDLE[r4_cpu()] = false;
+            r4_switch_to(frame, tid);
+        }
+    }
+
+    /// Whether a tick that lands in kernel mode leaves the task alone: a
+    /// driver waiting with interrupts on, unless the running task is itself
+    /// a kernel worker.
+    #[cfg(feature = "go_test")]
+    #[inline(always)]
+    unsafe fn r4_tick_in_kernel(frame: *mut u64) -> bool {
+        *frame.add(18) & 3 != 3 && !R4_TASKS[r4_current()].kernel
+    }
+
+    /// IRQ0, on the boot CPU: advance the clock, pass the tick on to the
+    /// busy APs, charge the running task, fire due timers and preempt the
+    /// task when the policy says another ready task should run. Idle ticks
+    /// go to `r4_idle_tick`. Other ticks that land in kernel mode only
+    /// advance the clock and poll for worker jobs.
+    #[cfg(feature = "go_test")]
+    pub(crate) unsafe fn r4_timer_tick(frame: *mut u64) {
+        sched::pic_send_eoi(0);
+        R4_TICKS += 1;
+        cputime::cpu_tick();
+        r4_kwork_poll_sources();
+        r4_sched_lock();
+        let online = r4_cpus_online();
+        for (cpu, &idle) in R4_IDLE.iter().enumerate() {
+            if cpu != r4_cpu() && online & (1 << cpu) != 0 && !idle {
+                smp::smp_send(cpu, smp::SMP_IPI_TICK);
+            }
+        }
+        if R4_IDLE[r4_cpu()] {
+            r4_idle_tick(frame);
+        } else if !r4_tick_in_kernel(frame) {
+            let cur = r4_current();
+            r4_charge_tick(cur);
+            r4_deadline_replenish();
+            r4_timers_expire();
+            r4_preempt_tick(frame, cur);
+        }
+        r4_sched_unlock();
+    }
+
+    /// An AP's share of a tick: charge its task and preempt it. The boot
+    /// CPU already fired the timers.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_ap_tick(frame: *mut u64) {
+        if R4_IDLE[r4_cpu()] {
+            r4_idle_pick(frame);
+            return;
+        }
+        if r4_tick_in_kernel(frame) {
+            return;
+        }
+        let cur = r4_current();
+        r4_charge_tick(cur);
+        r4_preempt_tick(frame, cur);
+    }
+
+    /// Spend one tick of `cur`'s quantum and switch to the next ready task
+    /// if the policy says it should take the CPU.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_preempt_tick(frame: *mut u64, cur: usize) {
+        let quantum_spent = R4_TASKS[cur].quantum_left <= 1;
+        if !quantum_spent {
+            R4_TASKS[cur].quantum_left -= 1;
+        }
+        match r4_find_ready(cur) {
+            Some(tid) if r4_should_preempt(cur, tid, quantum_spent) => {
+                r4_save_frame(frame, cur);
+                r4_set_state(cur, R4State::Ready);
+                r4_switch_to(frame, tid);
+            }
+            _ => {
+                if quantum_spent {
+                    R4_TASKS[cur].quantum_left = r4_quantum_ticks(R4_TASKS[cur].sched_class);
+                }
+            }
+        }
+    }
+
+    /// The scheduler's IPI: an AP's tick, or a request to look for work.
+    /// Needs only the scheduler lock; see `trap_handler`.
+    #[cfg(feature = "go_test")]
+    pub(crate) unsafe fn r4_ipi(frame: *mut u64) {
+        r4_sched_lock();
+        let reasons = smp::smp_ipi_take();
+        if reasons & smp::SMP_IPI_TICK != 0 {
+            r4_ap_tick(frame);
+        }
+        if reasons & smp::SMP_IPI_RESCHED != 0 {
+            r4_resched(frame);
+        }
+        r4_sched_unlock();
+    }
+
+    /// Another CPU asked this one to look again: an idle CPU picks up ready
+    /// work, and a task whose affinity no longer allows this CPU moves to
+    /// the run queue of one it allows.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_resched(frame: *mut u64) {
+        let cpu = r4_cpu();
+        if R4_IDLE[cpu] {
+            r4_idle_pick(frame);
+            return;
+        }
+        let cur = r4_current();
+        if *frame.add(18) & 3 != 3 || r4_cpu_allowed(cur, cpu) {
+            return;
+        }
+        r4_save_frame(frame, cur);
+        R4_TASKS[cur].cpu = (R4_TASKS[cur].affinity & r4_cpus_online()).trailing_zeros() as usize;
+        r4_set_state(cur, R4State::Ready);
+        match r4_find_ready(cur) {
+            Some(tid) => r4_switch_to(frame, tid),
+            None => r4_idle_or_finish(frame, false),
+        }
+    }
+
+    /// On the way out of the kernel: wake every idle CPU that may run a task
+    /// now ready, so it can pull the task.
+    #[cfg(feature = "go_test")]
+    pub(crate) unsafe fn r4_kick_idle_cpus() {
+        let me = r4_cpu();
+        let online = r4_cpus_online();
+        r4_sched_lock();
+        for (cpu, &idle) in R4_IDLE.iter().enumerate() {
+            if cpu == me || online & (1 << cpu) == 0 || !idle {
+                continue;
+            }
+            if r4_pick_ready(R4_TASKS.len(), !0, |tid| r4_cpu_allowed(tid, cpu)).is_some() {
+                smp::smp_send(cpu, smp::SMP_IPI_RESCHED);
+            }
+        }
+        r4_sched_unlock();
+    }
+
+    #[inline(always)]
+    unsafe fn r4_wait_matches(target: i32, child_tid: usize) -> bool {
+        target == -1 || target == child_tid as i32
+    }
+
+    /// True if `len` (at most 8) bytes at `ptr` can be read. The check reads
+    /// them through the exception table, so a reserved page is faulted in
+    /// and nothing walks the page tables first.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_user_readable(ptr: u64, len: usize) -> bool {
+        let mut probe = [0u8; 8];
+        len <= probe.len() && copyin_user(&mut probe, ptr, len).is_ok()
+    }
+
+    /// True if `len` bytes at `ptr` can be written. The walk faults reserved
+    /// pages in and breaks copy-on-write, so the first write from the new
+    /// thread finds the page ready; the data itself is left alone.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_user_writable(ptr: u64, len: usize) -> bool {
+        user_pages_ok(ptr, len, USER_PERM_WRITE)
+    }
+
+    #[inline(always)]
+    unsafe fn r4_copy_wait_status(status_ptr: u64, status: u64) -> bool {
+        if status_ptr == 0 {
+            return true;
+        }
+        let st = status.to_le_bytes();
+        copyout_user(status_ptr, &st, st.len()).is_ok()
+    }
+
+    unsafe fn r4_has_waitable_child(parent_tid: usize, target: i32) -> bool {
+        for tid in 0..R4_NUM_TASKS {
+            if tid == parent_tid || R4_TASKS[tid].parent_tid != parent_tid {
+                continue;
+            }
+            if R4_TASKS[tid].detached {
+                continue;
+            }
+            if !r4_wait_matches(target, tid) {
+                continue;
+            }
+            if R4_TASKS[tid].state != R4State::Dead {
+                return true;
+            }
         }
         false
     }
@@ -2345,42 +3998,125 @@ cfg_r4! {
 
     unsafe fn r4_wake_waiter(parent_tid: usize, child_tid: usize) {
         let status_ptr = R4_TASKS[parent_tid].wait_status_ptr;
-        if r4_copy_wait_status(status_ptr, R4_TASKS[child_tid].exit_status) {
-            R4_TASKS[parent_tid].saved_frame[14] = child_tid as u64;
-        } else {
-            R4_TASKS[parent_tid].saved_frame[14] = 0xFFFF_FFFF_FFFF_FFFF;
-        }
+        let prev_cr3 = vm::vm_enter_space(R4_TASKS[parent_tid].space);
+        let copied = r4_copy_wait_status(status_ptr, R4_TASKS[child_tid].exit_status);
+        vm::vm_leave_space(prev_cr3);
         R4_TASKS[parent_tid].wait_target = R4_WAIT_NONE;
         R4_TASKS[parent_tid].wait_status_ptr = 0;
-        R4_TASKS[parent_tid].state = R4State::Ready;
-        R4_TASKS[child_tid].state = R4State::Dead;
+        r4_set_state(parent_tid, R4State::Ready);
+        // A status word that cannot be written fails the wait and leaves the
+        // child to be reaped by a later one.
+        if !copied {
+            R4_TASKS[parent_tid].saved_frame[14] = 0xFFFF_FFFF_FFFF_FFFF;
+            return;
+        }
+        R4_TASKS[parent_tid].saved_frame[14] = r4_task_id(child_tid);
+        r4_set_state(child_tid, R4State::Dead);
         R4_TASKS[child_tid].exit_status = 0;
     }
 
     unsafe fn r4_yield_and_switch(frame: *mut u64) {
-        let cur = R4_CURRENT;
+        let cur = r4_current();
         r4_save_frame(frame, cur);
         R4_TASKS[cur].yield_count += 1;
         R4_TASKS[cur].saved_frame[14] = 0;
+        r4_sched_lock();
         match r4_find_ready(cur) {
             Some(tid) => {
-                R4_TASKS[cur].state = R4State::Ready;
+                r4_set_state(cur, R4State::Ready);
                 r4_switch_to(frame, tid);
             }
             None => {
-                R4_TASKS[cur].state = R4State::Running;
                 *frame.add(14) = 0;
             }
         }
+        r4_sched_unlock();
     }
 
-    unsafe fn r4_exit_and_switch(frame: *mut u64, exit_status: u64) {
-        let cur = R4_CURRENT;
+    /// Block the current task, whose frame is already saved, and switch to
+    /// the next ready one. With a `deadline` tick the task also wakes then,
+    /// with `timeout_ret` in rax.
+    unsafe fn r4_block_current(frame: *mut u64, deadline: Option<u64>, timeout_ret: u64) {
+        let cur = r4_current();
+        R4_TASKS[cur].block_count += 1;
+        r4_sched_lock();
+        r4_set_state(cur, R4State::Blocked);
+        #[cfg(feature = "go_test")]
+        if let Some(tick) = deadline {
+            R4_TASKS[cur].timeout_ret = timeout_ret;
+            timer::timer_arm(tick, timer::TimerEvent::Task(cur));
+        }
+        #[cfg(not(feature = "go_test"))]
+        let _ = (deadline, timeout_ret);
+        match r4_find_ready(cur) {
+            Some(tid) => { r4_switch_to(frame, tid); }
+            None => r4_idle_or_finish(frame, true),
+        }
+        r4_sched_unlock();
+    }
+
+    /// No task is ready for this CPU. Idle if a pending timer can wake one,
+    /// or if another CPU still has work that may wake or hand one over;
+    /// otherwise return the frame to `r4_all_done`, reporting a deadlock if
+    /// asked.
+    unsafe fn r4_idle_or_finish(frame: *mut u64, deadlock: bool) {
+        #[cfg(feature = "go_test")]
+        let kstack = smp::kernel_stack_top(r4_cpu());
+        #[cfg(not(feature = "go_test"))]
+        let kstack = &stack_top as *const u8 as u64;
+        #[cfg(feature = "go_test")]
+        {
+            let busy = (0..r4_sched_span())
+                .any(|tid| matches!(R4_TASKS[tid].state, R4State::Ready | R4State::Running));
+            if r4_timer_wakeup_pending() || busy {
+                R4_IDLE[r4_cpu()] = true;
+                // Off every user space, so no shootdown waits on this CPU.
+                vm::vm_activate_kernel();
+                *frame.add(17) = r4_idle as *const () as u64;
+                *frame.add(18) = 0x08;
+                *frame.add(19) = 0x202; // IF set so the tick gets in
+                *frame.add(20) = kstack;
+                *frame.add(21) = 0x10;
+                return;
+            }
+        }
+        if deadlock {
+            serial_write(b"R4: deadlock\n");
+        }
+        *frame.add(17) = r4_all_done as *const () as u64;
+        *frame.add(18) = 0x08;
+        *frame.add(19) = 0x02;
+        *frame.add(20) = kstack;
+        *frame.add(21) = 0x10;
+    }
+
+    /// Kernel-mode wait for the timer tick or IPI that wakes a task.
+    #[cfg(feature = "go_test")]
+    extern "C" fn r4_idle() -> ! {
+        loop { unsafe { core::arch::asm!("sti; hlt", options(nomem, nostack)); } }
+    }
+
+    /// Release a task's resources and mark it exited, waking a parent
+    /// blocked in wait. Does not switch away from the current task.
+    unsafe fn r4_retire_task(cur: usize, exit_status: u64) {
         let parent = R4_TASKS[cur].parent_tid;
+        // Clear the join word while the exiting task's space is still live so
+        // threads sharing it see the exit.
+        if R4_TASKS[cur].join_ptr != 0 {
+            let zero = 0u64.to_le_bytes();
+            let prev_cr3 = vm::vm_enter_space(R4_TASKS[cur].space);
+            let _ = copyout_user(R4_TASKS[cur].join_ptr, &zero, zero.len());
+            vm::vm_leave_space(prev_cr3);
+        }
         r4_cleanup_task_resources(cur);
         R4_TASKS[cur].exit_status = exit_status;
-        R4_TASKS[cur].state = R4State::Exited;
+        r4_set_state(cur, if R4_TASKS[cur].detached {
+            R4State::Dead
+        } else {
+            R4State::Exited
+        });
         if parent != cur
+            && !R4_TASKS[cur].detached
             && parent < R4_NUM_TASKS
             && R4_TASKS[parent].state == R4State::Blocked
             && R4_TASKS[parent].wait_target != R4_WAIT_NONE
@@ -2388,21 +4124,26 @@ cfg_r4! {
         {
             r4_wake_waiter(parent, cur);
         }
-        match r4_find_ready(R4_CURRENT) {
+    }
+
+    unsafe fn r4_exit_and_switch(frame: *mut u64, exit_status: u64) {
+        r4_retire_task(r4_current(), exit_status);
+        r4_sched_lock();
+        match r4_find_ready(r4_current()) {
             Some(tid) => { r4_switch_to(frame, tid); }
-            None => {
-                // All tasks done â€” exit
-                let kstack = &stack_top as *const u8 as u64;
-                *frame.add(17) = r4_all_done as *const () as u64;
-                *frame.add(18) = 0x08;
-                *frame.add(19) = 0x02;
-                *frame.add(20) = kstack;
-                *frame.add(21) = 0x10;
-            }
+            // All tasks done, unless a sleeper is still due to wake.
+            None => r4_idle_or_finish(frame, false),
         }
+        r4_sched_unlock();
     }
 
     extern "C" fn r4_all_done() -> ! {
+        // Entered with interrupts off; the compat lane keeps the lock while
+        // it sets up the next app.
+        #[cfg(feature = "go_test")]
+        unsafe {
+            smp::smp_lock();
+        }
         #[cfg(feature = "stress_ipc_test")]
         serial_write(b"STRESS: ipc ok");
         #[cfg(feature = "compat_real_test")]
@@ -2415,13 +4156,21 @@ cfg_r4! {
         loop { unsafe { core::arch::asm!("cli; hlt", options(nomem, nostack)); } }
     }
 
+    /// User task slots that may be in use at once, from the boot limits.
+    #[inline(always)]
+    unsafe fn r4_user_task_limit() -> usize {
+        limits::max_tasks()
+    }
+
+    /// Reuse a dead slot under a new generation, or take the next fresh one.
     unsafe fn r4_find_spawn_slot() -> Option<usize> {
         for tid in 1..R4_NUM_TASKS {
             if R4_TASKS[tid].state == R4State::Dead {
+                R4_TASKS[tid].generation = R4_TASKS[tid].generation.wrapping_add(1) & R4_TASK_GEN_MASK;
                 return Some(tid);
             }
         }
-        if R4_NUM_TASKS >= R4_MAX_TASKS {
+        if R4_NUM_TASKS >= r4_user_task_limit() {
             return None;
         }
         let tid = R4_NUM_TASKS;
@@ -2429,6 +4178,36 @@ cfg_r4! {
         Some(tid)
     }
 
+    /// The id user space sees for slot `tid`.
+    #[inline(always)]
+    unsafe fn r4_task_id(tid: usize) -> u64 {
+        tid as u64 | (R4_TASKS[tid].generation as u64) << R4_TASK_ID_SLOT_BITS
+    }
+
+    /// Resolve a task id to its slot. An id from before the slot was reused
+    /// carries an old generation and resolves to nothing.
+    unsafe fn r4_task_lookup(id: u64) -> Option<usize> {
+        let tid = (id & ((1 << R4_TASK_ID_SLOT_BITS) - 1)) as usize;
+        if !r4_sched_target_ok(tid) {
+            return None;
+        }
+        if id >> R4_TASK_ID_SLOT_BITS != R4_TASKS[tid].generation as u64 {
+            return None;
+        }
+        Some(tid)
+    }
+
+    /// Live threads across all spaces, for the boot thread limit. The quota
+    /// lane's threads never run; they are only counted on the task that
+    /// spawned them.
+    #[cfg(any(feature = "go_test", feature = "quota_threads_test"))]
+    unsafe fn r4_live_threads() -> usize {
+        (0..R4_NUM_TASKS)
+            .filter(|&tid| r4_task_live(tid))
+            .map(|tid| R4_TASKS[tid].thread_count + R4_TASKS[tid].thread as usize)
+            .sum()
+    }
+
     #[inline(always)]
     unsafe fn r4_state_code(state: R4State) -> u64 {
         match state {
@@ -2440,16 +4219,23 @@ cfg_r4! {
         }
     }
 
+    /// Whether the scheduling and info calls reach `tid`: a user task slot
+    /// or a kernel worker.
+    #[inline(always)]
+    unsafe fn r4_sched_target_ok(tid: usize) -> bool {
+        tid < R4_NUM_TASKS || (tid < R4_TASKS.len() && R4_TASKS[tid].kernel)
+    }
+
     #[inline(always)]
     unsafe fn r4_can_control_task(requester: usize, target: usize) -> bool {
         requester == target
             || R4_TASKS[target].parent_tid == requester
-            || R4_TASKS[requester].can_spawn
+            || R4_TASKS[requester].cap_flags & R4_TASK_CAP_SPAWN != 0
     }
 
     #[inline(always)]
     unsafe fn r4_current_has_cap(flag: u8) -> bool {
-        R4_TASKS[R4_CURRENT].cap_flags & flag != 0
+        R4_TASKS[r4_current()].cap_flags & flag != 0
     }
 
     unsafe fn r4_cleanup_task_resources(tid: usize) {
@@ -2459,18 +4245,540 @@ cfg_r4! {
             net::r4_release_owned_sockets(tid);
             r4_release_owned_endpoints(tid);
             r4_release_stale_services();
+            r4_release_owned_timers(tid);
+        }
+        #[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+        r4_release_shm_handles(tid);
+        vm::vm_space_release(R4_TASKS[tid].space);
+        R4_TASKS[tid].space = vm::VM_NO_SPACE;
+    }
+
+    /// Demand-fault hook for kernel accesses to user memory. Faults resolve
+    /// against the loaded space, which is the peer's during a cross-task copy.
+    unsafe fn r4_fault_in_user_page(va: u64, write: bool) -> bool {
+        match vm::vm_active_space() {
+            Some(space) => vm::vm_fault_in(space, va, write, false),
+            None => false,
+        }
+    }
+
+    /// Anonymous mapping in the caller's space; returns the chosen address.
+    unsafe fn sys_mmap_r4(addr_hint: u64, len: u64, prot: u64) -> u64 {
+        if prot > 0xFF | vm::VM_MAP_STACK as u64 {
+            return 0xFFFF_FFFF_FFFF_FFFF;
+        }
+        if !vm::vm_commit_ok(R4_TASKS[r4_current()].space, len) {
+            return R4_ERR_MEM_LIMIT;
+        }
+        vm::vm_mmap(R4_TASKS[r4_current()].space, addr_hint, len, prot as u16)
+            .unwrap_or(0xFFFF_FFFF_FFFF_FFFF)
+    }
+
+    unsafe fn sys_munmap_r4(addr: u64, len: u64) -> u64 {
+        if vm::vm_munmap(R4_TASKS[r4_current()].space, addr, len) {
+            0
+        } else {
+            0xFFFF_FFFF_FFFF_FFFF
+        }
+    }
+
+    unsafe fn sys_mprotect_r4(addr: u64, len: u64, prot: u64) -> u64 {
+        if prot > 0xFF {
+            return 0xFFFF_FFFF_FFFF_FFFF;
+        }
+        if vm::vm_mprotect(R4_TASKS[r4_current()].space, addr, len, prot as u8) {
+            0
+        } else {
+            0xFFFF_FFFF_FFFF_FFFF
+        }
+    }
+
+    // Memory pressure: the frame pool's level is polled after each R4
+    // syscall and after user page faults. Level changes, OOM kills and
+    // spaces going past their soft limit are posted to the registered
+    // supervisor endpoint as `R4_MEM_EVENT_WORDS` little-endian words:
+    // event, level, free frames, task id (the victim, or a task of the
+    // space over its soft limit).
+    const R4_MEM_EVENT_LEVEL: u64 = 1;
+    const R4_MEM_EVENT_OOM_KILL: u64 = 2;
+    const R4_MEM_EVENT_SOFT_LIMIT: u64 = 3;
+    const R4_MEM_EVENT_WORDS: usize = 4;
+    const R4_MEM_NO_VICTIM: u64 = 0xFFFF_FFFF_FFFF_FFFF;
+    /// Exit status of a task killed by the OOM policy.
+    const R4_EXIT_OOM_KILLED: u64 = 137;
+
+    static mut R4_MEM_SUPERVISOR: i32 = -1;
+    static mut R4_MEM_LEVEL: u8 = vm::VM_PRESSURE_NORMAL;
+
+    /// Get or set the caller's FS or GS base. Bases must be user addresses;
+    /// the get forms store the base at `addr`.
+    unsafe fn sys_arch_prctl_r4(code: u64, addr: u64) -> u64 {
+        let cur = r4_current();
+        match code {
+            R4_ARCH_SET_FS | R4_ARCH_SET_GS => {
+                if addr >= USER_VA_LIMIT {
+                    return 0xFFFF_FFFF_FFFF_FFFF;
+                }
+                if code == R4_ARCH_SET_FS {
+                    R4_TASKS[cur].fs_base = addr;
+                    r4_load_fs_base(addr);
+                } else {
+                    R4_TASKS[cur].gs_base = addr;
+                    r4_load_gs_base(addr);
+                }
+                0
+            }
+            R4_ARCH_GET_FS | R4_ARCH_GET_GS => {
+                let base = if code == R4_ARCH_GET_FS {
+                    R4_TASKS[cur].fs_base
+                } else {
+                    R4_TASKS[cur].gs_base
+                };
+                if !user_range_ok(addr, 8) || copyout_user(addr, &base.to_le_bytes(), 8).is_err() {
+                    return 0xFFFF_FFFF_FFFF_FFFF;
+                }
+                0
+            }
+            _ => 0xFFFF_FFFF_FFFF_FFFF,
+        }
+    }
+
+    /// Load an ELF image into `space`. Each `PT_LOAD` becomes its own area
+    /// at the requested address with the segment's permissions; its file
+    /// bytes are copied into pool frames now and the rest is demand-zero.
+    /// The image is bounded only by the space's memory limit and the frame
+    /// pool. ET_DYN images are placed at a random slide above
+    /// `R4_ELF_DYN_BASE` and relocated after every segment is in place.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_load_elf(space: usize, image: &[u8]) -> Option<ElfV1LoadInfo> {
+        // The signature is checked before anything of the image is mapped.
+        let (image, sig) = sign::sign_split_trailer(image);
+        if !sign::sign_admit(b"ELF", &[image], sig) {
+            return None;
+        }
+        if !elf_v1_validate_image(image) || image.len() < 64 {
+            return None;
+        }
+
+        let e_type = elf_v1_read_u16(image, 16)?;
+        if e_type != ELF_V1_ET_EXEC && e_type != ELF_V1_ET_DYN {
+            return None;
+        }
+
+        let e_entry = elf_v1_read_u64(image, 24)?;
+        let e_phoff = elf_v1_read_u64(image, 32)? as usize;
+        let e_phentsize = elf_v1_read_u16(image, 54)? as usize;
+        let e_phnum = elf_v1_read_u16(image, 56)? as usize;
+        let (span_low, span_high) = elf_v1_load_span(image)?;
+        let tls = elf_v1_tls_segment(image).ok()?;
+        let bias = if e_type == ELF_V1_ET_DYN {
+            if let Err(err) = elf_v1_for_each_relative(image, span_low, span_high, |_, _| true) {
+                m3_log_elf_reloc_error(err);
+                return None;
+            }
+            let slide = elf_v1_entropy().map_or(0, |v| v % R4_ELF_DYN_SLIDE_PAGES);
+            (R4_ELF_DYN_BASE + slide * 4096).wrapping_sub(span_low)
+        } else {
+            0
+        };
+
+        for idx in 0..e_phnum {
+            let off = e_phoff.checked_add(idx.checked_mul(e_phentsize)?)?;
+            if elf_v1_read_u32(image, off)? != ELF_V1_PT_LOAD {
+                continue;
+            }
+
+            let p_flags = elf_v1_read_u32(image, off + 4)?;
+            let p_offset = elf_v1_read_u64(image, off + 8)? as usize;
+            let p_vaddr = elf_v1_read_u64(image, off + 16)?.wrapping_add(bias);
+            let p_filesz = elf_v1_read_u64(image, off + 32)? as usize;
+            let p_memsz = elf_v1_read_u64(image, off + 40)?;
+            if p_memsz == 0 {
+                continue;
+            }
+            let file_end = p_offset.checked_add(p_filesz)?;
+            if file_end > image.len() {
+                return None;
+            }
+
+            let mut prot = 0u8;
+            if p_flags & 4 != 0 {
+                prot |= vm::VM_PROT_READ;
+            }
+            if p_flags & 2 != 0 {
+                prot |= vm::VM_PROT_WRITE;
+            }
+            if p_flags & 1 != 0 {
+                prot |= vm::VM_PROT_EXEC;
+            }
+            if !vm::vm_load_segment(space, p_vaddr, p_memsz, &image[p_offset..file_end], prot) {
+                serial_write(b"ELF: segment map fail\n");
+                return None;
+            }
+        }
+
+        if e_type == ELF_V1_ET_DYN {
+            let applied = elf_v1_for_each_relative(image, span_low, span_high, |target, addend| {
+                let value = addend.wrapping_add(bias).to_le_bytes();
+                vm::vm_write_user(space, target.wrapping_add(bias), &value)
+            });
+            if let Err(err) = applied {
+                m3_log_elf_reloc_error(err);
+                return None;
+            }
+        }
+
+        let phdr = elf_v1_phdr_vaddr(image);
+        Some(ElfV1LoadInfo {
+            entry: e_entry.wrapping_add(bias),
+            phdr: if phdr == 0 { 0 } else { phdr.wrapping_add(bias) },
+            phent: e_phentsize as u64,
+            phnum: e_phnum as u64,
+            tls: tls.map(|tls| ElfV1Tls { vaddr: tls.vaddr.wrapping_add(bias), ..tls }),
+        })
+    }
+
+    /// Build the initial thread's static TLS block from the loaded image's
+    /// `PT_TLS` template (x86-64 variant II): the block ends at the thread
+    /// pointer, whose first word points at itself, and the task's FS base
+    /// is set to it; the MSR is loaded when the task runs. Call after
+    /// `r4_init_task`, with the task's space active.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_setup_initial_tls(tid: usize, info: &ElfV1LoadInfo) -> bool {
+        let tls = match info.tls {
+            Some(tls) => tls,
+            None => return true,
+        };
+        let align = core::cmp::max(tls.align, 8);
+        let block = (tls.memsz + align - 1) & !(align - 1);
+        let prot = (vm::VM_PROT_READ | vm::VM_PROT_WRITE) as u16;
+        let base = match vm::vm_mmap(R4_TASKS[tid].space, 0, block + R4_TLS_TCB_SIZE, prot) {
+            Some(va) => va,
+            None => return false,
+        };
+        let mut chunk = [0u8; 64];
+        let mut copied = 0u64;
+        while copied < tls.filesz {
+            let n = core::cmp::min(chunk.len() as u64, tls.filesz - copied) as usize;
+            if copyin_user(&mut chunk[..n], tls.vaddr + copied, n).is_err()
+                || copyout_user(base + copied, &chunk[..n], n).is_err()
+            {
+                return false;
+            }
+            copied += n as u64;
+        }
+        let tp = base + block;
+        if copyout_user(tp, &tp.to_le_bytes(), 8).is_err() {
+            return false;
+        }
+        R4_TASKS[tid].fs_base = tp;
+        true
+    }
+
+    /// Write the System V initial stack below `top` for a loaded image:
+    /// argc, the argv and envp pointer arrays (each NULL-terminated)
+    /// and the aux vector, with the strings and `AT_RANDOM` bytes above them.
+    /// Returns the 16-byte aligned entry `rsp`, which points at argc.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_build_startup_stack(
+        top: u64,
+        info: &ElfV1LoadInfo,
+        argv: &[&[u8]],
+        envp: &[&[u8]],
+        execfn: &[u8],
+    ) -> Option<u64> {
+        if argv.len() + envp.len() > R4_STARTUP_MAX_ARGS {
+            return None;
+        }
+        // Built downward in a kernel buffer that mirrors
+        // [top - R4_STARTUP_MAX_BYTES, top), then copied out at once.
+        let mut buf = [0u8; R4_STARTUP_MAX_BYTES];
+        let base = top - R4_STARTUP_MAX_BYTES as u64;
+        let mut pos = R4_STARTUP_MAX_BYTES;
+        let mut push = |bytes: &[u8], nul: bool, pos: &mut usize| -> Option<u64> {
+            let len = bytes.len() + nul as usize;
+            *pos = pos.checked_sub(len)?;
+            buf[*pos..*pos + bytes.len()].copy_from_slice(bytes);
+            if nul {
+                buf[*pos + bytes.len()] = 0;
+            }
+            Some(base + *pos as u64)
+        };
+        let execfn_va = push(execfn, true, &mut pos)?;
+        // Pointer slots: argv first, then envp; strings end up in the same
+        // order going up.
+        let mut ptrs = [0u64; R4_STARTUP_MAX_ARGS];
+        for idx in (0..envp.len()).rev() {
+            ptrs[argv.len() + idx] = push(envp[idx], true, &mut pos)?;
+        }
+        for idx in (0..argv.len()).rev() {
+            ptrs[idx] = push(argv[idx], true, &mut pos)?;
+        }
+        let random_va = push(&elf_v1_random_bytes(), false, &mut pos)?;
+        let auxv = elf_v1_build_auxv(info, random_va, execfn_va);
+
+        let words = 1 + argv.len() + 1 + envp.len() + 1 + 2 * auxv.len();
+        pos &= !15;
+        pos = pos.checked_sub((words + words % 2) * 8)?;
+        let mut off = pos;
+        let mut put = |word: u64| {
+            buf[off..off + 8].copy_from_slice(&word.to_le_bytes());
+            off += 8;
+        };
+        put(argv.len() as u64);
+        for &ptr in ptrs[..argv.len()].iter() {
+            put(ptr);
+        }
+        put(0);
+        for &ptr in ptrs[argv.len()..argv.len() + envp.len()].iter() {
+            put(ptr);
+        }
+        put(0);
+        for (key, value) in auxv {
+            put(key);
+            put(value);
+        }
+        if copyout_user(base + pos as u64, &buf[pos..], R4_STARTUP_MAX_BYTES - pos).is_err() {
+            return None;
+        }
+        Some(base + pos as u64)
+    }
+
+    /// Register the endpoint that receives memory-pressure events. The
+    /// supervisor's space is spared by the OOM policy, so only a task that
+    /// can spawn may register, and only the owner of the registered
+    /// endpoint may replace it. The caller must be able to receive on the
+    /// new one.
+    unsafe fn sys_mem_notify_r4(endpoint: u64) -> u64 {
+        let ep = endpoint as usize;
+        if ep >= R4_MAX_ENDPOINTS || !R4_ENDPOINTS[ep].active {
+            return 0xFFFF_FFFF_FFFF_FFFF;
+        }
+        if !r4_current_has_cap(R4_TASK_CAP_SPAWN) {
+            return 0xFFFF_FFFF_FFFF_FFFF;
+        }
+        if R4_MEM_SUPERVISOR >= 0
+            && R4_ENDPOINTS[R4_MEM_SUPERVISOR as usize].owner_tid != r4_current()
+        {
+            return 0xFFFF_FFFF_FFFF_FFFF;
+        }
+        if !r4_endpoint_owner_has_right(ep, R4_EP_RIGHT_RECV) {
+            return 0xFFFF_FFFF_FFFF_FFFF;
+        }
+        R4_MEM_SUPERVISOR = ep as i32;
+        0
+    }
+
+    /// Post an event without blocking. It is dropped when the supervisor
+    /// still has an unread message.
+    unsafe fn r4_mem_notify(event: u64, level: u8, victim: u64) {
+        let ep = R4_MEM_SUPERVISOR;
+        if ep < 0 || !R4_ENDPOINTS[ep as usize].active || R4_ENDPOINTS[ep as usize].has_msg {
+            return;
+        }
+        let words = [event, level as u64, vm::vm_free_frames() as u64, victim];
+        let mut msg = [0u8; R4_MEM_EVENT_WORDS * 8];
+        for (idx, word) in words.iter().enumerate() {
+            msg[idx * 8..idx * 8 + 8].copy_from_slice(&word.to_le_bytes());
+        }
+        let _ = r4_ipc_deliver(ep as usize, &mut msg);
+    }
+
+    unsafe fn r4_oom_log(level: u8, victim: u64) {
+        serial_write(b"OOM: level=");
+        serial_write_u64_dec(level as u64);
+        serial_write(b" free=");
+        serial_write_u64_dec(vm::vm_free_frames() as u64);
+        serial_write(b" victim=");
+        if victim == R4_MEM_NO_VICTIM {
+            serial_write(b"none");
+        } else {
+            serial_write_u64_dec(victim);
+        }
+        serial_write(b"\n");
+        for tid in 0..R4_NUM_TASKS {
+            let task = &R4_TASKS[tid];
+            if task.state == R4State::Dead || task.state == R4State::Exited {
+                continue;
+            }
+            serial_write(b"OOM: tid=");
+            serial_write_u64_dec(r4_task_id(tid));
+            serial_write(b" class=");
+            serial_write_u64_dec(task.sched_class as u64);
+            serial_write(b" resident=");
+            serial_write_u64_dec(vm::vm_resident_pages(task.space));
+            serial_write(b" reserved=");
+            serial_write_u64_dec(vm::vm_reserved_pages(task.space));
+            serial_write(b"\n");
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn r4_task_live(tid: usize) -> bool {
+        R4_TASKS[tid].state != R4State::Dead && R4_TASKS[tid].state != R4State::Exited
+    }
+
+    /// The live task with the most resident pages, skipping critical tasks,
+    /// spaces shared with one, and the owner of the supervisor endpoint.
+    /// A space over its soft limit goes before any space within it.
+    unsafe fn r4_oom_pick_victim() -> Option<usize> {
+        let supervisor = if R4_MEM_SUPERVISOR >= 0 {
+            Some(R4_ENDPOINTS[R4_MEM_SUPERVISOR as usize].owner_tid)
+        } else {
+            None
+        };
+        let mut victim = None;
+        let mut victim_rank = (false, 0);
+        for tid in 0..R4_NUM_TASKS {
+            if !r4_task_live(tid) || Some(tid) == supervisor {
+                continue;
+            }
+            let space = R4_TASKS[tid].space;
+            if space == vm::VM_NO_SPACE {
+                continue;
+            }
+            let protected = (0..R4_NUM_TASKS).any(|other| {
+                r4_task_live(other)
+                    && R4_TASKS[other].space == space
+                    && (R4_TASKS[other].sched_class == R4_SCHED_CLASS_CRITICAL
+                        || Some(other) == supervisor)
+            });
+            let rank = (vm::vm_over_soft_limit(space), vm::vm_resident_pages(space));
+            if !protected && rank > victim_rank {
+                victim = Some(tid);
+                victim_rank = rank;
+            }
+        }
+        victim
+    }
+
+    /// Retire a task other than the current one. One running on another CPU
+    /// is only marked, and exits there. A ready one is first taken off its
+    /// run queue, under the lock that saw it was not running, so no CPU
+    /// picks it up while it is retired.
+    unsafe fn r4_kill_task(tid: usize, exit_status: u64) {
+        r4_sched_lock();
+        #[cfg(feature = "go_test")]
+        if R4_TASKS[tid].state == R4State::Running {
+            R4_TASKS[tid].kill_pending = true;
+            R4_TASKS[tid].exit_status = exit_status;
+            smp::smp_send(R4_TASKS[tid].cpu, smp::SMP_IPI_RESCHED);
+            r4_sched_unlock();
+            return;
+        }
+        let blocked = R4_TASKS[tid].state == R4State::Blocked;
+        if R4_TASKS[tid].state == R4State::Ready {
+            r4_set_state(tid, R4State::Blocked);
+        }
+        r4_sched_unlock();
+        let ep = R4_TASKS[tid].recv_ep as usize;
+        if blocked
+            && ep < R4_MAX_ENDPOINTS
+            && R4_ENDPOINTS[ep].waiter == tid as i32
+        {
+            R4_ENDPOINTS[ep].waiter = -1;
+        }
+        r4_retire_task(tid, exit_status);
+    }
+
+    /// Whether the task running here has a kill posted, which only the
+    /// kernel lock may carry out. Read without a lock: only this CPU changes
+    /// its idle flag, and a kill is posted before its IPI is sent.
+    #[cfg(feature = "go_test")]
+    pub(crate) unsafe fn r4_kill_posted() -> bool {
+        !R4_IDLE[r4_cpu()] && R4_TASKS[r4_current()].kill_pending
+    }
+
+    /// Finish a kill another CPU posted for the task running here, at its
+    /// first entry from user mode. True if the task is gone and `frame`
+    /// belongs to the next one.
+    #[cfg(feature = "go_test")]
+    pub(crate) unsafe fn r4_take_kill(frame: *mut u64) -> bool {
+        let cur = r4_current();
+        if R4_IDLE[r4_cpu()] || !R4_TASKS[cur].kill_pending || *frame.add(18) & 3 != 3 {
+            return false;
+        }
+        R4_TASKS[cur].kill_pending = false;
+        r4_exit_and_switch(frame, R4_TASKS[cur].exit_status);
+        true
+    }
+
+    /// Kill the victim and every task sharing its space, the current task
+    /// last since that switches away. Returns the victim.
+    unsafe fn r4_oom_kill(frame: *mut u64) -> Option<usize> {
+        let victim = r4_oom_pick_victim()?;
+        let space = R4_TASKS[victim].space;
+        let mut kill_current = false;
+        for tid in 0..R4_NUM_TASKS {
+            if !r4_task_live(tid) || R4_TASKS[tid].space != space {
+                continue;
+            }
+            if tid == r4_current() {
+                kill_current = true;
+            } else {
+                r4_kill_task(tid, R4_EXIT_OOM_KILLED);
+            }
         }
+        if kill_current {
+            r4_exit_and_switch(frame, R4_EXIT_OOM_KILLED);
+        }
+        Some(victim)
+    }
+
+    /// Check the pool after a syscall or fault that may have allocated.
+    /// Entering the critical level, or any failed allocation, logs an OOM
+    /// event and kills a victim; a space going past its soft limit posts a
+    /// soft-limit event. Returns true if a task was killed; the frame then
+    /// may belong to another task.
+    pub(crate) unsafe fn r4_mem_pressure_poll(frame: *mut u64) -> bool {
+        let failed = vm::vm_take_alloc_failure();
+        let level = vm::vm_pressure_level();
+        let entered_critical = level == vm::VM_PRESSURE_CRITICAL && R4_MEM_LEVEL != level;
+        let level_changed = level != R4_MEM_LEVEL;
+        R4_MEM_LEVEL = level;
+        if failed || entered_critical {
+            let victim = r4_oom_pick_victim().map_or(R4_MEM_NO_VICTIM, |tid| r4_task_id(tid));
+            r4_oom_log(level, victim);
+            let killed = r4_oom_kill(frame).is_some();
+            R4_MEM_LEVEL = vm::vm_pressure_level();
+            r4_mem_notify(R4_MEM_EVENT_OOM_KILL, R4_MEM_LEVEL, victim);
+            return killed;
+        }
+        if level_changed {
+            r4_mem_notify(R4_MEM_EVENT_LEVEL, level, R4_MEM_NO_VICTIM);
+        }
+        if let Some(space) = vm::vm_take_soft_crossing() {
+            let task = (0..R4_NUM_TASKS)
+                .find(|&tid| r4_task_live(tid) && R4_TASKS[tid].space == space)
+                .map_or(R4_MEM_NO_VICTIM, |tid| r4_task_id(tid));
+            r4_mem_notify(R4_MEM_EVENT_SOFT_LIMIT, level, task);
+        }
+        false
+    }
+
+    /// An isolation config as read from user memory and range-checked.
+    struct R4IsolationConfig {
+        domain: u8,
+        flags: u8,
+        fd_limit: u8,
+        socket_limit: u8,
+        endpoint_limit: u8,
+        // (hard, soft) memory limits, when the config carries them.
+        mem_limits: Option<(u64, u64)>,
     }
 
-    unsafe fn r4_copy_isolation_config(
-        cfg_ptr: u64,
-        cfg_len: u64,
-    ) -> Option<(u8, u8, u8, u8, u8)> {
-        if cfg_len < 24 {
+    unsafe fn r4_copy_isolation_config(cfg_ptr: u64, cfg_len: u64) -> Option<R4IsolationConfig> {
+        if cfg_len < R4_ISOLATION_CONFIG_SIZE as u64 {
             return None;
         }
-        let mut raw = [0u8; 24];
-        let raw_len = raw.len();
+        // The memory-limit words are optional; shorter configs keep the
+        // task's current limits.
+        let raw_len = if cfg_len >= R4_ISOLATION_CONFIG_MEM_SIZE as u64 {
+            R4_ISOLATION_CONFIG_MEM_SIZE
+        } else {
+            R4_ISOLATION_CONFIG_SIZE
+        };
+        let mut raw = [0u8; R4_ISOLATION_CONFIG_MEM_SIZE];
         if copyin_user(&mut raw, cfg_ptr, raw_len).is_err() {
             return None;
         }
@@ -2489,38 +4797,57 @@ cfg_r4! {
         {
             return None;
         }
-        Some((domain, flags, fd_limit, socket_limit, endpoint_limit))
+        let mem_limits = if raw_len == R4_ISOLATION_CONFIG_MEM_SIZE {
+            let hard = u64::from_le_bytes(raw[24..32].try_into().ok()?);
+            let soft = u64::from_le_bytes(raw[32..40].try_into().ok()?);
+            if hard != 0 && soft > hard {
+                return None;
+            }
+            Some((hard, soft))
+        } else {
+            None
+        };
+        Some(R4IsolationConfig { domain, flags, fd_limit, socket_limit, endpoint_limit, mem_limits })
     }
 
     unsafe fn sys_proc_info_r4(tid: u64, info_ptr: u64, info_len: u64) -> u64 {
-        let target = tid as usize;
-        if target >= R4_NUM_TASKS {
-            return 0xFFFF_FFFF_FFFF_FFFF;
-        }
+        let target = match r4_task_lookup(tid) {
+            Some(target) => target,
+            None => return 0xFFFF_FFFF_FFFF_FFFF,
+        };
         if info_len < R4_PROC_INFO_BASE_SIZE as u64 {
             return 0xFFFF_FFFF_FFFF_FFFF;
         }
-        let copy_len = if info_len >= R4_PROC_INFO_EXT_SIZE as u64 {
+        let copy_len = if info_len >= R4_PROC_INFO_AFFINITY_SIZE as u64 {
+            R4_PROC_INFO_AFFINITY_SIZE
+        } else if info_len >= R4_PROC_INFO_CPU_SIZE as u64 {
+            R4_PROC_INFO_CPU_SIZE
+        } else if info_len >= R4_PROC_INFO_DEADLINE_SIZE as u64 {
+            R4_PROC_INFO_DEADLINE_SIZE
+        } else if info_len >= R4_PROC_INFO_SCHED_SIZE as u64 {
+            R4_PROC_INFO_SCHED_SIZE
+        } else if info_len >= R4_PROC_INFO_MEM_SIZE as u64 {
+            R4_PROC_INFO_MEM_SIZE
+        } else if info_len >= R4_PROC_INFO_EXT_SIZE as u64 {
             R4_PROC_INFO_EXT_SIZE
         } else {
             R4_PROC_INFO_BASE_SIZE
         };
-        if !user_range_ok(info_ptr, copy_len)
-            || !user_pages_ok(info_ptr, copy_len, USER_PERM_WRITE)
-        {
+        if !user_range_ok(info_ptr, copy_len) {
             return 0xFFFF_FFFF_FFFF_FFFF;
         }
         #[cfg(not(feature = "go_test"))]
         {
-            if target != R4_CURRENT {
+            if target != r4_current() {
                 return 0xFFFF_FFFF_FFFF_FFFF;
             }
         }
 
         let task = R4_TASKS[target];
+        let (mem_hard, mem_soft) = vm::vm_limits(task.space);
         let fields = [
-            target as u64,
-            task.parent_tid as u64,
+            r4_task_id(target),
+            r4_task_id(task.parent_tid),
             r4_state_code(task.state),
             task.sched_class as u64,
             task.dispatch_count,
@@ -2536,8 +4863,22 @@ cfg_r4! {
             task.cap_flags as u64,
             task.fd_count as u64,
             task.socket_count as u64,
+            vm::vm_resident_pages(task.space),
+            vm::vm_reserved_pages(task.space),
+            mem_hard,
+            mem_soft,
+            task.nice as i64 as u64,
+            task.cpu_time_us,
+            task.vruntime,
+            task.dl_runtime_us,
+            task.dl_period_us,
+            task.dl_overruns,
+            task.dl_misses,
+            r4_cycles_to_us(task.user_cycles),
+            r4_cycles_to_us(task.kernel_cycles),
+            task.affinity,
         ];
-        let mut out = [0u8; R4_PROC_INFO_EXT_SIZE];
+        let mut out = [0u8; R4_PROC_INFO_AFFINITY_SIZE];
         for (idx, field) in fields.iter().enumerate() {
             let start = idx * 8;
             out[start..start + 8].copy_from_slice(&field.to_le_bytes());
@@ -2548,25 +4889,79 @@ cfg_r4! {
         0
     }
 
-    unsafe fn sys_isolation_config_r4(tid: u64, cfg_ptr: u64, cfg_len: u64) -> u64 {
-        let target = tid as usize;
-        if target >= R4_NUM_TASKS {
+    /// Measured CPU time in microseconds; only the Go lane runs the clock
+    /// that accounting needs.
+    #[inline(always)]
+    unsafe fn r4_cycles_to_us(cycles: u64) -> u64 {
+        #[cfg(feature = "go_test")]
+        {
+            cputime::cycles_to_us(cycles)
+        }
+        #[cfg(not(feature = "go_test"))]
+        {
+            let _ = cycles;
+            0
+        }
+    }
+
+    /// System-wide CPU accounting: uptime, idle, user and kernel time in
+    /// microseconds, the 1/5/15 minute load averages in 16.16 fixed point,
+    /// and the tasks runnable now.
+    #[cfg(feature = "go_test")]
+    unsafe fn sys_sysinfo_r4(info_ptr: u64, info_len: u64) -> u64 {
+        if info_len < R4_SYSINFO_SIZE as u64 || !user_range_ok(info_ptr, R4_SYSINFO_SIZE) {
             return 0xFFFF_FFFF_FFFF_FFFF;
         }
-        if !r4_can_control_task(R4_CURRENT, target) {
+        let [load1, load5, load15] = cputime::cpu_loads();
+        let fields = [
+            R4_TICKS * R4_TICK_US,
+            cputime::cpu_idle_us(),
+            cputime::cpu_user_us(),
+            cputime::cpu_kernel_us(),
+            load1,
+            load5,
+            load15,
+            cputime::cpu_runnable(),
+        ];
+        let mut out = [0u8; R4_SYSINFO_SIZE];
+        for (idx, field) in fields.iter().enumerate() {
+            out[idx * 8..idx * 8 + 8].copy_from_slice(&field.to_le_bytes());
+        }
+        if copyout_user(info_ptr, &out, R4_SYSINFO_SIZE).is_err() {
+            return 0xFFFF_FFFF_FFFF_FFFF;
+        }
+        0
+    }
+
+    unsafe fn sys_isolation_config_r4(tid: u64, cfg_ptr: u64, cfg_len: u64) -> u64 {
+        let target = match r4_task_lookup(tid) {
+            Some(target) if target < R4_NUM_TASKS => target,
+            _ => return 0xFFFF_FFFF_FFFF_FFFF,
+        };
+        if !r4_can_control_task(r4_current(), target) {
             return 0xFFFF_FFFF_FFFF_FFFF;
         }
-        let (domain, flags, fd_limit, socket_limit, endpoint_limit) =
+        let R4IsolationConfig { domain, flags, fd_limit, socket_limit, endpoint_limit, mem_limits } =
             match r4_copy_isolation_config(cfg_ptr, cfg_len) {
                 Some(v) => v,
                 None => return 0xFFFF_FFFF_FFFF_FFFF,
             };
+        // A task hands on only the capabilities it holds, so none can be
+        // raised past its controller's.
+        if flags & !R4_TASKS[r4_current()].cap_flags != 0 {
+            return 0xFFFF_FFFF_FFFF_FFFF;
+        }
         if R4_TASKS[target].fd_count > fd_limit as usize
             || R4_TASKS[target].socket_count > socket_limit as usize
             || R4_TASKS[target].endpoint_count > endpoint_limit as usize
         {
             return 0xFFFF_FFFF_FFFF_FFFF;
         }
+        if let Some((hard, soft)) = mem_limits {
+            if !vm::vm_set_limits(R4_TASKS[target].space, hard, soft) {
+                return 0xFFFF_FFFF_FFFF_FFFF;
+            }
+        }
         R4_TASKS[target].isolation_domain = domain;
         R4_TASKS[target].cap_flags = flags;
         R4_TASKS[target].fd_limit = fd_limit;
@@ -2575,23 +4970,138 @@ cfg_r4! {
         0
     }
 
-    unsafe fn sys_sched_set_r4(tid: u64, class: u64) -> u64 {
-        let target = tid as usize;
-        if target >= R4_NUM_TASKS {
-            return 0xFFFF_FFFF_FFFF_FFFF;
+    /// Bandwidth of a reservation, in parts per million of one CPU.
+    #[inline(always)]
+    fn r4_deadline_bandwidth(runtime_us: u64, period_us: u64) -> u64 {
+        runtime_us * 1_000_000 / period_us
+    }
+
+    /// Bandwidth held by live deadline tasks other than `exclude`.
+    unsafe fn r4_deadline_admitted(exclude: usize) -> u64 {
+        let mut total = 0;
+        for tid in 0..r4_sched_span() {
+            if tid != exclude
+                && r4_task_live(tid)
+                && R4_TASKS[tid].sched_class == R4_SCHED_CLASS_DEADLINE
+            {
+                total += r4_deadline_bandwidth(R4_TASKS[tid].dl_runtime_us, R4_TASKS[tid].dl_period_us);
+            }
+        }
+        total
+    }
+
+    unsafe fn r4_copy_deadline_params(params_ptr: u64) -> Option<(u64, u64)> {
+        let mut raw = [0u8; R4_DEADLINE_PARAMS_SIZE];
+        if copyin_user(&mut raw, params_ptr, R4_DEADLINE_PARAMS_SIZE).is_err() {
+            return None;
+        }
+        let runtime_us = u64::from_le_bytes(raw[0..8].try_into().ok()?);
+        let period_us = u64::from_le_bytes(raw[8..16].try_into().ok()?);
+        if runtime_us == 0
+            || runtime_us > period_us
+            || period_us > R4_DEADLINE_PERIOD_MAX_US
+            || runtime_us % R4_TICK_US != 0
+            || period_us % R4_TICK_US != 0
+        {
+            return None;
         }
+        Some((runtime_us, period_us))
+    }
+
+    /// Class `2` reads `{runtime_us, period_us}` from `params_ptr`. Both must
+    /// be whole ticks with runtime no longer than the period. The reservation
+    /// starts a fresh period with a full budget and cleared counters.
+    unsafe fn sys_sched_set_r4(tid: u64, class: u64, params_ptr: u64) -> u64 {
+        let target = match r4_task_lookup(tid) {
+            Some(target) => target,
+            None => return 0xFFFF_FFFF_FFFF_FFFF,
+        };
         if R4_TASKS[target].state == R4State::Dead {
             return 0xFFFF_FFFF_FFFF_FFFF;
         }
+        if class == R4_SCHED_SET_AFFINITY {
+            return r4_set_affinity(target, params_ptr);
+        }
         let next_class = match class as u8 {
             R4_SCHED_CLASS_BEST_EFFORT => R4_SCHED_CLASS_BEST_EFFORT,
             R4_SCHED_CLASS_CRITICAL => R4_SCHED_CLASS_CRITICAL,
+            R4_SCHED_CLASS_DEADLINE => R4_SCHED_CLASS_DEADLINE,
             _ => return 0xFFFF_FFFF_FFFF_FFFF,
         };
-        if !r4_can_control_task(R4_CURRENT, target) {
+        if !r4_can_control_task(r4_current(), target) {
+            return 0xFFFF_FFFF_FFFF_FFFF;
+        }
+        if next_class != R4_SCHED_CLASS_DEADLINE {
+            r4_sched_lock();
+            R4_TASKS[target].sched_class = next_class;
+            r4_deadline_reset(target);
+            r4_sched_unlock();
+            return 0;
+        }
+
+        let (runtime_us, period_us) = match r4_copy_deadline_params(params_ptr) {
+            Some(v) => v,
+            None => return 0xFFFF_FFFF_FFFF_FFFF,
+        };
+        if r4_deadline_admitted(target) + r4_deadline_bandwidth(runtime_us, period_us)
+            > R4_DEADLINE_CAPACITY_PPM
+        {
+            return R4_ERR_SCHED_CAPACITY;
+        }
+        r4_sched_lock();
+        r4_deadline_reset(target);
+        R4_TASKS[target].sched_class = R4_SCHED_CLASS_DEADLINE;
+        R4_TASKS[target].dl_runtime_us = runtime_us;
+        R4_TASKS[target].dl_period_us = period_us;
+        R4_TASKS[target].dl_budget_us = runtime_us;
+        R4_TASKS[target].dl_deadline = R4_TICKS + period_us / R4_TICK_US;
+        r4_sched_unlock();
+        0
+    }
+
+    /// Restrict `target` to the CPUs in `mask`. Bits for CPUs that are not
+    /// online are kept, but the mask must name at least one online CPU.
+    unsafe fn r4_set_affinity(target: usize, mask: u64) -> u64 {
+        if mask & r4_cpus_online() == 0 || !r4_can_control_task(r4_current(), target) {
+            return 0xFFFF_FFFF_FFFF_FFFF;
+        }
+        r4_sched_lock();
+        R4_TASKS[target].affinity = mask;
+        // A task left on a CPU it no longer allows moves: a running one at
+        // that CPU's next reschedule, which may be this CPU's own IPI.
+        #[cfg(feature = "go_test")]
+        {
+            let cpu = R4_TASKS[target].cpu;
+            if !r4_cpu_allowed(target, cpu) {
+                if R4_TASKS[target].state == R4State::Running {
+                    smp::smp_send(cpu, smp::SMP_IPI_RESCHED);
+                } else {
+                    r4_set_cpu(target, (mask & r4_cpus_online()).trailing_zeros() as usize);
+                }
+            }
+        }
+        r4_sched_unlock();
+        0
+    }
+
+    unsafe fn sys_sched_nice_r4(tid: u64, nice: u64) -> u64 {
+        let target = match r4_task_lookup(tid) {
+            Some(target) => target,
+            None => return 0xFFFF_FFFF_FFFF_FFFF,
+        };
+        if R4_TASKS[target].state == R4State::Dead {
+            return 0xFFFF_FFFF_FFFF_FFFF;
+        }
+        let nice = nice as i64;
+        if !(R4_NICE_MIN..=R4_NICE_MAX).contains(&nice) {
+            return 0xFFFF_FFFF_FFFF_FFFF;
+        }
+        if !r4_can_control_task(r4_current(), target) {
             return 0xFFFF_FFFF_FFFF_FFFF;
         }
-        R4_TASKS[target].sched_class = next_class;
+        r4_sched_lock();
+        R4_TASKS[target].nice = nice as i8;
+        r4_sched_unlock();
         0
     }
 
@@ -2600,17 +5110,16 @@ cfg_r4! {
         {
             if entry >= 0x0000_8000_0000_0000 { return 0xFFFF_FFFF_FFFF_FFFF; }
             if !runtime::isolation::under_quota(
-                R4_TASKS[R4_CURRENT].thread_count,
+                R4_TASKS[r4_current()].thread_count,
                 MAX_THREADS_PER_PROC,
             ) {
                 return 0xFFFF_FFFF_FFFF_FFFF;
             }
-            if !runtime::isolation::under_quota(R4_THREADS_CREATED, MAX_THREADS_GLOBAL) {
+            if r4_live_threads() >= limits::max_threads() {
                 return 0xFFFF_FFFF_FFFF_FFFF;
             }
-            let tid = R4_TASKS[R4_CURRENT].thread_count as u64;
-            R4_TASKS[R4_CURRENT].thread_count += 1;
-            R4_THREADS_CREATED += 1;
+            let tid = R4_TASKS[r4_current()].thread_count as u64;
+            R4_TASKS[r4_current()].thread_count += 1;
             return tid;
         }
         #[cfg(all(feature = "go_test", not(feature = "quota_threads_test")))]
@@ -2618,32 +5127,427 @@ cfg_r4! {
             if entry >= 0x0000_8000_0000_0000 {
                 return 0xFFFF_FFFF_FFFF_FFFF;
             }
-            if !R4_TASKS[R4_CURRENT].can_spawn {
+            if !r4_current_has_cap(R4_TASK_CAP_SPAWN) {
+                return 0xFFFF_FFFF_FFFF_FFFF;
+            }
+            if !r4_user_readable(entry, 1) {
+                return 0xFFFF_FFFF_FFFF_FFFF;
+            }
+            if r4_live_threads() >= limits::max_threads() {
+                return 0xFFFF_FFFF_FFFF_FFFF;
+            }
+            let tid = match r4_find_spawn_slot() {
+                Some(tid) => tid,
+                None => return 0xFFFF_FFFF_FFFF_FFFF,
+            };
+            if tid >= R4_TASKS.len() {
+                return 0xFFFF_FFFF_FFFF_FFFF;
+            }
+            r4_init_task(tid, entry, r4_stack_top_for_slot(tid), r4_current());
+            R4_TASKS[tid].thread = true;
+            r4_set_state(tid, R4State::Ready);
+            r4_task_id(tid)
+        }
+        #[cfg(all(not(feature = "quota_threads_test"), not(feature = "go_test")))]
+        {
+            let _ = entry;
+            0xFFFF_FFFF_FFFF_FFFF
+        }
+    }
+
+    /// Duplicate the calling task. The child resumes after the same syscall
+    /// with rax = 0, sees the parent's memory copy-on-write and holds the
+    /// parent's fds, sockets and endpoints with the same rights. It is
+    /// reaped through `sys_wait_r4` like any other child.
+    unsafe fn sys_fork_r4(frame: *mut u64) -> u64 {
+        #[cfg(feature = "go_test")]
+        {
+            let parent = r4_current();
+            if !r4_current_has_cap(R4_TASK_CAP_SPAWN) {
+                return 0xFFFF_FFFF_FFFF_FFFF;
+            }
+            let tid = match r4_find_spawn_slot() {
+                Some(tid) => tid,
+                None => return 0xFFFF_FFFF_FFFF_FFFF,
+            };
+            let space = match vm::vm_space_fork(R4_TASKS[parent].space) {
+                Some(space) => space,
+                None => return 0xFFFF_FFFF_FFFF_FFFF,
+            };
+            R4_TASKS[tid].space = space;
+            r4_init_task(tid, *frame.add(17), *frame.add(20), parent);
+            r4_save_frame(frame, tid);
+            R4_TASKS[tid].saved_frame[14] = 0;
+            R4_TASKS[tid].sched_class = r4_inherited_class(parent);
+            R4_TASKS[tid].nice = R4_TASKS[parent].nice;
+            R4_TASKS[tid].affinity = R4_TASKS[parent].affinity;
+            R4_TASKS[tid].fs_base = R4_TASKS[parent].fs_base;
+            R4_TASKS[tid].gs_base = R4_TASKS[parent].gs_base;
+            r4_share_fds(parent, tid);
+            net::r4_share_sockets(parent, tid);
+            r4_share_endpoints(parent, tid);
+            r4_share_shm(parent, tid);
+            r4_set_state(tid, R4State::Ready);
+            r4_task_id(tid)
+        }
+        #[cfg(not(feature = "go_test"))]
+        {
+            let _ = frame;
+            0xFFFF_FFFF_FFFF_FFFF
+        }
+    }
+
+    /// Create a task from a 48-byte argument block: flags, entry, arg,
+    /// stack, tls, join_ptr. `R4_CLONE_VM` shares the caller's space (a
+    /// thread); without it the child gets a copy-on-write copy.
+    /// `R4_CLONE_FILES` shares handles, `R4_CLONE_SETTLS` sets the FS base
+    /// and `R4_CLONE_DETACHED` reaps the child on exit instead of leaving
+    /// it for `sys_wait_r4`. A non-zero `join_ptr` is cleared on exit.
+    unsafe fn sys_clone_r4(args_ptr: u64, args_len: u64) -> u64 {
+        #[cfg(feature = "go_test")]
+        {
+            let parent = r4_current();
+            if !r4_current_has_cap(R4_TASK_CAP_SPAWN) {
+                return 0xFFFF_FFFF_FFFF_FFFF;
+            }
+            if args_len < R4_CLONE_ARGS_SIZE as u64 {
+                return 0xFFFF_FFFF_FFFF_FFFF;
+            }
+            let mut raw = [0u8; R4_CLONE_ARGS_SIZE];
+            if copyin_user(&mut raw, args_ptr, R4_CLONE_ARGS_SIZE).is_err() {
+                return 0xFFFF_FFFF_FFFF_FFFF;
+            }
+            let mut words = [0u64; R4_CLONE_ARGS_SIZE / 8];
+            for (i, word) in words.iter_mut().enumerate() {
+                let mut b = [0u8; 8];
+                b.copy_from_slice(&raw[i * 8..i * 8 + 8]);
+                *word = u64::from_le_bytes(b);
+            }
+            let [flags, entry, arg, stack, tls, join_ptr] = words;
+            if flags & !R4_CLONE_MASK != 0 {
+                return 0xFFFF_FFFF_FFFF_FFFF;
+            }
+            if !r4_user_readable(entry, 1) {
+                return 0xFFFF_FFFF_FFFF_FFFF;
+            }
+            if stack < 16 || stack & 0xF != 0 || !r4_user_writable(stack - 8, 8) {
+                return 0xFFFF_FFFF_FFFF_FFFF;
+            }
+            if flags & R4_CLONE_SETTLS != 0 && tls >= 0x0000_8000_0000_0000 {
+                return 0xFFFF_FFFF_FFFF_FFFF;
+            }
+            if join_ptr != 0
+                && (join_ptr & 0x7 != 0 || !r4_user_writable(join_ptr, 8))
+            {
+                return 0xFFFF_FFFF_FFFF_FFFF;
+            }
+            if flags & R4_CLONE_VM != 0 && r4_live_threads() >= limits::max_threads() {
+                return 0xFFFF_FFFF_FFFF_FFFF;
+            }
+            let tid = match r4_find_spawn_slot() {
+                Some(tid) => tid,
+                None => return 0xFFFF_FFFF_FFFF_FFFF,
+            };
+            if flags & R4_CLONE_VM == 0 {
+                R4_TASKS[tid].space = match vm::vm_space_fork(R4_TASKS[parent].space) {
+                    Some(space) => space,
+                    None => return 0xFFFF_FFFF_FFFF_FFFF,
+                };
+            }
+            r4_init_task(tid, entry, stack, parent);
+            R4_TASKS[tid].saved_frame[9] = arg;  // RDI
+            R4_TASKS[tid].sched_class = r4_inherited_class(parent);
+            R4_TASKS[tid].nice = R4_TASKS[parent].nice;
+            R4_TASKS[tid].affinity = R4_TASKS[parent].affinity;
+            R4_TASKS[tid].fs_base = if flags & R4_CLONE_SETTLS != 0 {
+                tls
+            } else {
+                R4_TASKS[parent].fs_base
+            };
+            R4_TASKS[tid].gs_base = R4_TASKS[parent].gs_base;
+            R4_TASKS[tid].detached = flags & R4_CLONE_DETACHED != 0;
+            R4_TASKS[tid].join_ptr = join_ptr;
+            R4_TASKS[tid].thread = flags & R4_CLONE_VM != 0;
+            if flags & R4_CLONE_FILES != 0 {
+                r4_share_fds(parent, tid);
+                net::r4_share_sockets(parent, tid);
+                r4_share_endpoints(parent, tid);
+                r4_share_shm(parent, tid);
+            }
+            r4_set_state(tid, R4State::Ready);
+            r4_task_id(tid)
+        }
+        #[cfg(not(feature = "go_test"))]
+        {
+            let _ = (args_ptr, args_len);
+            0xFFFF_FFFF_FFFF_FFFF
+        }
+    }
+
+    // Packages spawned from the package store, kept for later spawns: their
+    // frames are carved from the memory map and never handed back.
+    #[cfg(feature = "go_test")]
+    const R4_PKG_CACHE_MAX: usize = 4;
+    #[cfg(feature = "go_test")]
+    const R4_PKG_HEADER_SIZE: usize = 64;
+
+    #[cfg(feature = "go_test")]
+    #[derive(Clone, Copy)]
+    struct R4Package {
+        name: [u8; R4_SPAWN_PATH_MAX],
+        name_len: usize,
+        image: &'static [u8],
+    }
+
+    #[cfg(feature = "go_test")]
+    static mut R4_PKG_CACHE: [Option<R4Package>; R4_PKG_CACHE_MAX] = [None; R4_PKG_CACHE_MAX];
+
+    /// Read `<name>.pkg` from the package store on the block disk and return
+    /// its payload once the header and payload hash check out. The payload
+    /// is an executable image; the loader checks its signature as it does a
+    /// catalog image's.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_pkg_lookup(name: &[u8]) -> Option<&'static [u8]> {
+        for pkg in R4_PKG_CACHE.iter().flatten() {
+            if &pkg.name[..pkg.name_len] == name {
+                return Some(pkg.image);
+            }
+        }
+        if !storage::r4_storage_available() {
+            return None;
+        }
+        let slot = (0..R4_PKG_CACHE_MAX).find(|&i| R4_PKG_CACHE[i].is_none())?;
+        let (sector, size) = pkg_find(name)?;
+        if size <= R4_PKG_HEADER_SIZE {
+            return None;
+        }
+        let pkg = pkg_read(sector, size)?;
+        let magic = u32::from_le_bytes(pkg[0..4].try_into().ok()?);
+        let bin_size = u32::from_le_bytes(pkg[4..8].try_into().ok()?) as usize;
+        if magic != runtime::storage::PKG_MAGIC_V1 || bin_size == 0 || bin_size > size - R4_PKG_HEADER_SIZE {
+            return None;
+        }
+        let image = &pkg[R4_PKG_HEADER_SIZE..R4_PKG_HEADER_SIZE + bin_size];
+        if sha256_digest(image) != pkg[32..64] {
+            serial_write(b"PKG: bad hash\n");
+            return None;
+        }
+        let mut entry = R4Package { name: [0; R4_SPAWN_PATH_MAX], name_len: name.len(), image };
+        entry.name[..name.len()].copy_from_slice(name);
+        R4_PKG_CACHE[slot] = Some(entry);
+        Some(image)
+    }
+
+    /// Resolve a spawn path to an image, by `/bin/<name>` or by the bare
+    /// package name: `<name>.pkg` in the package store first, then the
+    /// built-in Go lane programs, then the X1 corpus on the `compat_real`
+    /// lane.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_exec_lookup(path: &[u8]) -> Option<&'static [u8]> {
+        let name = path.strip_prefix(b"/bin/").unwrap_or(path);
+        if let Some(image) = r4_pkg_lookup(name) {
+            return Some(image);
+        }
+        if let Some(program) = R4_PROGRAMS.iter().find(|program| program.name == name) {
+            return Some(program.image);
+        }
+        #[cfg(feature = "compat_real_test")]
+        if let Some(app) = COMPAT_REAL_APPS.iter().find(|app| app.name == name) {
+            return Some(app.image);
+        }
+        None
+    }
+
+    /// Copy a user array of `count` string pointers into `buf` from `*used`
+    /// on, recording each string's span without its NUL.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_copy_string_vector(
+        array_ptr: u64,
+        count: u64,
+        buf: &mut [u8],
+        used: &mut usize,
+        spans: &mut [(usize, usize)],
+    ) -> bool {
+        for (idx, span) in spans.iter_mut().take(count as usize).enumerate() {
+            let mut raw = [0u8; 8];
+            if copyin_user(&mut raw, array_ptr + idx as u64 * 8, 8).is_err() {
+                return false;
+            }
+            let string = match copyinstr_user(u64::from_le_bytes(raw), R4_SPAWN_STRING_MAX) {
+                Ok(string) => string,
+                Err(_) => return false,
+            };
+            let len = string.len() - 1;
+            if *used + len > buf.len() {
+                return false;
+            }
+            buf[*used..*used + len].copy_from_slice(&string[..len]);
+            *span = (*used, len);
+            *used += len;
+        }
+        true
+    }
+
+    /// Start a program in a new task from an 88-byte argument block: path,
+    /// path_len, argv, argc, envp, envc, then an isolation config in the
+    /// 40-byte form. The image loads into a fresh space whose memory limits
+    /// are set first, so the image counts against them. Capabilities can
+    /// only narrow the caller's. Returns the child's tid, which the caller
+    /// reaps through `sys_wait_r4`.
+    unsafe fn sys_spawn_r4(args_ptr: u64, args_len: u64) -> u64 {
+        #[cfg(feature = "go_test")]
+        {
+            if !r4_current_has_cap(R4_TASK_CAP_SPAWN) {
+                return 0xFFFF_FFFF_FFFF_FFFF;
+            }
+            if args_len < R4_SPAWN_ARGS_SIZE as u64 {
                 return 0xFFFF_FFFF_FFFF_FFFF;
             }
-            if !user_pages_ok(entry, 1, USER_PERM_READ) {
+            // The argument and environment strings are copied out of the
+            // caller into a pool frame held for this call only.
+            let frame = match vm::vm_frame_alloc() {
+                Some(phys) => phys,
+                None => return 0xFFFF_FFFF_FFFF_FFFF,
+            };
+            let strings = core::slice::from_raw_parts_mut(vm::vm_table(frame) as *mut u8, R4_STARTUP_MAX_BYTES);
+            let ret = r4_spawn(args_ptr, strings);
+            vm::vm_frame_release(frame);
+            ret
+        }
+        #[cfg(not(feature = "go_test"))]
+        {
+            let _ = (args_ptr, args_len);
+            0xFFFF_FFFF_FFFF_FFFF
+        }
+    }
+
+    /// The body of `sys_spawn_r4`, with `buf` holding the copied strings.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_spawn(args_ptr: u64, buf: &mut [u8]) -> u64 {
+        let parent = r4_current();
+        let mut raw = [0u8; R4_SPAWN_HEADER_SIZE];
+        if copyin_user(&mut raw, args_ptr, R4_SPAWN_HEADER_SIZE).is_err() {
+            return 0xFFFF_FFFF_FFFF_FFFF;
+        }
+        let mut words = [0u64; R4_SPAWN_HEADER_SIZE / 8];
+        for (i, word) in words.iter_mut().enumerate() {
+            let mut b = [0u8; 8];
+            b.copy_from_slice(&raw[i * 8..i * 8 + 8]);
+            *word = u64::from_le_bytes(b);
+        }
+        let [path_ptr, path_len, argv_ptr, argc, envp_ptr, envc] = words;
+        if path_len == 0 || path_len > R4_SPAWN_PATH_MAX as u64 {
+            return 0xFFFF_FFFF_FFFF_FFFF;
+        }
+        if argc.saturating_add(envc) > R4_STARTUP_MAX_ARGS as u64 {
+            return 0xFFFF_FFFF_FFFF_FFFF;
+        }
+        let mut path = [0u8; R4_SPAWN_PATH_MAX];
+        if copyin_user(&mut path[..path_len as usize], path_ptr, path_len as usize).is_err() {
+            return 0xFFFF_FFFF_FFFF_FFFF;
+        }
+        let path = &path[..path_len as usize];
+        let R4IsolationConfig { domain, flags, fd_limit, socket_limit, endpoint_limit, mem_limits } =
+            match r4_copy_isolation_config(
+                args_ptr + R4_SPAWN_HEADER_SIZE as u64,
+                R4_ISOLATION_CONFIG_MEM_SIZE as u64,
+            ) {
+                Some(v) => v,
+                None => return 0xFFFF_FFFF_FFFF_FFFF,
+            };
+        if flags & !R4_TASKS[parent].cap_flags != 0 {
+            return 0xFFFF_FFFF_FFFF_FFFF;
+        }
+        let argc = argc as usize;
+        let mut spans = [(0usize, 0usize); R4_STARTUP_MAX_ARGS];
+        let mut used = 0usize;
+        if !r4_copy_string_vector(argv_ptr, argc as u64, buf, &mut used, &mut spans[..argc])
+            || !r4_copy_string_vector(envp_ptr, envc, buf, &mut used, &mut spans[argc..])
+        {
+            return 0xFFFF_FFFF_FFFF_FFFF;
+        }
+        let image = match r4_exec_lookup(path) {
+            Some(image) => image,
+            None => return 0xFFFF_FFFF_FFFF_FFFF,
+        };
+
+        let (hard, soft) = mem_limits.unwrap_or((0, 0));
+        let (span_low, span_high) = match elf_v1_load_span(image) {
+            Some(span) => span,
+            None => return 0xFFFF_FFFF_FFFF_FFFF,
+        };
+        if hard != 0 && (span_high - span_low + R4_USER_STACK_MAX) / 4096 > hard {
+            return R4_ERR_MEM_LIMIT;
+        }
+
+        let tid = match r4_find_spawn_slot() {
+            Some(tid) => tid,
+            None => return 0xFFFF_FFFF_FFFF_FFFF,
+        };
+        let space = match vm::vm_space_create() {
+            Some(space) => space,
+            None => return 0xFFFF_FFFF_FFFF_FFFF,
+        };
+        let info = if vm::vm_set_limits(space, hard, soft) {
+            r4_load_elf(space, image)
+        } else {
+            None
+        };
+        // One stack, at the child's slot; further threads come from
+        // `sys_clone_r4` with their own stacks.
+        let stack = r4_stack_top_for_slot(tid);
+        let info = match info {
+            Some(info) if vm::vm_stack_reserve(space, stack, R4_USER_STACK_MAX) => info,
+            _ => {
+                vm::vm_space_release(space);
                 return 0xFFFF_FFFF_FFFF_FFFF;
             }
-            let tid = match r4_find_spawn_slot() {
-                Some(tid) => tid,
-                None => return 0xFFFF_FFFF_FFFF_FFFF,
-            };
-            if tid >= R4_MAX_TASKS {
+        };
+        R4_TASKS[tid].space = space;
+        r4_init_task(tid, info.entry, stack, parent);
+        R4_TASKS[tid].isolation_domain = domain;
+        R4_TASKS[tid].cap_flags = flags;
+        R4_TASKS[tid].fd_limit = fd_limit;
+        R4_TASKS[tid].socket_limit = socket_limit;
+        R4_TASKS[tid].endpoint_limit = endpoint_limit;
+        R4_TASKS[tid].sched_class = r4_inherited_class(parent);
+        R4_TASKS[tid].nice = R4_TASKS[parent].nice;
+        R4_TASKS[tid].affinity = R4_TASKS[parent].affinity;
+
+        let mut strings: [&[u8]; R4_STARTUP_MAX_ARGS] = [&[]; R4_STARTUP_MAX_ARGS];
+        for (string, &(start, len)) in strings.iter_mut().zip(spans.iter()) {
+            *string = &buf[start..start + len];
+        }
+        let count = argc + envc as usize;
+        let prev = vm::vm_enter_space(space);
+        let user_sp = if r4_setup_initial_tls(tid, &info) {
+            r4_build_startup_stack(stack, &info, &strings[..argc], &strings[argc..count], path)
+        } else {
+            None
+        };
+        vm::vm_leave_space(prev);
+        let user_sp = match user_sp {
+            Some(sp) => sp,
+            None => {
+                r4_cleanup_task_resources(tid);
+                r4_set_state(tid, R4State::Dead);
                 return 0xFFFF_FFFF_FFFF_FFFF;
             }
-            r4_init_task(tid, entry, r4_stack_top_for_slot(tid), R4_CURRENT);
-            R4_TASKS[tid].state = R4State::Ready;
-            R4_THREADS_CREATED += 1;
-            tid as u64
-        }
-        #[cfg(all(not(feature = "quota_threads_test"), not(feature = "go_test")))]
-        {
-            let _ = entry;
-            0xFFFF_FFFF_FFFF_FFFF
-        }
-    }
-
-    unsafe fn sys_wait_r4(frame: *mut u64, pid: u64, status_ptr: u64, options: u64) {
+        };
+        R4_TASKS[tid].saved_frame[20] = user_sp;
+        r4_set_state(tid, R4State::Ready);
+        r4_task_id(tid)
+    }
+
+    /// With a `deadline` tick, gives up with `R4_ERR_TIMED_OUT` once it
+    /// passes and no child has exited.
+    unsafe fn sys_wait_r4(
+        frame: *mut u64,
+        pid: u64,
+        status_ptr: u64,
+        options: u64,
+        deadline: Option<u64>,
+    ) {
         if options != 0 {
             *frame.add(14) = 0xFFFF_FFFF_FFFF_FFFF;
             return;
@@ -2651,30 +5555,30 @@ cfg_r4! {
 
         let target = if pid == u64::MAX {
             -1
-        } else if (pid as usize) < R4_NUM_TASKS {
-            pid as i32
         } else {
-            *frame.add(14) = 0xFFFF_FFFF_FFFF_FFFF;
-            return;
+            match r4_task_lookup(pid) {
+                Some(tid) if tid < R4_NUM_TASKS => tid as i32,
+                _ => {
+                    *frame.add(14) = 0xFFFF_FFFF_FFFF_FFFF;
+                    return;
+                }
+            }
         };
 
-        if status_ptr != 0
-            && (!user_range_ok(status_ptr, 8)
-                || !user_pages_ok(status_ptr, 8, USER_PERM_WRITE))
-        {
+        if status_ptr != 0 && !user_range_ok(status_ptr, 8) {
             *frame.add(14) = 0xFFFF_FFFF_FFFF_FFFF;
             return;
         }
 
-        let cur = R4_CURRENT;
+        let cur = r4_current();
         if let Some(child) = r4_find_exited_child(cur, target) {
             if !r4_copy_wait_status(status_ptr, R4_TASKS[child].exit_status) {
                 *frame.add(14) = 0xFFFF_FFFF_FFFF_FFFF;
                 return;
             }
-            R4_TASKS[child].state = R4State::Dead;
+            r4_set_state(child, R4State::Dead);
             R4_TASKS[child].exit_status = 0;
-            *frame.add(14) = child as u64;
+            *frame.add(14) = r4_task_id(child);
             return;
         }
 
@@ -2683,23 +5587,15 @@ cfg_r4! {
             return;
         }
 
+        if deadline.is_some_and(|tick| tick <= R4_TICKS) {
+            *frame.add(14) = R4_ERR_TIMED_OUT;
+            return;
+        }
+
         r4_save_frame(frame, cur);
         R4_TASKS[cur].wait_target = target;
         R4_TASKS[cur].wait_status_ptr = status_ptr;
-        R4_TASKS[cur].block_count += 1;
-        R4_TASKS[cur].state = R4State::Blocked;
-        match r4_find_ready(cur) {
-            Some(tid) => { r4_switch_to(frame, tid); }
-            None => {
-                serial_write(b"R4: deadlock\n");
-                let kstack = &stack_top as *const u8 as u64;
-                *frame.add(17) = r4_all_done as *const () as u64;
-                *frame.add(18) = 0x08;
-                *frame.add(19) = 0x02;
-                *frame.add(20) = kstack;
-                *frame.add(21) = 0x10;
-            }
-        }
+        r4_block_current(frame, deadline, R4_ERR_TIMED_OUT);
     }
 }
 
@@ -2720,6 +5616,12 @@ cfg_r4! {
         waiter: i32, // task id blocked on recv, or -1
         owner_tid: usize,
         owner_rights: u8,
+        holders: u64,
+        // SHM object travelling with the buffered message, or -1.
+        #[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+        shm_grant: i32,
+        #[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+        shm_grant_rights: u8,
     }
 
     impl IpcEndpoint {
@@ -2729,7 +5631,20 @@ cfg_r4! {
             waiter: -1,
             owner_tid: 0,
             owner_rights: 0,
+            holders: 0,
+            #[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+            shm_grant: -1,
+            #[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+            shm_grant_rights: 0,
         };
+
+        #[cfg(feature = "go_test")]
+        #[inline(always)]
+        fn held_by(&self, tid: usize) -> bool {
+            self.active
+                && (self.owner_tid == tid
+                    || self.holders & runtime::isolation::holder_bit(tid) != 0)
+        }
     }
 
     static mut R4_ENDPOINTS: [IpcEndpoint; R4_MAX_ENDPOINTS] =
@@ -2739,9 +5654,10 @@ cfg_r4! {
     unsafe fn r4_endpoint_owner_has_right(ep: usize, right: u8) -> bool {
         #[cfg(feature = "go_test")]
         {
-            runtime::isolation::owner_has_right(
+            runtime::isolation::holder_has_right(
                 R4_ENDPOINTS[ep].owner_tid,
-                R4_CURRENT,
+                R4_ENDPOINTS[ep].holders,
+                r4_current(),
                 R4_ENDPOINTS[ep].owner_rights,
                 right,
             )
@@ -2754,24 +5670,63 @@ cfg_r4! {
         }
     }
 
+    #[cfg(feature = "go_test")]
+    #[inline(always)]
+    unsafe fn r4_endpoint_held_by(ep: usize, tid: usize) -> bool {
+        R4_ENDPOINTS[ep].held_by(tid)
+    }
+
+    /// Fork: the child holds every endpoint the parent holds, with the same
+    /// rights.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_share_endpoints(parent: usize, child: usize) {
+        for endpoint in R4_ENDPOINTS.iter_mut() {
+            if endpoint.held_by(parent) {
+                endpoint.holders |= runtime::isolation::holder_bit(child);
+            }
+        }
+        R4_TASKS[child].endpoint_count = R4_TASKS[parent].endpoint_count;
+    }
+
+    /// Drop `tid`'s hold on an endpoint. Endpoints shared through fork stay
+    /// alive while another holder remains and ownership moves to that
+    /// holder; the last holder's drop closes the endpoint and fails its
+    /// blocked receiver.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_endpoint_drop_holder(ep: usize, tid: usize) {
+        R4_ENDPOINTS[ep].holders &= !runtime::isolation::holder_bit(tid);
+        if R4_ENDPOINTS[ep].owner_tid != tid {
+            return;
+        }
+        if let Some(next) = runtime::isolation::next_holder(R4_ENDPOINTS[ep].holders) {
+            R4_ENDPOINTS[ep].owner_tid = next;
+            R4_ENDPOINTS[ep].holders &= !runtime::isolation::holder_bit(next);
+            return;
+        }
+        let waiter = R4_ENDPOINTS[ep].waiter;
+        if waiter >= 0 {
+            let wt = waiter as usize;
+            if wt < R4_NUM_TASKS && R4_TASKS[wt].state == R4State::Blocked {
+                R4_TASKS[wt].recv_ep = 0;
+                R4_TASKS[wt].recv_buf = 0;
+                R4_TASKS[wt].recv_cap = 0;
+                R4_TASKS[wt].saved_frame[14] = 0xFFFF_FFFF_FFFF_FFFF;
+                r4_set_state(wt, R4State::Ready);
+            }
+        }
+        r4_shm_drop_grant(ep);
+        if R4_MEM_SUPERVISOR == ep as i32 {
+            R4_MEM_SUPERVISOR = -1;
+        }
+        R4_ENDPOINTS[ep] = IpcEndpoint::EMPTY;
+    }
+
     #[cfg(feature = "go_test")]
     unsafe fn r4_release_owned_endpoints(owner_tid: usize) {
         for ep in 0..R4_MAX_ENDPOINTS {
-            if !R4_ENDPOINTS[ep].active || R4_ENDPOINTS[ep].owner_tid != owner_tid {
-                continue;
-            }
-            let waiter = R4_ENDPOINTS[ep].waiter;
-            if waiter >= 0 {
-                let wt = waiter as usize;
-                if wt < R4_NUM_TASKS && R4_TASKS[wt].state == R4State::Blocked {
-                    R4_TASKS[wt].recv_ep = 0;
-                    R4_TASKS[wt].recv_buf = 0;
-                    R4_TASKS[wt].recv_cap = 0;
-                    R4_TASKS[wt].saved_frame[14] = 0xFFFF_FFFF_FFFF_FFFF;
-                    R4_TASKS[wt].state = R4State::Ready;
-                }
+            if r4_endpoint_held_by(ep, owner_tid) {
+                r4_endpoint_drop_holder(ep, owner_tid);
             }
-            R4_ENDPOINTS[ep] = IpcEndpoint::EMPTY;
         }
         if owner_tid < R4_NUM_TASKS {
             R4_TASKS[owner_tid].endpoint_count = 0;
@@ -2782,12 +5737,12 @@ cfg_r4! {
         #[cfg(any(feature = "quota_endpoints_test", feature = "go_test"))]
         {
             let limit = if cfg!(feature = "go_test") {
-                R4_TASKS[R4_CURRENT].endpoint_limit as usize
+                R4_TASKS[r4_current()].endpoint_limit as usize
             } else {
                 MAX_ENDPOINTS_PER_PROC
             };
             if !runtime::isolation::under_quota(
-                R4_TASKS[R4_CURRENT].endpoint_count,
+                R4_TASKS[r4_current()].endpoint_count,
                 limit,
             ) {
                 return 0xFFFF_FFFF_FFFF_FFFF;
@@ -2798,9 +5753,10 @@ cfg_r4! {
                     R4_ENDPOINTS[i].has_msg = false;
                     R4_ENDPOINTS[i].msg_len = 0;
                     R4_ENDPOINTS[i].waiter = -1;
-                    R4_ENDPOINTS[i].owner_tid = R4_CURRENT;
+                    R4_ENDPOINTS[i].owner_tid = r4_current();
                     R4_ENDPOINTS[i].owner_rights = R4_EP_RIGHT_RECV | R4_EP_RIGHT_CONTROL;
-                    R4_TASKS[R4_CURRENT].endpoint_count += 1;
+                    R4_ENDPOINTS[i].holders = 0;
+                    R4_TASKS[r4_current()].endpoint_count += 1;
                     return i as u64;
                 }
             }
@@ -2832,18 +5788,25 @@ cfg_r4! {
         if n > 0 {
             if copyin_user(&mut kbuf[..n], buf, n).is_err() { return 0xFFFF_FFFF_FFFF_FFFF; }
         }
-        R4_TASKS[R4_CURRENT].ipc_send_count += 1;
+        R4_TASKS[r4_current()].ipc_send_count += 1;
+        r4_ipc_deliver(ep, &mut kbuf[..n])
+    }
+
+    /// Hand a copied-in message to the endpoint's blocked receiver, or buffer
+    /// it. A pending SHM grant becomes a handle of whoever takes the message.
+    unsafe fn r4_ipc_deliver(ep: usize, kbuf: &mut [u8]) -> u64 {
+        let n = kbuf.len();
 
         // If someone is blocked on recv for this endpoint, deliver directly
         let waiter = R4_ENDPOINTS[ep].waiter;
         if waiter >= 0 {
             let wt = waiter as usize;
-            if wt >= R4_MAX_TASKS {
+            if wt >= R4_TASKS.len() {
                 R4_ENDPOINTS[ep].waiter = -1;
                 return 0xFFFF_FFFF_FFFF_FFFF;
             }
             // Stale waiter can happen if bookkeeping got out of sync.
-            if R4_TASKS[wt].state != R4State::Blocked || R4_TASKS[wt].recv_ep != endpoint {
+            if R4_TASKS[wt].state != R4State::Blocked || R4_TASKS[wt].recv_ep != ep as u64 {
                 R4_ENDPOINTS[ep].waiter = -1;
                 return 0xFFFF_FFFF_FFFF_FFFF;
             }
@@ -2851,11 +5814,24 @@ cfg_r4! {
             if (R4_TASKS[wt].recv_cap as usize) < n {
                 return 0xFFFF_FFFF_FFFF_FFFF;
             }
-            if copyout_user(R4_TASKS[wt].recv_buf, &kbuf[..n], n).is_err() {
+            #[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+            let granted = match r4_shm_take_grant(ep, wt, kbuf) {
+                Ok(granted) => granted,
+                Err(()) => return 0xFFFF_FFFF_FFFF_FFFF,
+            };
+            // The receive buffer lives in the waiter's address space.
+            let prev_cr3 = vm::vm_enter_space(R4_TASKS[wt].space);
+            let delivered = copyout_user(R4_TASKS[wt].recv_buf, &kbuf[..n], n).is_ok();
+            vm::vm_leave_space(prev_cr3);
+            if !delivered {
+                #[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+                if let Some(h) = granted {
+                    r4_shm_restore_grant(ep, h, wt);
+                }
                 return 0xFFFF_FFFF_FFFF_FFFF;
             }
             R4_TASKS[wt].saved_frame[14] = n as u64; // return value for recv
-            R4_TASKS[wt].state = R4State::Ready;
+            r4_set_state(wt, R4State::Ready);
             R4_TASKS[wt].ipc_recv_count += 1;
             R4_ENDPOINTS[ep].waiter = -1;
             return 0;
@@ -2868,7 +5844,15 @@ cfg_r4! {
         0
     }
 
-    unsafe fn sys_ipc_recv_r4(frame: *mut u64, endpoint: u64, buf: u64, cap: u64) {
+    /// With a `deadline` tick, gives up with `R4_ERR_TIMED_OUT` once it
+    /// passes and no message has arrived.
+    unsafe fn sys_ipc_recv_r4(
+        frame: *mut u64,
+        endpoint: u64,
+        buf: u64,
+        cap: u64,
+        deadline: Option<u64>,
+    ) {
         let ep = endpoint as usize;
         if ep >= R4_MAX_ENDPOINTS || !R4_ENDPOINTS[ep].active {
             *frame.add(14) = 0xFFFF_FFFF_FFFF_FFFF;
@@ -2896,44 +5880,521 @@ cfg_r4! {
                 *frame.add(14) = 0xFFFF_FFFF_FFFF_FFFF;
                 return;
             }
-            if copyout_user(buf, &R4_ENDPOINTS[ep].msg_data[..n], n).is_err() {
+            let kbuf = R4_ENDPOINTS[ep].msg_data;
+            #[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+            let mut kbuf = kbuf;
+            #[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+            let granted = match r4_shm_take_grant(ep, r4_current(), &mut kbuf[..n]) {
+                Ok(granted) => granted,
+                Err(()) => {
+                    *frame.add(14) = 0xFFFF_FFFF_FFFF_FFFF;
+                    return;
+                }
+            };
+            if copyout_user(buf, &kbuf[..n], n).is_err() {
+                #[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+                if let Some(h) = granted {
+                    r4_shm_restore_grant(ep, h, r4_current());
+                }
                 *frame.add(14) = 0xFFFF_FFFF_FFFF_FFFF;
                 return;
             }
             R4_ENDPOINTS[ep].has_msg = false;
-            R4_TASKS[R4_CURRENT].ipc_recv_count += 1;
+            R4_TASKS[r4_current()].ipc_recv_count += 1;
             *frame.add(14) = n as u64;
             return;
         }
 
-        // A second waiter on the same endpoint is rejected explicitly.
-        if R4_ENDPOINTS[ep].waiter >= 0 && R4_ENDPOINTS[ep].waiter != R4_CURRENT as i32 {
-            *frame.add(14) = 0xFFFF_FFFF_FFFF_FFFF;
-            return;
+        // A second waiter on the same endpoint is rejected explicitly.
+        if R4_ENDPOINTS[ep].waiter >= 0 && R4_ENDPOINTS[ep].waiter != r4_current() as i32 {
+            *frame.add(14) = 0xFFFF_FFFF_FFFF_FFFF;
+            return;
+        }
+
+        if deadline.is_some_and(|tick| tick <= R4_TICKS) {
+            *frame.add(14) = R4_ERR_TIMED_OUT;
+            return;
+        }
+
+        // No message â€” block current task and switch
+        R4_TASKS[r4_current()].recv_ep = endpoint;
+        R4_TASKS[r4_current()].recv_buf = buf;
+        R4_TASKS[r4_current()].recv_cap = cap_n as u64;
+        r4_save_frame(frame, r4_current());
+        R4_ENDPOINTS[ep].waiter = r4_current() as i32;
+        r4_block_current(frame, deadline, R4_ERR_TIMED_OUT);
+    }
+}
+
+// --------------- R4: Timers --------------------------------------------------
+
+cfg_r4! {
+    #[cfg(feature = "go_test")]
+    #[derive(Clone, Copy)]
+    struct R4TimerHandle {
+        active: bool,
+        owner_tid: usize,
+        armed: bool,
+        deadline: u64,
+        period: u64,
+        // Expirations since the owner last waited.
+        expirations: u64,
+        waiter: i32,
+    }
+
+    #[cfg(feature = "go_test")]
+    impl R4TimerHandle {
+        const EMPTY: Self = Self {
+            active: false,
+            owner_tid: 0,
+            armed: false,
+            deadline: 0,
+            period: 0,
+            expirations: 0,
+            waiter: -1,
+        };
+    }
+
+    #[cfg(feature = "go_test")]
+    static mut R4_TIMER_HANDLES: [R4TimerHandle; R4_MAX_TIMER_HANDLES] =
+        [R4TimerHandle::EMPTY; R4_MAX_TIMER_HANDLES];
+
+    /// Nanoseconds on the monotonic clock, counted from the first R4 tick.
+    #[cfg(feature = "go_test")]
+    #[inline(always)]
+    unsafe fn r4_clock_ns() -> u64 {
+        R4_TICKS * R4_TICK_NS
+    }
+
+    /// The first tick at or after `ns` on the monotonic clock, or `None`
+    /// for `R4_DEADLINE_NONE`.
+    #[cfg(feature = "go_test")]
+    #[inline(always)]
+    fn r4_deadline_tick(ns: u64) -> Option<u64> {
+        if ns == R4_DEADLINE_NONE {
+            None
+        } else {
+            Some(ns.div_ceil(R4_TICK_NS))
+        }
+    }
+
+    /// Undo whatever a blocked task was waiting on, so a timeout leaves no
+    /// endpoint, wait or timer-handle waiter behind.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_cancel_block(tid: usize) {
+        R4_TASKS[tid].wait_target = R4_WAIT_NONE;
+        R4_TASKS[tid].wait_status_ptr = 0;
+        R4_TASKS[tid].polling = false;
+        let ep = R4_TASKS[tid].recv_ep as usize;
+        if ep < R4_MAX_ENDPOINTS && R4_ENDPOINTS[ep].waiter == tid as i32 {
+            R4_ENDPOINTS[ep].waiter = -1;
+        }
+        for handle in R4_TIMER_HANDLES.iter_mut() {
+            if handle.waiter == tid as i32 {
+                handle.waiter = -1;
+            }
+        }
+    }
+
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_wake_with(tid: usize, ret: u64) {
+        R4_TASKS[tid].saved_frame[14] = ret;
+        r4_set_state(tid, R4State::Ready);
+    }
+
+    /// Count one expiration, re-arm a periodic handle and hand the count to
+    /// a blocked waiter.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_timer_handle_fire(h: usize) {
+        let handle = &mut R4_TIMER_HANDLES[h];
+        if !handle.active || !handle.armed {
+            return;
+        }
+        handle.expirations += 1;
+        if handle.period > 0 {
+            // Missed periods come back round this loop and each count once.
+            handle.deadline += handle.period;
+            timer::timer_arm(handle.deadline, timer::TimerEvent::Handle(h));
+        } else {
+            handle.armed = false;
+        }
+        if handle.waiter >= 0 {
+            let wt = handle.waiter as usize;
+            handle.waiter = -1;
+            r4_wake_with(wt, handle.expirations);
+            handle.expirations = 0;
+        }
+    }
+
+    /// Re-scan every blocked poller in its own space and wake those with a
+    /// ready descriptor (or a poll error).
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_poll_rescan() {
+        for tid in 0..R4_NUM_TASKS {
+            if R4_TASKS[tid].state != R4State::Blocked || !R4_TASKS[tid].polling {
+                continue;
+            }
+            let prev_cr3 = vm::vm_enter_space(R4_TASKS[tid].space);
+            let ready = sys_poll_v1(R4_TASKS[tid].poll_fds, R4_TASKS[tid].poll_nfds, 0);
+            vm::vm_leave_space(prev_cr3);
+            if ready != 0 {
+                R4_TASKS[tid].polling = false;
+                r4_wake_with(tid, ready);
+            }
+        }
+    }
+
+    /// Run everything due by the current tick: poll readiness first, so a
+    /// descriptor that became ready by its deadline still counts, then
+    /// task timeouts and timer handles in deadline order.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_timers_expire() {
+        r4_poll_rescan();
+        while let Some(event) = timer::timer_pop_expired(R4_TICKS) {
+            match event {
+                timer::TimerEvent::Task(tid) => {
+                    if R4_TASKS[tid].state == R4State::Blocked {
+                        r4_cancel_block(tid);
+                        r4_wake_with(tid, R4_TASKS[tid].timeout_ret);
+                    }
+                }
+                timer::TimerEvent::Handle(h) => r4_timer_handle_fire(h),
+            }
+        }
+    }
+
+    /// Whether idling until the next tick can wake a task: a blocked task
+    /// has a timeout, waits on an armed handle, or is polling.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_timer_wakeup_pending() -> bool {
+        let timed = timer::timer_any(|event| match event {
+            timer::TimerEvent::Task(tid) => R4_TASKS[tid].state == R4State::Blocked,
+            timer::TimerEvent::Handle(h) => R4_TIMER_HANDLES[h].waiter >= 0,
+        });
+        timed || (0..R4_NUM_TASKS).any(|tid| {
+            R4_TASKS[tid].state == R4State::Blocked && R4_TASKS[tid].polling
+        })
+    }
+
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_release_owned_timers(tid: usize) {
+        for (h, handle) in R4_TIMER_HANDLES.iter_mut().enumerate() {
+            if handle.active && handle.owner_tid == tid {
+                timer::timer_cancel(timer::TimerEvent::Handle(h));
+                *handle = R4TimerHandle::EMPTY;
+            }
+        }
+        timer::timer_cancel(timer::TimerEvent::Task(tid));
+    }
+
+    #[cfg(feature = "go_test")]
+    unsafe fn sys_clock_now_r4() -> u64 {
+        r4_clock_ns()
+    }
+
+    /// Sleep for `ns`, or until the clock reaches `ns` with
+    /// `R4_SLEEP_ABSOLUTE`. Rounds up to whole ticks.
+    #[cfg(feature = "go_test")]
+    unsafe fn sys_nanosleep_r4(frame: *mut u64, ns: u64, flags: u64) {
+        if flags & !R4_SLEEP_ABSOLUTE != 0 {
+            *frame.add(14) = 0xFFFF_FFFF_FFFF_FFFF;
+            return;
+        }
+        let deadline_ns = if flags & R4_SLEEP_ABSOLUTE != 0 {
+            ns
+        } else {
+            r4_clock_ns().saturating_add(ns)
+        };
+        let tick = match r4_deadline_tick(deadline_ns) {
+            Some(tick) => tick,
+            None => {
+                *frame.add(14) = 0xFFFF_FFFF_FFFF_FFFF;
+                return;
+            }
+        };
+        *frame.add(14) = 0;
+        if tick <= R4_TICKS {
+            return;
+        }
+        r4_save_frame(frame, r4_current());
+        r4_block_current(frame, Some(tick), 0);
+    }
+
+    /// Poll until a descriptor is ready or the deadline tick passes, when
+    /// it returns 0. Blocked pollers are re-scanned on every tick.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_poll_until(frame: *mut u64, fds_ptr: u64, nfds: u64, deadline: Option<u64>) {
+        let ready = sys_poll_v1(fds_ptr, nfds, 0);
+        *frame.add(14) = ready;
+        if ready != 0 || deadline.is_some_and(|tick| tick <= R4_TICKS) {
+            return;
+        }
+        let cur = r4_current();
+        r4_save_frame(frame, cur);
+        R4_TASKS[cur].poll_fds = fds_ptr;
+        R4_TASKS[cur].poll_nfds = nfds;
+        R4_TASKS[cur].polling = true;
+        r4_block_current(frame, deadline, 0);
+    }
+
+    /// sys_poll: `timeout_ticks` of `0` checks once, `u64::MAX` waits
+    /// without a deadline.
+    #[cfg(feature = "go_test")]
+    unsafe fn sys_poll_r4(frame: *mut u64, fds_ptr: u64, nfds: u64, timeout_ticks: u64) {
+        let deadline = if timeout_ticks == u64::MAX {
+            None
+        } else {
+            Some(R4_TICKS.saturating_add(timeout_ticks))
+        };
+        r4_poll_until(frame, fds_ptr, nfds, deadline);
+    }
+
+    /// A timer handle first due at `deadline_ns`, then every `period_ns`
+    /// if non-zero (rounded up to a whole tick).
+    #[cfg(feature = "go_test")]
+    unsafe fn sys_timer_create_r4(deadline_ns: u64, period_ns: u64) -> u64 {
+        let tick = match r4_deadline_tick(deadline_ns) {
+            Some(tick) => tick,
+            None => return 0xFFFF_FFFF_FFFF_FFFF,
+        };
+        let period = period_ns.div_ceil(R4_TICK_NS);
+        let h = match R4_TIMER_HANDLES.iter().position(|handle| !handle.active) {
+            Some(h) => h,
+            None => return 0xFFFF_FFFF_FFFF_FFFF,
+        };
+        R4_TIMER_HANDLES[h] = R4TimerHandle {
+            active: true,
+            owner_tid: r4_current(),
+            armed: true,
+            deadline: tick,
+            period,
+            expirations: 0,
+            waiter: -1,
+        };
+        timer::timer_arm(tick, timer::TimerEvent::Handle(h));
+        h as u64
+    }
+
+    #[cfg(feature = "go_test")]
+    #[inline(always)]
+    unsafe fn r4_timer_handle_owned(handle: u64) -> Option<usize> {
+        let h = handle as usize;
+        if h < R4_MAX_TIMER_HANDLES
+            && R4_TIMER_HANDLES[h].active
+            && R4_TIMER_HANDLES[h].owner_tid == r4_current()
+        {
+            Some(h)
+        } else {
+            None
+        }
+    }
+
+    /// Return the expirations since the last wait, blocking for the next
+    /// one if there are none. A spent one-shot handle returns -1.
+    #[cfg(feature = "go_test")]
+    unsafe fn sys_timer_wait_r4(frame: *mut u64, handle: u64) {
+        let h = match r4_timer_handle_owned(handle) {
+            Some(h) => h,
+            None => {
+                *frame.add(14) = 0xFFFF_FFFF_FFFF_FFFF;
+                return;
+            }
+        };
+        let expirations = R4_TIMER_HANDLES[h].expirations;
+        if expirations > 0 {
+            R4_TIMER_HANDLES[h].expirations = 0;
+            *frame.add(14) = expirations;
+            return;
+        }
+        if !R4_TIMER_HANDLES[h].armed {
+            *frame.add(14) = 0xFFFF_FFFF_FFFF_FFFF;
+            return;
+        }
+        r4_save_frame(frame, r4_current());
+        R4_TIMER_HANDLES[h].waiter = r4_current() as i32;
+        r4_block_current(frame, None, 0);
+    }
+
+    #[cfg(feature = "go_test")]
+    unsafe fn sys_timer_close_r4(handle: u64) -> u64 {
+        let h = match r4_timer_handle_owned(handle) {
+            Some(h) => h,
+            None => return 0xFFFF_FFFF_FFFF_FFFF,
+        };
+        timer::timer_cancel(timer::TimerEvent::Handle(h));
+        R4_TIMER_HANDLES[h] = R4TimerHandle::EMPTY;
+        0
+    }
+}
+
+// --------------- R4: Kernel workers ------------------------------------------
+
+cfg_r4! {
+    // rax of a worker's ring-0 `int 0x80`: park until the queue has work.
+    #[cfg(feature = "go_test")]
+    const R4_KWORKER_WAIT: u64 = 0;
+    #[cfg(feature = "go_test")]
+    const R4_KWORKER_STACK_SIZE: usize = 16384;
+    // Worker indices; worker `w` runs as the tid `w` slots above the user
+    // task limit.
+    #[cfg(feature = "go_test")]
+    const R4_KWORKER_FLUSH: usize = 0;
+    #[cfg(feature = "go_test")]
+    const R4_KWORKER_NET_RX: usize = 1;
+
+    #[cfg(feature = "go_test")]
+    static mut R4_KWORKER_STACKS: [[u8; R4_KWORKER_STACK_SIZE]; R4_MAX_KWORKERS] =
+        [[0u8; R4_KWORKER_STACK_SIZE]; R4_MAX_KWORKERS];
+
+    #[cfg(feature = "go_test")]
+    #[inline(always)]
+    unsafe fn r4_kworker_tid(worker: usize) -> usize {
+        r4_user_task_limit() + worker
+    }
+
+    /// Put every kernel worker in its slot above the user tasks, parked with
+    /// an empty queue. Workers are critical-class tasks like any other: a
+    /// ring-0 frame on their own stack entering `r4_kworker_main`, with
+    /// interrupts on, and the same quantum, accounting and `proc_info`.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_kworkers_start() {
+        workq::workq_reset();
+        for (worker, stack) in R4_KWORKER_STACKS.iter().enumerate() {
+            let tid = r4_kworker_tid(worker);
+            let stack = stack.as_ptr() as u64;
+            // Entered as if called: the return slot just below a 16-byte
+            // boundary.
+            let top = (stack + R4_KWORKER_STACK_SIZE as u64) & !0xF;
+            R4_TASKS[tid] = R4Task::EMPTY;
+            R4_TASKS[tid].parent_tid = tid;
+            R4_TASKS[tid].kernel = true;
+            // A worker's trap frame sits on its own stack, where the CPU it
+            // left may still be reading it, so workers stay on one CPU.
+            R4_TASKS[tid].affinity = 1 << R4_CPU_BOOT;
+            R4_TASKS[tid].sched_class = R4_SCHED_CLASS_CRITICAL;
+            R4_TASKS[tid].vruntime = R4_MIN_VRUNTIME;
+            R4_TASKS[tid].saved_frame[9] = worker as u64;  // RDI
+            R4_TASKS[tid].saved_frame[17] = r4_kworker_main as *const () as u64; // RIP
+            R4_TASKS[tid].saved_frame[18] = 0x08;          // CS (kernel code)
+            R4_TASKS[tid].saved_frame[19] = 0x202;         // RFLAGS, IF set
+            R4_TASKS[tid].saved_frame[20] = top - 8;       // RSP
+            R4_TASKS[tid].saved_frame[21] = 0x10;          // SS (kernel data)
+            r4_set_state(tid, R4State::Blocked);
+        }
+    }
+
+    /// Body of every kernel worker: run queued jobs one at a time with
+    /// interrupts off and the kernel lock held, and park once the queue is
+    /// empty. Each pass ends with interrupts briefly on, where the tick may
+    /// preempt the worker.
+    #[cfg(feature = "go_test")]
+    extern "C" fn r4_kworker_main(worker: usize) -> ! {
+        loop {
+            unsafe {
+                core::arch::asm!("cli", options(nomem, nostack));
+                smp::smp_lock();
+                match workq::workq_pop(worker) {
+                    Some(work) => {
+                        (work.func)(work.arg);
+                        smp::smp_unlock();
+                    }
+                    None => {
+                        smp::smp_unlock();
+                        core::arch::asm!(
+                            "int 0x80",
+                            inlateout("rax") R4_KWORKER_WAIT => _,
+                            options(nostack),
+                        );
+                    }
+                }
+                core::arch::asm!("sti; nop", options(nomem, nostack));
+            }
+        }
+    }
+
+    /// A ring-0 `int 0x80`, which only a kernel worker issues: park it until
+    /// its queue has work. The CPU goes to the next ready task, or idles or
+    /// finishes as when the last user task blocks; parked workers alone are
+    /// no deadlock.
+    #[cfg(feature = "go_test")]
+    pub(crate) unsafe fn r4_kworker_trap(frame: *mut u64) {
+        let cur = r4_current();
+        if !R4_TASKS[cur].kernel || *frame.add(14) != R4_KWORKER_WAIT {
+            *frame.add(14) = 0xFFFF_FFFF_FFFF_FFFF;
+            return;
+        }
+        *frame.add(14) = 0;
+        if workq::workq_pending(cur - r4_user_task_limit()) {
+            return;
+        }
+        r4_save_frame(frame, cur);
+        R4_TASKS[cur].block_count += 1;
+        r4_sched_lock();
+        r4_set_state(cur, R4State::Blocked);
+        match r4_find_ready(cur) {
+            Some(tid) => r4_switch_to(frame, tid),
+            None => {
+                let deadlock = (0..R4_NUM_TASKS).any(|tid| R4_TASKS[tid].state == R4State::Blocked);
+                r4_idle_or_finish(frame, deadlock);
+            }
+        }
+        r4_sched_unlock();
+    }
+
+    /// Queue `func(arg)` on a kernel worker and wake the worker if parked.
+    /// Safe from interrupt context. False when the worker's queue is full.
+    #[cfg(feature = "go_test")]
+    pub(crate) unsafe fn r4_defer_work(worker: usize, func: unsafe fn(u64), arg: u64) -> bool {
+        if !workq::workq_push(worker, workq::Work { func, arg }) {
+            return false;
+        }
+        let tid = r4_kworker_tid(worker);
+        r4_sched_lock();
+        if R4_TASKS[tid].kernel && R4_TASKS[tid].state == R4State::Blocked {
+            r4_set_state(tid, R4State::Ready);
+        }
+        r4_sched_unlock();
+        true
+    }
+
+    /// Per-tick check of devices that hand work to the workers: completed
+    /// receives on the NIC go to the network RX worker.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_kwork_poll_sources() {
+        if net::virtio_net_rx_pending() {
+            r4_defer_work(R4_KWORKER_NET_RX, net::r4_net_rx_work, 0);
         }
+    }
 
-        // No message â€” block current task and switch
-        R4_TASKS[R4_CURRENT].recv_ep = endpoint;
-        R4_TASKS[R4_CURRENT].recv_buf = buf;
-        R4_TASKS[R4_CURRENT].recv_cap = cap_n as u64;
-        r4_save_frame(frame, R4_CURRENT);
-        R4_TASKS[R4_CURRENT].block_count += 1;
-        R4_TASKS[R4_CURRENT].state = R4State::Blocked;
-        R4_ENDPOINTS[ep].waiter = R4_CURRENT as i32;
-
-        match r4_find_ready(R4_CURRENT) {
-            Some(tid) => { r4_switch_to(frame, tid); }
-            None => {
-                // Deadlock â€” no ready tasks
-                serial_write(b"R4: deadlock\n");
-                let kstack = &stack_top as *const u8 as u64;
-                *frame.add(17) = r4_all_done as *const () as u64;
-                *frame.add(18) = 0x08;
-                *frame.add(19) = 0x02;
-                *frame.add(20) = kstack;
-                *frame.add(21) = 0x10;
-            }
+    /// Wake `tid` with `ret` if it still waits on a worker job. A task
+    /// killed in the meantime, or a new task in its slot, is left alone.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_kwork_complete(tid: usize, ret: u64) {
+        if R4_TASKS[tid].state == R4State::Blocked && R4_TASKS[tid].kwork_wait {
+            R4_TASKS[tid].kwork_wait = false;
+            r4_wake_with(tid, ret);
+        }
+    }
+
+    /// Storage flusher job: commit the journal for the blocked task `arg`.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_kflush_fsync(arg: u64) {
+        let ret = if storage::r4_storage_fsync() { 0 } else { 0xFFFF_FFFF_FFFF_FFFF };
+        r4_kwork_complete(arg as usize, ret);
+    }
+
+    /// fsync of the journal runs on the storage flusher while the caller
+    /// blocks. Other descriptors, and a full flusher queue, complete inline.
+    #[cfg(feature = "go_test")]
+    unsafe fn sys_fsync_r4(frame: *mut u64, fd: u64) {
+        let cur = r4_current();
+        if !r4_fsync_defers(fd) || !r4_defer_work(R4_KWORKER_FLUSH, r4_kflush_fsync, cur as u64) {
+            *frame.add(14) = sys_fsync_v1(fd);
+            return;
         }
+        r4_save_frame(frame, cur);
+        R4_TASKS[cur].kwork_wait = true;
+        r4_block_current(frame, None, 0);
     }
 }
 
@@ -3028,152 +6489,308 @@ cfg_r4! {
 // --------------- R4: SHM syscalls --------------------------------------------
 
 cfg_r4! {
+    #[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+    #[inline(always)]
+    unsafe fn r4_shm_held_by(h: usize, tid: usize) -> bool {
+        R4_SHM_HANDLES[h].held_by(tid)
+    }
+
+    /// Drop one handle or grant reference; the frames go back to the pool
+    /// with the last one (live mappings keep their own frame references).
+    #[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+    unsafe fn r4_shm_object_put(obj: usize) {
+        R4_SHM_OBJECTS[obj].refs -= 1;
+        if R4_SHM_OBJECTS[obj].refs != 0 {
+            return;
+        }
+        let object = &R4_SHM_OBJECTS[obj];
+        for &frame in &object.frames[..object.pages] {
+            if object.large {
+                vm::vm_large_release(frame);
+            } else {
+                vm::vm_frame_release(frame);
+            }
+        }
+        R4_SHM_OBJECTS[obj] = ShmObject::EMPTY;
+    }
+
+    /// Give `tid` a handle to `obj`, taking over a reference the caller
+    /// already holds. Fails at the task's SHM quota or when the table is full.
+    #[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+    unsafe fn r4_shm_handle_alloc(tid: usize, obj: usize, rights: u8) -> Option<usize> {
+        if !runtime::isolation::under_quota(R4_TASKS[tid].shm_count, MAX_SHM_PER_PROC) {
+            return None;
+        }
+        let h = R4_SHM_HANDLES.iter().position(|handle| !handle.active)?;
+        R4_SHM_HANDLES[h] = ShmHandle {
+            active: true,
+            object: obj,
+            rights,
+            owner_tid: tid,
+            holders: 0,
+        };
+        R4_TASKS[tid].shm_count += 1;
+        Some(h)
+    }
+
+    /// Drop `tid`'s hold on a handle, handing ownership to another holder if
+    /// it was the owner, and release the object reference with the last one.
+    #[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+    unsafe fn r4_shm_drop_holder(h: usize, tid: usize) {
+        let entry = &mut R4_SHM_HANDLES[h];
+        entry.holders &= !runtime::isolation::holder_bit(tid);
+        R4_TASKS[tid].shm_count = R4_TASKS[tid].shm_count.saturating_sub(1);
+        if entry.owner_tid == tid {
+            match runtime::isolation::next_holder(entry.holders) {
+                Some(next) => {
+                    entry.owner_tid = next;
+                    entry.holders &= !runtime::isolation::holder_bit(next);
+                }
+                None => {
+                    let obj = entry.object;
+                    *entry = ShmHandle::EMPTY;
+                    r4_shm_object_put(obj);
+                }
+            }
+        }
+    }
+
+    /// Fork: the child holds every SHM handle the parent holds.
+    #[cfg(feature = "go_test")]
+    unsafe fn r4_share_shm(parent: usize, child: usize) {
+        for handle in R4_SHM_HANDLES.iter_mut() {
+            if handle.held_by(parent) {
+                handle.holders |= runtime::isolation::holder_bit(child);
+            }
+        }
+        R4_TASKS[child].shm_count = R4_TASKS[parent].shm_count;
+    }
+
+    #[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+    unsafe fn r4_release_shm_handles(tid: usize) {
+        for h in 0..R4_MAX_SHM_HANDLES {
+            if r4_shm_held_by(h, tid) {
+                r4_shm_drop_holder(h, tid);
+            }
+        }
+        R4_TASKS[tid].shm_count = 0;
+    }
+
+    #[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+    unsafe fn r4_shm_drop_grant(ep: usize) {
+        let obj = R4_ENDPOINTS[ep].shm_grant;
+        if obj >= 0 {
+            R4_ENDPOINTS[ep].shm_grant = -1;
+            R4_ENDPOINTS[ep].shm_grant_rights = 0;
+            r4_shm_object_put(obj as usize);
+        }
+    }
+
+    /// Turn the endpoint's pending grant into a handle for `tid` and write
+    /// its id (little-endian) over the first 8 payload bytes.
+    #[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+    unsafe fn r4_shm_take_grant(ep: usize, tid: usize, payload: &mut [u8]) -> Result<Option<usize>, ()> {
+        let obj = R4_ENDPOINTS[ep].shm_grant;
+        if obj < 0 {
+            return Ok(None);
+        }
+        if payload.len() < 8 {
+            return Err(());
+        }
+        let h = r4_shm_handle_alloc(tid, obj as usize, R4_ENDPOINTS[ep].shm_grant_rights).ok_or(())?;
+        R4_ENDPOINTS[ep].shm_grant = -1;
+        R4_ENDPOINTS[ep].shm_grant_rights = 0;
+        payload[..8].copy_from_slice(&(h as u64).to_le_bytes());
+        Ok(Some(h))
+    }
+
+    /// Undo `r4_shm_take_grant` when the message could not be copied out.
+    #[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+    unsafe fn r4_shm_restore_grant(ep: usize, h: usize, tid: usize) {
+        R4_ENDPOINTS[ep].shm_grant = R4_SHM_HANDLES[h].object as i32;
+        R4_ENDPOINTS[ep].shm_grant_rights = R4_SHM_HANDLES[h].rights;
+        R4_SHM_HANDLES[h] = ShmHandle::EMPTY;
+        R4_TASKS[tid].shm_count = R4_TASKS[tid].shm_count.saturating_sub(1);
+    }
+
     unsafe fn sys_shm_create_r4(size: u64) -> u64 {
-        #[cfg(any(feature = "shm_test", feature = "quota_shm_test"))]
+        #[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
         {
-            if size == 0 || size > 4096 { return 0xFFFF_FFFF_FFFF_FFFF; }
+            // Whole 2 MiB multiples take large frames, so they can be mapped
+            // with large leaves; anything else takes 4 KiB pool frames.
+            let large = size % vm::VM_LARGE_PAGE == 0;
+            let page_size = if large { vm::VM_LARGE_PAGE } else { 4096 };
+            if size == 0 || size > R4_SHM_MAX_PAGES as u64 * page_size { return 0xFFFF_FFFF_FFFF_FFFF; }
             if !runtime::isolation::under_quota(
-                R4_TASKS[R4_CURRENT].shm_count,
+                R4_TASKS[r4_current()].shm_count,
                 MAX_SHM_PER_PROC,
             ) {
                 return 0xFFFF_FFFF_FFFF_FFFF;
             }
-            for i in 0..R4_MAX_SHM {
-                if !R4_SHM_OBJECTS[i].active {
-                    R4_SHM_OBJECTS[i].active = true;
-                    R4_SHM_OBJECTS[i].size = 4096;
-                    core::ptr::write_bytes(R4_SHM_PAGES[i].0.as_mut_ptr(), 0, 4096);
-                    R4_TASKS[R4_CURRENT].shm_count += 1;
-                    return i as u64;
+            let obj = match (0..R4_MAX_SHM).find(|&i| !R4_SHM_OBJECTS[i].active) {
+                Some(obj) => obj,
+                None => return 0xFFFF_FFFF_FFFF_FFFF,
+            };
+            let pages = size.div_ceil(page_size) as usize;
+            R4_SHM_OBJECTS[obj].active = true;
+            R4_SHM_OBJECTS[obj].large = large;
+            R4_SHM_OBJECTS[obj].refs = 1;
+            while R4_SHM_OBJECTS[obj].pages < pages {
+                let frame = if large { vm::vm_large_alloc() } else { vm::vm_frame_alloc() };
+                match frame {
+                    Some(frame) => {
+                        let object = &mut R4_SHM_OBJECTS[obj];
+                        object.frames[object.pages] = frame;
+                        object.pages += 1;
+                    }
+                    None => {
+                        r4_shm_object_put(obj);
+                        return 0xFFFF_FFFF_FFFF_FFFF;
+                    }
+                }
+            }
+            match r4_shm_handle_alloc(r4_current(), obj, R4_SHM_RIGHT_MASK) {
+                Some(h) => h as u64,
+                None => {
+                    r4_shm_object_put(obj);
+                    0xFFFF_FFFF_FFFF_FFFF
                 }
             }
-            return 0xFFFF_FFFF_FFFF_FFFF;
         }
-        #[cfg(not(any(feature = "shm_test", feature = "quota_shm_test")))]
+        #[cfg(not(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test")))]
         { let _ = size; 0xFFFF_FFFF_FFFF_FFFF }
     }
 
-    unsafe fn sys_shm_map_r4(handle: u64, addr_hint: u64, _flags: u64) -> u64 {
-        #[cfg(any(feature = "shm_test", feature = "quota_shm_test"))]
+    unsafe fn sys_shm_map_r4(handle: u64, addr_hint: u64, flags: u64) -> u64 {
+        #[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
         {
             let h = handle as usize;
-            if h >= R4_MAX_SHM || !R4_SHM_OBJECTS[h].active { return 0xFFFF_FFFF_FFFF_FFFF; }
+            if h >= R4_MAX_SHM_HANDLES || !r4_shm_held_by(h, r4_current()) { return 0xFFFF_FFFF_FFFF_FFFF; }
+            if flags & !R4_SHM_MAP_READONLY != 0 { return 0xFFFF_FFFF_FFFF_FFFF; }
             if addr_hint & 0xFFF != 0 { return 0xFFFF_FFFF_FFFF_FFFF; }
-            if addr_hint >= 0x0000_8000_0000_0000 { return 0xFFFF_FFFF_FFFF_FFFF; }
-
-            // Get physical address of SHM backing page via kv2p
-            let hhdm_resp_ptr = core::ptr::read_volatile(
-                core::ptr::addr_of!(HHDM_REQUEST.response));
-            let kaddr_resp_ptr = core::ptr::read_volatile(
-                core::ptr::addr_of!(KADDR_REQUEST.response));
-            let hhdm = (*hhdm_resp_ptr).offset;
-            let kphys = (*kaddr_resp_ptr).physical_base;
-            let kvirt = (*kaddr_resp_ptr).virtual_base;
-            let shm_phys = R4_SHM_PAGES[h].0.as_ptr() as u64 - kvirt + kphys;
-
-            // Walk page tables to find PT and install PTE
-            let cr3: u64;
-            core::arch::asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack));
-            let pml4_phys = cr3 & 0x000F_FFFF_FFFF_F000;
-            let pml4 = (pml4_phys + hhdm) as *const u64;
-            let pml4e = *pml4.add(((addr_hint >> 39) & 0x1FF) as usize);
-            if pml4e & 1 == 0 { return 0xFFFF_FFFF_FFFF_FFFF; }
-            let pdpt = ((pml4e & 0x000F_FFFF_FFFF_F000) + hhdm) as *const u64;
-            let pdpte = *pdpt.add(((addr_hint >> 30) & 0x1FF) as usize);
-            if pdpte & 1 == 0 { return 0xFFFF_FFFF_FFFF_FFFF; }
-            let pd = ((pdpte & 0x000F_FFFF_FFFF_F000) + hhdm) as *const u64;
-            let pde = *pd.add(((addr_hint >> 21) & 0x1FF) as usize);
-            if pde & 1 == 0 { return 0xFFFF_FFFF_FFFF_FFFF; }
-            let pt = ((pde & 0x000F_FFFF_FFFF_F000) + hhdm) as *mut u64;
-            let pt_idx = ((addr_hint >> 12) & 0x1FF) as usize;
-            *pt.add(pt_idx) = shm_phys | 0x07; // Present | Writable | Use
-            core::arch::asm!("invlpg [{}]", in(reg) addr_hint, options(nostack));
-            return addr_hint;
-        }
-        #[cfg(not(any(feature = "shm_test", feature = "quota_shm_test")))]
-        { let _ = (handle, addr_hint, _flags); 0xFFFF_FFFF_FFFF_FFFF }
+            let writable = flags & R4_SHM_MAP_READONLY == 0;
+            let rights = R4_SHM_HANDLES[h].rights;
+            if rights & R4_SHM_RIGHT_READ == 0 || (writable && rights & R4_SHM_RIGHT_WRITE == 0) {
+                return 0xFFFF_FFFF_FFFF_FFFF;
+            }
+            let object = &R4_SHM_OBJECTS[R4_SHM_HANDLES[h].object];
+            let frames = &object.frames[..object.pages];
+            let page_size = object.page_size();
+            if !vm::vm_commit_ok(R4_TASKS[r4_current()].space, frames.len() as u64 * page_size) {
+                return R4_ERR_MEM_LIMIT;
+            }
+            vm::vm_shm_map(R4_TASKS[r4_current()].space, addr_hint, frames, page_size, writable)
+                .unwrap_or(0xFFFF_FFFF_FFFF_FFFF)
+        }
+        #[cfg(not(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test")))]
+        { let _ = (handle, addr_hint, flags); 0xFFFF_FFFF_FFFF_FFFF }
     }
 
     unsafe fn sys_shm_unmap_r4(addr: u64) -> u64 {
-        #[cfg(any(feature = "shm_test", feature = "quota_shm_test"))]
+        #[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
         {
-            if addr & 0xFFF != 0 { return 0xFFFF_FFFF_FFFF_FFFF; }
-            if addr >= 0x0000_8000_0000_0000 { return 0xFFFF_FFFF_FFFF_FFFF; }
-
-            let hhdm_resp_ptr = core::ptr::read_volatile(
-                core::ptr::addr_of!(HHDM_REQUEST.response));
-            let hhdm = (*hhdm_resp_ptr).offset;
-
-            let cr3: u64;
-            core::arch::asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack));
-            let pml4_phys = cr3 & 0x000F_FFFF_FFFF_F000;
-            let pml4 = (pml4_phys + hhdm) as *const u64;
-            let pml4e = *pml4.add(((addr >> 39) & 0x1FF) as usize);
-            if pml4e & 1 == 0 { return 0xFFFF_FFFF_FFFF_FFFF; }
-            let pdpt = ((pml4e & 0x000F_FFFF_FFFF_F000) + hhdm) as *const u64;
-            let pdpte = *pdpt.add(((addr >> 30) & 0x1FF) as usize);
-            if pdpte & 1 == 0 { return 0xFFFF_FFFF_FFFF_FFFF; }
-            let pd = ((pdpte & 0x000F_FFFF_FFFF_F000) + hhdm) as *const u64;
-            let pde = *pd.add(((addr >> 21) & 0x1FF) as usize);
-            if pde & 1 == 0 { return 0xFFFF_FFFF_FFFF_FFFF; }
-            let pt = ((pde & 0x000F_FFFF_FFFF_F000) + hhdm) as *mut u64;
-            let pt_idx = ((addr >> 12) & 0x1FF) as usize;
-            if *pt.add(pt_idx) & 1 == 0 { return 0xFFFF_FFFF_FFFF_FFFF; }
-            *pt.add(pt_idx) = 0;
-            core::arch::asm!("invlpg [{}]", in(reg) addr, options(nostack));
-            0
+            if vm::vm_shm_unmap(R4_TASKS[r4_current()].space, addr) {
+                0
+            } else {
+                0xFFFF_FFFF_FFFF_FFFF
+            }
         }
-        #[cfg(not(any(feature = "shm_test", feature = "quota_shm_test")))]
+        #[cfg(not(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test")))]
         { let _ = addr; 0xFFFF_FFFF_FFFF_FFFF }
     }
+
+    /// Send an SHM handle over an endpoint as an 8-byte message carrying the
+    /// receiver's new handle id. Needs the grant right; `rights` may only
+    /// drop rights the sender's handle has.
+    unsafe fn sys_shm_send_r4(endpoint: u64, handle: u64, rights: u64) -> u64 {
+        #[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+        {
+            let h = handle as usize;
+            if h >= R4_MAX_SHM_HANDLES || !r4_shm_held_by(h, r4_current()) { return 0xFFFF_FFFF_FFFF_FFFF; }
+            let held = R4_SHM_HANDLES[h].rights;
+            if held & R4_SHM_RIGHT_GRANT == 0 || rights & !(held as u64) != 0 {
+                return 0xFFFF_FFFF_FFFF_FFFF;
+            }
+            let ep = endpoint as usize;
+            if ep >= R4_MAX_ENDPOINTS || !R4_ENDPOINTS[ep].active || R4_ENDPOINTS[ep].has_msg {
+                return 0xFFFF_FFFF_FFFF_FFFF;
+            }
+            let obj = R4_SHM_HANDLES[h].object;
+            R4_SHM_OBJECTS[obj].refs += 1;
+            R4_ENDPOINTS[ep].shm_grant = obj as i32;
+            R4_ENDPOINTS[ep].shm_grant_rights = rights as u8;
+            let mut payload = [0u8; 8];
+            R4_TASKS[r4_current()].ipc_send_count += 1;
+            let ret = r4_ipc_deliver(ep, &mut payload);
+            if ret != 0 {
+                r4_shm_drop_grant(ep);
+            }
+            ret
+        }
+        #[cfg(not(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test")))]
+        { let _ = (endpoint, handle, rights); 0xFFFF_FFFF_FFFF_FFFF }
+    }
 }
 
-// --------------- R4: Page table setup for two tasks --------------------------
+// --------------- R4: Per-task address space setup ---------------------------
 
 cfg_r4! {
-    unsafe fn setup_r4_pages(blob0: &[u8], blob1: &[u8]) {
+    unsafe fn r4_pages_init() {
         let hhdm_resp_ptr = core::ptr::read_volatile(
             core::ptr::addr_of!(HHDM_REQUEST.response));
-        let kaddr_resp_ptr = core::ptr::read_volatile(
-            core::ptr::addr_of!(KADDR_REQUEST.response));
-        let hhdm = (*hhdm_resp_ptr).offset;
-        let kphys = (*kaddr_resp_ptr).physical_base;
-        let kvirt = (*kaddr_resp_ptr).virtual_base;
-        HHDM_OFFSET = hhdm;
-        let kv2p = |va: u64| -> u64 { va - kvirt + kphys };
-
-        // Clone current PML4
-        let cr3: u64;
-        core::arch::asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack));
-        let old_pml4 = ((cr3 & 0x000F_FFFF_FFFF_F000) + hhdm) as *const u64;
-        let new_pml4 = USER_PML4.0.as_mut_ptr() as *mut u64;
-        for i in 0..512 { *new_pml4.add(i) = *old_pml4.add(i); }
-
-        // PDPT entry 0 -> PD
-        let pdpt = USER_PDPT.0.as_mut_ptr() as *mut u64;
-        *pdpt = kv2p(USER_PD.0.as_ptr() as u64) | 0x07;
-
-        // PD entry 2 -> PT_CODE (covers 0x400000-0x5FFFFF)
-        // PD entry 3 -> PT_STACK (covers 0x600000-0x7FFFFF)
-        let pd = USER_PD.0.as_mut_ptr() as *mut u64;
-        *pd.add(2) = kv2p(USER_PT_CODE.0.as_ptr() as u64) | 0x07;
-        *pd.add(3) = kv2p(USER_PT_STACK.0.as_ptr() as u64) | 0x07;
-
-        // PT_CODE[0] = task 0 code page at 0x400000 (RX User)
-        // PT_CODE[1] = task 1 code page at 0x401000 (RX User)
+        HHDM_OFFSET = (*hhdm_resp_ptr).offset;
+        vm::vm_init();
+        // Every slot the boot limits allow, the kernel workers' included, is
+        // backed here, so no task slot is ever missing storage.
+        if !R4_TASKS.init(r4_user_task_limit() + R4_MAX_KWORKERS, R4Task::EMPTY) {
+            serial_write(b"R4: task table alloc fail\n");
+            qemu_exit(0x33);
+            loop { core::arch::asm!("cli; hlt", options(nomem, nostack)); }
+        }
+        R4_RUNQ = [R4RunQueue::EMPTY; arch_x86::MAX_CPUS];
+        #[cfg(feature = "go_test")]
+        timer::timer_init(R4_TASKS.len());
+    }
+
+    /// Build a process space holding one code page at the task's fixed slot
+    /// address and a growable stack below `stk_top`. Nothing of the other
+    /// tasks is mapped.
+    #[cfg(r4_ipc)]
+    unsafe fn r4_build_task_space(
+        tid: usize,
+        code_va: u64,
+        code_page: *const u8,
+        stk_top: u64,
+    ) {
         let code_flags = if cfg!(feature = "go_test") { 0x07 } else { 0x05 };
-        let pt_code = USER_PT_CODE.0.as_mut_ptr() as *mut u64;
-        *pt_code.add(0) = kv2p(USER_CODE_PAGE.0.as_ptr() as u64) | code_flags;
-        *pt_code.add(1) = kv2p(USER_CODE_PAGE_2.0.as_ptr() as u64) | code_flags;
+        let space = match vm::vm_space_create() {
+            Some(space) => space,
+            None => {
+                serial_write(b"R4: space alloc fail\n");
+                qemu_exit(0x33);
+                loop { core::arch::asm!("cli; hlt", options(nomem, nostack)); }
+            }
+        };
+        if !vm::vm_map_kernel_page(space, code_va, code_page, code_flags)
+            || !vm::vm_stack_reserve(space, stk_top, R4_USER_STACK_MAX)
+        {
+            serial_write(b"R4: space map fail\n");
+            qemu_exit(0x33);
+            loop { core::arch::asm!("cli; hlt", options(nomem, nostack)); }
+        }
+        R4_TASKS[tid].space = space;
+    }
 
-        // PT_STACK[511] = task 0 stack page at 0x7FF000 (RW User)
-        // PT_STACK[510] = task 1 stack page at 0x7FE000 (RW User)
-        let pt_stack = USER_PT_STACK.0.as_mut_ptr() as *mut u64;
-        *pt_stack.add(511) = kv2p(USER_STACK_PAGE.0.as_ptr() as u64) | 0x07;
-        *pt_stack.add(510) = kv2p(USER_STACK_PAGE_2.0.as_ptr() as u64) | 0x07;
+    #[cfg(r4_ipc)]
+    unsafe fn setup_r4_pages(blob0: &[u8], blob1: &[u8]) {
+        r4_pages_init();
 
-        // PML4[0] -> our user PDPT
-        *new_pml4 = kv2p(USER_PDPT.0.as_ptr() as u64) | 0x07;
+        // Task 0 code at 0x400000, stack growing down from 0x800000.
+        // Task 1 code at 0x401000, stack growing down from 0x7FF000.
+        r4_build_task_space(
+            0, USER_CODE_VA, USER_CODE_PAGE.0.as_ptr(), USER_STACK_TOP);
+        r4_build_task_space(
+            1, USER_CODE2_VA, USER_CODE_PAGE_2.0.as_ptr(), USER_STACK2_TOP);
 
         // Copy code blobs
         core::ptr::copy_nonoverlapping(
@@ -3181,52 +6798,21 @@ cfg_r4! {
         core::ptr::copy_nonoverlapping(
             blob1.as_ptr(), USER_CODE_PAGE_2.0.as_mut_ptr(), blob1.len());
 
-        // Switch CR3
-        let new_pml4_phys = kv2p(new_pml4 as u64);
-        core::arch::asm!("mov cr3, {}", in(reg) new_pml4_phys, options(nostack));
+        vm::vm_activate(R4_TASKS[0].space);
     }
 
-    #[cfg(any(feature = "stress_ipc_test", feature = "go_test"))]
+    #[cfg(feature = "stress_ipc_test")]
     unsafe fn setup_r4_pages4(blob0: &[u8], blob1: &[u8], blob2: &[u8], blob3: &[u8]) {
-        let hhdm_resp_ptr = core::ptr::read_volatile(
-            core::ptr::addr_of!(HHDM_REQUEST.response));
-        let kaddr_resp_ptr = core::ptr::read_volatile(
-            core::ptr::addr_of!(KADDR_REQUEST.response));
-        let hhdm = (*hhdm_resp_ptr).offset;
-        let kphys = (*kaddr_resp_ptr).physical_base;
-        let kvirt = (*kaddr_resp_ptr).virtual_base;
-        HHDM_OFFSET = hhdm;
-        let kv2p = |va: u64| -> u64 { va - kvirt + kphys };
-
-        let cr3: u64;
-        core::arch::asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack));
-        let old_pml4 = ((cr3 & 0x000F_FFFF_FFFF_F000) + hhdm) as *const u64;
-        let new_pml4 = USER_PML4.0.as_mut_ptr() as *mut u64;
-        for i in 0..512 { *new_pml4.add(i) = *old_pml4.add(i); }
-
-        let pdpt = USER_PDPT.0.as_mut_ptr() as *mut u64;
-        *pdpt = kv2p(USER_PD.0.as_ptr() as u64) | 0x07;
-
-        let pd = USER_PD.0.as_mut_ptr() as *mut u64;
-        *pd.add(2) = kv2p(USER_PT_CODE.0.as_ptr() as u64) | 0x07;
-        *pd.add(3) = kv2p(USER_PT_STACK.0.as_ptr() as u64) | 0x07;
-
-        let code_flags = if cfg!(feature = "go_test") { 0x07 } else { 0x05 };
-        let pt_code = USER_PT_CODE.0.as_mut_ptr() as *mut u64;
-        *pt_code.add(0) = kv2p(USER_CODE_PAGE.0.as_ptr() as u64) | code_flags;
-        *pt_code.add(1) = kv2p(USER_CODE_PAGE_2.0.as_ptr() as u64) | code_flags;
-        *pt_code.add(2) = kv2p(USER_CODE_PAGE_3.0.as_ptr() as u64) | code_flags;
-        *pt_code.add(3) = kv2p(USER_CODE_PAGE_4.0.as_ptr() as u64) | code_flags;
-        *pt_code.add(4) = kv2p(USER_CODE_PAGE_5.0.as_ptr() as u64) | code_flags;
-        *pt_code.add(5) = kv2p(USER_CODE_PAGE_6.0.as_ptr() as u64) | code_flags;
-
-        let pt_stack = USER_PT_STACK.0.as_mut_ptr() as *mut u64;
-        *pt_stack.add(511) = kv2p(USER_STACK_PAGE.0.as_ptr() as u64) | 0x07;
-        *pt_stack.add(510) = kv2p(USER_STACK_PAGE_2.0.as_ptr() as u64) | 0x07;
-        *pt_stack.add(509) = kv2p(USER_STACK_PAGE_3.0.as_ptr() as u64) | 0x07;
-        *pt_stack.add(508) = kv2p(USER_STACK_PAGE_4.0.as_ptr() as u64) | 0x07;
+        r4_pages_init();
 
-        *new_pml4 = kv2p(USER_PDPT.0.as_ptr() as u64) | 0x07;
+        r4_build_task_space(
+            0, USER_CODE_VA, USER_CODE_PAGE.0.as_ptr(), USER_STACK_TOP);
+        r4_build_task_space(
+            1, USER_CODE2_VA, USER_CODE_PAGE_2.0.as_ptr(), USER_STACK2_TOP);
+        r4_build_task_space(
+            2, USER_CODE3_VA, USER_CODE_PAGE_3.0.as_ptr(), USER_STACK3_TOP);
+        r4_build_task_space(
+            3, USER_CODE4_VA, USER_CODE_PAGE_4.0.as_ptr(), USER_STACK4_TOP);
 
         core::ptr::copy_nonoverlapping(
             blob0.as_ptr(), USER_CODE_PAGE.0.as_mut_ptr(), blob0.len());
@@ -3237,102 +6823,56 @@ cfg_r4! {
         core::ptr::copy_nonoverlapping(
             blob3.as_ptr(), USER_CODE_PAGE_4.0.as_mut_ptr(), blob3.len());
 
-        let new_pml4_phys = kv2p(new_pml4 as u64);
-        core::arch::asm!("mov cr3, {}", in(reg) new_pml4_phys, options(nostack));
+        vm::vm_activate(R4_TASKS[0].space);
     }
 
-    #[cfg(feature = "go_test")]
+    #[cfg(all(feature = "go_test", not(feature = "compat_real_test")))]
     unsafe fn setup_go_user_pages(blob: &[u8]) {
-        if blob.is_empty() || blob.len() > runtime::process::GO_IMAGE_MAX_BYTES {
-            serial_write(b"GO: image too large\n");
+        if blob.is_empty() {
+            serial_write(b"GO: empty image\n");
             qemu_exit(0x33);
             loop { core::arch::asm!("cli; hlt", options(nomem, nostack)); }
         }
 
-        let hhdm_resp_ptr = core::ptr::read_volatile(
-            core::ptr::addr_of!(HHDM_REQUEST.response));
-        let kaddr_resp_ptr = core::ptr::read_volatile(
-            core::ptr::addr_of!(KADDR_REQUEST.response));
-        let hhdm = (*hhdm_resp_ptr).offset;
-        let kphys = (*kaddr_resp_ptr).physical_base;
-        let kvirt = (*kaddr_resp_ptr).virtual_base;
-        HHDM_OFFSET = hhdm;
-        let kv2p = |va: u64| -> u64 { va - kvirt + kphys };
-
-        let cr3: u64;
-        core::arch::asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack));
-        let old_pml4 = ((cr3 & 0x000F_FFFF_FFFF_F000) + hhdm) as *const u64;
-        let new_pml4 = USER_PML4.0.as_mut_ptr() as *mut u64;
-        for i in 0..512 { *new_pml4.add(i) = *old_pml4.add(i); }
-
-        let pdpt = USER_PDPT.0.as_mut_ptr() as *mut u64;
-        *pdpt = kv2p(USER_PD.0.as_ptr() as u64) | 0x07;
-
-        let pd = USER_PD.0.as_mut_ptr() as *mut u64;
-        *pd.add(2) = kv2p(USER_PT_CODE.0.as_ptr() as u64) | 0x07;
-        *pd.add(3) = kv2p(USER_PT_STACK.0.as_ptr() as u64) | 0x07;
-
-        let pt_code = USER_PT_CODE.0.as_mut_ptr() as *mut u64;
-        let code_flags = 0x07;
-        *pt_code.add(0) = kv2p(USER_CODE_PAGE.0.as_ptr() as u64) | code_flags;
-        *pt_code.add(1) = kv2p(USER_CODE_PAGE_2.0.as_ptr() as u64) | code_flags;
-        *pt_code.add(2) = kv2p(USER_CODE_PAGE_3.0.as_ptr() as u64) | code_flags;
-        *pt_code.add(3) = kv2p(USER_CODE_PAGE_4.0.as_ptr() as u64) | code_flags;
-        *pt_code.add(4) = kv2p(USER_CODE_PAGE_5.0.as_ptr() as u64) | code_flags;
-        *pt_code.add(5) = kv2p(USER_CODE_PAGE_6.0.as_ptr() as u64) | code_flags;
-
-        let pt_stack = USER_PT_STACK.0.as_mut_ptr() as *mut u64;
-        *pt_stack.add(511) = kv2p(USER_STACK_PAGE.0.as_ptr() as u64) | 0x07;
-        *pt_stack.add(510) = kv2p(USER_STACK_PAGE_2.0.as_ptr() as u64) | 0x07;
-        *pt_stack.add(509) = kv2p(USER_STACK_PAGE_3.0.as_ptr() as u64) | 0x07;
-        *pt_stack.add(508) = kv2p(USER_STACK_PAGE_4.0.as_ptr() as u64) | 0x07;
-        *pt_stack.add(507) = kv2p(USER_STACK_PAGE_5.0.as_ptr() as u64) | 0x07;
-        *pt_stack.add(506) = kv2p(USER_STACK_PAGE_6.0.as_ptr() as u64) | 0x07;
-        *pt_stack.add(505) = kv2p(USER_STACK_PAGE_7.0.as_ptr() as u64) | 0x07;
-        *pt_stack.add(504) = kv2p(USER_STACK_PAGE_8.0.as_ptr() as u64) | 0x07;
-        *pt_stack.add(503) = kv2p(USER_HEAP_PAGE_1.0.as_ptr() as u64) | 0x07;
-        *pt_stack.add(502) = kv2p(USER_HEAP_PAGE_2.0.as_ptr() as u64) | 0x07;
-        *pt_stack.add(501) = kv2p(USER_HEAP_PAGE_3.0.as_ptr() as u64) | 0x07;
-        *pt_stack.add(500) = kv2p(USER_HEAP_PAGE_4.0.as_ptr() as u64) | 0x07;
-
-        *new_pml4 = kv2p(USER_PDPT.0.as_ptr() as u64) | 0x07;
+        r4_pages_init();
 
-        core::ptr::write_bytes(USER_CODE_PAGE.0.as_mut_ptr(), 0, runtime::process::GO_IMAGE_PAGE_SIZE);
-        core::ptr::write_bytes(USER_CODE_PAGE_2.0.as_mut_ptr(), 0, runtime::process::GO_IMAGE_PAGE_SIZE);
-        core::ptr::write_bytes(USER_CODE_PAGE_3.0.as_mut_ptr(), 0, runtime::process::GO_IMAGE_PAGE_SIZE);
-        core::ptr::write_bytes(USER_CODE_PAGE_4.0.as_mut_ptr(), 0, runtime::process::GO_IMAGE_PAGE_SIZE);
-        core::ptr::write_bytes(USER_CODE_PAGE_5.0.as_mut_ptr(), 0, runtime::process::GO_IMAGE_PAGE_SIZE);
-        core::ptr::write_bytes(USER_CODE_PAGE_6.0.as_mut_ptr(), 0, runtime::process::GO_IMAGE_PAGE_SIZE);
-
-        let chunks = [
-            runtime::process::go_image_chunk(blob, 0),
-            runtime::process::go_image_chunk(blob, 1),
-            runtime::process::go_image_chunk(blob, 2),
-            runtime::process::go_image_chunk(blob, 3),
-            runtime::process::go_image_chunk(blob, 4),
-            runtime::process::go_image_chunk(blob, 5),
-        ];
-        let pages = [
-            USER_CODE_PAGE.0.as_mut_ptr(),
-            USER_CODE_PAGE_2.0.as_mut_ptr(),
-            USER_CODE_PAGE_3.0.as_mut_ptr(),
-            USER_CODE_PAGE_4.0.as_mut_ptr(),
-            USER_CODE_PAGE_5.0.as_mut_ptr(),
-            USER_CODE_PAGE_6.0.as_mut_ptr(),
-        ];
-        for i in 0..runtime::process::GO_IMAGE_MAX_PAGES {
-            if chunks[i].is_empty() {
-                continue;
+        // The Go services run as threads of task 0, so the whole image, every
+        // thread stack slot and the heap live in a single process space.
+        let space = match vm::vm_space_create() {
+            Some(space) => space,
+            None => {
+                serial_write(b"GO: space alloc fail\n");
+                qemu_exit(0x33);
+                loop { core::arch::asm!("cli; hlt", options(nomem, nostack)); }
             }
-            core::ptr::copy_nonoverlapping(
-                chunks[i].as_ptr(),
-                pages[i],
-                chunks[i].len(),
-            );
+        };
+        // The image is copied into pool frames at `USER_CODE_VA`; only the
+        // space's memory limit bounds its size.
+        let mut mapped = vm::vm_load_segment(
+            space,
+            USER_CODE_VA,
+            blob.len() as u64,
+            blob,
+            vm::VM_PROT_READ | vm::VM_PROT_WRITE | vm::VM_PROT_EXEC,
+        );
+        // Each thread slot gets a growable stack under
+        // `GO_USER_STACK_REGION_TOP`; the heap is only backed once the runtime
+        // allocator touches it.
+        mapped &= r4_reserve_slot_stacks(space);
+        mapped &= vm::vm_area_reserve(
+            space,
+            GO_USER_HEAP_BASE,
+            GO_USER_HEAP_TOP,
+            vm::VM_PROT_READ | vm::VM_PROT_WRITE,
+        );
+        if !mapped {
+            serial_write(b"GO: space map fail\n");
+            qemu_exit(0x33);
+            loop { core::arch::asm!("cli; hlt", options(nomem, nostack)); }
         }
+        R4_TASKS[0].space = space;
 
-        let new_pml4_phys = kv2p(new_pml4 as u64);
-        core::arch::asm!("mov cr3, {}", in(reg) new_pml4_phys, options(nostack));
+        vm::vm_activate(space);
     }
 }
 
@@ -3629,6 +7169,62 @@ unsafe fn block_flush_dispatch() -> bool {
     }
 }
 
+/// Read a `size`-byte package starting at `sector` into frames carved from
+/// the memory map, a page of sectors at a time.
+#[cfg(any(feature = "fs_test", feature = "go_test"))]
+unsafe fn pkg_read(sector: u64, size: usize) -> Option<&'static [u8]> {
+    let hhdm_resp_ptr = core::ptr::read_volatile(core::ptr::addr_of!(HHDM_REQUEST.response));
+    let phys = pmm::pmm_carve(size.div_ceil(4096), 4096)?;
+    let buf = (phys + (*hhdm_resp_ptr).offset) as *mut u8;
+    let mut done = 0usize;
+    while done < size {
+        let chunk = core::cmp::min(size - done, 4096);
+        if !block_io_dispatch(false, sector + (done / 512) as u64, (chunk + 511) & !511, false) {
+            return None;
+        }
+        core::ptr::copy_nonoverlapping(BLK_DATA_PAGE.0.as_ptr(), buf.add(done), chunk);
+        done += chunk;
+    }
+    Some(core::slice::from_raw_parts(buf, size))
+}
+
+/// Find `<name>.pkg` in the SimpleFS file table on the block disk and return
+/// its first sector and size. None if the disk holds no SimpleFS.
+#[cfg(feature = "go_test")]
+unsafe fn pkg_find(name: &[u8]) -> Option<(u64, usize)> {
+    let mut file = [0u8; 24];
+    let file_len = name.len() + 4;
+    if name.is_empty() || file_len > file.len() {
+        return None;
+    }
+    file[..name.len()].copy_from_slice(name);
+    file[name.len()..file_len].copy_from_slice(b".pkg");
+
+    let mut sector = [0u8; 512];
+    if !block_io_dispatch(false, 0, 512, false) {
+        return None;
+    }
+    core::ptr::copy_nonoverlapping(BLK_DATA_PAGE.0.as_ptr(), sector.as_mut_ptr(), 512);
+    if u32::from_le_bytes([sector[0], sector[1], sector[2], sector[3]]) != runtime::storage::SIMPLEFS_MAGIC {
+        return None;
+    }
+    let file_count = u32::from_le_bytes([sector[4], sector[5], sector[6], sector[7]]).min(16) as usize;
+    if !block_io_dispatch(false, 1, 512, false) {
+        return None;
+    }
+    core::ptr::copy_nonoverlapping(BLK_DATA_PAGE.0.as_ptr(), sector.as_mut_ptr(), 512);
+    sector[..file_count * 32]
+        .as_chunks::<32>()
+        .0
+        .iter()
+        .find(|entry| entry[..24] == file)
+        .map(|entry| {
+            let start = u32::from_le_bytes([entry[24], entry[25], entry[26], entry[27]]);
+            let size = u32::from_le_bytes([entry[28], entry[29], entry[30], entry[31]]);
+            (start as u64, size as usize)
+        })
+}
+
 // --------------- M5: VirtIO block init ---------------------------------------
 
 #[cfg(any(feature = "blk_test", feature = "blk_invariants_test", feature = "fs_test", feature = "go_test"))]
@@ -4660,7 +8256,7 @@ static QUOTA_THREADS_BLOB: [u8; 87] = [
 
 // SHM blobs
 #[cfg(feature = "shm_test")]
-static SHM_WRITER_BLOB: [u8; 69] = [
+static SHM_WRITER_BLOB: [u8; 68] = [
     // sys_shm_create(4096)
     0xbf, 0x00, 0x10, 0x00, 0x00,               // mov edi, 4096
     0xb8, 0x06, 0x00, 0x00, 0x00,               // mov eax, 6
@@ -4680,18 +8276,17 @@ static SHM_WRITER_BLOB: [u8; 69] = [
     0xff, 0xc1,                                   // inc ecx
     0x81, 0xf9, 0x00, 0x01, 0x00, 0x00,         // cmp ecx, 256
     0x75, 0xf3,                                   // jne .loop (-13)
-    // Send handle byte via IPC endpoint 0
-    0x53,                                         // push rbx
+    // sys_shm_send(0, handle, READ): the reader only gets read access
     0x31, 0xff,                                   // xor edi, edi
-    0x48, 0x89, 0xe6,                             // mov rsi, rsp
+    0x48, 0x89, 0xde,                             // mov rsi, rbx
     0xba, 0x01, 0x00, 0x00, 0x00,               // mov edx, 1
-    0xb8, 0x08, 0x00, 0x00, 0x00,               // mov eax, 8
+    0xb8, 0x31, 0x00, 0x00, 0x00,               // mov eax, 49
     0xcd, 0x80,                                   // int 0x80
     0xf4,                                         // hlt
 ];
 
 #[cfg(feature = "shm_test")]
-static SHM_READER_BLOB: [u8; 105] = [
+static SHM_READER_BLOB: [u8; 108] = [
     // sys_ipc_recv(0, stack_buf, 8)
     0x48, 0x83, 0xec, 0x10,                     // sub rsp, 16
     0x31, 0xff,                                   // xor edi, edi
@@ -4699,18 +8294,18 @@ static SHM_READER_BLOB: [u8; 105] = [
     0xba, 0x08, 0x00, 0x00, 0x00,               // mov edx, 8
     0xb8, 0x09, 0x00, 0x00, 0x00,               // mov eax, 9
     0xcd, 0x80,                                   // int 0x80
-    // Load handle from buffe
-    0x0f, 0xb6, 0x3c, 0x24,                     // movzx edi, byte [rsp]
-    // sys_shm_map(handle, 0x500000, 0)
+    // Load the received handle id from the buffer
+    0x48, 0x8b, 0x3c, 0x24,                     // mov rdi, [rsp]
+    // sys_shm_map(handle, 0x500000, READONLY)
     0xbe, 0x00, 0x00, 0x50, 0x00,               // mov esi, 0x500000
-    0x31, 0xd2,                                   // xor edx, edx
+    0xba, 0x01, 0x00, 0x00, 0x00,               // mov edx, 1
     0xb8, 0x07, 0x00, 0x00, 0x00,               // mov eax, 7
     0xcd, 0x80,                                   // int 0x80
     // Compute checksum: sum 256 bytes
     0x48, 0x89, 0xc6,                             // mov rsi, rax
     0x31, 0xc9,                                   // xor ecx, ecx
     0x31, 0xd2,                                   // xor edx, edx
-    // .loop @46:
+    // .loop @49:
     0x0f, 0xb6, 0x04, 0x0e,                     // movzx eax, byte [rsi+rcx]
     0x01, 0xc2,                                   // add edx, eax
     0xff, 0xc1,                                   // inc ecx
@@ -4720,14 +8315,14 @@ static SHM_READER_BLOB: [u8; 105] = [
     0x81, 0xfa, 0x80, 0x7f, 0x00, 0x00,         // cmp edx, 32640
     0x75, 0x11,                                   // jne .bad (+17)
     // sys_debug_write("SHM: checksum ok\n", 17)
-    0x48, 0x8d, 0x3d, 0x0b, 0x00, 0x00, 0x00,   // lea rdi, [rip+0x0B] -> msg @88
+    0x48, 0x8d, 0x3d, 0x0b, 0x00, 0x00, 0x00,   // lea rdi, [rip+0x0B] -> msg @91
     0xbe, 0x11, 0x00, 0x00, 0x00,               // mov esi, 17
     0x31, 0xc0,                                   // xor eax, eax
     0xcd, 0x80,                                   // int 0x80
     0xf4,                                         // hlt
     // .bad:
     0xf4,                                         // hlt
-    // Data @88:
+    // Data @91:
     b'S', b'H', b'M', b':', b' ', b'c', b'h', b'e',
     b'c', b'k', b's', b'u', b'm', b' ', b'o', b'k', b'\n',
 ];
@@ -4968,8 +8563,9 @@ pub extern "C" fn kmain() -> ! {
         R4_NUM_TASKS = 2;
         r4_init_task(0, USER_CODE_VA, USER_STACK_TOP, 0);
         r4_init_task(1, USER_CODE2_VA, USER_STACK2_TOP, 1);
-        R4_TASKS[0].state = R4State::Running;
-        R4_CURRENT = 0;
+        r4_set_state(1, R4State::Ready);
+        r4_set_state(0, R4State::Running);
+        r4_set_current(0);
 
         // Enter ring 3 with task 0 (pong)
         enter_ring3_at(USER_CODE_VA, USER_STACK_TOP);
@@ -4995,10 +8591,13 @@ pub extern "C" fn kmain() -> ! {
         R4_NUM_TASKS = 4;
         r4_init_task(0, USER_CODE_VA, USER_STACK_TOP, 0);
         r4_init_task(1, USER_CODE2_VA, USER_STACK2_TOP, 1);
+        r4_set_state(1, R4State::Ready);
         r4_init_task(2, USER_CODE3_VA, USER_STACK3_TOP, 2);
+        r4_set_state(2, R4State::Ready);
         r4_init_task(3, USER_CODE4_VA, USER_STACK4_TOP, 3);
-        R4_TASKS[0].state = R4State::Running;
-        R4_CURRENT = 0;
+        r4_set_state(3, R4State::Ready);
+        r4_set_state(0, R4State::Running);
+        r4_set_current(0);
 
         enter_ring3_at(USER_CODE_VA, USER_STACK_TOP);
     }
@@ -5014,8 +8613,8 @@ pub extern "C" fn kmain() -> ! {
             setup_r4_pages(&SHM_PRESSURE_BLOB, &SHM_PRESSURE_BLOB);
             R4_NUM_TASKS = 1;
             r4_init_task(0, USER_CODE_VA, USER_STACK_TOP, 0);
-            R4_TASKS[0].state = R4State::Running;
-            R4_CURRENT = 0;
+            r4_set_state(0, R4State::Running);
+            r4_set_current(0);
             enter_ring3_at(USER_CODE_VA, USER_STACK_TOP);
         }
 
@@ -5030,8 +8629,9 @@ pub extern "C" fn kmain() -> ! {
             R4_NUM_TASKS = 2;
             r4_init_task(0, USER_CODE_VA, USER_STACK_TOP, 0);
             r4_init_task(1, USER_CODE2_VA, USER_STACK2_TOP, 1);
-            R4_TASKS[0].state = R4State::Running;
-            R4_CURRENT = 0;
+            r4_set_state(1, R4State::Ready);
+            r4_set_state(0, R4State::Running);
+            r4_set_current(0);
 
             enter_ring3_at(USER_CODE_VA, USER_STACK_TOP);
         }
@@ -5050,8 +8650,8 @@ pub extern "C" fn kmain() -> ! {
         // Single task
         R4_NUM_TASKS = 1;
         r4_init_task(0, USER_CODE_VA, USER_STACK_TOP, 0);
-        R4_TASKS[0].state = R4State::Running;
-        R4_CURRENT = 0;
+        r4_set_state(0, R4State::Running);
+        r4_set_current(0);
 
         enter_ring3_at(USER_CODE_VA, USER_STACK_TOP);
     }
@@ -5069,8 +8669,8 @@ pub extern "C" fn kmain() -> ! {
         // Single task
         R4_NUM_TASKS = 1;
         r4_init_task(0, USER_CODE_VA, USER_STACK_TOP, 0);
-        R4_TASKS[0].state = R4State::Running;
-        R4_CURRENT = 0;
+        r4_set_state(0, R4State::Running);
+        r4_set_current(0);
 
         enter_ring3_at(USER_CODE_VA, USER_STACK_TOP);
     }
@@ -5085,8 +8685,8 @@ pub extern "C" fn kmain() -> ! {
         // Single task (no endpoints needed for svc_register)
         R4_NUM_TASKS = 1;
         r4_init_task(0, USER_CODE_VA, USER_STACK_TOP, 0);
-        R4_TASKS[0].state = R4State::Running;
-        R4_CURRENT = 0;
+        r4_set_state(0, R4State::Running);
+        r4_set_current(0);
 
         enter_ring3_at(USER_CODE_VA, USER_STACK_TOP);
     }
@@ -5104,8 +8704,8 @@ pub extern "C" fn kmain() -> ! {
         // Single task
         R4_NUM_TASKS = 1;
         r4_init_task(0, USER_CODE_VA, USER_STACK_TOP, 0);
-        R4_TASKS[0].state = R4State::Running;
-        R4_CURRENT = 0;
+        r4_set_state(0, R4State::Running);
+        r4_set_current(0);
 
         enter_ring3_at(USER_CODE_VA, USER_STACK_TOP);
     }
@@ -5124,8 +8724,9 @@ pub extern "C" fn kmain() -> ! {
         R4_NUM_TASKS = 2;
         r4_init_task(0, USER_CODE_VA, USER_STACK_TOP, 0);
         r4_init_task(1, USER_CODE2_VA, USER_STACK2_TOP, 1);
-        R4_TASKS[0].state = R4State::Running;
-        R4_CURRENT = 0;
+        r4_set_state(1, R4State::Ready);
+        r4_set_state(0, R4State::Running);
+        r4_set_current(0);
 
         enter_ring3_at(USER_CODE_VA, USER_STACK_TOP);
     }
@@ -5144,8 +8745,8 @@ pub extern "C" fn kmain() -> ! {
         // Single task
         R4_NUM_TASKS = 1;
         r4_init_task(0, USER_CODE_VA, USER_STACK_TOP, 0);
-        R4_TASKS[0].state = R4State::Running;
-        R4_CURRENT = 0;
+        r4_set_state(0, R4State::Running);
+        r4_set_current(0);
 
         enter_ring3_at(USER_CODE_VA, USER_STACK_TOP);
     }
@@ -5167,8 +8768,8 @@ pub extern "C" fn kmain() -> ! {
         // Single task
         R4_NUM_TASKS = 1;
         r4_init_task(0, USER_CODE_VA, USER_STACK_TOP, 0);
-        R4_TASKS[0].state = R4State::Running;
-        R4_CURRENT = 0;
+        r4_set_state(0, R4State::Running);
+        r4_set_current(0);
 
         enter_ring3_at(USER_CODE_VA, USER_STACK_TOP);
     }
@@ -5183,8 +8784,8 @@ pub extern "C" fn kmain() -> ! {
         // Single task; no endpoints are pre-created on purpose.
         R4_NUM_TASKS = 1;
         r4_init_task(0, USER_CODE_VA, USER_STACK_TOP, 0);
-        R4_TASKS[0].state = R4State::Running;
-        R4_CURRENT = 0;
+        r4_set_state(0, R4State::Running);
+        r4_set_current(0);
 
         enter_ring3_at(USER_CODE_VA, USER_STACK_TOP);
     }
@@ -5198,8 +8799,8 @@ pub extern "C" fn kmain() -> ! {
 
         R4_NUM_TASKS = 1;
         r4_init_task(0, USER_CODE_VA, USER_STACK_TOP, 0);
-        R4_TASKS[0].state = R4State::Running;
-        R4_CURRENT = 0;
+        r4_set_state(0, R4State::Running);
+        r4_set_current(0);
 
         enter_ring3_at(USER_CODE_VA, USER_STACK_TOP);
     }
@@ -5213,8 +8814,8 @@ pub extern "C" fn kmain() -> ! {
 
         R4_NUM_TASKS = 1;
         r4_init_task(0, USER_CODE_VA, USER_STACK_TOP, 0);
-        R4_TASKS[0].state = R4State::Running;
-        R4_CURRENT = 0;
+        r4_set_state(0, R4State::Running);
+        r4_set_current(0);
 
         enter_ring3_at(USER_CODE_VA, USER_STACK_TOP);
     }
@@ -5228,8 +8829,8 @@ pub extern "C" fn kmain() -> ! {
 
         R4_NUM_TASKS = 1;
         r4_init_task(0, USER_CODE_VA, USER_STACK_TOP, 0);
-        R4_TASKS[0].state = R4State::Running;
-        R4_CURRENT = 0;
+        r4_set_state(0, R4State::Running);
+        r4_set_current(0);
 
         enter_ring3_at(USER_CODE_VA, USER_STACK_TOP);
     }
@@ -5318,6 +8919,9 @@ pub extern "C" fn kmain() -> ! {
     // the full stack:  block driver â†’ SimpleFS â†’ PKG â†’ user execution.
     #[cfg(feature = "fs_test")]
     unsafe {
+        // --- image signing: policy and trust root come from the boot loader ---
+        sign::sign_init();
+
         // --- block driver init (same as blk_test) ---
         let hhdm_resp_ptr = core::ptr::read_volatile(
             core::ptr::addr_of!(HHDM_REQUEST.response));
@@ -5369,6 +8973,7 @@ pub extern "C" fn kmain() -> ! {
         // --- pkg: find hello.pkg in file table ---
         let pkg_name: &[u8; 9] = b"hello.pkg";
         let mut pkg_sector = 0u32;
+        let mut pkg_size = 0usize;
         let mut pkg_found = false;
         let fc = if file_count > 16 { 16 } else { file_count as usize };
         let mut fi = 0usize;
@@ -5385,6 +8990,10 @@ pub extern "C" fn kmain() -> ! {
                     ft_buf[base + 24], ft_buf[base + 25],
                     ft_buf[base + 26], ft_buf[base + 27],
                 ]);
+                pkg_size = u32::from_le_bytes([
+                    ft_buf[base + 28], ft_buf[base + 29],
+                    ft_buf[base + 30], ft_buf[base + 31],
+                ]) as usize;
                 pkg_found = true;
                 break;
             }
@@ -5397,36 +9006,45 @@ pub extern "C" fn kmain() -> ! {
         }
 
         // --- pkg: read hello.pkg from disk ---
-        if !block_io_dispatch(false, pkg_sector as u64, 512, false) {
-            serial_write(b"PKG: read error\n");
+        if pkg_size <= 64 {
+            serial_write(b"PKG: bad size\n");
             qemu_exit(0x31);
             loop { core::arch::asm!("cli; hlt", options(nomem, nostack)); }
         }
+        let pkg = match pkg_read(pkg_sector as u64, pkg_size) {
+            Some(pkg) => pkg,
+            None => {
+                serial_write(b"PKG: read error\n");
+                qemu_exit(0x31);
+                loop { core::arch::asm!("cli; hlt", options(nomem, nostack)); }
+            }
+        };
 
         // Parse PKG v0 header: magic(4) + bin_size(4) + name(24) + sha256(32) = 64 bytes
-        let pkg_magic = u32::from_le_bytes([
-            BLK_DATA_PAGE.0[0], BLK_DATA_PAGE.0[1],
-            BLK_DATA_PAGE.0[2], BLK_DATA_PAGE.0[3],
-        ]);
+        let pkg_magic = u32::from_le_bytes([pkg[0], pkg[1], pkg[2], pkg[3]]);
         if pkg_magic != runtime::storage::PKG_MAGIC_V1 {
             serial_write(b"PKG: bad magic\n");
             qemu_exit(0x31);
             loop { core::arch::asm!("cli; hlt", options(nomem, nostack)); }
         }
-        let bin_size = u32::from_le_bytes([
-            BLK_DATA_PAGE.0[4], BLK_DATA_PAGE.0[5],
-            BLK_DATA_PAGE.0[6], BLK_DATA_PAGE.0[7],
-        ]) as usize;
-        if bin_size == 0 || bin_size > 4032 {
+        let bin_size = u32::from_le_bytes([pkg[4], pkg[5], pkg[6], pkg[7]]) as usize;
+        if bin_size == 0 || bin_size > pkg_size - 64 {
             serial_write(b"PKG: bad bin_size\n");
             qemu_exit(0x31);
             loop { core::arch::asm!("cli; hlt", options(nomem, nostack)); }
         }
+        // A signed package carries a 64-byte Ed25519 trailer over header || payload.
+        let signed = pkg_size == 64 + bin_size + sign::SIGN_SIG_SIZE;
+        if !signed && pkg_size != 64 + bin_size {
+            serial_write(b"PKG: bad size\n");
+            qemu_exit(0x31);
+            loop { core::arch::asm!("cli; hlt", options(nomem, nostack)); }
+        }
         let mut expected_hash = [0u8; 32];
-        expected_hash.copy_from_slice(&BLK_DATA_PAGE.0[32..64]);
+        expected_hash.copy_from_slice(&pkg[32..64]);
 
-        // --- sh: load hello binary into user page and run it ---
-        let hello_bin = &BLK_DATA_PAGE.0[64..64 + bin_size];
+        // --- sh: load hello binary into user pages and run it ---
+        let hello_bin = &pkg[64..64 + bin_size];
         let actual_hash = sha256_digest(hello_bin);
         if actual_hash != expected_hash {
             serial_write(b"PKG: bad hash\n");
@@ -5434,6 +9052,17 @@ pub extern "C" fn kmain() -> ! {
             loop { core::arch::asm!("cli; hlt", options(nomem, nostack)); }
         }
         serial_write(b"PKG: hash ok\n");
+
+        // --- pkg: check the signature before anything is mapped ---
+        let mut sig = [0u8; sign::SIGN_SIG_SIZE];
+        if signed {
+            sig.copy_from_slice(&pkg[64 + bin_size..pkg_size]);
+        }
+        let signed_part = &pkg[..64 + bin_size];
+        if !sign::sign_admit(b"PKG", &[signed_part], if signed { Some(&sig) } else { None }) {
+            qemu_exit(0x31);
+            loop { core::arch::asm!("cli; hlt", options(nomem, nostack)); }
+        }
         let kstack = &stack_top as *const u8 as u64;
         tss_init(kstack);
         HHDM_OFFSET = (*hhdm_resp_ptr).offset;
@@ -5449,6 +9078,12 @@ pub extern "C" fn kmain() -> ! {
             serial_write(b"PKG: elf ok\n");
             enter_ring3_at(entry, USER_STACK_TOP);
         } else {
+            // A flat binary runs from the single static code page.
+            if hello_bin.len() > 4096 {
+                serial_write(b"PKG: flat binary too large\n");
+                qemu_exit(0x31);
+                loop { core::arch::asm!("cli; hlt", options(nomem, nostack)); }
+            }
             setup_user_pages(hello_bin);
             enter_ring3_at(USER_CODE_VA, USER_STACK_TOP);
         }
@@ -5460,7 +9095,12 @@ pub extern "C" fn kmain() -> ! {
         let kstack = &stack_top as *const u8 as u64;
         tss_init(kstack);
         net::r4_c4_runtime_init();
+        limits::limits_init();
+        sign::sign_init();
+        smp::smp_start();
+        r4_timer_start();
         COMPAT_REAL_APP_INDEX = 0;
+        smp::smp_lock();
         process::compat_real_enter_current_app();
     }
 
@@ -5469,16 +9109,21 @@ pub extern "C" fn kmain() -> ! {
         let kstack = &stack_top as *const u8 as u64;
         tss_init(kstack);
         net::r4_c4_runtime_init();
+        limits::limits_init();
+        sign::sign_init();
+        smp::smp_start();
         #[cfg(feature = "go_desktop_test")]
         let go_user_bin = GO_DESKTOP_BIN;
         #[cfg(not(feature = "go_desktop_test"))]
         let go_user_bin = GO_USER_BIN;
         setup_go_user_pages(go_user_bin);
         R4_NUM_TASKS = 1;
-        r4_init_task(0, USER_CODE_VA, USER_STACK_TOP, 0);
-        R4_TASKS[0].state = R4State::Running;
-        R4_CURRENT = 0;
-        enter_ring3_at(USER_CODE_VA, USER_STACK_TOP);
+        r4_init_task(0, USER_CODE_VA, r4_stack_top_for_slot(0), 0);
+        r4_kworkers_start();
+        r4_set_state(0, R4State::Running);
+        r4_set_current(0);
+        r4_timer_start();
+        enter_ring3_at(USER_CODE_VA, r4_stack_top_for_slot(0));
     }
 
     // G2 spike: go_std_test â€” std-port candidate user program
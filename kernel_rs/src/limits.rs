@@ -0,0 +1,198 @@
+// Boot-time policy limits, read from the kernel command line. The tables
+// they size are allocated at boot from the kernel-object pool (`kobj`), so
+// a limit is the real size of the table rather than a cap on a compiled-in
+// array.
+//
+// - `rugo.max_tasks=N` caps live R4 user tasks, threads included.
+// - `rugo.max_threads=N` caps live R4 threads.
+// - `rugo.vm_frames=N` sizes the user frame pool, which the memory map may
+//   clamp further.
+// - `rugo.vm_large_frames=N` sizes the pool of 2 MiB user frames the same
+//   way; 0 turns large pages off.
+// - `rugo.kobj_frames=N` sizes the kernel-object pool.
+// - `rugo.m3_threads=N` sizes the M3 thread table.
+//
+// A missing or malformed value keeps the default; an out-of-range one is
+// clamped.
+
+use crate::*;
+
+#[cfg(r4)]
+const LIMIT_TASKS_KEY: &[u8] = b"rugo.max_tasks=";
+#[cfg(r4)]
+const LIMIT_THREADS_KEY: &[u8] = b"rugo.max_threads=";
+#[cfg(r4)]
+const LIMIT_VM_FRAMES_KEY: &[u8] = b"rugo.vm_frames=";
+#[cfg(r4)]
+const LIMIT_VM_LARGE_FRAMES_KEY: &[u8] = b"rugo.vm_large_frames=";
+const LIMIT_KOBJ_FRAMES_KEY: &[u8] = b"rugo.kobj_frames=";
+#[cfg(m3)]
+const LIMIT_M3_THREADS_KEY: &[u8] = b"rugo.m3_threads=";
+// Smallest pool that still holds a task space and its first pages.
+#[cfg(r4)]
+const MIN_VM_FRAMES: usize = 16;
+// Smallest kernel-object pool that holds the default tables.
+const MIN_KOBJ_FRAMES: usize = 16;
+// The task counts the lanes ran with before they became a boot option.
+#[cfg(any(feature = "stress_ipc_test", feature = "go_test"))]
+const DEFAULT_MAX_TASKS: usize = 6;
+#[cfg(all(r4, not(any(feature = "stress_ipc_test", feature = "go_test"))))]
+const DEFAULT_MAX_TASKS: usize = 2;
+#[cfg(m3)]
+const DEFAULT_M3_THREADS: usize = 4;
+
+#[derive(Clone, Copy)]
+struct BootLimits {
+    #[cfg(r4)]
+    tasks: usize,
+    #[cfg(r4)]
+    threads: usize,
+    #[cfg(r4)]
+    vm_frames: usize,
+    #[cfg(r4)]
+    vm_large_frames: usize,
+    kobj_frames: usize,
+    #[cfg(m3)]
+    m3_threads: usize,
+}
+
+impl BootLimits {
+    const DEFAULT: Self = Self {
+        #[cfg(r4)]
+        tasks: DEFAULT_MAX_TASKS,
+        #[cfg(r4)]
+        threads: MAX_THREADS_GLOBAL,
+        #[cfg(r4)]
+        vm_frames: vm::VM_DEFAULT_FRAMES,
+        #[cfg(r4)]
+        vm_large_frames: vm::VM_DEFAULT_LARGE_FRAMES,
+        kobj_frames: kobj::KOBJ_DEFAULT_FRAMES,
+        #[cfg(m3)]
+        m3_threads: DEFAULT_M3_THREADS,
+    };
+}
+
+static mut BOOT_LIMITS: BootLimits = BootLimits::DEFAULT;
+static mut BOOT_LIMITS_READ: bool = false;
+
+fn parse_count(value: &[u8]) -> Option<usize> {
+    if value.is_empty() {
+        return None;
+    }
+    let mut count = 0usize;
+    for &b in value {
+        if !b.is_ascii_digit() {
+            return None;
+        }
+        count = count.checked_mul(10)?.checked_add((b - b'0') as usize)?;
+    }
+    Some(count)
+}
+
+/// Set `field` from `value`, or log `name` and keep the default.
+fn parse_into(field: &mut usize, value: &[u8], name: &[u8]) {
+    match parse_count(value) {
+        Some(count) => *field = count,
+        None => {
+            serial_write(b"LIMITS: bad ");
+            serial_write(name);
+            serial_write(b", using default\n");
+        }
+    }
+}
+
+fn parse_limits(cmdline: &[u8]) -> BootLimits {
+    let mut limits = BootLimits::DEFAULT;
+    for word in cmdline.split(|&b| b == b' ') {
+        #[cfg(r4)]
+        if let Some(value) = word.strip_prefix(LIMIT_TASKS_KEY) {
+            parse_into(&mut limits.tasks, value, b"max_tasks");
+        } else if let Some(value) = word.strip_prefix(LIMIT_THREADS_KEY) {
+            parse_into(&mut limits.threads, value, b"max_threads");
+        } else if let Some(value) = word.strip_prefix(LIMIT_VM_FRAMES_KEY) {
+            parse_into(&mut limits.vm_frames, value, b"vm_frames");
+        } else if let Some(value) = word.strip_prefix(LIMIT_VM_LARGE_FRAMES_KEY) {
+            parse_into(&mut limits.vm_large_frames, value, b"vm_large_frames");
+        }
+        #[cfg(m3)]
+        if let Some(value) = word.strip_prefix(LIMIT_M3_THREADS_KEY) {
+            parse_into(&mut limits.m3_threads, value, b"m3_threads");
+        }
+        if let Some(value) = word.strip_prefix(LIMIT_KOBJ_FRAMES_KEY) {
+            parse_into(&mut limits.kobj_frames, value, b"kobj_frames");
+        }
+    }
+    #[cfg(r4)]
+    {
+        limits.tasks = limits.tasks.clamp(1, R4_TASK_LIMIT_MAX);
+        limits.threads = limits.threads.min(MAX_THREADS_GLOBAL);
+        limits.vm_frames = limits.vm_frames.max(MIN_VM_FRAMES);
+    }
+    #[cfg(m3)]
+    {
+        limits.m3_threads = limits.m3_threads.clamp(1, M3_MAX_THREADS);
+    }
+    limits.kobj_frames = limits.kobj_frames.max(MIN_KOBJ_FRAMES);
+    limits
+}
+
+unsafe fn boot_limits() -> &'static BootLimits {
+    if !BOOT_LIMITS_READ {
+        BOOT_LIMITS = parse_limits(boot_cmdline());
+        BOOT_LIMITS_READ = true;
+    }
+    &BOOT_LIMITS
+}
+
+/// Read the limits from the command line and log them. Runs before the
+/// first task space is built, since the stack reservation follows the task
+/// limit; lanes that skip it read the limits on first use.
+#[cfg(feature = "go_test")]
+pub(crate) unsafe fn limits_init() {
+    let limits = boot_limits();
+    serial_write(b"LIMITS: tasks=");
+    serial_write_u64_dec(limits.tasks as u64);
+    serial_write(b" threads=");
+    serial_write_u64_dec(limits.threads as u64);
+    serial_write(b" vm_frames=");
+    serial_write_u64_dec(limits.vm_frames as u64);
+    serial_write(b" vm_large_frames=");
+    serial_write_u64_dec(limits.vm_large_frames as u64);
+    serial_write(b" kobj_frames=");
+    serial_write_u64_dec(limits.kobj_frames as u64);
+    #[cfg(m3)]
+    {
+        serial_write(b" m3_threads=");
+        serial_write_u64_dec(limits.m3_threads as u64);
+    }
+    serial_write(b"\n");
+}
+
+#[cfg(r4)]
+pub(crate) unsafe fn max_tasks() -> usize {
+    boot_limits().tasks
+}
+
+#[cfg(r4)]
+pub(crate) unsafe fn max_threads() -> usize {
+    boot_limits().threads
+}
+
+#[cfg(r4)]
+pub(crate) unsafe fn vm_frames() -> usize {
+    boot_limits().vm_frames
+}
+
+#[cfg(r4)]
+pub(crate) unsafe fn vm_large_frames() -> usize {
+    boot_limits().vm_large_frames
+}
+
+pub(crate) unsafe fn kobj_frames() -> usize {
+    boot_limits().kobj_frames
+}
+
+#[cfg(all(m3, any(not(feature = "go_test"), feature = "compat_real_test")))]
+pub(crate) unsafe fn m3_threads() -> usize {
+    boot_limits().m3_threads
+}
@@ -1,12 +1,81 @@
 // User virtual-address validation and copy helpers.
 
+#[cfg(user_mode)]
 use crate::HHDM_OFFSET;
 
 pub(crate) const USER_VA_LIMIT: u64 = 0x0000_8000_0000_0000;
 pub(crate) const USER_PERM_READ: u64 = 1 << 0;
 pub(crate) const USER_PERM_WRITE: u64 = 1 << 1;
+#[cfg(user_mode)]
 const USER_COPYINSTR_MAX: usize = 256;
+#[cfg(user_mode)]
+const PTE_ADDR_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+#[cfg(user_mode)]
+const PTE_PS: u64 = 1 << 7;
+const PF_PRESENT: u64 = 1 << 0;
+const PF_WRITE: u64 = 1 << 1;
 
+// User copies touch user memory without walking the page tables first. Each
+// instruction that may fault on a user address has an entry in the exception
+// table below; the #PF handler resumes at its fixup, which returns an error.
+core::arch::global_asm!(
+    ".section .text.rugo_user_copy, \"ax\"",
+    // rugo_user_copy(dst, src, len) -> 0, or 1 after a fault
+    ".global rugo_user_copy",
+    "rugo_user_copy:",
+    "    mov rcx, rdx",
+    "rugo_user_copy_insn:",
+    "    rep movsb",
+    "    xor eax, eax",
+    "    ret",
+    "rugo_user_copy_fixup:",
+    "    mov eax, 1",
+    "    ret",
+    // rugo_user_copy_str(dst, src, max) -> bytes copied up to and including
+    // the NUL (or max without one), or -1 after a fault
+    ".global rugo_user_copy_str",
+    "rugo_user_copy_str:",
+    "    xor eax, eax",
+    "rugo_user_copy_str_loop:",
+    "    cmp rax, rdx",
+    "    je rugo_user_copy_str_done",
+    "rugo_user_copy_str_insn:",
+    "    movzx ecx, byte ptr [rsi + rax]",
+    "    mov byte ptr [rdi + rax], cl",
+    "    inc rax",
+    "    test ecx, ecx",
+    "    jnz rugo_user_copy_str_loop",
+    "rugo_user_copy_str_done:",
+    "    ret",
+    "rugo_user_copy_str_fixup:",
+    "    mov rax, -1",
+    "    ret",
+    ".section .rodata.rugo_ex_table, \"a\"",
+    ".balign 8",
+    ".global rugo_ex_table_start",
+    "rugo_ex_table_start:",
+    "    .quad rugo_user_copy_insn, rugo_user_copy_fixup",
+    "    .quad rugo_user_copy_str_insn, rugo_user_copy_str_fixup",
+    ".global rugo_ex_table_end",
+    "rugo_ex_table_end:",
+    ".text",
+);
+
+#[repr(C)]
+struct ExTableEntry {
+    insn: u64,
+    fixup: u64,
+}
+
+extern "C" {
+    static rugo_ex_table_start: ExTableEntry;
+    static rugo_ex_table_end: ExTableEntry;
+    fn rugo_user_copy(dst: *mut u8, src: *const u8, len: usize) -> u64;
+    #[cfg(user_mode)]
+    fn rugo_user_copy_str(dst: *mut u8, src: *const u8, max: usize) -> i64;
+}
+
+#[cfg(user_mode)]
 #[allow(dead_code)]
 pub(crate) struct Vec<T> {
     len: usize,
@@ -14,6 +83,7 @@ pub(crate) struct Vec<T> {
     _marker: core::marker::PhantomData<T>,
 }
 
+#[cfg(user_mode)]
 impl Vec<u8> {
     fn new() -> Self {
         Self {
@@ -23,20 +93,12 @@ impl Vec<u8> {
         }
     }
 
-    fn push(&mut self, b: u8) -> Result<(), ()> {
-        if self.len >= self.buf.len() {
-            return Err(());
-        }
-        self.buf[self.len] = b;
-        self.len += 1;
-        Ok(())
-    }
-
     fn as_slice(&self) -> &[u8] {
         &self.buf[..self.len]
     }
 }
 
+#[cfg(user_mode)]
 impl core::ops::Deref for Vec<u8> {
     type Target = [u8];
 
@@ -45,50 +107,54 @@ impl core::ops::Deref for Vec<u8> {
     }
 }
 
-pub(crate) unsafe fn check_page_user_perms(va: u64, hhdm: u64, required_perms: u64) -> bool {
-    let need_write = (required_perms & USER_PERM_WRITE) != 0;
+/// Walk the active page table to the leaf covering `va`. Every table entry
+/// on the way must carry all `need` bits. Returns the leaf slot and the size
+/// it maps: 4 KiB for a PT entry, 2 MiB or 1 GiB for a PS entry. The leaf
+/// itself is not checked, so callers can find empty slots too.
+#[cfg(user_mode)]
+pub(crate) unsafe fn user_leaf_walk(va: u64, hhdm: u64, need: u64) -> Option<(*mut u64, u64)> {
     let cr3: u64;
     core::arch::asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack));
-    let pml4_phys = cr3 & 0x000F_FFFF_FFFF_F000;
-    let pml4 = (pml4_phys + hhdm) as *const u64;
-    let pml4e = *pml4.add(((va >> 39) & 0x1FF) as usize);
-    if pml4e & 1 == 0 || pml4e & 4 == 0 {
-        return false;
-    }
-    if need_write && pml4e & 2 == 0 {
-        return false;
-    }
-    let pdpt = ((pml4e & 0x000F_FFFF_FFFF_F000) + hhdm) as *const u64;
-    let pdpte = *pdpt.add(((va >> 30) & 0x1FF) as usize);
-    if pdpte & 1 == 0 || pdpte & 4 == 0 {
-        return false;
-    }
-    if need_write && pdpte & 2 == 0 {
-        return false;
-    }
-    if pdpte & 0x80 != 0 {
-        return true;
-    }
-    let pd = ((pdpte & 0x000F_FFFF_FFFF_F000) + hhdm) as *const u64;
-    let pde = *pd.add(((va >> 21) & 0x1FF) as usize);
-    if pde & 1 == 0 || pde & 4 == 0 {
-        return false;
-    }
-    if need_write && pde & 2 == 0 {
-        return false;
-    }
-    if pde & 0x80 != 0 {
-        return true;
+    let mut table = ((cr3 & PTE_ADDR_MASK) + hhdm) as *mut u64;
+    let mut shift = 39u64;
+    loop {
+        let entry = table.add(((va >> shift) & 0x1FF) as usize);
+        if shift == 12 || (shift < 39 && *entry & 1 != 0 && *entry & PTE_PS != 0) {
+            return Some((entry, 1u64 << shift));
+        }
+        if *entry & need != need {
+            return None;
+        }
+        table = ((*entry & PTE_ADDR_MASK) + hhdm) as *mut u64;
+        shift -= 9;
     }
-    let pt = ((pde & 0x000F_FFFF_FFFF_F000) + hhdm) as *const u64;
-    let pte = *pt.add(((va >> 12) & 0x1FF) as usize);
-    if pte & 1 == 0 || pte & 4 == 0 {
-        return false;
+}
+
+/// Size of the present user leaf covering `va` if every level grants
+/// `required_perms`, so range checks can step over whole large pages.
+#[cfg(user_mode)]
+unsafe fn check_page_user_perms(va: u64, hhdm: u64, required_perms: u64) -> Option<u64> {
+    let mut need = 1 | 4;
+    if required_perms & USER_PERM_WRITE != 0 {
+        need |= 2;
     }
-    if need_write && pte & 2 == 0 {
-        return false;
+    let (leaf, size) = user_leaf_walk(va, hhdm, need)?;
+    if *leaf & need != need {
+        return None;
     }
-    true
+    Some(size)
+}
+
+// Reserved-but-unbacked pages are populated before the kernel touches them,
+// so syscalls see the same demand paging as user code.
+#[cfg(r4)]
+unsafe fn fault_in_user_page(va: u64, required_perms: u64) -> bool {
+    crate::r4_fault_in_user_page(va, (required_perms & USER_PERM_WRITE) != 0)
+}
+
+#[cfg(not(r4))]
+unsafe fn fault_in_user_page(_va: u64, _required_perms: u64) -> bool {
+    false
 }
 
 pub(crate) fn user_range_ok(ptr: u64, len: usize) -> bool {
@@ -101,7 +167,35 @@ pub(crate) fn user_range_ok(ptr: u64, len: usize) -> bool {
     }
 }
 
+#[cfg(user_mode)]
 pub(crate) unsafe fn user_pages_ok(ptr: u64, len: usize, required_perms: u64) -> bool {
+    user_pages_walk(ptr, len, required_perms, true)
+}
+
+/// `user_pages_ok` without demand paging: true only if every page is
+/// already mapped with `required_perms`. For assertions, which must not
+/// change what is mapped.
+#[cfg(user_mode)]
+unsafe fn user_pages_mapped(ptr: u64, len: usize, required_perms: u64) -> bool {
+    user_pages_walk(ptr, len, required_perms, false)
+}
+
+// A successful copy leaves every page it touched mapped, so the debug
+// cross-check below must hold, except where demand paging lets another
+// thread of a shared space unmap the range between the copy and the walk.
+// Those copies are exempt.
+#[cfg(r4)]
+unsafe fn user_copy_checkable() -> bool {
+    crate::vm::vm_active_space_private()
+}
+
+#[cfg(all(user_mode, not(r4)))]
+unsafe fn user_copy_checkable() -> bool {
+    true
+}
+
+#[cfg(user_mode)]
+unsafe fn user_pages_walk(ptr: u64, len: usize, required_perms: u64, fault_in: bool) -> bool {
     if len == 0 {
         return true;
     }
@@ -117,13 +211,43 @@ pub(crate) unsafe fn user_pages_ok(ptr: u64, len: usize, required_perms: u64) ->
     let end_page = end & !0xFFF;
     let mut page = start_page;
     loop {
-        if !check_page_user_perms(page, hhdm, required_perms) {
-            return false;
-        }
-        if page >= end_page {
+        let span = match check_page_user_perms(page, hhdm, required_perms) {
+            Some(span) => span,
+            None if fault_in && fault_in_user_page(page, required_perms) => 4096,
+            None => return false,
+        };
+        let next = (page & !(span - 1)) + span;
+        if next > end_page {
             break;
         }
-        page += 4096;
+        page = next;
+    }
+    true
+}
+
+/// Fixup address for a faulting instruction, if it is a user access.
+unsafe fn ex_table_fixup(rip: u64) -> Option<u64> {
+    let start = core::ptr::addr_of!(rugo_ex_table_start);
+    let end = core::ptr::addr_of!(rugo_ex_table_end);
+    let count = (end as usize - start as usize) / core::mem::size_of::<ExTableEntry>();
+    core::slice::from_raw_parts(start, count)
+        .iter()
+        .find(|entry| entry.insn == rip)
+        .map(|entry| entry.fixup)
+}
+
+/// Kernel-mode #PF hook. A fault on a user address inside a copy routine is
+/// serviced like a user fault (demand paging, copy-on-write) and retried, or
+/// else resumes at the routine's fixup. Returns false for any other fault.
+pub(crate) unsafe fn user_copy_fault(frame: *mut u64, cr2: u64, error_code: u64) -> bool {
+    let fixup = match ex_table_fixup(*frame.add(17)) {
+        Some(fixup) => fixup,
+        None => return false,
+    };
+    let perms = if error_code & PF_WRITE != 0 { USER_PERM_WRITE } else { USER_PERM_READ };
+    let serviceable = error_code & PF_PRESENT == 0 || error_code & PF_WRITE != 0;
+    if !(serviceable && cr2 < USER_VA_LIMIT && fault_in_user_page(cr2, perms)) {
+        *frame.add(17) = fixup;
     }
     true
 }
@@ -135,15 +259,18 @@ pub(crate) unsafe fn copyin_user(dst: &mut [u8], user_ptr: u64, len: usize) -> R
     if !user_range_ok(user_ptr, len) {
         return Err(());
     }
-    if !user_pages_ok(user_ptr, len, USER_PERM_READ) {
+    if len > 0 && rugo_user_copy(dst.as_mut_ptr(), user_ptr as *const u8, len) != 0 {
         return Err(());
     }
-    if len > 0 {
-        core::ptr::copy_nonoverlapping(user_ptr as *const u8, dst.as_mut_ptr(), len);
-    }
+    #[cfg(user_mode)]
+    debug_assert!(
+        !user_copy_checkable() || user_pages_mapped(user_ptr, len, USER_PERM_READ),
+        "copyin_user succeeded on an unmapped page (shared spaces are exempt)"
+    );
     Ok(())
 }
 
+#[cfg(any(user_mode, feature = "net_test"))]
 pub(crate) unsafe fn copyout_user(user_ptr: u64, src: &[u8], len: usize) -> Result<(), ()> {
     if len > src.len() {
         return Err(());
@@ -151,15 +278,18 @@ pub(crate) unsafe fn copyout_user(user_ptr: u64, src: &[u8], len: usize) -> Resu
     if !user_range_ok(user_ptr, len) {
         return Err(());
     }
-    if !user_pages_ok(user_ptr, len, USER_PERM_WRITE) {
+    if len > 0 && rugo_user_copy(user_ptr as *mut u8, src.as_ptr(), len) != 0 {
         return Err(());
     }
-    if len > 0 {
-        core::ptr::copy_nonoverlapping(src.as_ptr(), user_ptr as *mut u8, len);
-    }
+    #[cfg(user_mode)]
+    debug_assert!(
+        !user_copy_checkable() || user_pages_mapped(user_ptr, len, USER_PERM_WRITE),
+        "copyout_user succeeded on an unwritable page (shared spaces are exempt)"
+    );
     Ok(())
 }
 
+#[cfg(user_mode)]
 pub(crate) unsafe fn copyinstr_user(user_ptr: u64, max: usize) -> Result<Vec<u8>, ()> {
     let limit = if max > USER_COPYINSTR_MAX {
         USER_COPYINSTR_MAX
@@ -169,16 +299,15 @@ pub(crate) unsafe fn copyinstr_user(user_ptr: u64, max: usize) -> Result<Vec<u8>
     if !user_range_ok(user_ptr, limit) {
         return Err(());
     }
-    if !user_pages_ok(user_ptr, limit, USER_PERM_READ) {
-        return Err(());
-    }
     let mut out = Vec::new();
-    for i in 0..limit {
-        let b = *(user_ptr as *const u8).add(i);
-        out.push(b)?;
-        if b == 0 {
-            return Ok(out);
-        }
+    let copied = rugo_user_copy_str(out.buf.as_mut_ptr(), user_ptr as *const u8, limit);
+    if copied <= 0 || out.buf[copied as usize - 1] != 0 {
+        return Err(());
     }
-    Err(())
+    out.len = copied as usize;
+    debug_assert!(
+        !user_copy_checkable() || user_pages_mapped(user_ptr, out.len, USER_PERM_READ),
+        "copyinstr_user succeeded on an unmapped page (shared spaces are exempt)"
+    );
+    Ok(out)
 }
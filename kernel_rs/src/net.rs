@@ -177,6 +177,54 @@ pub(crate) unsafe fn virtio_net_recv(buf: &mut [u8]) -> usize {
     copy_len
 }
 
+// Frames the RX worker took off the NIC that no task has read yet. The
+// device has a single receive buffer, so draining it early keeps bursts
+// from stalling behind a slow reader.
+#[cfg(feature = "go_test")]
+const NET_RX_BACKLOG_FRAMES: usize = 4;
+#[cfg(feature = "go_test")]
+static mut NET_RX_BACKLOG: [[u8; 1514]; NET_RX_BACKLOG_FRAMES] = [[0; 1514]; NET_RX_BACKLOG_FRAMES];
+#[cfg(feature = "go_test")]
+static mut NET_RX_BACKLOG_LEN: [usize; NET_RX_BACKLOG_FRAMES] = [0; NET_RX_BACKLOG_FRAMES];
+#[cfg(feature = "go_test")]
+static mut NET_RX_BACKLOG_HEAD: usize = 0;
+#[cfg(feature = "go_test")]
+static mut NET_RX_BACKLOG_COUNT: usize = 0;
+
+/// Whether the NIC has completed a receive the driver has not taken.
+#[cfg(feature = "go_test")]
+pub(crate) unsafe fn virtio_net_rx_pending() -> bool {
+    R4_NET_NIC_READY
+        && core::ptr::read_volatile((NET_RX_USED as *const u16).add(1)) != NET_RX_LAST_USED
+}
+
+/// Network RX worker job: move completed receives into the backlog.
+#[cfg(feature = "go_test")]
+pub(crate) unsafe fn r4_net_rx_work(_arg: u64) {
+    while NET_RX_BACKLOG_COUNT < NET_RX_BACKLOG_FRAMES && virtio_net_rx_pending() {
+        let slot = (NET_RX_BACKLOG_HEAD + NET_RX_BACKLOG_COUNT) % NET_RX_BACKLOG_FRAMES;
+        let n = virtio_net_recv(&mut NET_RX_BACKLOG[slot]);
+        if n != 0 {
+            NET_RX_BACKLOG_LEN[slot] = n;
+            NET_RX_BACKLOG_COUNT += 1;
+        }
+    }
+}
+
+/// Copy the oldest backlog frame into `buf`, or `None` if there is none.
+#[cfg(feature = "go_test")]
+unsafe fn net_rx_backlog_take(buf: &mut [u8]) -> Option<usize> {
+    if NET_RX_BACKLOG_COUNT == 0 {
+        return None;
+    }
+    let slot = NET_RX_BACKLOG_HEAD;
+    let n = NET_RX_BACKLOG_LEN[slot].min(buf.len());
+    buf[..n].copy_from_slice(&NET_RX_BACKLOG[slot][..n]);
+    NET_RX_BACKLOG_HEAD = (slot + 1) % NET_RX_BACKLOG_FRAMES;
+    NET_RX_BACKLOG_COUNT -= 1;
+    Some(n)
+}
+
 #[cfg(any(feature = "net_test", feature = "go_test"))]
 unsafe fn virtio_net_send(frame: &[u8]) -> bool {
     let total_len = VIRTIO_NET_HDR_SIZE + frame.len();
@@ -250,10 +298,18 @@ pub(crate) unsafe fn sys_net_recv(buf: u64, cap: u64) -> u64 {
         return 0;
     }
     let cap_n = cap as usize;
-    if !user_range_ok(buf, cap_n) || !user_pages_ok(buf, cap_n, USER_PERM_WRITE) {
+    // A buffer that faults drops the frame, as a full one would.
+    if !user_range_ok(buf, cap_n) {
         return 0xFFFF_FFFF_FFFF_FFFF;
     }
     let mut kbuf = [0u8; 1514];
+    // Frames the RX worker already drained come first, in arrival order.
+    #[cfg(feature = "go_test")]
+    let n = match net_rx_backlog_take(&mut kbuf[..cap_n]) {
+        Some(n) => n,
+        None => virtio_net_recv(&mut kbuf[..cap_n]),
+    };
+    #[cfg(not(feature = "go_test"))]
     let n = virtio_net_recv(&mut kbuf[..cap_n]);
     if n > 0 && copyout_user(buf, &kbuf[..n], n).is_err() {
         return 0xFFFF_FFFF_FFFF_FFFF;
@@ -461,6 +517,7 @@ impl R4NetRoute {
 struct R4Socket {
     active: bool,
     owner_tid: usize,
+    holders: u64,
     domain: u8,
     kind: u8,
     state: u8,
@@ -481,6 +538,7 @@ impl R4Socket {
     const EMPTY: Self = Self {
         active: false,
         owner_tid: 0,
+        holders: 0,
         domain: 0,
         kind: 0,
         state: 0,
@@ -512,6 +570,8 @@ pub(crate) static mut R4_NET_NIC_READY: bool = false;
 #[cfg(feature = "go_test")]
 pub(crate) unsafe fn r4_net_reset(has_nic: bool) {
     R4_NET_NIC_READY = has_nic;
+    NET_RX_BACKLOG_HEAD = 0;
+    NET_RX_BACKLOG_COUNT = 0;
     for idx in 0..R4_NET_IF_MAX {
         R4_NET_INTERFACES[idx] = R4NetInterface::EMPTY;
     }
@@ -677,9 +737,47 @@ unsafe fn r4_net_alloc_socket() -> Option<usize> {
 #[cfg(feature = "go_test")]
 #[inline(always)]
 unsafe fn r4_socket_owner_ok(socket_id: usize) -> bool {
-    socket_id < R4_NET_SOCKET_MAX
-        && R4_SOCKETS[socket_id].active
-        && R4_SOCKETS[socket_id].owner_tid == R4_CURRENT
+    socket_id < R4_NET_SOCKET_MAX && r4_socket_held_by(socket_id, r4_current())
+}
+
+#[cfg(feature = "go_test")]
+#[inline(always)]
+unsafe fn r4_socket_held_by(socket_id: usize, tid: usize) -> bool {
+    R4_SOCKETS[socket_id].active
+        && (R4_SOCKETS[socket_id].owner_tid == tid
+            || R4_SOCKETS[socket_id].holders & runtime::isolation::holder_bit(tid) != 0)
+}
+
+/// Drop `tid`'s hold on a socket shared through fork. Returns false when
+/// `tid` was the last holder and the caller must release the socket.
+#[cfg(feature = "go_test")]
+unsafe fn r4_socket_drop_holder(socket_id: usize, tid: usize) -> bool {
+    let sock = &mut R4_SOCKETS[socket_id];
+    sock.holders &= !runtime::isolation::holder_bit(tid);
+    if sock.owner_tid == tid {
+        match runtime::isolation::next_holder(sock.holders) {
+            Some(next) => {
+                sock.owner_tid = next;
+                sock.holders &= !runtime::isolation::holder_bit(next);
+            }
+            None => return false,
+        }
+    }
+    if tid < R4_NUM_TASKS && R4_TASKS[tid].socket_count != 0 {
+        R4_TASKS[tid].socket_count -= 1;
+    }
+    true
+}
+
+/// Fork: the child holds every socket the parent holds.
+#[cfg(feature = "go_test")]
+pub(crate) unsafe fn r4_share_sockets(parent: usize, child: usize) {
+    for socket_id in 0..R4_NET_SOCKET_MAX {
+        if r4_socket_held_by(socket_id, parent) {
+            R4_SOCKETS[socket_id].holders |= runtime::isolation::holder_bit(child);
+        }
+    }
+    R4_TASKS[child].socket_count = R4_TASKS[parent].socket_count;
 }
 
 #[cfg(feature = "go_test")]
@@ -719,7 +817,9 @@ unsafe fn r4_release_socket(socket_id: usize) {
 #[cfg(feature = "go_test")]
 pub(crate) unsafe fn r4_release_owned_sockets(owner_tid: usize) {
     for socket_id in 0..R4_NET_SOCKET_MAX {
-        if R4_SOCKETS[socket_id].active && R4_SOCKETS[socket_id].owner_tid == owner_tid {
+        if r4_socket_held_by(socket_id, owner_tid)
+            && !r4_socket_drop_holder(socket_id, owner_tid)
+        {
             r4_release_socket(socket_id);
         }
     }
@@ -783,18 +883,18 @@ pub(crate) unsafe fn sys_socket_open_r4(domain: u64, kind: u64) -> u64 {
         return 0xFFFF_FFFF_FFFF_FFFF;
     }
     if !runtime::isolation::under_quota(
-        R4_TASKS[R4_CURRENT].socket_count,
-        R4_TASKS[R4_CURRENT].socket_limit as usize,
+        R4_TASKS[r4_current()].socket_count,
+        R4_TASKS[r4_current()].socket_limit as usize,
     ) {
         return 0xFFFF_FFFF_FFFF_FFFF;
     }
     match r4_net_alloc_socket() {
         Some(idx) => {
-            R4_SOCKETS[idx].owner_tid = R4_CURRENT;
+            R4_SOCKETS[idx].owner_tid = r4_current();
             R4_SOCKETS[idx].domain = dom;
             R4_SOCKETS[idx].kind = typ;
             R4_SOCKETS[idx].state = 1;
-            R4_TASKS[R4_CURRENT].socket_count += 1;
+            R4_TASKS[r4_current()].socket_count += 1;
             idx as u64
         }
         None => 0xFFFF_FFFF_FFFF_FFFF,
@@ -1019,7 +1119,9 @@ pub(crate) unsafe fn sys_socket_close_r4(socket_id: u64) -> u64 {
     if !r4_socket_owner_ok(sid) {
         return 0xFFFF_FFFF_FFFF_FFFF;
     }
-    r4_release_socket(sid);
+    if !r4_socket_drop_holder(sid, r4_current()) {
+        r4_release_socket(sid);
+    }
     0
 }
 
@@ -0,0 +1,79 @@
+// Physical memory for the kernel's frame pools, taken from the usable
+// entries of the Limine memory map.
+//
+// Carving is one-way: a pool takes its run once at boot and manages the
+// frames itself, so nothing is handed back here. Memory below 1 MiB is
+// left to the firmware and the bootloader.
+
+use crate::*;
+
+const PMM_MAX_RUNS: usize = 64;
+const PMM_LOW_LIMIT: u64 = 0x10_0000;
+
+#[derive(Clone, Copy)]
+struct PmmRun {
+    base: u64,
+    end: u64,
+}
+
+static mut PMM_RUNS: [PmmRun; PMM_MAX_RUNS] = [PmmRun { base: 0, end: 0 }; PMM_MAX_RUNS];
+static mut PMM_RUN_COUNT: usize = 0;
+static mut PMM_READY: bool = false;
+
+unsafe fn pmm_init() {
+    PMM_READY = true;
+    let resp = core::ptr::read_volatile(core::ptr::addr_of!(MEMMAP_REQUEST.response));
+    if resp.is_null() {
+        serial_write(b"PMM: no memory map\n");
+        return;
+    }
+    for i in 0..(*resp).entry_count as usize {
+        let entry = *(*resp).entries.add(i);
+        if (*entry).kind != LIMINE_MEMMAP_USABLE {
+            continue;
+        }
+        let base = ((*entry).base.max(PMM_LOW_LIMIT) + 0xFFF) & !0xFFF;
+        let end = ((*entry).base + (*entry).length) & !0xFFF;
+        if base >= end {
+            continue;
+        }
+        if PMM_RUN_COUNT == PMM_MAX_RUNS {
+            serial_write(b"PMM: memory map truncated\n");
+            break;
+        }
+        PMM_RUNS[PMM_RUN_COUNT] = PmmRun { base, end };
+        PMM_RUN_COUNT += 1;
+    }
+}
+
+/// Take `pages` contiguous frames starting on an `align`-byte boundary and
+/// return the physical address of the first. The frames are not zeroed.
+pub(crate) unsafe fn pmm_carve(pages: usize, align: u64) -> Option<u64> {
+    if !PMM_READY {
+        pmm_init();
+    }
+    let len = (pages as u64).checked_mul(4096)?;
+    for run in PMM_RUNS[..PMM_RUN_COUNT].iter_mut() {
+        let base = (run.base + align - 1) & !(align - 1);
+        if base.checked_add(len)? <= run.end {
+            run.base = base + len;
+            return Some(base);
+        }
+    }
+    None
+}
+
+/// Frames in the largest run not yet carved, counted from the run's first
+/// `align`-byte boundary.
+#[cfg(r4)]
+pub(crate) unsafe fn pmm_largest_run(align: u64) -> usize {
+    if !PMM_READY {
+        pmm_init();
+    }
+    let mut largest = 0u64;
+    for run in PMM_RUNS[..PMM_RUN_COUNT].iter() {
+        let base = (run.base + align - 1) & !(align - 1);
+        largest = largest.max(run.end.saturating_sub(base));
+    }
+    (largest / 4096) as usize
+}
@@ -18,25 +18,58 @@ pub(crate) unsafe fn compat_real_enter_current_app() -> ! {
 
     m3_reset_state();
     crate::net::r4_net_reset(crate::net::R4_NET_NIC_READY);
-    for tid in 0..R4_MAX_TASKS {
-        R4_TASKS[tid] = R4Task::EMPTY;
-    }
-    R4_CURRENT = 0;
+    // Rebuilds the frame pool and with it an empty task table, so the
+    // kernel workers are started after it.
+    r4_pages_init();
+    r4_set_current(0);
     R4_NUM_TASKS = 1;
-    R4_THREADS_CREATED = 0;
+    r4_kworkers_start();
 
-    let entry = match setup_user_elf_pages(app.image) {
-        Some(v) => v,
+    // Each PT_LOAD becomes an area of the app's own space, backed from the
+    // frame pool, so the image size is bounded by the space's memory limit
+    // rather than a fixed code window.
+    let space = match crate::vm::vm_space_create() {
+        Some(space) => space,
+        None => {
+            serial_write(b"X1APP: space alloc fail\n");
+            qemu_exit(0x33);
+            loop { core::arch::asm!("cli; hlt", options(nomem, nostack)); }
+        }
+    };
+    R4_TASKS[0].space = space;
+    let info = match r4_load_elf(space, app.image) {
+        Some(info) => info,
         None => {
             serial_write(b"X1APP: load fail\n");
             qemu_exit(0x33);
             loop { core::arch::asm!("cli; hlt", options(nomem, nostack)); }
         }
     };
-
-    r4_init_task(0, entry, USER_STACK_TOP, 0);
-    R4_TASKS[0].state = R4State::Running;
-    enter_ring3_at(entry, USER_STACK_TOP);
+    if !r4_reserve_slot_stacks(space) {
+        serial_write(b"X1APP: stack reserve fail\n");
+        qemu_exit(0x33);
+        loop { core::arch::asm!("cli; hlt", options(nomem, nostack)); }
+    }
+    crate::vm::vm_activate(space);
+    r4_init_task(0, info.entry, r4_stack_top_for_slot(0), 0);
+    if !r4_setup_initial_tls(0, &info) {
+        serial_write(b"X1APP: tls fail\n");
+        qemu_exit(0x33);
+        loop { core::arch::asm!("cli; hlt", options(nomem, nostack)); }
+    }
+    r4_load_fs_base(R4_TASKS[0].fs_base);
+    let user_sp = match r4_build_startup_stack(r4_stack_top_for_slot(0), &info, app.argv, app.envp, app.name) {
+        Some(sp) => sp,
+        None => {
+            serial_write(b"X1APP: startup stack fail\n");
+            qemu_exit(0x33);
+            loop { core::arch::asm!("cli; hlt", options(nomem, nostack)); }
+        }
+    };
+    R4_TASKS[0].saved_frame[20] = user_sp;
+    r4_set_state(0, R4State::Running);
+    smp::smp_unlock();
+    enter_ring3_at(info.entry, user_sp);
 }
 
 #[cfg(feature = "compat_real_test")]
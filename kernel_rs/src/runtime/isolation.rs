@@ -10,3 +10,33 @@ pub fn owner_has_right(
 ) -> bool {
     owner_tid == current_tid && (owner_rights & required_right) != 0
 }
+
+/// Task slots a holder mask can name; the R4 task limit never exceeds it.
+#[cfg(r4)]
+pub const HOLDER_SLOTS: usize = u64::BITS as usize;
+
+/// Bit for `tid` in a handle's holder mask. Handles inherited through fork
+/// keep one owner plus a mask of the other tasks that hold them. Only the R4
+/// lanes fork, so only they track holders.
+#[cfg(r4)]
+pub fn holder_bit(tid: usize) -> u64 {
+    if tid < HOLDER_SLOTS { 1u64 << tid } else { 0 }
+}
+
+#[cfg(r4)]
+pub fn holder_has_right(
+    owner_tid: usize,
+    holders: u64,
+    current_tid: usize,
+    owner_rights: u8,
+    required_right: u8,
+) -> bool {
+    owner_has_right(owner_tid, current_tid, owner_rights, required_right)
+        || (holders & holder_bit(current_tid) != 0 && (owner_rights & required_right) != 0)
+}
+
+/// The holder that inherits ownership when the owner drops a shared handle.
+#[cfg(r4)]
+pub fn next_holder(holders: u64) -> Option<usize> {
+    if holders == 0 { None } else { Some(holders.trailing_zeros() as usize) }
+}
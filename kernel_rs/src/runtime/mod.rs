@@ -1,6 +1,5 @@
 pub mod isolation;
 pub mod native;
 pub mod networking;
-pub mod process;
 pub mod security;
 pub mod storage;
@@ -1,17 +1,23 @@
-// Timer interrupt handling and the cooperative scheduler test harness.
-
-use crate::{outb, qemu_exit, serial_write};
+// PIC/PIT setup, timer interrupt handling and the cooperative scheduler
+// test harness. The R4 lane drives task preemption from the same PIT tick,
+// and its scheduler runs kernel worker threads alongside user tasks; the
+// `THREADS` harness below only backs the `sched_test` boot smoke test.
 
+#[cfg(any(feature = "sched_test", feature = "go_test"))]
+use crate::outb;
 #[cfg(feature = "sched_test")]
+use crate::{qemu_exit, serial_write};
+
+#[cfg(any(feature = "sched_test", feature = "go_test"))]
 const PIC1_CMD: u16 = 0x20;
-#[cfg(feature = "sched_test")]
+#[cfg(any(feature = "sched_test", feature = "go_test"))]
 const PIC1_DATA: u16 = 0x21;
-#[cfg(feature = "sched_test")]
+#[cfg(any(feature = "sched_test", feature = "go_test"))]
 const PIC2_CMD: u16 = 0xA0;
-#[cfg(feature = "sched_test")]
+#[cfg(any(feature = "sched_test", feature = "go_test"))]
 const PIC2_DATA: u16 = 0xA1;
 
-#[cfg(feature = "sched_test")]
+#[cfg(any(feature = "sched_test", feature = "go_test"))]
 pub(crate) unsafe fn pic_init() {
     outb(PIC1_CMD, 0x11);
     outb(PIC2_CMD, 0x11);
@@ -25,15 +31,15 @@ pub(crate) unsafe fn pic_init() {
     outb(PIC2_DATA, 0xFF);
 }
 
-#[cfg(feature = "sched_test")]
-unsafe fn pic_send_eoi(irq: u8) {
+#[cfg(any(feature = "sched_test", feature = "go_test"))]
+pub(crate) unsafe fn pic_send_eoi(irq: u8) {
     if irq >= 8 {
         outb(PIC2_CMD, 0x20);
     }
     outb(PIC1_CMD, 0x20);
 }
 
-#[cfg(feature = "sched_test")]
+#[cfg(any(feature = "sched_test", feature = "go_test"))]
 pub(crate) unsafe fn pit_init(freq: u32) {
     let divisor = 1_193_182u32 / freq;
     outb(0x43, 0x34);
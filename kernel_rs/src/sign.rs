@@ -0,0 +1,724 @@
+// Ed25519 signature verification for packages and executable images.
+//
+// The field and group arithmetic follows TweetNaCl: 16 limbs of 16 bits in
+// i64, with the point operations in extended coordinates. Only verification
+// is needed in the kernel, so there is no secret-dependent timing to hide.
+
+use crate::*;
+
+// --------------- SHA-512 ---------------
+
+const SHA512_K: [u64; 80] = [
+    0x428a2f98d728ae22, 0x7137449123ef65cd, 0xb5c0fbcfec4d3b2f, 0xe9b5dba58189dbbc,
+    0x3956c25bf348b538, 0x59f111f1b605d019, 0x923f82a4af194f9b, 0xab1c5ed5da6d8118,
+    0xd807aa98a3030242, 0x12835b0145706fbe, 0x243185be4ee4b28c, 0x550c7dc3d5ffb4e2,
+    0x72be5d74f27b896f, 0x80deb1fe3b1696b1, 0x9bdc06a725c71235, 0xc19bf174cf692694,
+    0xe49b69c19ef14ad2, 0xefbe4786384f25e3, 0x0fc19dc68b8cd5b5, 0x240ca1cc77ac9c65,
+    0x2de92c6f592b0275, 0x4a7484aa6ea6e483, 0x5cb0a9dcbd41fbd4, 0x76f988da831153b5,
+    0x983e5152ee66dfab, 0xa831c66d2db43210, 0xb00327c898fb213f, 0xbf597fc7beef0ee4,
+    0xc6e00bf33da88fc2, 0xd5a79147930aa725, 0x06ca6351e003826f, 0x142929670a0e6e70,
+    0x27b70a8546d22ffc, 0x2e1b21385c26c926, 0x4d2c6dfc5ac42aed, 0x53380d139d95b3df,
+    0x650a73548baf63de, 0x766a0abb3c77b2a8, 0x81c2c92e47edaee6, 0x92722c851482353b,
+    0xa2bfe8a14cf10364, 0xa81a664bbc423001, 0xc24b8b70d0f89791, 0xc76c51a30654be30,
+    0xd192e819d6ef5218, 0xd69906245565a910, 0xf40e35855771202a, 0x106aa07032bbd1b8,
+    0x19a4c116b8d2d0c8, 0x1e376c085141ab53, 0x2748774cdf8eeb99, 0x34b0bcb5e19b48a8,
+    0x391c0cb3c5c95a63, 0x4ed8aa4ae3418acb, 0x5b9cca4f7763e373, 0x682e6ff3d6b2b8a3,
+    0x748f82ee5defb2fc, 0x78a5636f43172f60, 0x84c87814a1f0ab72, 0x8cc702081a6439ec,
+    0x90befffa23631e28, 0xa4506cebde82bde9, 0xbef9a3f7b2c67915, 0xc67178f2e372532b,
+    0xca273eceea26619c, 0xd186b8c721c0c207, 0xeada7dd6cde0eb1e, 0xf57d4f7fee6ed178,
+    0x06f067aa72176fba, 0x0a637dc5a2c898a6, 0x113f9804bef90dae, 0x1b710b35131c471b,
+    0x28db77f523047d84, 0x32caab7b40c72493, 0x3c9ebe0a15c9bebc, 0x431d67c49c100d4c,
+    0x4cc5d4becb3e42b6, 0x597f299cfc657e2a, 0x5fcb6fab3ad6faec, 0x6c44198c4a475817,
+];
+
+const SHA512_IV: [u64; 8] = [
+    0x6a09e667f3bcc908, 0xbb67ae8584caa73b, 0x3c6ef372fe94f82b, 0xa54ff53a5f1d36f1,
+    0x510e527fade682d1, 0x9b05688c2b3e6c1f, 0x1f83d9abfb41bd6b, 0x5be0cd19137e2179,
+];
+
+struct Sha512 {
+    state: [u64; 8],
+    block: [u8; 128],
+    used: usize,
+    total: u64,
+}
+
+impl Sha512 {
+    fn new() -> Self {
+        Sha512 { state: SHA512_IV, block: [0; 128], used: 0, total: 0 }
+    }
+
+    fn compress(&mut self) {
+        let mut w = [0u64; 80];
+        let mut t = 0usize;
+        while t < 16 {
+            let mut word = [0u8; 8];
+            word.copy_from_slice(&self.block[t * 8..t * 8 + 8]);
+            w[t] = u64::from_be_bytes(word);
+            t += 1;
+        }
+        while t < 80 {
+            let s0 = w[t - 15].rotate_right(1) ^ w[t - 15].rotate_right(8) ^ (w[t - 15] >> 7);
+            let s1 = w[t - 2].rotate_right(19) ^ w[t - 2].rotate_right(61) ^ (w[t - 2] >> 6);
+            w[t] = w[t - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[t - 7])
+                .wrapping_add(s1);
+            t += 1;
+        }
+
+        let mut v = self.state;
+        t = 0;
+        while t < 80 {
+            let s1 = v[4].rotate_right(14) ^ v[4].rotate_right(18) ^ v[4].rotate_right(41);
+            let ch = (v[4] & v[5]) ^ (!v[4] & v[6]);
+            let t1 = v[7]
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(SHA512_K[t])
+                .wrapping_add(w[t]);
+            let s0 = v[0].rotate_right(28) ^ v[0].rotate_right(34) ^ v[0].rotate_right(39);
+            let maj = (v[0] & v[1]) ^ (v[0] & v[2]) ^ (v[1] & v[2]);
+            let t2 = s0.wrapping_add(maj);
+            v[7] = v[6];
+            v[6] = v[5];
+            v[5] = v[4];
+            v[4] = v[3].wrapping_add(t1);
+            v[3] = v[2];
+            v[2] = v[1];
+            v[1] = v[0];
+            v[0] = t1.wrapping_add(t2);
+            t += 1;
+        }
+
+        let mut i = 0usize;
+        while i < 8 {
+            self.state[i] = self.state[i].wrapping_add(v[i]);
+            i += 1;
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.total = self.total.wrapping_add(data.len() as u64);
+        for &byte in data {
+            self.block[self.used] = byte;
+            self.used += 1;
+            if self.used == 128 {
+                self.compress();
+                self.used = 0;
+            }
+        }
+    }
+
+    fn finish(mut self) -> [u8; 64] {
+        let bits = self.total.wrapping_mul(8);
+        self.block[self.used] = 0x80;
+        self.used += 1;
+        if self.used > 112 {
+            self.block[self.used..].fill(0);
+            self.compress();
+            self.used = 0;
+        }
+        self.block[self.used..120].fill(0);
+        self.block[120..128].copy_from_slice(&bits.to_be_bytes());
+        self.compress();
+
+        let mut out = [0u8; 64];
+        let mut i = 0usize;
+        while i < 8 {
+            out[i * 8..i * 8 + 8].copy_from_slice(&self.state[i].to_be_bytes());
+            i += 1;
+        }
+        out
+    }
+}
+
+// --------------- Field arithmetic mod 2^255 - 19 ---------------
+
+type Gf = [i64; 16];
+
+const GF0: Gf = [0; 16];
+const GF1: Gf = [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+const ED_D: Gf = [
+    0x78a3, 0x1359, 0x4dca, 0x75eb, 0xd8ab, 0x4141, 0x0a4d, 0x0070,
+    0xe898, 0x7779, 0x4079, 0x8cc7, 0xfe73, 0x2b6f, 0x6cee, 0x5203,
+];
+const ED_D2: Gf = [
+    0xf159, 0x26b2, 0x9b94, 0xebd6, 0xb156, 0x8283, 0x149a, 0x00e0,
+    0xd130, 0xeef3, 0x80f2, 0x198e, 0xfce7, 0x56df, 0xd9dc, 0x2406,
+];
+const ED_X: Gf = [
+    0xd51a, 0x8f25, 0x2d60, 0xc956, 0xa7b2, 0x9525, 0xc760, 0x692c,
+    0xdc5c, 0xfdd6, 0xe231, 0xc0a4, 0x53fe, 0xcd6e, 0x36d3, 0x2169,
+];
+const ED_Y: Gf = [
+    0x6658, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666,
+    0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666,
+];
+// sqrt(-1) mod p.
+const ED_I: Gf = [
+    0xa0b0, 0x4a0e, 0x1b27, 0xc4ee, 0xe478, 0xad2f, 0x1806, 0x2f43,
+    0xd7a7, 0x3dfb, 0x0099, 0x2b4d, 0xdf0b, 0x4fc1, 0x2480, 0x2b83,
+];
+
+// Group order L, little-endian bytes.
+const ED_L: [i64; 32] = [
+    0xed, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58,
+    0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9, 0xde, 0x14,
+    0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0x10,
+];
+
+fn car25519(o: &mut Gf) {
+    let mut i = 0usize;
+    while i < 16 {
+        o[i] += 1 << 16;
+        let c = o[i] >> 16;
+        if i < 15 {
+            o[i + 1] += c - 1;
+        } else {
+            o[0] += 38 * (c - 1);
+        }
+        o[i] -= c << 16;
+        i += 1;
+    }
+}
+
+fn sel25519(p: &mut Gf, q: &mut Gf, b: i64) {
+    let c = !(b - 1);
+    let mut i = 0usize;
+    while i < 16 {
+        let t = c & (p[i] ^ q[i]);
+        p[i] ^= t;
+        q[i] ^= t;
+        i += 1;
+    }
+}
+
+fn pack25519(n: &Gf) -> [u8; 32] {
+    let mut t = *n;
+    car25519(&mut t);
+    car25519(&mut t);
+    car25519(&mut t);
+    let mut m = GF0;
+    let mut pass = 0;
+    while pass < 2 {
+        m[0] = t[0] - 0xffed;
+        let mut i = 1usize;
+        while i < 15 {
+            m[i] = t[i] - 0xffff - ((m[i - 1] >> 16) & 1);
+            m[i - 1] &= 0xffff;
+            i += 1;
+        }
+        m[15] = t[15] - 0x7fff - ((m[14] >> 16) & 1);
+        let b = (m[15] >> 16) & 1;
+        m[14] &= 0xffff;
+        sel25519(&mut t, &mut m, 1 - b);
+        pass += 1;
+    }
+    let mut out = [0u8; 32];
+    let mut i = 0usize;
+    while i < 16 {
+        out[2 * i] = (t[i] & 0xff) as u8;
+        out[2 * i + 1] = (t[i] >> 8) as u8;
+        i += 1;
+    }
+    out
+}
+
+fn unpack25519(n: &[u8; 32]) -> Gf {
+    let mut o = GF0;
+    let mut i = 0usize;
+    while i < 16 {
+        o[i] = n[2 * i] as i64 + ((n[2 * i + 1] as i64) << 8);
+        i += 1;
+    }
+    o[15] &= 0x7fff;
+    o
+}
+
+fn neq25519(a: &Gf, b: &Gf) -> bool {
+    pack25519(a) != pack25519(b)
+}
+
+fn par25519(a: &Gf) -> u8 {
+    pack25519(a)[0] & 1
+}
+
+fn gf_add(a: &Gf, b: &Gf) -> Gf {
+    let mut o = GF0;
+    let mut i = 0usize;
+    while i < 16 {
+        o[i] = a[i] + b[i];
+        i += 1;
+    }
+    o
+}
+
+fn gf_sub(a: &Gf, b: &Gf) -> Gf {
+    let mut o = GF0;
+    let mut i = 0usize;
+    while i < 16 {
+        o[i] = a[i] - b[i];
+        i += 1;
+    }
+    o
+}
+
+fn gf_mul(a: &Gf, b: &Gf) -> Gf {
+    let mut t = [0i64; 31];
+    let mut i = 0usize;
+    while i < 16 {
+        let mut j = 0usize;
+        while j < 16 {
+            t[i + j] += a[i] * b[j];
+            j += 1;
+        }
+        i += 1;
+    }
+    i = 0;
+    while i < 15 {
+        t[i] += 38 * t[i + 16];
+        i += 1;
+    }
+    let mut o = GF0;
+    o.copy_from_slice(&t[..16]);
+    car25519(&mut o);
+    car25519(&mut o);
+    o
+}
+
+fn gf_sq(a: &Gf) -> Gf {
+    gf_mul(a, a)
+}
+
+fn inv25519(i: &Gf) -> Gf {
+    let mut c = *i;
+    let mut a = 253i32;
+    while a >= 0 {
+        c = gf_sq(&c);
+        if a != 2 && a != 4 {
+            c = gf_mul(&c, i);
+        }
+        a -= 1;
+    }
+    c
+}
+
+fn pow2523(i: &Gf) -> Gf {
+    let mut c = *i;
+    let mut a = 250i32;
+    while a >= 0 {
+        c = gf_sq(&c);
+        if a != 1 {
+            c = gf_mul(&c, i);
+        }
+        a -= 1;
+    }
+    c
+}
+
+// --------------- Edwards points (X, Y, Z, T) ---------------
+
+type Point = [Gf; 4];
+
+fn point_add(p: &mut Point, q: &Point) {
+    let a = gf_mul(&gf_sub(&p[1], &p[0]), &gf_sub(&q[1], &q[0]));
+    let b = gf_mul(&gf_add(&p[0], &p[1]), &gf_add(&q[0], &q[1]));
+    let c = gf_mul(&gf_mul(&p[3], &q[3]), &ED_D2);
+    let d = gf_mul(&p[2], &q[2]);
+    let d = gf_add(&d, &d);
+    let e = gf_sub(&b, &a);
+    let f = gf_sub(&d, &c);
+    let g = gf_add(&d, &c);
+    let h = gf_add(&b, &a);
+    p[0] = gf_mul(&e, &f);
+    p[1] = gf_mul(&h, &g);
+    p[2] = gf_mul(&g, &f);
+    p[3] = gf_mul(&e, &h);
+}
+
+fn point_cswap(p: &mut Point, q: &mut Point, b: u8) {
+    let mut i = 0usize;
+    while i < 4 {
+        sel25519(&mut p[i], &mut q[i], b as i64);
+        i += 1;
+    }
+}
+
+fn point_pack(p: &Point) -> [u8; 32] {
+    let zi = inv25519(&p[2]);
+    let tx = gf_mul(&p[0], &zi);
+    let ty = gf_mul(&p[1], &zi);
+    let mut r = pack25519(&ty);
+    r[31] ^= par25519(&tx) << 7;
+    r
+}
+
+fn scalarmult(q: &mut Point, s: &[u8; 32]) -> Point {
+    let mut p: Point = [GF0, GF1, GF1, GF0];
+    let mut i = 255i32;
+    while i >= 0 {
+        let b = (s[(i / 8) as usize] >> (i & 7)) & 1;
+        point_cswap(&mut p, q, b);
+        point_add(q, &p);
+        let doubled = p;
+        point_add(&mut p, &doubled);
+        point_cswap(&mut p, q, b);
+        i -= 1;
+    }
+    p
+}
+
+fn scalarbase(s: &[u8; 32]) -> Point {
+    let mut q: Point = [ED_X, ED_Y, GF1, gf_mul(&ED_X, &ED_Y)];
+    scalarmult(&mut q, s)
+}
+
+// Decodes `p` and negates it, so the verifier can compute [s]B - [h]A.
+fn unpackneg(p: &[u8; 32]) -> Option<Point> {
+    let mut r: Point = [GF0, unpack25519(p), GF1, GF0];
+    let mut num = gf_sq(&r[1]);
+    let mut den = gf_mul(&num, &ED_D);
+    num = gf_sub(&num, &r[2]);
+    den = gf_add(&r[2], &den);
+
+    let den2 = gf_sq(&den);
+    let den4 = gf_sq(&den2);
+    let den6 = gf_mul(&den4, &den2);
+    let mut t = gf_mul(&den6, &num);
+    t = gf_mul(&t, &den);
+    t = pow2523(&t);
+    t = gf_mul(&t, &num);
+    t = gf_mul(&t, &den);
+    t = gf_mul(&t, &den);
+    r[0] = gf_mul(&t, &den);
+
+    let mut chk = gf_mul(&gf_sq(&r[0]), &den);
+    if neq25519(&chk, &num) {
+        r[0] = gf_mul(&r[0], &ED_I);
+    }
+    chk = gf_mul(&gf_sq(&r[0]), &den);
+    if neq25519(&chk, &num) {
+        return None;
+    }
+    if par25519(&r[0]) == (p[31] >> 7) {
+        r[0] = gf_sub(&GF0, &r[0]);
+    }
+    r[3] = gf_mul(&r[0], &r[1]);
+    Some(r)
+}
+
+// --------------- Scalars mod L ---------------
+
+fn mod_l(x: &mut [i64; 64]) -> [u8; 32] {
+    let mut i = 63usize;
+    while i >= 32 {
+        let mut carry = 0i64;
+        let mut j = i - 32;
+        while j < i - 12 {
+            x[j] += carry - 16 * x[i] * ED_L[j - (i - 32)];
+            carry = (x[j] + 128) >> 8;
+            x[j] -= carry << 8;
+            j += 1;
+        }
+        x[j] += carry;
+        x[i] = 0;
+        i -= 1;
+    }
+    let mut carry = 0i64;
+    let mut j = 0usize;
+    while j < 32 {
+        x[j] += carry - (x[31] >> 4) * ED_L[j];
+        carry = x[j] >> 8;
+        x[j] &= 255;
+        j += 1;
+    }
+    j = 0;
+    while j < 32 {
+        x[j] -= carry * ED_L[j];
+        j += 1;
+    }
+    let mut r = [0u8; 32];
+    i = 0;
+    while i < 32 {
+        x[i + 1] += x[i] >> 8;
+        r[i] = (x[i] & 255) as u8;
+        i += 1;
+    }
+    r
+}
+
+fn reduce(h: &[u8; 64]) -> [u8; 32] {
+    let mut x = [0i64; 64];
+    let mut i = 0usize;
+    while i < 64 {
+        x[i] = h[i] as i64;
+        i += 1;
+    }
+    mod_l(&mut x)
+}
+
+// Rejects non-canonical S (S >= L) so a signature has exactly one encoding.
+fn scalar_is_canonical(s: &[u8]) -> bool {
+    let mut i = 32usize;
+    while i > 0 {
+        i -= 1;
+        let l = ED_L[i] as u8;
+        if s[i] < l {
+            return true;
+        }
+        if s[i] > l {
+            return false;
+        }
+    }
+    false
+}
+
+/// Verifies an Ed25519 signature over the concatenation of `parts`.
+pub(crate) fn ed25519_verify(public_key: &[u8; 32], parts: &[&[u8]], sig: &[u8; 64]) -> bool {
+    if !scalar_is_canonical(&sig[32..]) {
+        return false;
+    }
+    let mut q = match unpackneg(public_key) {
+        Some(q) => q,
+        None => return false,
+    };
+
+    let mut hasher = Sha512::new();
+    hasher.update(&sig[..32]);
+    hasher.update(public_key);
+    for part in parts {
+        hasher.update(part);
+    }
+    let h = reduce(&hasher.finish());
+
+    let mut s = [0u8; 32];
+    s.copy_from_slice(&sig[32..]);
+    let mut p = scalarmult(&mut q, &h);
+    let sb = scalarbase(&s);
+    point_add(&mut p, &sb);
+    point_pack(&p) == sig[..32]
+}
+
+// --------------- Boot-time policy and trust root ---------------
+
+/// What the loader does when an image is unsigned or its signature fails.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SignPolicy {
+    Off,
+    Warn,
+    Enforce,
+}
+
+pub(crate) const SIGN_SIG_SIZE: usize = 64;
+// An executable image is signed by appending the signature and this magic.
+#[cfg(feature = "go_test")]
+const SIGN_TRAILER_MAGIC: &[u8; 8] = b"RUGOSIG1";
+#[cfg(feature = "go_test")]
+const SIGN_TRAILER_SIZE: usize = SIGN_SIG_SIZE + SIGN_TRAILER_MAGIC.len();
+
+// Public half of the development key in `tools/ed25519_v1.py`. Its seed is
+// published, so the kernel never trusts it on its own; it is kept only to
+// warn when a boot configures it as the trust root.
+const SIGN_DEV_TRUST_ROOT: [u8; 32] = [
+    0x3f, 0x5d, 0x2a, 0xed, 0xe5, 0xb0, 0x30, 0xaa,
+    0x85, 0x16, 0x36, 0x5b, 0xb1, 0xd2, 0x85, 0x9a,
+    0x4c, 0x6c, 0x83, 0xb0, 0xca, 0xcf, 0x4a, 0xbb,
+    0x52, 0x01, 0xff, 0x2f, 0x48, 0x47, 0xf4, 0xfb,
+];
+
+const SIGN_POLICY_KEY: &[u8] = b"rugo.sigpolicy=";
+const SIGN_TRUST_ROOT_KEY: &[u8] = b"rugo.trust_root=";
+const SIGN_TRUST_ROOT_MODULE: &[u8] = b"rugo.trust_root";
+
+pub(crate) static mut SIGN_POLICY: SignPolicy = SignPolicy::Enforce;
+// No root means no signature can verify.
+static mut SIGN_TRUST_ROOT: Option<[u8; 32]> = None;
+
+fn parse_policy(cmdline: &[u8]) -> Option<SignPolicy> {
+    for word in cmdline.split(|&b| b == b' ') {
+        if let Some(value) = word.strip_prefix(SIGN_POLICY_KEY) {
+            return match value {
+                b"enforce" => Some(SignPolicy::Enforce),
+                b"warn" => Some(SignPolicy::Warn),
+                b"off" => Some(SignPolicy::Off),
+                _ => None,
+            };
+        }
+    }
+    Some(SignPolicy::Enforce)
+}
+
+fn hex_nibble(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// `rugo.trust_root=<64 hex digits>` from the command line. `Err` if the
+/// key is present but malformed.
+fn parse_trust_root(cmdline: &[u8]) -> Result<Option<[u8; 32]>, ()> {
+    for word in cmdline.split(|&b| b == b' ') {
+        if let Some(value) = word.strip_prefix(SIGN_TRUST_ROOT_KEY) {
+            if value.len() != 64 {
+                return Err(());
+            }
+            let mut root = [0u8; 32];
+            for (i, byte) in root.iter_mut().enumerate() {
+                let hi = hex_nibble(value[i * 2]).ok_or(())?;
+                let lo = hex_nibble(value[i * 2 + 1]).ok_or(())?;
+                *byte = (hi << 4) | lo;
+            }
+            return Ok(Some(root));
+        }
+    }
+    Ok(None)
+}
+
+/// The 32-byte `rugo.trust_root` boot module, if the loader passed one.
+unsafe fn module_trust_root() -> Option<[u8; 32]> {
+    let mod_resp = core::ptr::read_volatile(core::ptr::addr_of!(MODULE_REQUEST.response));
+    if mod_resp.is_null() {
+        return None;
+    }
+    let mut i = 0u64;
+    while i < (*mod_resp).module_count {
+        let module = *(*mod_resp).modules.add(i as usize);
+        if !module.is_null() && c_str((*module).string) == SIGN_TRUST_ROOT_MODULE {
+            if (*module).size != 32 {
+                serial_write(b"SIGN: trust root module bad size\n");
+                return None;
+            }
+            let mut root = [0u8; 32];
+            core::ptr::copy_nonoverlapping((*module).address, root.as_mut_ptr(), 32);
+            serial_write(b"SIGN: trust root module\n");
+            return Some(root);
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Reads `rugo.sigpolicy=` from the kernel command line and takes the
+/// trust root from a `rugo.trust_root` boot module, or failing that from
+/// `rugo.trust_root=` on the command line. There is no built-in root: with
+/// none configured, nothing verifies and `enforce` rejects every image.
+pub(crate) unsafe fn sign_init() {
+    let cmdline = boot_cmdline();
+    SIGN_POLICY = match parse_policy(cmdline) {
+        Some(policy) => policy,
+        None => {
+            serial_write(b"SIGN: bad policy, enforcing\n");
+            SignPolicy::Enforce
+        }
+    };
+    match SIGN_POLICY {
+        SignPolicy::Enforce => serial_write(b"SIGN: policy enforce\n"),
+        SignPolicy::Warn => serial_write(b"SIGN: policy warn\n"),
+        SignPolicy::Off => serial_write(b"SIGN: policy off\n"),
+    }
+
+    SIGN_TRUST_ROOT = match module_trust_root() {
+        Some(root) => Some(root),
+        None => match parse_trust_root(cmdline) {
+            Ok(Some(root)) => {
+                serial_write(b"SIGN: trust root cmdline\n");
+                Some(root)
+            }
+            Ok(None) => None,
+            Err(()) => {
+                serial_write(b"SIGN: bad trust root\n");
+                None
+            }
+        },
+    };
+    match SIGN_TRUST_ROOT {
+        None if SIGN_POLICY == SignPolicy::Enforce => {
+            serial_write(b"SIGN: WARNING: no trust root, every image will be rejected\n");
+        }
+        None if SIGN_POLICY == SignPolicy::Warn => {
+            serial_write(b"SIGN: WARNING: no trust root, no image will verify\n");
+        }
+        Some(root) if root == SIGN_DEV_TRUST_ROOT => {
+            serial_write(b"SIGN: WARNING: trust root is the public development key\n");
+        }
+        _ => {}
+    }
+}
+
+/// Outcome of checking one image against the active policy.
+pub(crate) enum SignCheck {
+    Verified,
+    Unsigned,
+    Bad,
+    NoRoot,
+}
+
+/// Checks `parts` against `sig` with the active trust root. `None` means
+/// the image carries no signature.
+pub(crate) unsafe fn sign_check(parts: &[&[u8]], sig: Option<&[u8; 64]>) -> SignCheck {
+    match (sig, SIGN_TRUST_ROOT) {
+        (None, _) => SignCheck::Unsigned,
+        (Some(_), None) => SignCheck::NoRoot,
+        (Some(sig), Some(root)) => {
+            if ed25519_verify(&root, parts, sig) {
+                SignCheck::Verified
+            } else {
+                SignCheck::Bad
+            }
+        }
+    }
+}
+
+/// Check an image under the active policy, reporting the outcome as
+/// `<tag>: sig ok`, `unsigned`, `sig bad` or `no trust root`. False if the
+/// policy rejects it; nothing of it may be mapped then.
+pub(crate) unsafe fn sign_admit(tag: &[u8], parts: &[&[u8]], sig: Option<&[u8; 64]>) -> bool {
+    if SIGN_POLICY == SignPolicy::Off {
+        return true;
+    }
+    serial_write(tag);
+    let verified = match sign_check(parts, sig) {
+        SignCheck::Verified => {
+            serial_write(b": sig ok\n");
+            true
+        }
+        SignCheck::Unsigned => {
+            serial_write(b": unsigned\n");
+            false
+        }
+        SignCheck::Bad => {
+            serial_write(b": sig bad\n");
+            false
+        }
+        SignCheck::NoRoot => {
+            serial_write(b": no trust root\n");
+            false
+        }
+    };
+    if !verified && SIGN_POLICY == SignPolicy::Enforce {
+        serial_write(tag);
+        serial_write(b": rejected by signature policy\n");
+        return false;
+    }
+    true
+}
+
+/// Split a signed executable image into the bytes the signature covers and
+/// the signature. An image without the `RUGOSIG1` trailer is returned whole
+/// and unsigned.
+#[cfg(feature = "go_test")]
+pub(crate) fn sign_split_trailer(image: &[u8]) -> (&[u8], Option<&[u8; 64]>) {
+    if image.len() < SIGN_TRAILER_SIZE || !image.ends_with(SIGN_TRAILER_MAGIC) {
+        return (image, None);
+    }
+    let body = image.len() - SIGN_TRAILER_SIZE;
+    match image[body..body + SIGN_SIG_SIZE].try_into() {
+        Ok(sig) => (&image[..body], Some(sig)),
+        Err(_) => (image, None),
+    }
+}
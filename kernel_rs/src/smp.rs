@@ -0,0 +1,360 @@
+// Application processors for the Go lane.
+//
+// Limine parks every AP and reports it in the MP response; `smp_start`
+// hands each one an index and an entry point. An AP loads the shared GDT
+// and IDT, a TSS and kernel stack of its own, and the boot page tables,
+// then idles until the scheduler gives it a task. A CPU tells itself apart
+// by the TSS selector it loaded (`arch_x86::cpu_index`).
+//
+// Two locks, both reentrant on the CPU that holds them. The kernel lock is
+// taken at every kernel entry but one and dropped on the way out. The
+// scheduler lock covers the run queues, task states and the other
+// scheduling fields; it nests inside the kernel lock, never the other way
+// round. The tick and reschedule IPI takes only the scheduler lock, so APs
+// switch tasks while another CPU is in a syscall.
+//
+// Only the boot CPU gets the PIT; it passes each tick on to the busy APs,
+// and any CPU asks another to reschedule, with an IPI on one vector. The
+// reasons sit in a per-CPU word, so IPIs that cross coalesce.
+//
+// A CPU that changes the page tables of a space another CPU has loaded
+// waits for that CPU to reload CR3 before it goes on. A CPU spinning on
+// either lock has interrupts off, so it does that reload while it spins.
+
+use core::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+use crate::*;
+
+pub(crate) const SMP_MAX_CPUS: usize = arch_x86::MAX_CPUS;
+pub(crate) const SMP_IPI_VECTOR: u64 = 0xF0;
+const SMP_SPURIOUS_VECTOR: u32 = 0xFF;
+// IPI reasons.
+pub(crate) const SMP_IPI_TICK: u32 = 1 << 0;
+pub(crate) const SMP_IPI_RESCHED: u32 = 1 << 1;
+const SMP_IPI_FLUSH: u32 = 1 << 2;
+const SMP_STACK_SIZE: usize = 16384;
+// How long `smp_start` spins for the APs to come online.
+const SMP_START_SPINS: u64 = 100_000_000;
+
+const IA32_APIC_BASE_MSR: u32 = 0x1B;
+const APIC_BASE_X2APIC: u64 = 1 << 10;
+// Local APIC registers by MMIO offset; x2APIC has each at MSR
+// 0x800 + offset / 16.
+const LAPIC_REG_EOI: usize = 0xB0;
+const LAPIC_REG_SVR: usize = 0xF0;
+const LAPIC_REG_ICR_LOW: usize = 0x300;
+const LAPIC_REG_ICR_HIGH: usize = 0x310;
+const LAPIC_X2APIC_MSR_BASE: u32 = 0x800;
+const LAPIC_SVR_ENABLE: u32 = 1 << 8;
+const LAPIC_ICR_PENDING: u32 = 1 << 12;
+const LAPIC_ICR_ASSERT: u32 = 1 << 14;
+
+#[repr(C, align(16))]
+struct SmpStack([u8; SMP_STACK_SIZE]);
+
+// Kernel stacks of the APs; the boot CPU keeps `stack_top`.
+static mut SMP_STACKS: [SmpStack; SMP_MAX_CPUS - 1] =
+    [const { SmpStack([0; SMP_STACK_SIZE]) }; SMP_MAX_CPUS - 1];
+static mut SMP_LAPIC_IDS: [u32; SMP_MAX_CPUS] = [0; SMP_MAX_CPUS];
+// Bit per CPU that finished bring-up.
+static SMP_ONLINE: AtomicU64 = AtomicU64::new(1);
+static SMP_PENDING: [AtomicU32; SMP_MAX_CPUS] = [const { AtomicU32::new(0) }; SMP_MAX_CPUS];
+// The PML4 each CPU has loaded, for TLB shootdowns.
+static SMP_CR3: [AtomicU64; SMP_MAX_CPUS] = [const { AtomicU64::new(0) }; SMP_MAX_CPUS];
+static mut SMP_KERNEL_LOCK: SmpLock = SmpLock::new();
+static mut SMP_SCHED_LOCK: SmpLock = SmpLock::new();
+// HHDM address of the xAPIC registers, mapped by `smp_start`.
+static mut SMP_LAPIC_MMIO: u64 = 0;
+// The boot CPU's control registers, copied by each AP.
+static mut SMP_CR0: u64 = 0;
+static mut SMP_CR4: u64 = 0;
+
+#[inline(always)]
+unsafe fn rdmsr(msr: u32) -> u64 {
+    let (lo, hi): (u32, u32);
+    core::arch::asm!("rdmsr", in("ecx") msr, out("eax") lo, out("edx") hi, options(nomem, nostack));
+    (hi as u64) << 32 | lo as u64
+}
+
+#[inline(always)]
+unsafe fn wrmsr(msr: u32, value: u64) {
+    core::arch::asm!(
+        "wrmsr",
+        in("ecx") msr,
+        in("eax") value as u32,
+        in("edx") (value >> 32) as u32,
+        options(nostack),
+    );
+}
+
+/// Each CPU picks its own mode: the native driver may have put the boot
+/// CPU's local APIC in x2APIC mode and left the APs in xAPIC mode.
+#[inline(always)]
+unsafe fn lapic_x2apic() -> bool {
+    rdmsr(IA32_APIC_BASE_MSR) & APIC_BASE_X2APIC != 0
+}
+
+unsafe fn lapic_read(offset: usize) -> u32 {
+    if lapic_x2apic() {
+        rdmsr(LAPIC_X2APIC_MSR_BASE + (offset >> 4) as u32) as u32
+    } else {
+        core::ptr::read_volatile((SMP_LAPIC_MMIO + offset as u64) as *const u32)
+    }
+}
+
+unsafe fn lapic_write(offset: usize, value: u32) {
+    if lapic_x2apic() {
+        wrmsr(LAPIC_X2APIC_MSR_BASE + (offset >> 4) as u32, value as u64);
+    } else {
+        core::ptr::write_volatile((SMP_LAPIC_MMIO + offset as u64) as *mut u32, value);
+    }
+}
+
+/// Send `vector` to the local APIC `lapic_id`.
+unsafe fn lapic_send(lapic_id: u32, vector: u32) {
+    if lapic_x2apic() {
+        let icr = (lapic_id as u64) << 32 | (LAPIC_ICR_ASSERT | vector) as u64;
+        wrmsr(LAPIC_X2APIC_MSR_BASE + (LAPIC_REG_ICR_LOW >> 4) as u32, icr);
+        return;
+    }
+    while lapic_read(LAPIC_REG_ICR_LOW) & LAPIC_ICR_PENDING != 0 {
+        core::hint::spin_loop();
+    }
+    lapic_write(LAPIC_REG_ICR_HIGH, lapic_id << 24);
+    lapic_write(LAPIC_REG_ICR_LOW, LAPIC_ICR_ASSERT | vector);
+}
+
+unsafe fn lapic_enable() {
+    lapic_write(LAPIC_REG_SVR, SMP_SPURIOUS_VECTOR | LAPIC_SVR_ENABLE);
+}
+
+/// This CPU's index: 0 for the boot CPU, then the APs in start order.
+#[inline(always)]
+pub(crate) fn cpu_id() -> usize {
+    arch_x86::cpu_index()
+}
+
+/// Bit per online CPU.
+#[inline(always)]
+pub(crate) fn online_mask() -> u64 {
+    SMP_ONLINE.load(Ordering::Acquire)
+}
+
+/// Top of `cpu`'s kernel stack, its ring-0 stack in the TSS.
+pub(crate) unsafe fn kernel_stack_top(cpu: usize) -> u64 {
+    if cpu == 0 {
+        &stack_top as *const u8 as u64
+    } else {
+        let stack = core::ptr::addr_of!(SMP_STACKS[cpu - 1]) as u64;
+        stack + SMP_STACK_SIZE as u64
+    }
+}
+
+/// Start the APs Limine reports and wait for them to come online. Called
+/// once by the boot CPU, before the PIT starts.
+pub(crate) unsafe fn smp_start() {
+    let resp = core::ptr::read_volatile(core::ptr::addr_of!(MP_REQUEST.response));
+    if resp.is_null() || (*resp).cpu_count <= 1 {
+        serial_write(b"SMP: cpus=1\n");
+        return;
+    }
+    if !lapic_x2apic() {
+        let hhdm = core::ptr::read_volatile(core::ptr::addr_of!(HHDM_REQUEST.response));
+        HHDM_OFFSET = (*hhdm).offset;
+        let phys = rdmsr(IA32_APIC_BASE_MSR) & 0x000F_FFFF_FFFF_F000;
+        if !vm::vm_map_kernel_mmio(phys) {
+            serial_write(b"SMP: no memory for the local APIC mapping\n");
+            serial_write(b"SMP: cpus=1\n");
+            return;
+        }
+        SMP_LAPIC_MMIO = HHDM_OFFSET + phys;
+    }
+    lapic_enable();
+    core::arch::asm!("mov {}, cr0", out(reg) SMP_CR0, options(nomem, nostack));
+    core::arch::asm!("mov {}, cr4", out(reg) SMP_CR4, options(nomem, nostack));
+    SMP_LAPIC_IDS[0] = (*resp).bsp_lapic_id;
+    let mut count = 1;
+    for i in 0..(*resp).cpu_count as usize {
+        let info = *(*resp).cpus.add(i);
+        if (*info).lapic_id == (*resp).bsp_lapic_id {
+            continue;
+        }
+        if count == SMP_MAX_CPUS {
+            serial_write(b"SMP: more CPUs than slots, the rest stay parked\n");
+            break;
+        }
+        SMP_LAPIC_IDS[count] = (*info).lapic_id;
+        (*info).extra_argument = count as u64;
+        // The AP polls this field and jumps as soon as it is written.
+        AtomicU64::from_ptr(core::ptr::addr_of_mut!((*info).goto_address))
+            .store(smp_ap_entry as *const () as u64, Ordering::Release);
+        count += 1;
+    }
+    let all = (1u64 << count) - 1;
+    let mut spins = 0;
+    while online_mask() != all && spins < SMP_START_SPINS {
+        core::hint::spin_loop();
+        spins += 1;
+    }
+    // APs print their online line under the lock; don't interleave with it.
+    smp_lock();
+    serial_write(b"SMP: cpus=");
+    serial_write_u64_dec(online_mask().count_ones() as u64);
+    serial_write(b"\n");
+    smp_unlock();
+}
+
+/// Limine's jump target, on its stack: move to the AP's own kernel stack.
+extern "C" fn smp_ap_entry(info: *const LimineMpInfo) -> ! {
+    unsafe {
+        let cpu = (*info).extra_argument as usize;
+        core::arch::asm!(
+            "mov rsp, {top}",
+            "xor ebp, ebp",
+            "call {main}",
+            top = in(reg) kernel_stack_top(cpu),
+            main = sym smp_ap_main,
+            in("rdi") cpu,
+            options(noreturn),
+        );
+    }
+}
+
+extern "C" fn smp_ap_main(cpu: usize) -> ! {
+    unsafe {
+        core::arch::asm!("mov cr0, {}", in(reg) SMP_CR0, options(nostack));
+        core::arch::asm!("mov cr4, {}", in(reg) SMP_CR4, options(nostack));
+        arch_x86::gdt_init();
+        arch_x86::tss_load(cpu, kernel_stack_top(cpu));
+        arch_x86::idt_load();
+        arch_x86::nx_enable();
+        vm::vm_activate_kernel();
+        lapic_enable();
+        cputime::cpu_join();
+        smp_lock();
+        R4_IDLE[cpu] = true;
+        SMP_ONLINE.fetch_or(1 << cpu, Ordering::Release);
+        serial_write(b"SMP: cpu ");
+        serial_write_u64_dec(cpu as u64);
+        serial_write(b" online\n");
+        smp_unlock();
+    }
+    r4_idle()
+}
+
+/// Reload CR3 if a shootdown asked this CPU to.
+#[inline(always)]
+unsafe fn smp_flush_pending(cpu: usize) {
+    if SMP_PENDING[cpu].load(Ordering::Acquire) & SMP_IPI_FLUSH != 0 {
+        core::arch::asm!("mov {0}, cr3", "mov cr3, {0}", out(reg) _, options(nostack));
+        SMP_PENDING[cpu].fetch_and(!SMP_IPI_FLUSH, Ordering::Release);
+    }
+}
+
+struct SmpLock {
+    owner: AtomicUsize,
+    // Only the owner touches the depth.
+    depth: u32,
+}
+
+impl SmpLock {
+    const fn new() -> Self {
+        Self { owner: AtomicUsize::new(usize::MAX), depth: 0 }
+    }
+
+    /// Take the lock, or nest in it on the CPU that holds it. Called with
+    /// interrupts off.
+    unsafe fn lock(&mut self) {
+        let cpu = cpu_id();
+        if self.owner.load(Ordering::Relaxed) == cpu {
+            self.depth += 1;
+            return;
+        }
+        loop {
+            smp_flush_pending(cpu);
+            if self
+                .owner
+                .compare_exchange_weak(usize::MAX, cpu, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+        self.depth = 1;
+    }
+
+    unsafe fn unlock(&mut self) {
+        self.depth -= 1;
+        if self.depth == 0 {
+            self.owner.store(usize::MAX, Ordering::Release);
+        }
+    }
+}
+
+/// Take the kernel lock.
+pub(crate) unsafe fn smp_lock() {
+    (*core::ptr::addr_of_mut!(SMP_KERNEL_LOCK)).lock();
+}
+
+pub(crate) unsafe fn smp_unlock() {
+    (*core::ptr::addr_of_mut!(SMP_KERNEL_LOCK)).unlock();
+}
+
+/// Take the scheduler lock. A CPU holding only this one must not go on to
+/// take the kernel lock.
+pub(crate) unsafe fn smp_sched_lock() {
+    (*core::ptr::addr_of_mut!(SMP_SCHED_LOCK)).lock();
+}
+
+pub(crate) unsafe fn smp_sched_unlock() {
+    (*core::ptr::addr_of_mut!(SMP_SCHED_LOCK)).unlock();
+}
+
+/// Post `reason` to `cpu`, interrupting it unless an IPI is already on its
+/// way there. Sent with the kernel or the scheduler lock held.
+pub(crate) unsafe fn smp_send(cpu: usize, reason: u32) {
+    if SMP_PENDING[cpu].fetch_or(reason, Ordering::AcqRel) == 0 {
+        lapic_send(SMP_LAPIC_IDS[cpu], SMP_IPI_VECTOR as u32);
+    }
+}
+
+/// Acknowledge the IPI and take its tick and reschedule reasons. A flush
+/// posted while they were pending sent no IPI of its own, so it is done
+/// here.
+pub(crate) unsafe fn smp_ipi_take() -> u32 {
+    let cpu = cpu_id();
+    lapic_write(LAPIC_REG_EOI, 0);
+    let reasons = SMP_PENDING[cpu].fetch_and(!(SMP_IPI_TICK | SMP_IPI_RESCHED), Ordering::AcqRel)
+        & (SMP_IPI_TICK | SMP_IPI_RESCHED);
+    smp_flush_pending(cpu);
+    reasons
+}
+
+/// Record the PML4 this CPU just loaded.
+#[inline(always)]
+pub(crate) fn smp_note_cr3(pml4_phys: u64) {
+    SMP_CR3[cpu_id()].store(pml4_phys, Ordering::Relaxed);
+}
+
+/// Make every other CPU with `pml4_phys` loaded drop its stale TLB entries,
+/// and wait until they have. Called with the kernel lock held.
+pub(crate) unsafe fn smp_tlb_shootdown(pml4_phys: u64) {
+    let me = cpu_id();
+    let mut waiting = 0u64;
+    let online = online_mask();
+    for (cpu, cr3) in SMP_CR3.iter().enumerate() {
+        if cpu != me && online & (1 << cpu) != 0 && cr3.load(Ordering::Relaxed) == pml4_phys {
+            smp_send(cpu, SMP_IPI_FLUSH);
+            waiting |= 1 << cpu;
+        }
+    }
+    while waiting != 0 {
+        for (cpu, pending) in SMP_PENDING.iter().enumerate() {
+            if waiting & (1 << cpu) != 0 && pending.load(Ordering::Acquire) & SMP_IPI_FLUSH == 0 {
+                waiting &= !(1 << cpu);
+            }
+        }
+        core::hint::spin_loop();
+    }
+}
@@ -38,8 +38,16 @@ pub(crate) unsafe fn syscall_dispatch(frame: *mut u64) {
         return;
     }
 
-    #[cfg(any(feature = "ipc_test", feature = "shm_test", feature = "ipc_badptr_send_test", feature = "ipc_badptr_recv_test", feature = "ipc_badptr_svc_test", feature = "ipc_buffer_full_test", feature = "ipc_waiter_busy_test", feature = "svc_overwrite_test", feature = "svc_full_test", feature = "svc_bad_endpoint_test", feature = "stress_ipc_test", feature = "quota_endpoints_test", feature = "quota_shm_test", feature = "quota_threads_test", feature = "go_test"))]
+    #[cfg(r4)]
     {
+        #[cfg(feature = "go_test")]
+        let arg4 = *frame.add(5); // r10
+        // Only kernel workers trap from ring 0.
+        #[cfg(feature = "go_test")]
+        if *frame.add(18) & 3 == 0 {
+            r4_kworker_trap(frame);
+            return;
+        }
         if nr == 98 {
             qemu_exit(arg1 as u8);
             loop { core::arch::asm!("cli; hlt", options(nomem, nostack)); }
@@ -79,7 +87,7 @@ pub(crate) unsafe fn syscall_dispatch(frame: *mut u64) {
                 *frame.add(14) = sys_ipc_send_r4(arg1, arg2, arg3);
             }
             9 => {
-                sys_ipc_recv_r4(frame, arg1, arg2, arg3);
+                sys_ipc_recv_r4(frame, arg1, arg2, arg3, None);
             }
             10 => {
                 *frame.add(14) = sys_time_now();
@@ -103,19 +111,29 @@ pub(crate) unsafe fn syscall_dispatch(frame: *mut u64) {
                 *frame.add(14) = sys_close_v1(arg1);
             }
             22 => {
-                sys_wait_r4(frame, arg1, arg2, arg3);
+                sys_wait_r4(frame, arg1, arg2, arg3, None);
             }
             23 => {
-                *frame.add(14) = sys_poll_v1(arg1, arg2, arg3);
+                #[cfg(feature = "go_test")]
+                sys_poll_r4(frame, arg1, arg2, arg3);
+                #[cfg(not(feature = "go_test"))]
+                {
+                    *frame.add(14) = sys_poll_v1(arg1, arg2, arg3);
+                }
             }
             28 => {
                 *frame.add(14) = sys_proc_info_r4(arg1, arg2, arg3);
             }
             29 => {
-                *frame.add(14) = sys_sched_set_r4(arg1, arg2);
+                *frame.add(14) = sys_sched_set_r4(arg1, arg2, arg3);
             }
             30 => {
-                *frame.add(14) = sys_fsync_v1(arg1);
+                #[cfg(feature = "go_test")]
+                sys_fsync_r4(frame, arg1);
+                #[cfg(not(feature = "go_test"))]
+                {
+                    *frame.add(14) = sys_fsync_v1(arg1);
+                }
             }
             31 => {
                 *frame.add(14) = net::sys_socket_open_r4(arg1, arg2);
@@ -151,24 +169,89 @@ pub(crate) unsafe fn syscall_dispatch(frame: *mut u64) {
                 *frame.add(14) = sys_isolation_config_r4(arg1, arg2, arg3);
             }
             43 => {
-                *frame.add(14) = sys_fork_deferred_v1();
+                *frame.add(14) = sys_fork_r4(frame);
             }
             44 => {
-                *frame.add(14) = sys_clone_deferred_v1();
+                *frame.add(14) = sys_clone_r4(arg1, arg2);
             }
             45 => {
                 *frame.add(14) = sys_epoll_deferred_v1();
             }
+            46 => {
+                *frame.add(14) = sys_mmap_r4(arg1, arg2, arg3);
+            }
+            47 => {
+                *frame.add(14) = sys_munmap_r4(arg1, arg2);
+            }
+            48 => {
+                *frame.add(14) = sys_mprotect_r4(arg1, arg2, arg3);
+            }
+            49 => {
+                *frame.add(14) = sys_shm_send_r4(arg1, arg2, arg3);
+            }
+            50 => {
+                *frame.add(14) = sys_mem_notify_r4(arg1);
+            }
+            51 => {
+                *frame.add(14) = sys_arch_prctl_r4(arg1, arg2);
+            }
+            52 => {
+                *frame.add(14) = sys_spawn_r4(arg1, arg2);
+            }
+            53 => {
+                *frame.add(14) = sys_sched_nice_r4(arg1, arg2);
+            }
+            #[cfg(feature = "go_test")]
+            54 => {
+                *frame.add(14) = sys_clock_now_r4();
+            }
+            #[cfg(feature = "go_test")]
+            55 => {
+                sys_nanosleep_r4(frame, arg1, arg2);
+            }
+            #[cfg(feature = "go_test")]
+            56 => {
+                sys_ipc_recv_r4(frame, arg1, arg2, arg3, r4_deadline_tick(arg4));
+            }
+            #[cfg(feature = "go_test")]
+            57 => {
+                sys_wait_r4(frame, arg1, arg2, arg3, r4_deadline_tick(arg4));
+            }
+            #[cfg(feature = "go_test")]
+            58 => {
+                r4_poll_until(frame, arg1, arg2, r4_deadline_tick(arg3));
+            }
+            #[cfg(feature = "go_test")]
+            59 => {
+                *frame.add(14) = sys_timer_create_r4(arg1, arg2);
+            }
+            #[cfg(feature = "go_test")]
+            60 => {
+                sys_timer_wait_r4(frame, arg1);
+            }
+            #[cfg(feature = "go_test")]
+            61 => {
+                *frame.add(14) = sys_timer_close_r4(arg1);
+            }
+            #[cfg(feature = "go_test")]
+            62 => {
+                *frame.add(14) = sys_sysinfo_r4(arg1, arg2);
+            }
             _ => {
                 *frame.add(14) = 0xFFFF_FFFF_FFFF_FFFF;
             }
         }
+        // Skip the poll once every task is gone and the frame returns to
+        // the kernel.
+        if *frame.add(18) & 3 == 3 {
+            r4_mem_pressure_poll(frame);
+        }
         return;
     }
 
-    #[cfg(not(any(feature = "ipc_test", feature = "shm_test", feature = "ipc_badptr_send_test", feature = "ipc_badptr_recv_test", feature = "ipc_badptr_svc_test", feature = "ipc_buffer_full_test", feature = "ipc_waiter_busy_test", feature = "svc_overwrite_test", feature = "svc_full_test", feature = "svc_bad_endpoint_test", feature = "stress_ipc_test", feature = "quota_endpoints_test", feature = "quota_shm_test", feature = "quota_threads_test", feature = "go_test")))]
+    #[cfg(not(r4))]
     {
-        #[cfg(any(feature = "user_hello_test", feature = "syscall_test", feature = "thread_exit_test", feature = "thread_spawn_test", feature = "vm_map_test", feature = "syscall_invalid_test", feature = "stress_syscall_test", feature = "yield_test", feature = "user_fault_test", feature = "blk_test", feature = "fs_test", feature = "go_test", feature = "go_std_test", feature = "sec_rights_test", feature = "sec_filter_test"))]
+        #[cfg(m3)]
         {
             if !m10_syscall_allowed(nr) {
                 *frame.add(14) = 0xFFFF_FFFF_FFFF_FFFF;
@@ -191,44 +274,45 @@ pub(crate) unsafe fn syscall_dispatch(frame: *mut u64) {
 
         let ret: u64 = match nr {
             0 => sys_debug_write(arg1, arg2),
-            #[cfg(any(feature = "user_hello_test", feature = "syscall_test", feature = "thread_exit_test", feature = "thread_spawn_test", feature = "vm_map_test", feature = "syscall_invalid_test", feature = "stress_syscall_test", feature = "yield_test", feature = "user_fault_test", feature = "blk_test", feature = "fs_test", feature = "go_test", feature = "go_std_test", feature = "sec_rights_test", feature = "sec_filter_test"))]
+            #[cfg(m3)]
+            #[cfg(not(feature = "go_test"))]
             1 => sys_thread_spawn_m3(frame, arg1),
-            #[cfg(any(feature = "user_hello_test", feature = "syscall_test", feature = "thread_exit_test", feature = "thread_spawn_test", feature = "vm_map_test", feature = "syscall_invalid_test", feature = "stress_syscall_test", feature = "yield_test", feature = "user_fault_test", feature = "blk_test", feature = "fs_test", feature = "go_test", feature = "go_std_test", feature = "sec_rights_test", feature = "sec_filter_test"))]
+            #[cfg(m3)]
             3 => sys_yield_m3(frame),
-            #[cfg(not(any(feature = "user_hello_test", feature = "syscall_test", feature = "thread_exit_test", feature = "thread_spawn_test", feature = "vm_map_test", feature = "syscall_invalid_test", feature = "stress_syscall_test", feature = "yield_test", feature = "user_fault_test", feature = "blk_test", feature = "fs_test", feature = "go_test", feature = "go_std_test", feature = "sec_rights_test", feature = "sec_filter_test")))]
+            #[cfg(not(m3))]
             3 => sys_yield(),
-            #[cfg(any(feature = "user_hello_test", feature = "syscall_test", feature = "thread_exit_test", feature = "thread_spawn_test", feature = "vm_map_test", feature = "syscall_invalid_test", feature = "stress_syscall_test", feature = "yield_test", feature = "user_fault_test", feature = "blk_test", feature = "fs_test", feature = "go_test", feature = "go_std_test", feature = "sec_rights_test", feature = "sec_filter_test"))]
+            #[cfg(m3)]
             4 => sys_vm_map_m3(arg1, arg2),
-            #[cfg(any(feature = "user_hello_test", feature = "syscall_test", feature = "thread_exit_test", feature = "thread_spawn_test", feature = "vm_map_test", feature = "syscall_invalid_test", feature = "stress_syscall_test", feature = "yield_test", feature = "user_fault_test", feature = "blk_test", feature = "fs_test", feature = "go_test", feature = "go_std_test", feature = "sec_rights_test", feature = "sec_filter_test"))]
+            #[cfg(m3)]
             5 => sys_vm_unmap_m3(arg1, arg2),
             10 => sys_time_now(),
-            #[cfg(any(feature = "user_hello_test", feature = "syscall_test", feature = "thread_exit_test", feature = "thread_spawn_test", feature = "vm_map_test", feature = "syscall_invalid_test", feature = "stress_syscall_test", feature = "yield_test", feature = "user_fault_test", feature = "blk_test", feature = "fs_test", feature = "go_test", feature = "go_std_test", feature = "sec_rights_test", feature = "sec_filter_test"))]
+            #[cfg(m3)]
             18 => sys_open_v1(arg1, arg2, arg3),
-            #[cfg(any(feature = "user_hello_test", feature = "syscall_test", feature = "thread_exit_test", feature = "thread_spawn_test", feature = "vm_map_test", feature = "syscall_invalid_test", feature = "stress_syscall_test", feature = "yield_test", feature = "user_fault_test", feature = "blk_test", feature = "fs_test", feature = "go_test", feature = "go_std_test", feature = "sec_rights_test", feature = "sec_filter_test"))]
+            #[cfg(m3)]
             19 => sys_read_v1(arg1, arg2, arg3),
-            #[cfg(any(feature = "user_hello_test", feature = "syscall_test", feature = "thread_exit_test", feature = "thread_spawn_test", feature = "vm_map_test", feature = "syscall_invalid_test", feature = "stress_syscall_test", feature = "yield_test", feature = "user_fault_test", feature = "blk_test", feature = "fs_test", feature = "go_test", feature = "go_std_test", feature = "sec_rights_test", feature = "sec_filter_test"))]
+            #[cfg(m3)]
             20 => sys_write_v1(arg1, arg2, arg3),
-            #[cfg(any(feature = "user_hello_test", feature = "syscall_test", feature = "thread_exit_test", feature = "thread_spawn_test", feature = "vm_map_test", feature = "syscall_invalid_test", feature = "stress_syscall_test", feature = "yield_test", feature = "user_fault_test", feature = "blk_test", feature = "fs_test", feature = "go_test", feature = "go_std_test", feature = "sec_rights_test", feature = "sec_filter_test"))]
+            #[cfg(m3)]
             21 => sys_close_v1(arg1),
-            #[cfg(any(feature = "user_hello_test", feature = "syscall_test", feature = "thread_exit_test", feature = "thread_spawn_test", feature = "vm_map_test", feature = "syscall_invalid_test", feature = "stress_syscall_test", feature = "yield_test", feature = "user_fault_test", feature = "blk_test", feature = "fs_test", feature = "go_test", feature = "go_std_test", feature = "sec_rights_test", feature = "sec_filter_test"))]
+            #[cfg(m3)]
             22 => sys_wait_v1(arg1, arg2, arg3),
-            #[cfg(any(feature = "user_hello_test", feature = "syscall_test", feature = "thread_exit_test", feature = "thread_spawn_test", feature = "vm_map_test", feature = "syscall_invalid_test", feature = "stress_syscall_test", feature = "yield_test", feature = "user_fault_test", feature = "blk_test", feature = "fs_test", feature = "go_test", feature = "go_std_test", feature = "sec_rights_test", feature = "sec_filter_test"))]
+            #[cfg(m3)]
             23 => sys_poll_v1(arg1, arg2, arg3),
-            #[cfg(any(feature = "user_hello_test", feature = "syscall_test", feature = "thread_exit_test", feature = "thread_spawn_test", feature = "vm_map_test", feature = "syscall_invalid_test", feature = "stress_syscall_test", feature = "yield_test", feature = "user_fault_test", feature = "blk_test", feature = "fs_test", feature = "go_test", feature = "go_std_test", feature = "sec_rights_test", feature = "sec_filter_test"))]
+            #[cfg(m3)]
             24 => sys_fd_rights_get_v1(arg1),
-            #[cfg(any(feature = "user_hello_test", feature = "syscall_test", feature = "thread_exit_test", feature = "thread_spawn_test", feature = "vm_map_test", feature = "syscall_invalid_test", feature = "stress_syscall_test", feature = "yield_test", feature = "user_fault_test", feature = "blk_test", feature = "fs_test", feature = "go_test", feature = "go_std_test", feature = "sec_rights_test", feature = "sec_filter_test"))]
+            #[cfg(m3)]
             25 => sys_fd_rights_reduce_v1(arg1, arg2),
-            #[cfg(any(feature = "user_hello_test", feature = "syscall_test", feature = "thread_exit_test", feature = "thread_spawn_test", feature = "vm_map_test", feature = "syscall_invalid_test", feature = "stress_syscall_test", feature = "yield_test", feature = "user_fault_test", feature = "blk_test", feature = "fs_test", feature = "go_test", feature = "go_std_test", feature = "sec_rights_test", feature = "sec_filter_test"))]
+            #[cfg(m3)]
             26 => sys_fd_rights_transfer_v1(arg1, arg2),
-            #[cfg(any(feature = "user_hello_test", feature = "syscall_test", feature = "thread_exit_test", feature = "thread_spawn_test", feature = "vm_map_test", feature = "syscall_invalid_test", feature = "stress_syscall_test", feature = "yield_test", feature = "user_fault_test", feature = "blk_test", feature = "fs_test", feature = "go_test", feature = "go_std_test", feature = "sec_rights_test", feature = "sec_filter_test"))]
+            #[cfg(m3)]
             27 => sys_sec_profile_set_v1(arg1),
-            #[cfg(any(feature = "user_hello_test", feature = "syscall_test", feature = "thread_exit_test", feature = "thread_spawn_test", feature = "vm_map_test", feature = "syscall_invalid_test", feature = "stress_syscall_test", feature = "yield_test", feature = "user_fault_test", feature = "blk_test", feature = "fs_test", feature = "go_test", feature = "go_std_test", feature = "sec_rights_test", feature = "sec_filter_test"))]
+            #[cfg(m3)]
             30 => sys_fsync_v1(arg1),
-            #[cfg(any(feature = "user_hello_test", feature = "syscall_test", feature = "thread_exit_test", feature = "thread_spawn_test", feature = "vm_map_test", feature = "syscall_invalid_test", feature = "stress_syscall_test", feature = "yield_test", feature = "user_fault_test", feature = "blk_test", feature = "fs_test", feature = "go_test", feature = "go_std_test", feature = "sec_rights_test", feature = "sec_filter_test"))]
+            #[cfg(m3)]
             43 => sys_fork_deferred_v1(),
-            #[cfg(any(feature = "user_hello_test", feature = "syscall_test", feature = "thread_exit_test", feature = "thread_spawn_test", feature = "vm_map_test", feature = "syscall_invalid_test", feature = "stress_syscall_test", feature = "yield_test", feature = "user_fault_test", feature = "blk_test", feature = "fs_test", feature = "go_test", feature = "go_std_test", feature = "sec_rights_test", feature = "sec_filter_test"))]
+            #[cfg(m3)]
             44 => sys_clone_deferred_v1(),
-            #[cfg(any(feature = "user_hello_test", feature = "syscall_test", feature = "thread_exit_test", feature = "thread_spawn_test", feature = "vm_map_test", feature = "syscall_invalid_test", feature = "stress_syscall_test", feature = "yield_test", feature = "user_fault_test", feature = "blk_test", feature = "fs_test", feature = "go_test", feature = "go_std_test", feature = "sec_rights_test", feature = "sec_filter_test"))]
+            #[cfg(m3)]
             45 => sys_epoll_deferred_v1(),
             _ => 0xFFFF_FFFF_FFFF_FFFF,
         };
@@ -0,0 +1,121 @@
+// Kernel timers for the Go lane: a min-heap of absolute tick deadlines
+// driven by the R4 PIT tick.
+//
+// Each event has at most one entry, so the heap never holds more than one
+// timeout per task plus one per timer handle, and is sized to that. The heap
+// sits under the scheduler lock: a CPU switching tasks in the scheduler IPI
+// cancels the new task's timeout without the kernel lock.
+
+use crate::*;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TimerEvent {
+    /// A blocked task's timeout, by tid.
+    Task(usize),
+    /// A one-shot or periodic timer handle, by handle index.
+    Handle(usize),
+}
+
+#[derive(Clone, Copy)]
+struct TimerEntry {
+    deadline: u64,
+    event: TimerEvent,
+}
+
+impl TimerEntry {
+    const EMPTY: Self = Self { deadline: 0, event: TimerEvent::Task(0) };
+}
+
+static mut TIMER_HEAP: kobj::KTable<TimerEntry> = kobj::KTable::EMPTY;
+static mut TIMER_LEN: usize = 0;
+
+/// Drop every pending entry and size the heap for `tasks` task slots.
+pub(crate) unsafe fn timer_init(tasks: usize) {
+    TIMER_LEN = 0;
+    if !TIMER_HEAP.init(tasks + R4_MAX_TIMER_HANDLES, TimerEntry::EMPTY) {
+        serial_write(b"TIMER: heap alloc fail\n");
+    }
+}
+
+unsafe fn timer_sift_up(mut idx: usize) {
+    while idx > 0 {
+        let parent = (idx - 1) / 2;
+        if TIMER_HEAP[parent].deadline <= TIMER_HEAP[idx].deadline {
+            break;
+        }
+        TIMER_HEAP.swap(parent, idx);
+        idx = parent;
+    }
+}
+
+unsafe fn timer_sift_down(mut idx: usize) {
+    loop {
+        let left = idx * 2 + 1;
+        let right = left + 1;
+        let mut least = idx;
+        if left < TIMER_LEN && TIMER_HEAP[left].deadline < TIMER_HEAP[least].deadline {
+            least = left;
+        }
+        if right < TIMER_LEN && TIMER_HEAP[right].deadline < TIMER_HEAP[least].deadline {
+            least = right;
+        }
+        if least == idx {
+            break;
+        }
+        TIMER_HEAP.swap(least, idx);
+        idx = least;
+    }
+}
+
+unsafe fn timer_remove_at(idx: usize) {
+    TIMER_LEN -= 1;
+    if idx == TIMER_LEN {
+        return;
+    }
+    TIMER_HEAP[idx] = TIMER_HEAP[TIMER_LEN];
+    timer_sift_down(idx);
+    timer_sift_up(idx);
+}
+
+/// Drop the pending entry for `event`, if any.
+pub(crate) unsafe fn timer_cancel(event: TimerEvent) {
+    smp::smp_sched_lock();
+    if let Some(idx) = (0..TIMER_LEN).find(|&idx| TIMER_HEAP[idx].event == event) {
+        timer_remove_at(idx);
+    }
+    smp::smp_sched_unlock();
+}
+
+/// Fire `event` once the tick count reaches `deadline`, replacing any entry
+/// it already had.
+pub(crate) unsafe fn timer_arm(deadline: u64, event: TimerEvent) {
+    smp::smp_sched_lock();
+    timer_cancel(event);
+    TIMER_HEAP[TIMER_LEN] = TimerEntry { deadline, event };
+    TIMER_LEN += 1;
+    timer_sift_up(TIMER_LEN - 1);
+    smp::smp_sched_unlock();
+}
+
+/// Take the earliest event due at or before `now`.
+pub(crate) unsafe fn timer_pop_expired(now: u64) -> Option<TimerEvent> {
+    smp::smp_sched_lock();
+    let event = if TIMER_LEN == 0 || TIMER_HEAP[0].deadline > now {
+        None
+    } else {
+        let event = TIMER_HEAP[0].event;
+        timer_remove_at(0);
+        Some(event)
+    };
+    smp::smp_sched_unlock();
+    event
+}
+
+/// Whether any pending event satisfies `wakes`, i.e. whether waiting for
+/// the next tick can make progress.
+pub(crate) unsafe fn timer_any(wakes: impl Fn(TimerEvent) -> bool) -> bool {
+    smp::smp_sched_lock();
+    let any = (0..TIMER_LEN).any(|idx| wakes(TIMER_HEAP[idx].event));
+    smp::smp_sched_unlock();
+    any
+}
@@ -4,8 +4,36 @@ use crate::arch_x86::qemu_exit;
 use crate::runtime;
 use crate::{serial_write, serial_write_hex, stack_top};
 
+/// Every interrupt, exception and `int 0x80` lands here. On the Go lane the
+/// CPU time accounting brackets the handler, and everything but the
+/// scheduler IPI runs under the kernel lock. That IPI takes only the
+/// scheduler lock, unless it carries a kill for the task running here.
 #[no_mangle]
 pub extern "C" fn trap_handler(frame: *mut u64) {
+    #[cfg(feature = "go_test")]
+    unsafe {
+        let int_num = *frame.add(15);
+        let kernel = int_num != crate::smp::SMP_IPI_VECTOR || crate::r4_kill_posted();
+        if kernel {
+            crate::smp::smp_lock();
+        }
+        crate::cputime::cpu_enter();
+        // A task killed from another CPU takes its syscall or fault with it;
+        // an interrupt is still handled, for the task switched to.
+        if !kernel || !crate::r4_take_kill(frame) || (int_num >= 32 && int_num != 128) {
+            trap_dispatch(frame);
+        }
+        crate::r4_kick_idle_cpus();
+        crate::cputime::cpu_exit(frame);
+        if kernel {
+            crate::smp::smp_unlock();
+        }
+    }
+    #[cfg(not(feature = "go_test"))]
+    trap_dispatch(frame);
+}
+
+fn trap_dispatch(frame: *mut u64) {
     unsafe {
         let int_num = *frame.add(15);
         let error_code = *frame.add(16);
@@ -49,6 +77,26 @@ pub extern "C" fn trap_handler(frame: *mut u64) {
             14 => {
                 let cs = *frame.add(18);
                 if cs & 3 == 3 {
+                    #[cfg(r4)]
+                    {
+                        let cr2: u64;
+                        core::arch::asm!("mov {}, cr2", out(reg) cr2, options(nomem, nostack));
+                        if crate::vm::vm_handle_fault(
+                            crate::R4_TASKS[crate::r4_current()].space,
+                            cr2,
+                            error_code,
+                        ) {
+                            crate::r4_mem_pressure_poll(frame);
+                            return;
+                        }
+                        // An OOM kill may have taken the faulting task.
+                        if crate::r4_mem_pressure_poll(frame) {
+                            return;
+                        }
+                        if crate::vm::vm_in_stack_guard(crate::R4_TASKS[crate::r4_current()].space, cr2) {
+                            serial_write(b"USER: stack overflow\n");
+                        }
+                    }
                     #[cfg(feature = "go_test")]
                     {
                         let cr2: u64;
@@ -66,6 +114,9 @@ pub extern "C" fn trap_handler(frame: *mut u64) {
                 }
                 let cr2: u64;
                 core::arch::asm!("mov {}, cr2", out(reg) cr2, options(nomem, nostack));
+                if crate::memory::user_copy_fault(frame, cr2, error_code) {
+                    return;
+                }
                 serial_write(b"PF: addr=0x");
                 serial_write_hex(cr2);
                 serial_write(b" err=0x");
@@ -78,6 +129,14 @@ pub extern "C" fn trap_handler(frame: *mut u64) {
             32 => {
                 crate::sched::handle_timer_irq();
             }
+            #[cfg(feature = "go_test")]
+            32 => {
+                crate::r4_timer_tick(frame);
+            }
+            #[cfg(feature = "go_test")]
+            crate::smp::SMP_IPI_VECTOR => {
+                crate::r4_ipi(frame);
+            }
             #[cfg(any(feature = "blk_test", feature = "fs_test", feature = "go_test"))]
             64 | 65 => {
                 if runtime::native::handle_irq(int_num) {
@@ -100,7 +159,7 @@ extern "C" fn user_fault_return() -> ! {
     }
 }
 
-#[cfg(not(any(feature = "ipc_test", feature = "shm_test", feature = "ipc_badptr_send_test", feature = "ipc_badptr_recv_test", feature = "ipc_badptr_svc_test", feature = "ipc_buffer_full_test", feature = "ipc_waiter_busy_test", feature = "svc_overwrite_test", feature = "svc_full_test", feature = "svc_bad_endpoint_test", feature = "stress_ipc_test", feature = "quota_endpoints_test", feature = "quota_shm_test", feature = "quota_threads_test")))]
+#[cfg(not(r4_ipc))]
 pub(crate) unsafe fn m3_return_to_kernel_halt(frame: *mut u64) {
     let kstack = &stack_top as *const u8 as u64;
     *frame.add(17) = user_fault_return as *const () as u64;
@@ -111,13 +170,13 @@ pub(crate) unsafe fn m3_return_to_kernel_halt(frame: *mut u64) {
 }
 
 unsafe fn handle_user_fault(frame: *mut u64) {
-    #[cfg(any(feature = "ipc_test", feature = "shm_test", feature = "ipc_badptr_send_test", feature = "ipc_badptr_recv_test", feature = "ipc_badptr_svc_test", feature = "ipc_buffer_full_test", feature = "ipc_waiter_busy_test", feature = "svc_overwrite_test", feature = "svc_full_test", feature = "svc_bad_endpoint_test", feature = "stress_ipc_test", feature = "quota_endpoints_test", feature = "quota_shm_test", feature = "quota_threads_test", feature = "go_test"))]
+    #[cfg(r4)]
     {
         crate::r4_exit_and_switch(frame, 1);
         return;
     }
 
-    #[cfg(not(any(feature = "ipc_test", feature = "shm_test", feature = "ipc_badptr_send_test", feature = "ipc_badptr_recv_test", feature = "ipc_badptr_svc_test", feature = "ipc_buffer_full_test", feature = "ipc_waiter_busy_test", feature = "svc_overwrite_test", feature = "svc_full_test", feature = "svc_bad_endpoint_test", feature = "stress_ipc_test", feature = "quota_endpoints_test", feature = "quota_shm_test", feature = "quota_threads_test", feature = "go_test")))]
+    #[cfg(not(r4))]
     {
         serial_write(b"USER: killed\n");
         m3_return_to_kernel_halt(frame);
@@ -0,0 +1,1612 @@
+// Per-task user address spaces and the frame pool backing them (R4 lanes).
+
+use crate::*;
+
+pub(crate) const VM_NO_SPACE: usize = usize::MAX;
+pub(crate) const VM_PTE_PRESENT: u64 = 1 << 0;
+pub(crate) const VM_PTE_WRITABLE: u64 = 1 << 1;
+pub(crate) const VM_PTE_USER: u64 = 1 << 2;
+// Write-through and cache-disable, for device pages.
+#[cfg(feature = "go_test")]
+const VM_PTE_PWT: u64 = 1 << 3;
+#[cfg(feature = "go_test")]
+const VM_PTE_PCD: u64 = 1 << 4;
+// Page-size bit: a PD entry with it set maps a 2 MiB leaf.
+pub(crate) const VM_PTE_PS: u64 = 1 << 7;
+pub(crate) const VM_PTE_ADDR_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+// No-execute bit; only valid once `vm_init` has turned on EFER.NXE.
+const VM_PTE_NX: u64 = 1 << 63;
+// Software PTE bits (ignored by the MMU).
+pub(crate) const VM_PTE_COW: u64 = 1 << 9;
+pub(crate) const VM_PTE_SHARED: u64 = 1 << 10;
+// A PROT_NONE page: not present to the MMU, but the frame is kept.
+const VM_PTE_PROTNONE: u64 = 1 << 11;
+const VM_TABLE_FLAGS: u64 = VM_PTE_PRESENT | VM_PTE_WRITABLE | VM_PTE_USER;
+pub(crate) const VM_PROT_READ: u8 = 1 << 0;
+pub(crate) const VM_PROT_WRITE: u8 = 1 << 1;
+pub(crate) const VM_PROT_EXEC: u8 = 1 << 2;
+const VM_FAULT_PRESENT: u64 = 1 << 0;
+const VM_FAULT_WRITE: u64 = 1 << 1;
+const VM_FAULT_FETCH: u64 = 1 << 4;
+// User mappings live under PML4[0]; every other slot is copied from the boot
+// table so the kernel image, HHDM and kernel stack survive a CR3 switch.
+const VM_USER_PML4_SLOTS: usize = 1;
+// Most spaces the space table can hold; `vm_init` sizes it to the user task
+// limit. Kernel workers run in whatever space was loaded and never own one.
+pub(crate) const VM_MAX_SPACES: usize = kobj::KTable::<VmSpace>::CAPACITY;
+// Pool size when the boot command line sets none. Go lanes load whole ELF
+// and Go images into pool frames.
+#[cfg(feature = "go_test")]
+pub(crate) const VM_DEFAULT_FRAMES: usize = 1024;
+#[cfg(not(feature = "go_test"))]
+pub(crate) const VM_DEFAULT_FRAMES: usize = 64;
+pub(crate) const VM_PRESSURE_NORMAL: u8 = 0;
+pub(crate) const VM_PRESSURE_LOW: u8 = 1;
+pub(crate) const VM_PRESSURE_CRITICAL: u8 = 2;
+pub(crate) const VM_LARGE_PAGE: u64 = 0x20_0000;
+// Large pool size when the boot command line sets none.
+#[cfg(feature = "go_test")]
+pub(crate) const VM_DEFAULT_LARGE_FRAMES: usize = 8;
+#[cfg(not(feature = "go_test"))]
+pub(crate) const VM_DEFAULT_LARGE_FRAMES: usize = 2;
+// Large frames are refcounted in 4 KiB units: a 2 MiB leaf holds this many,
+// a PTE pointing into a split frame holds one.
+const VM_LARGE_UNITS: u32 = (VM_LARGE_PAGE / 4096) as u32;
+// Thread stack slots a shared space can reserve: one per task slot a holder
+// mask can name, the most the task limit allows.
+pub(crate) const VM_STACK_SLOTS: usize = runtime::isolation::HOLDER_SLOTS;
+// Every thread slot of a shared space takes two areas, stack and guard, so
+// the Go space at the full task capacity still leaves room for mappings.
+const VM_MAX_AREAS: usize = 2 * VM_STACK_SLOTS + 32;
+const VM_USER_TOP: u64 = (VM_USER_PML4_SLOTS as u64) << 39;
+// Lowest address handed out by `vm_mmap` when the hint cannot be used.
+pub(crate) const VM_MMAP_BASE: u64 = 0x1000_0000;
+// `vm_mmap` prot flag: reserve a growable stack with a guard gap below it.
+pub(crate) const VM_MAP_STACK: u16 = 1 << 8;
+// Unbacked, unreservable gap kept below every stack area.
+pub(crate) const VM_STACK_GUARD: u64 = 0x1_0000;
+const VM_AREA_ANON: u8 = 0;
+const VM_AREA_STACK: u8 = 1;
+const VM_AREA_GUARD: u8 = 2;
+// Eagerly mapped SHM object pages; never demand-filled.
+const VM_AREA_SHM: u8 = 3;
+
+/// A reserved user range. Pages inside it are backed on first touch.
+#[derive(Clone, Copy)]
+struct VmArea {
+    start: u64,
+    end: u64,
+    active: bool,
+    prot: u8,
+    kind: u8,
+}
+
+impl VmArea {
+    const EMPTY: Self = Self {
+        start: 0,
+        end: 0,
+        active: false,
+        prot: 0,
+        kind: VM_AREA_ANON,
+    };
+}
+
+#[derive(Clone, Copy)]
+struct VmSpace {
+    active: bool,
+    pml4_phys: u64,
+    refs: usize,
+    owns_tables: bool,
+    areas: [VmArea; VM_MAX_AREAS],
+    // Mapped user leaves, in 4 KiB pages.
+    resident: u64,
+    // Hard cap on reserved pages and soft mark on resident pages; 0 is none.
+    hard_limit: u64,
+    soft_limit: u64,
+    // Past the soft limit as of the last pressure poll.
+    over_soft: bool,
+}
+
+impl VmSpace {
+    const EMPTY: Self = Self {
+        active: false,
+        pml4_phys: 0,
+        refs: 0,
+        owns_tables: false,
+        areas: [VmArea::EMPTY; VM_MAX_AREAS],
+        resident: 0,
+        hard_limit: 0,
+        soft_limit: 0,
+        over_soft: false,
+    };
+}
+
+static mut VM_SPACES: kobj::KTable<VmSpace> = kobj::KTable::EMPTY;
+static mut VM_KERNEL_CR3: u64 = 0;
+// `VM_PTE_NX` when the CPU enforces it, else 0.
+static mut VM_NX: u64 = 0;
+// The pool is one physical run carved from the memory map on the first
+// `vm_init`, sized by the boot quota; the reference counts sit in frames
+// of their own.
+static mut VM_FRAME_BASE: u64 = 0;
+static mut VM_FRAME_COUNT: usize = 0;
+static mut VM_FRAME_REFS: *mut u16 = core::ptr::dangling_mut();
+static mut VM_FRAMES_FREE: usize = 0;
+// Where the next free-frame search starts.
+static mut VM_FRAME_HINT: usize = 0;
+// Set when `vm_frame_alloc` finds the pool empty; cleared by the pressure
+// poll that handles it.
+static mut VM_ALLOC_FAILED: bool = false;
+
+// 2 MiB frames, carved on a 2 MiB boundary after the pool and sized by
+// their own boot quota. With none carved, `vm_large_alloc` always fails and
+// faults fall back to 4 KiB pages.
+static mut VM_LARGE_BASE: u64 = 0;
+static mut VM_LARGE_COUNT: usize = 0;
+static mut VM_LARGE_REFS: *mut u32 = core::ptr::dangling_mut();
+
+#[inline(always)]
+unsafe fn vm_read_cr3() -> u64 {
+    let cr3: u64;
+    core::arch::asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack));
+    cr3 & VM_PTE_ADDR_MASK
+}
+
+#[inline(always)]
+unsafe fn vm_write_cr3(pml4_phys: u64) {
+    core::arch::asm!("mov cr3, {}", in(reg) pml4_phys, options(nostack));
+    #[cfg(feature = "go_test")]
+    smp::smp_note_cr3(pml4_phys);
+}
+
+/// Drop stale TLB entries for `space` on the other CPUs that have it
+/// loaded, after its page tables changed.
+#[inline(always)]
+unsafe fn vm_shootdown(space: usize) {
+    #[cfg(feature = "go_test")]
+    smp::smp_tlb_shootdown(VM_SPACES[space].pml4_phys);
+    #[cfg(not(feature = "go_test"))]
+    let _ = space;
+}
+
+#[cfg(r4_ipc)]
+#[inline(always)]
+unsafe fn vm_kv2p(va: u64) -> u64 {
+    let kaddr_resp_ptr = core::ptr::read_volatile(
+        core::ptr::addr_of!(KADDR_REQUEST.response));
+    va - (*kaddr_resp_ptr).virtual_base + (*kaddr_resp_ptr).physical_base
+}
+
+#[inline(always)]
+pub(crate) unsafe fn vm_table(phys: u64) -> *mut u64 {
+    ((phys & VM_PTE_ADDR_MASK) + HHDM_OFFSET) as *mut u64
+}
+
+/// Record the boot page table and drop every space and frame. Called by the
+/// R4 page setup paths before any task space is built.
+pub(crate) unsafe fn vm_init() {
+    if VM_KERNEL_CR3 == 0 {
+        VM_KERNEL_CR3 = vm_read_cr3();
+        if arch_x86::nx_enable() {
+            VM_NX = VM_PTE_NX;
+        } else {
+            serial_write(b"VM: no NX, data pages stay executable\n");
+        }
+    } else if vm_read_cr3() != VM_KERNEL_CR3 {
+        vm_write_cr3(VM_KERNEL_CR3);
+    }
+    if VM_FRAME_BASE == 0 {
+        vm_pool_carve();
+    }
+    if !VM_SPACES.init(limits::max_tasks(), VmSpace::EMPTY) {
+        serial_write(b"VM: space table alloc fail\n");
+    }
+    vm_frame_refs().fill(0);
+    VM_FRAMES_FREE = VM_FRAME_COUNT;
+    VM_FRAME_HINT = 0;
+    vm_large_refs().fill(0);
+    VM_ALLOC_FAILED = false;
+}
+
+/// Take the pool's frames from the memory map: the boot quota, or as much
+/// of it as the largest free run holds.
+unsafe fn vm_pool_carve() {
+    let quota = limits::vm_frames();
+    let ref_pages = (quota * core::mem::size_of::<u16>()).div_ceil(4096);
+    let refs = match pmm::pmm_carve(ref_pages, 4096) {
+        Some(phys) => phys,
+        None => {
+            serial_write(b"VM: no memory for the frame pool\n");
+            return;
+        }
+    };
+    let count = quota.min(pmm::pmm_largest_run(4096));
+    let base = match pmm::pmm_carve(count, 4096) {
+        Some(phys) if count != 0 => phys,
+        _ => {
+            serial_write(b"VM: no memory for the frame pool\n");
+            return;
+        }
+    };
+    if count < quota {
+        serial_write(b"VM: frame pool clamped to ");
+        serial_write_u64_dec(count as u64);
+        serial_write(b" frames\n");
+    }
+    VM_FRAME_REFS = vm_table(refs) as *mut u16;
+    VM_FRAME_COUNT = count;
+    VM_FRAME_BASE = base;
+    vm_large_pool_carve();
+}
+
+/// Take the large frames and their reference counts from the memory map,
+/// as many of the boot quota as the largest aligned run holds.
+unsafe fn vm_large_pool_carve() {
+    let quota = limits::vm_large_frames();
+    let count = quota.min(pmm::pmm_largest_run(VM_LARGE_PAGE) / VM_LARGE_UNITS as usize);
+    let ref_pages = (count * core::mem::size_of::<u32>()).div_ceil(4096);
+    let refs = if count != 0 { pmm::pmm_carve(ref_pages, 4096) } else { None };
+    let base = match refs {
+        Some(_) => pmm::pmm_carve(count * VM_LARGE_UNITS as usize, VM_LARGE_PAGE),
+        None => None,
+    };
+    match (refs, base) {
+        (Some(refs), Some(base)) => {
+            if count < quota {
+                serial_write(b"VM: large frame pool clamped to ");
+                serial_write_u64_dec(count as u64);
+                serial_write(b" frames\n");
+            }
+            VM_LARGE_REFS = vm_table(refs) as *mut u32;
+            VM_LARGE_COUNT = count;
+            VM_LARGE_BASE = base;
+        }
+        _ if quota != 0 => serial_write(b"VM: no large frames, using 4 KiB pages\n"),
+        _ => {}
+    }
+}
+
+#[inline(always)]
+unsafe fn vm_large_refs() -> &'static mut [u32] {
+    core::slice::from_raw_parts_mut(VM_LARGE_REFS, VM_LARGE_COUNT)
+}
+
+#[inline(always)]
+unsafe fn vm_frame_refs() -> &'static mut [u16] {
+    core::slice::from_raw_parts_mut(VM_FRAME_REFS, VM_FRAME_COUNT)
+}
+
+fn vm_frame_index(phys: u64) -> Option<usize> {
+    let base = unsafe { VM_FRAME_BASE };
+    if base == 0 || phys < base {
+        return None;
+    }
+    let idx = ((phys - base) / 4096) as usize;
+    if idx < unsafe { VM_FRAME_COUNT } { Some(idx) } else { None }
+}
+
+/// Allocate one zeroed frame from the pool and return its physical address.
+pub(crate) unsafe fn vm_frame_alloc() -> Option<u64> {
+    let refs = vm_frame_refs();
+    for n in 0..VM_FRAME_COUNT {
+        let i = (VM_FRAME_HINT + n) % VM_FRAME_COUNT;
+        if refs[i] == 0 {
+            refs[i] = 1;
+            VM_FRAMES_FREE -= 1;
+            VM_FRAME_HINT = i + 1;
+            let phys = VM_FRAME_BASE + (i as u64) * 4096;
+            core::ptr::write_bytes(vm_table(phys) as *mut u8, 0, 4096);
+            return Some(phys);
+        }
+    }
+    VM_ALLOC_FAILED = true;
+    None
+}
+
+pub(crate) unsafe fn vm_free_frames() -> usize {
+    VM_FRAMES_FREE
+}
+
+/// Pressure level from the free 4 KiB frames left in the pool: low under a
+/// quarter, critical under a sixteenth.
+pub(crate) unsafe fn vm_pressure_level() -> u8 {
+    let free = vm_free_frames();
+    if free <= VM_FRAME_COUNT / 16 {
+        VM_PRESSURE_CRITICAL
+    } else if free <= VM_FRAME_COUNT / 4 {
+        VM_PRESSURE_LOW
+    } else {
+        VM_PRESSURE_NORMAL
+    }
+}
+
+/// Report and clear an allocation failure since the last call.
+pub(crate) unsafe fn vm_take_alloc_failure() -> bool {
+    let failed = VM_ALLOC_FAILED;
+    VM_ALLOC_FAILED = false;
+    failed
+}
+
+fn vm_large_index(phys: u64) -> Option<usize> {
+    let base = unsafe { VM_LARGE_BASE };
+    if base == 0 || phys < base {
+        return None;
+    }
+    let idx = ((phys - base) / VM_LARGE_PAGE) as usize;
+    if idx < unsafe { VM_LARGE_COUNT } { Some(idx) } else { None }
+}
+
+/// Allocate one zeroed 2 MiB frame. Fails when every large frame is in use
+/// or none were carved.
+pub(crate) unsafe fn vm_large_alloc() -> Option<u64> {
+    for (i, refs) in vm_large_refs().iter_mut().enumerate() {
+        if *refs == 0 {
+            let phys = VM_LARGE_BASE + (i as u64) * VM_LARGE_PAGE;
+            *refs = VM_LARGE_UNITS;
+            core::ptr::write_bytes(vm_table(phys) as *mut u8, 0, VM_LARGE_PAGE as usize);
+            return Some(phys);
+        }
+    }
+    None
+}
+
+/// Take another reference to a pool frame shared by a second mapping.
+pub(crate) unsafe fn vm_frame_ref(phys: u64) {
+    vm_leaf_ref(phys, 4096);
+}
+
+/// Drop one reference to a pool frame. Static kernel pages mapped into user
+/// space (boot images, SHM backing) are not pool frames and are ignored.
+pub(crate) unsafe fn vm_frame_release(phys: u64) {
+    vm_leaf_release(phys, 4096);
+}
+
+/// Drop the reference `vm_large_alloc` handed out with a large frame.
+#[cfg(any(feature = "shm_test", feature = "quota_shm_test", feature = "go_test"))]
+pub(crate) unsafe fn vm_large_release(phys: u64) {
+    vm_leaf_release(phys, VM_LARGE_PAGE);
+}
+
+/// Reference the frame behind a leaf of `size` bytes (4 KiB or 2 MiB).
+unsafe fn vm_leaf_ref(phys: u64, size: u64) {
+    let phys = phys & VM_PTE_ADDR_MASK;
+    if let Some(idx) = vm_frame_index(phys) {
+        vm_frame_refs()[idx] += 1;
+    } else if let Some(idx) = vm_large_index(phys) {
+        vm_large_refs()[idx] += (size / 4096) as u32;
+    }
+}
+
+unsafe fn vm_leaf_release(phys: u64, size: u64) {
+    let phys = phys & VM_PTE_ADDR_MASK;
+    if let Some(idx) = vm_frame_index(phys) {
+        let refs = &mut vm_frame_refs()[idx];
+        if *refs != 0 {
+            *refs -= 1;
+            if *refs == 0 {
+                VM_FRAMES_FREE += 1;
+            }
+        }
+    } else if let Some(idx) = vm_large_index(phys) {
+        let refs = &mut vm_large_refs()[idx];
+        *refs = refs.saturating_sub((size / 4096) as u32);
+    }
+}
+
+unsafe fn vm_space_slot() -> Option<usize> {
+    (0..VM_SPACES.len()).find(|&i| !VM_SPACES[i].active)
+}
+
+/// Create an empty user address space sharing the boot kernel mappings.
+pub(crate) unsafe fn vm_space_create() -> Option<usize> {
+    let slot = vm_space_slot()?;
+    let pml4_phys = vm_frame_alloc()?;
+    let new_pml4 = vm_table(pml4_phys);
+    let boot_pml4 = vm_table(VM_KERNEL_CR3);
+    for i in VM_USER_PML4_SLOTS..512 {
+        *new_pml4.add(i) = *boot_pml4.add(i);
+    }
+    VM_SPACES[slot] = VmSpace {
+        active: true,
+        pml4_phys,
+        refs: 1,
+        owns_tables: true,
+        ..VmSpace::EMPTY
+    };
+    Some(slot)
+}
+
+#[inline(always)]
+unsafe fn vm_space_ok(space: usize) -> bool {
+    space < VM_SPACES.len() && VM_SPACES[space].active
+}
+
+pub(crate) unsafe fn vm_space_retain(space: usize) {
+    if vm_space_ok(space) {
+        VM_SPACES[space].refs += 1;
+    }
+}
+
+#[inline(always)]
+fn vm_leaf_mapped(entry: u64) -> bool {
+    entry & (VM_PTE_PRESENT | VM_PTE_PROTNONE) != 0
+}
+
+#[inline(always)]
+fn vm_large_leaf(entry: u64) -> bool {
+    entry & VM_PTE_PS != 0 && vm_leaf_mapped(entry)
+}
+
+unsafe fn vm_free_table(table_phys: u64, level: usize) {
+    let table = vm_table(table_phys);
+    let entries = if level == 4 { VM_USER_PML4_SLOTS } else { 512 };
+    for i in 0..entries {
+        let entry = *table.add(i);
+        if level == 2 && vm_large_leaf(entry) {
+            vm_leaf_release(entry, VM_LARGE_PAGE);
+            *table.add(i) = 0;
+            continue;
+        }
+        if (level == 1 && !vm_leaf_mapped(entry)) || (level > 1 && entry & VM_PTE_PRESENT == 0) {
+            continue;
+        }
+        if level == 1 {
+            vm_frame_release(entry);
+        } else {
+            vm_free_table(entry & VM_PTE_ADDR_MASK, level - 1);
+        }
+        *table.add(i) = 0;
+    }
+    vm_frame_release(table_phys);
+}
+
+/// Drop one reference; the last one tears down the user half of the table.
+pub(crate) unsafe fn vm_space_release(space: usize) {
+    if !vm_space_ok(space) {
+        return;
+    }
+    VM_SPACES[space].refs -= 1;
+    if VM_SPACES[space].refs != 0 {
+        return;
+    }
+    let pml4_phys = VM_SPACES[space].pml4_phys;
+    if vm_read_cr3() == pml4_phys {
+        vm_write_cr3(VM_KERNEL_CR3);
+    }
+    if VM_SPACES[space].owns_tables {
+        vm_free_table(pml4_phys, 4);
+    }
+    VM_SPACES[space] = VmSpace::EMPTY;
+}
+
+/// Install `phys | flags` at `va`, allocating intermediate tables as needed.
+/// Fails if `va` is already mapped: the old leaf holds a frame reference
+/// that only its owner knows how to drop.
+pub(crate) unsafe fn vm_map_page(space: usize, va: u64, phys: u64, flags: u64) -> bool {
+    if !vm_space_ok(space) || va & 0xFFF != 0 {
+        return false;
+    }
+    if ((va >> 39) & 0x1FF) as usize >= VM_USER_PML4_SLOTS {
+        return false;
+    }
+    let pd = match vm_pd_entry(space, va) {
+        Some(pd) => pd,
+        None => return false,
+    };
+    if vm_large_leaf(*pd) {
+        return false;
+    }
+    if *pd & VM_PTE_PRESENT == 0 {
+        let next = match vm_frame_alloc() {
+            Some(phys) => phys,
+            None => return false,
+        };
+        *pd = next | VM_TABLE_FLAGS;
+    }
+    let leaf = vm_table(*pd).add(((va >> 12) & 0x1FF) as usize);
+    if vm_leaf_mapped(*leaf) {
+        return false;
+    }
+    *leaf = (phys & VM_PTE_ADDR_MASK) | flags;
+    if vm_leaf_mapped(*leaf) {
+        VM_SPACES[space].resident += 1;
+    }
+    if vm_read_cr3() == VM_SPACES[space].pml4_phys {
+        core::arch::asm!("invlpg [{}]", in(reg) va, options(nostack));
+    }
+    true
+}
+
+/// Return the PD entry covering `va`, allocating the upper tables as needed.
+unsafe fn vm_pd_entry(space: usize, va: u64) -> Option<*mut u64> {
+    let mut table = vm_table(VM_SPACES[space].pml4_phys);
+    let mut shift = 39u64;
+    while shift > 21 {
+        let entry = table.add(((va >> shift) & 0x1FF) as usize);
+        if *entry & VM_PTE_PRESENT == 0 {
+            *entry = vm_frame_alloc()? | VM_TABLE_FLAGS;
+        }
+        table = vm_table(*entry);
+        shift -= 9;
+    }
+    Some(table.add(((va >> 21) & 0x1FF) as usize))
+}
+
+/// Install a 2 MiB leaf at the 2 MiB-aligned `va`. The PD slot must be empty.
+unsafe fn vm_map_large(space: usize, va: u64, phys: u64, flags: u64) -> bool {
+    if !vm_space_ok(space) || va & (VM_LARGE_PAGE - 1) != 0 || va >= VM_USER_TOP {
+        return false;
+    }
+    let pd = match vm_pd_entry(space, va) {
+        Some(pd) => pd,
+        None => return false,
+    };
+    if *pd & (VM_PTE_PRESENT | VM_PTE_PROTNONE) != 0 {
+        return false;
+    }
+    *pd = (phys & VM_PTE_ADDR_MASK) | flags | VM_PTE_PS;
+    VM_SPACES[space].resident += VM_LARGE_UNITS as u64;
+    vm_invalidate(space, va);
+    true
+}
+
+/// Replace the 2 MiB leaf at `pd` with a page table of 4 KiB entries over
+/// the same frame and flags, so the range can be changed page by page.
+unsafe fn vm_split_large(space: usize, pd: *mut u64) -> bool {
+    let old = *pd;
+    let table_phys = match vm_frame_alloc() {
+        Some(phys) => phys,
+        None => return false,
+    };
+    let table = vm_table(table_phys);
+    let phys = old & VM_PTE_ADDR_MASK;
+    let flags = old & !(VM_PTE_ADDR_MASK | VM_PTE_PS);
+    for i in 0..512u64 {
+        *table.add(i as usize) = (phys + i * 4096) | flags;
+    }
+    *pd = table_phys | VM_TABLE_FLAGS;
+    let pml4_phys = VM_SPACES[space].pml4_phys;
+    if vm_read_cr3() == pml4_phys {
+        vm_write_cr3(pml4_phys);
+    }
+    vm_shootdown(space);
+    true
+}
+
+/// Make `va` a 4 KiB boundary: split a 2 MiB leaf that straddles it.
+unsafe fn vm_split_large_at(space: usize, va: u64) -> bool {
+    if va & (VM_LARGE_PAGE - 1) == 0 {
+        return true;
+    }
+    match vm_walk(space, va) {
+        Ok((leaf, size)) if size == VM_LARGE_PAGE => vm_split_large(space, leaf),
+        _ => true,
+    }
+}
+
+/// Map a statically allocated kernel page into a user space.
+#[cfg(r4_ipc)]
+pub(crate) unsafe fn vm_map_kernel_page(space: usize, va: u64, page: *const u8, flags: u64) -> bool {
+    vm_map_page(space, va, vm_kv2p(page as u64), flags)
+}
+
+/// Load the space's PML4 unless it is already active.
+pub(crate) unsafe fn vm_activate(space: usize) {
+    if !vm_space_ok(space) {
+        return;
+    }
+    let pml4_phys = VM_SPACES[space].pml4_phys;
+    if vm_read_cr3() != pml4_phys {
+        vm_write_cr3(pml4_phys);
+    }
+}
+
+/// Load the boot page tables, which map only the kernel.
+#[cfg(feature = "go_test")]
+pub(crate) unsafe fn vm_activate_kernel() {
+    if VM_KERNEL_CR3 != 0 && vm_read_cr3() != VM_KERNEL_CR3 {
+        vm_write_cr3(VM_KERNEL_CR3);
+    }
+}
+
+/// Map the device page at `phys` uncached at its HHDM address, which the
+/// boot page tables leave out for anything but RAM. The mapping goes into
+/// the boot table; a new PML4 entry is also copied into every live space,
+/// since spaces copy the kernel half only when they are created.
+#[cfg(feature = "go_test")]
+pub(crate) unsafe fn vm_map_kernel_mmio(phys: u64) -> bool {
+    let va = HHDM_OFFSET + (phys & VM_PTE_ADDR_MASK);
+    let kernel_pml4 = if VM_KERNEL_CR3 != 0 { VM_KERNEL_CR3 } else { vm_read_cr3() };
+    let mut table = vm_table(kernel_pml4);
+    let mut shift = 39u64;
+    while shift > 12 {
+        let index = ((va >> shift) & 0x1FF) as usize;
+        let entry = table.add(index);
+        if *entry & VM_PTE_PRESENT == 0 {
+            let next = match pmm::pmm_carve(1, 4096) {
+                Some(next) => next,
+                None => return false,
+            };
+            core::ptr::write_bytes(vm_table(next), 0, 512);
+            *entry = next | VM_PTE_PRESENT | VM_PTE_WRITABLE;
+            if shift == 39 {
+                vm_share_kernel_slot(index, *entry);
+            }
+        } else if *entry & VM_PTE_PS != 0 {
+            return true;
+        }
+        table = vm_table(*entry);
+        shift -= 9;
+    }
+    *table.add(((va >> 12) & 0x1FF) as usize) = (phys & VM_PTE_ADDR_MASK)
+        | VM_PTE_PRESENT
+        | VM_PTE_WRITABLE
+        | VM_PTE_PCD
+        | VM_PTE_PWT
+        | VM_NX;
+    core::arch::asm!("invlpg [{}]", in(reg) va, options(nostack));
+    true
+}
+
+/// Copy a kernel PML4 entry into every live space.
+#[cfg(feature = "go_test")]
+unsafe fn vm_share_kernel_slot(index: usize, entry: u64) {
+    for i in 0..VM_SPACES.len() {
+        if VM_SPACES[i].active {
+            *vm_table(VM_SPACES[i].pml4_phys).add(index) = entry;
+        }
+    }
+}
+
+/// Temporarily switch to another task's space for a cross-task copy; pair
+/// with `vm_leave_space`.
+pub(crate) unsafe fn vm_enter_space(space: usize) -> u64 {
+    let prev = vm_read_cr3();
+    vm_activate(space);
+    prev
+}
+
+/// The space whose PML4 is loaded. Differs from the current task's space
+/// while a cross-task copy runs under `vm_enter_space`.
+pub(crate) unsafe fn vm_active_space() -> Option<usize> {
+    let cr3 = vm_read_cr3();
+    (0..VM_SPACES.len()).find(|&i| VM_SPACES[i].active && VM_SPACES[i].pml4_phys == cr3)
+}
+
+/// Whether the loaded space is held by one task alone. Only that task
+/// changes its mappings then, and it is either the caller or, during a
+/// cross-task copy, a peer blocked in IPC.
+pub(crate) unsafe fn vm_active_space_private() -> bool {
+    vm_active_space().is_some_and(|space| VM_SPACES[space].refs == 1)
+}
+
+pub(crate) unsafe fn vm_leave_space(prev: u64) {
+    if vm_read_cr3() != prev {
+        vm_write_cr3(prev);
+    }
+}
+
+/// Return the leaf slot for `va` and the size it maps (4 KiB, or 2 MiB for
+/// a PS entry in the PD), or `None` if a table level is missing.
+pub(crate) unsafe fn vm_lookup_pte(space: usize, va: u64) -> Option<(*mut u64, u64)> {
+    vm_walk(space, va).ok()
+}
+
+/// Like `vm_lookup_pte`, but on a missing table level reports the size of
+/// the unmapped span around `va` so range walks can skip it.
+unsafe fn vm_walk(space: usize, va: u64) -> Result<(*mut u64, u64), u64> {
+    if !vm_space_ok(space) || va >= VM_USER_TOP {
+        return Err(4096);
+    }
+    let mut table = vm_table(VM_SPACES[space].pml4_phys);
+    let mut shift = 39u64;
+    while shift > 12 {
+        let slot = table.add(((va >> shift) & 0x1FF) as usize);
+        if shift == 21 && vm_large_leaf(*slot) {
+            return Ok((slot, VM_LARGE_PAGE));
+        }
+        if *slot & VM_PTE_PRESENT == 0 {
+            return Err(1u64 << shift);
+        }
+        table = vm_table(*slot);
+        shift -= 9;
+    }
+    Ok((table.add(((va >> 12) & 0x1FF) as usize), 4096))
+}
+
+/// Call `f` on every mapped leaf in `[start, end)` with the leaf's base
+/// address and size. 2 MiB leaves are passed whole, so callers that act on
+/// part of one split it first.
+unsafe fn vm_for_each_leaf(
+    space: usize,
+    start: u64,
+    end: u64,
+    mut f: impl FnMut(u64, *mut u64, u64) -> bool,
+) -> bool {
+    let mut va = start;
+    while va < end {
+        match vm_walk(space, va) {
+            Ok((leaf, size)) => {
+                let base = va & !(size - 1);
+                if vm_leaf_mapped(*leaf) && !f(base, leaf, size) {
+                    return false;
+                }
+                va = base + size;
+            }
+            Err(span) => va = (va & !(span - 1)) + span,
+        }
+    }
+    true
+}
+
+#[inline(always)]
+unsafe fn vm_invalidate(space: usize, va: u64) {
+    if vm_read_cr3() == VM_SPACES[space].pml4_phys {
+        core::arch::asm!("invlpg [{}]", in(reg) va, options(nostack));
+    }
+    vm_shootdown(space);
+}
+
+#[inline(always)]
+pub(crate) fn vm_prot_pte_flags(prot: u8) -> u64 {
+    let mut flags = VM_PTE_PRESENT | VM_PTE_USER;
+    if prot & VM_PROT_WRITE != 0 {
+        flags |= VM_PTE_WRITABLE;
+    }
+    if prot & VM_PROT_EXEC == 0 {
+        flags |= unsafe { VM_NX };
+    }
+    flags
+}
+
+/// Pages reserved by the space's areas. Guard gaps are never backed and do
+/// not count.
+pub(crate) unsafe fn vm_reserved_pages(space: usize) -> u64 {
+    if !vm_space_ok(space) {
+        return 0;
+    }
+    VM_SPACES[space]
+        .areas
+        .iter()
+        .filter(|area| area.active && area.kind != VM_AREA_GUARD)
+        .map(|area| (area.end - area.start) / 4096)
+        .sum()
+}
+
+pub(crate) unsafe fn vm_resident_pages(space: usize) -> u64 {
+    if vm_space_ok(space) { VM_SPACES[space].resident } else { 0 }
+}
+
+/// Whether the space holds more resident pages than its soft limit.
+pub(crate) unsafe fn vm_over_soft_limit(space: usize) -> bool {
+    vm_space_ok(space)
+        && VM_SPACES[space].soft_limit != 0
+        && VM_SPACES[space].resident > VM_SPACES[space].soft_limit
+}
+
+/// Report a space that went past its soft limit since the last call. A
+/// space is reported again only after it drops back to the limit.
+pub(crate) unsafe fn vm_take_soft_crossing() -> Option<usize> {
+    for space in 0..VM_SPACES.len() {
+        let over = vm_over_soft_limit(space);
+        let crossed = over && !VM_SPACES[space].over_soft;
+        VM_SPACES[space].over_soft = over;
+        if crossed {
+            return Some(space);
+        }
+    }
+    None
+}
+
+/// Whether `len` more reserved bytes stay within the space's hard limit.
+pub(crate) unsafe fn vm_commit_ok(space: usize, len: u64) -> bool {
+    if !vm_space_ok(space) {
+        return false;
+    }
+    let limit = VM_SPACES[space].hard_limit;
+    limit == 0 || vm_reserved_pages(space) + len.div_ceil(4096) <= limit
+}
+
+pub(crate) unsafe fn vm_limits(space: usize) -> (u64, u64) {
+    if vm_space_ok(space) {
+        (VM_SPACES[space].hard_limit, VM_SPACES[space].soft_limit)
+    } else {
+        (0, 0)
+    }
+}
+
+/// Set the hard and soft limits in pages. Fails if the space already
+/// reserves more than the new hard limit.
+pub(crate) unsafe fn vm_set_limits(space: usize, hard: u64, soft: u64) -> bool {
+    if !vm_space_ok(space) {
+        return false;
+    }
+    if hard != 0 && vm_reserved_pages(space) > hard {
+        return false;
+    }
+    VM_SPACES[space].hard_limit = hard;
+    VM_SPACES[space].soft_limit = soft;
+    true
+}
+
+/// Reserve `[start, end)` as lazily backed anonymous memory. Nothing is
+/// allocated until the first access faults the page in.
+pub(crate) unsafe fn vm_area_reserve(space: usize, start: u64, end: u64, prot: u8) -> bool {
+    vm_area_insert(space, start, end, prot, VM_AREA_ANON)
+}
+
+unsafe fn vm_area_insert(space: usize, start: u64, end: u64, prot: u8, kind: u8) -> bool {
+    if !vm_space_ok(space) || start & 0xFFF != 0 || end & 0xFFF != 0 {
+        return false;
+    }
+    if start >= end || end > VM_USER_TOP {
+        return false;
+    }
+    if kind != VM_AREA_GUARD && !vm_commit_ok(space, end - start) {
+        return false;
+    }
+    let areas = &mut VM_SPACES[space].areas;
+    let mut free = None;
+    for (i, area) in areas.iter().enumerate() {
+        if !area.active {
+            if free.is_none() {
+                free = Some(i);
+            }
+            continue;
+        }
+        if start < area.end && area.start < end {
+            return false;
+        }
+    }
+    match free {
+        Some(i) => {
+            areas[i] = VmArea { active: true, start, end, prot, kind };
+            true
+        }
+        None => false,
+    }
+}
+
+/// Reserve a stack growing down from `top` to at most `max` bytes, with a
+/// `VM_STACK_GUARD` gap below it that is never backed.
+pub(crate) unsafe fn vm_stack_reserve(space: usize, top: u64, max: u64) -> bool {
+    if max == 0 || max & 0xFFF != 0 || top < max + VM_STACK_GUARD + 0x1000 {
+        return false;
+    }
+    let base = top - max;
+    if !vm_area_insert(space, base - VM_STACK_GUARD, base, 0, VM_AREA_GUARD) {
+        return false;
+    }
+    if !vm_area_insert(space, base, top, VM_PROT_READ | VM_PROT_WRITE, VM_AREA_STACK) {
+        vm_area_remove_guard(space, base);
+        return false;
+    }
+    true
+}
+
+/// Top of the stack whose lowest address is `stack_base`, following its
+/// areas upward while they stay contiguous; `stack_base` if there is none.
+unsafe fn vm_stack_top_above(space: usize, stack_base: u64) -> u64 {
+    let mut top = stack_base;
+    while let Some(area) = VM_SPACES[space]
+        .areas
+        .iter()
+        .find(|area| area.active && area.kind == VM_AREA_STACK && area.start == top)
+    {
+        top = area.end;
+    }
+    top
+}
+
+unsafe fn vm_area_remove_guard(space: usize, stack_base: u64) {
+    for area in VM_SPACES[space].areas.iter_mut() {
+        if area.active && area.kind == VM_AREA_GUARD && area.end == stack_base {
+            *area = VmArea::EMPTY;
+        }
+    }
+}
+
+/// True when `va` falls in the guard gap below a stack, i.e. the fault is a
+/// stack overflow.
+pub(crate) unsafe fn vm_in_stack_guard(space: usize, va: u64) -> bool {
+    match vm_area_find(space, va) {
+        Some(idx) => VM_SPACES[space].areas[idx].kind == VM_AREA_GUARD,
+        None => false,
+    }
+}
+
+unsafe fn vm_area_find(space: usize, va: u64) -> Option<usize> {
+    if !vm_space_ok(space) {
+        return None;
+    }
+    VM_SPACES[space]
+        .areas
+        .iter()
+        .position(|area| area.active && va >= area.start && va < area.end)
+}
+
+/// Give a write-faulting space a private copy of a copy-on-write page. The
+/// last pool-frame holder just keeps the frame. The page becomes user
+/// writable only if `writable`; kernel writes to read-only pages pass false.
+unsafe fn vm_break_cow(space: usize, va: u64, pte: *mut u64, writable: bool) -> bool {
+    let old = *pte;
+    let old_phys = old & VM_PTE_ADDR_MASK;
+    let mut flags = old & !(VM_PTE_ADDR_MASK | VM_PTE_COW);
+    if writable {
+        flags |= VM_PTE_WRITABLE;
+    }
+    let sole_owner = match vm_frame_index(old_phys) {
+        Some(idx) => vm_frame_refs()[idx] == 1,
+        None => false,
+    };
+    if sole_owner {
+        *pte = old_phys | flags;
+    } else {
+        let frame = match vm_frame_alloc() {
+            Some(frame) => frame,
+            None => return false,
+        };
+        core::ptr::copy_nonoverlapping(
+            vm_table(old_phys) as *const u8,
+            vm_table(frame) as *mut u8,
+            4096,
+        );
+        *pte = frame | flags;
+        vm_frame_release(old_phys);
+    }
+    if vm_read_cr3() == VM_SPACES[space].pml4_phys {
+        core::arch::asm!("invlpg [{}]", in(reg) va, options(nostack));
+    }
+    vm_shootdown(space);
+    true
+}
+
+/// Copy-on-write break for a 2 MiB leaf. A sole holder keeps the frame;
+/// otherwise the leaf is copied whole, or split and only the faulting page
+/// copied when no large frame is free. `writable` as for `vm_break_cow`.
+unsafe fn vm_break_cow_large(space: usize, va: u64, pd: *mut u64, writable: bool) -> bool {
+    let old = *pd;
+    let old_phys = old & VM_PTE_ADDR_MASK;
+    let mut flags = old & !(VM_PTE_ADDR_MASK | VM_PTE_COW);
+    if writable {
+        flags |= VM_PTE_WRITABLE;
+    }
+    let sole_owner = match vm_large_index(old_phys) {
+        Some(idx) => vm_large_refs()[idx] == VM_LARGE_UNITS,
+        None => false,
+    };
+    if sole_owner {
+        *pd = old_phys | flags;
+    } else if let Some(frame) = vm_large_alloc() {
+        core::ptr::copy_nonoverlapping(
+            vm_table(old_phys) as *const u8,
+            vm_table(frame) as *mut u8,
+            VM_LARGE_PAGE as usize,
+        );
+        *pd = frame | flags;
+        vm_leaf_release(old_phys, VM_LARGE_PAGE);
+    } else {
+        if !vm_split_large(space, pd) {
+            return false;
+        }
+        let page = va & !0xFFF;
+        return match vm_walk(space, page) {
+            Ok((pte, _)) => vm_break_cow(space, page, pte, writable),
+            Err(_) => false,
+        };
+    }
+    vm_invalidate(space, va & !(VM_LARGE_PAGE - 1));
+    true
+}
+
+/// Back the 2 MiB block holding `va` with one large leaf when the block lies
+/// wholly inside an anonymous area and nothing in it is mapped yet.
+unsafe fn vm_fault_in_large(space: usize, va: u64, area: VmArea) -> bool {
+    let block = va & !(VM_LARGE_PAGE - 1);
+    if area.kind != VM_AREA_ANON || block < area.start || block + VM_LARGE_PAGE > area.end {
+        return false;
+    }
+    match vm_walk(space, block) {
+        Err(span) if span >= VM_LARGE_PAGE => {}
+        _ => return false,
+    }
+    let frame = match vm_large_alloc() {
+        Some(frame) => frame,
+        None => return false,
+    };
+    if !vm_map_large(space, block, frame, vm_prot_pte_flags(area.prot)) {
+        vm_leaf_release(frame, VM_LARGE_PAGE);
+        return false;
+    }
+    true
+}
+
+/// Back the page holding `va` if it lies in a reserved area that permits the
+/// access, or break copy-on-write sharing on a write. Returns false for
+/// genuine violations.
+pub(crate) unsafe fn vm_fault_in(space: usize, va: u64, write: bool, fetch: bool) -> bool {
+    let page = va & !0xFFF;
+    let area = vm_area_find(space, va);
+    if let Some((leaf, size)) = vm_lookup_pte(space, page) {
+        if *leaf & VM_PTE_PRESENT != 0 {
+            let area_writable = match area {
+                Some(idx) => VM_SPACES[space].areas[idx].prot & VM_PROT_WRITE != 0,
+                None => true,
+            };
+            if write && *leaf & VM_PTE_COW != 0 && area_writable {
+                if size == VM_LARGE_PAGE {
+                    return vm_break_cow_large(space, va, leaf, true);
+                }
+                return vm_break_cow(space, page, leaf, true);
+            }
+            return false;
+        }
+        if *leaf & VM_PTE_PROTNONE != 0 {
+            return false;
+        }
+    }
+    let area = match area {
+        Some(area) => VM_SPACES[space].areas[area],
+        None => return false,
+    };
+    if area.kind == VM_AREA_SHM {
+        return false;
+    }
+    if area.prot & (VM_PROT_READ | VM_PROT_WRITE | VM_PROT_EXEC) == 0 {
+        return false;
+    }
+    if (write && area.prot & VM_PROT_WRITE == 0) || (fetch && area.prot & VM_PROT_EXEC == 0) {
+        return false;
+    }
+    if vm_fault_in_large(space, va, area) {
+        return true;
+    }
+    let frame = match vm_frame_alloc() {
+        Some(frame) => frame,
+        None => return false,
+    };
+    if !vm_map_page(space, page, frame, vm_prot_pte_flags(area.prot)) {
+        vm_frame_release(frame);
+        return false;
+    }
+    true
+}
+
+/// Resolve a user-mode #PF. Not-present faults inside a reserved area and
+/// writes to copy-on-write pages are serviced; everything else is left to the
+/// caller to kill the task.
+pub(crate) unsafe fn vm_handle_fault(space: usize, addr: u64, error_code: u64) -> bool {
+    if error_code & VM_FAULT_PRESENT != 0 && error_code & VM_FAULT_WRITE == 0 {
+        return false;
+    }
+    vm_fault_in(
+        space,
+        addr,
+        error_code & VM_FAULT_WRITE != 0,
+        error_code & VM_FAULT_FETCH != 0,
+    )
+}
+
+unsafe fn vm_fork_table(
+    parent_table: *mut u64,
+    child: usize,
+    base: u64,
+    level: usize,
+) -> bool {
+    let entries = if level == 4 { VM_USER_PML4_SLOTS } else { 512 };
+    let span = 1u64 << (12 + 9 * (level as u64 - 1));
+    for i in 0..entries {
+        let entry = parent_table.add(i);
+        if (level == 1 && !vm_leaf_mapped(*entry)) || (level > 1 && *entry & VM_PTE_PRESENT == 0) {
+            continue;
+        }
+        let va = base + (i as u64) * span;
+        if level == 2 && vm_large_leaf(*entry) {
+            let phys = *entry & VM_PTE_ADDR_MASK;
+            let mut flags = *entry & !(VM_PTE_ADDR_MASK | VM_PTE_PS);
+            if flags & VM_PTE_SHARED == 0 {
+                flags = (flags & !VM_PTE_WRITABLE) | VM_PTE_COW;
+                *entry = phys | flags | VM_PTE_PS;
+            }
+            if !vm_map_large(child, va, phys, flags) {
+                return false;
+            }
+            vm_leaf_ref(phys, VM_LARGE_PAGE);
+            continue;
+        }
+        if level > 1 {
+            if !vm_fork_table(vm_table(*entry), child, va, level - 1) {
+                return false;
+            }
+            continue;
+        }
+        let phys = *entry & VM_PTE_ADDR_MASK;
+        let mut flags = *entry & !VM_PTE_ADDR_MASK;
+        // Private writable pages become read-only in both spaces until the
+        // first write; SHM mappings stay shared. Pages in mmap areas are
+        // always marked, since mprotect may make them writable later.
+        let private = flags & VM_PTE_SHARED == 0;
+        if private && (flags & VM_PTE_WRITABLE != 0 || vm_area_find(child, va).is_some()) {
+            flags = (flags & !VM_PTE_WRITABLE) | VM_PTE_COW;
+            *entry = phys | flags;
+        }
+        if !vm_map_page(child, va, phys, flags) {
+            return false;
+        }
+        vm_frame_ref(phys);
+    }
+    true
+}
+
+/// Duplicate `parent` for fork: reserved areas are copied and every mapped
+/// page is shared copy-on-write.
+pub(crate) unsafe fn vm_space_fork(parent: usize) -> Option<usize> {
+    if !vm_space_ok(parent) {
+        return None;
+    }
+    let child = vm_space_create()?;
+    VM_SPACES[child].areas = VM_SPACES[parent].areas;
+    VM_SPACES[child].hard_limit = VM_SPACES[parent].hard_limit;
+    VM_SPACES[child].soft_limit = VM_SPACES[parent].soft_limit;
+    let parent_pml4 = VM_SPACES[parent].pml4_phys;
+    let ok = vm_fork_table(vm_table(parent_pml4), child, 0, 4);
+    // Parent PTEs lost write access; drop any stale writable TLB entries.
+    if vm_read_cr3() == parent_pml4 {
+        vm_write_cr3(parent_pml4);
+    }
+    vm_shootdown(parent);
+    if !ok {
+        vm_space_release(child);
+        return None;
+    }
+    Some(child)
+}
+
+/// Split the area containing `va` so that `va` becomes an area boundary.
+unsafe fn vm_area_split(space: usize, va: u64) -> bool {
+    let idx = match vm_area_find(space, va) {
+        Some(idx) => idx,
+        None => return true,
+    };
+    let areas = &mut VM_SPACES[space].areas;
+    if areas[idx].start == va {
+        return true;
+    }
+    for i in 0..VM_MAX_AREAS {
+        if !areas[i].active {
+            areas[i] = VmArea { start: va, ..areas[idx] };
+            areas[idx].end = va;
+            return true;
+        }
+    }
+    false
+}
+
+/// Join adjacent areas with the same protection.
+unsafe fn vm_area_merge(space: usize) {
+    let areas = &mut VM_SPACES[space].areas;
+    let mut merged = true;
+    while merged {
+        merged = false;
+        for i in 0..VM_MAX_AREAS {
+            if !areas[i].active {
+                continue;
+            }
+            for j in 0..VM_MAX_AREAS {
+                if i != j && areas[j].active && areas[i].end == areas[j].start
+                    && areas[i].prot == areas[j].prot
+                    && areas[i].kind == areas[j].kind
+                    && areas[i].kind != VM_AREA_GUARD
+                    && areas[i].kind != VM_AREA_SHM
+                {
+                    areas[i].end = areas[j].end;
+                    areas[j] = VmArea::EMPTY;
+                    merged = true;
+                }
+            }
+        }
+    }
+}
+
+/// True when every page of `[start, end)` lies inside some area.
+unsafe fn vm_area_covers(space: usize, start: u64, end: u64) -> bool {
+    let mut va = start;
+    while va < end {
+        match vm_area_find(space, va) {
+            Some(idx) => va = VM_SPACES[space].areas[idx].end,
+            None => return false,
+        }
+    }
+    true
+}
+
+/// True when `[start, end)` has no area and no mapped page.
+unsafe fn vm_range_free(space: usize, start: u64, end: u64) -> bool {
+    let overlaps = VM_SPACES[space]
+        .areas
+        .iter()
+        .any(|area| area.active && start < area.end && area.start < end);
+    if overlaps {
+        return false;
+    }
+    vm_for_each_leaf(space, start, end, |_, _, _| false)
+}
+
+#[inline(always)]
+fn vm_range_ok(start: u64, len: u64) -> Option<u64> {
+    if start & 0xFFF != 0 || len == 0 {
+        return None;
+    }
+    let len = len.checked_add(0xFFF)? & !0xFFF;
+    let end = start.checked_add(len)?;
+    if end > VM_USER_TOP { None } else { Some(end) }
+}
+
+/// Pick a free range of `len` bytes: the page-aligned hint when nothing is
+/// reserved or mapped there, else the first `align`-aligned gap at or above
+/// `VM_MMAP_BASE`.
+unsafe fn vm_find_free(space: usize, hint: u64, len: u64, align: u64) -> Option<u64> {
+    let mut start = if hint != 0 && hint & 0xFFF == 0 { hint } else { VM_MMAP_BASE };
+    if hint != 0 && vm_range_ok(start, len).is_some() && vm_range_free(space, start, start + len) {
+        return Some(start);
+    }
+    start = VM_MMAP_BASE;
+    loop {
+        let end = vm_range_ok(start, len)?;
+        let mut blocker = None;
+        for area in VM_SPACES[space].areas.iter() {
+            if area.active && start < area.end && area.start < end {
+                blocker = Some(area.end);
+                break;
+            }
+        }
+        if blocker.is_none() {
+            vm_for_each_leaf(space, start, end, |va, _, size| {
+                blocker = Some(va + size);
+                false
+            });
+        }
+        match blocker {
+            Some(next) => start = next.checked_add(align - 1)? & !(align - 1),
+            None => return Some(start),
+        }
+    }
+}
+
+/// Reserve anonymous memory of `len` bytes (rounded up to pages). The hint
+/// is used when the range is free; otherwise the first free range at or
+/// above `VM_MMAP_BASE` is chosen. Pages are backed on first touch. With
+/// `VM_MAP_STACK` the range becomes a growable stack and a guard gap is
+/// reserved below it; the returned address is still the stack's lowest
+/// usable byte.
+pub(crate) unsafe fn vm_mmap(space: usize, hint: u64, len: u64, flags: u16) -> Option<u64> {
+    let prot = (flags & 0xFF) as u8;
+    let stack = flags & VM_MAP_STACK != 0;
+    if !vm_space_ok(space) || prot & !(VM_PROT_READ | VM_PROT_WRITE | VM_PROT_EXEC) != 0 {
+        return None;
+    }
+    if flags & !(0xFF | VM_MAP_STACK) != 0 {
+        return None;
+    }
+    if stack && prot != VM_PROT_READ | VM_PROT_WRITE {
+        return None;
+    }
+    let guard = if stack { VM_STACK_GUARD } else { 0 };
+    let stack_len = vm_range_ok(0, len)?;
+    let pages = stack_len + guard;
+    // A stack hint names the usable range, so the guard goes below it; a
+    // hint too low to fit the guard is ignored like any other bad hint.
+    let hint = if stack { hint.saturating_sub(guard) } else { hint };
+    // Big anonymous regions start on a 2 MiB boundary so whole blocks of
+    // them can be backed by large leaves.
+    let align = if !stack && pages >= VM_LARGE_PAGE { VM_LARGE_PAGE } else { 4096 };
+    let start = vm_find_free(space, hint, pages, align)?;
+    if stack {
+        if !vm_stack_reserve(space, start + pages, stack_len) {
+            return None;
+        }
+        return Some(start + guard);
+    }
+    if !vm_area_reserve(space, start, start + pages, prot) {
+        return None;
+    }
+    vm_area_merge(space);
+    Some(start)
+}
+
+/// Drop every area page in `[start, start+len)`. Areas straddling the edges
+/// are split. Ranges touching pages the loader mapped outside any area, or
+/// that would leave a stack without its guard gap, are rejected.
+pub(crate) unsafe fn vm_munmap(space: usize, start: u64, len: u64) -> bool {
+    if !vm_space_ok(space) {
+        return false;
+    }
+    let end = match vm_range_ok(start, len) {
+        Some(end) => end,
+        None => return false,
+    };
+    if !vm_for_each_leaf(space, start, end, |va, _, _| vm_area_find(space, va).is_some()) {
+        return false;
+    }
+    // A guard gap only goes away together with its stack: a range that
+    // touches the gap or the stack's lowest page must reach the stack's top,
+    // or the stack would be left without a guard.
+    for guard in VM_SPACES[space].areas.iter() {
+        if guard.active
+            && guard.kind == VM_AREA_GUARD
+            && start < guard.end + 4096
+            && guard.start < end
+            && end < vm_stack_top_above(space, guard.end)
+        {
+            return false;
+        }
+    }
+    if !vm_split_large_at(space, start) || !vm_split_large_at(space, end) {
+        return false;
+    }
+    if !vm_area_split(space, start) || !vm_area_split(space, end) {
+        vm_area_merge(space);
+        return false;
+    }
+    for area in VM_SPACES[space].areas.iter_mut() {
+        if area.active && area.start >= start && area.end <= end {
+            *area = VmArea::EMPTY;
+        }
+    }
+    // A guard gap goes away with the stack directly above it.
+    let areas = &mut VM_SPACES[space].areas;
+    for i in 0..VM_MAX_AREAS {
+        let guard = areas[i];
+        if guard.active && guard.kind == VM_AREA_GUARD {
+            let orphaned = !areas.iter().any(|area| {
+                area.active
+                    && area.kind == VM_AREA_STACK
+                    && guard.end >= area.start
+                    && guard.end < area.end
+            });
+            if orphaned {
+                areas[i] = VmArea::EMPTY;
+            }
+        }
+    }
+    vm_for_each_leaf(space, start, end, |va, leaf, size| {
+        vm_leaf_release(*leaf, size);
+        VM_SPACES[space].resident -= size / 4096;
+        *leaf = 0;
+        vm_invalidate(space, va);
+        true
+    });
+    vm_area_merge(space);
+    true
+}
+
+/// Change the protection of `[start, start+len)`, which must be covered by
+/// areas and must not touch a stack guard gap or an SHM mapping. Mapped pages
+/// are updated in place; PROT_NONE pages keep their frame but stop being
+/// present.
+pub(crate) unsafe fn vm_mprotect(space: usize, start: u64, len: u64, prot: u8) -> bool {
+    if !vm_space_ok(space) || prot & !(VM_PROT_READ | VM_PROT_WRITE | VM_PROT_EXEC) != 0 {
+        return false;
+    }
+    let end = match vm_range_ok(start, len) {
+        Some(end) => end,
+        None => return false,
+    };
+    if !vm_area_covers(space, start, end) {
+        return false;
+    }
+    // Guard gaps stay inaccessible, and SHM mappings keep the access their
+    // handle granted.
+    for area in VM_SPACES[space].areas.iter() {
+        if area.active
+            && (area.kind == VM_AREA_GUARD || area.kind == VM_AREA_SHM)
+            && start < area.end
+            && area.start < end
+        {
+            return false;
+        }
+    }
+    if !vm_split_large_at(space, start) || !vm_split_large_at(space, end) {
+        return false;
+    }
+    if !vm_area_split(space, start) || !vm_area_split(space, end) {
+        vm_area_merge(space);
+        return false;
+    }
+    for area in VM_SPACES[space].areas.iter_mut() {
+        if area.active && area.start >= start && area.end <= end {
+            area.prot = prot;
+        }
+    }
+    vm_for_each_leaf(space, start, end, |va, pte, _| {
+        let keep = *pte & (VM_PTE_ADDR_MASK | VM_PTE_COW | VM_PTE_SHARED | VM_PTE_PS);
+        let mut flags = if prot == 0 { VM_PTE_PROTNONE } else { vm_prot_pte_flags(prot) };
+        if keep & VM_PTE_COW != 0 {
+            flags &= !VM_PTE_WRITABLE;
+        }
+        *pte = keep | flags;
+        vm_invalidate(space, va);
+        true
+    });
+    vm_area_merge(space);
+    true
+}
+
+/// Reserve `[va, va + memsz)` for an image segment and fill its file-backed
+/// pages from `data` now; the rest is demand-zeroed like any anonymous area.
+/// A page shared with an earlier segment keeps its frame and area and gains
+/// this segment's write and execute permissions.
+pub(crate) unsafe fn vm_load_segment(space: usize, va: u64, memsz: u64, data: &[u8], prot: u8) -> bool {
+    if !vm_space_ok(space) || data.len() as u64 > memsz || memsz == 0 {
+        return false;
+    }
+    let end = match va.checked_add(memsz) {
+        Some(end) if end <= VM_USER_TOP => end,
+        _ => return false,
+    };
+    let first = va & !0xFFF;
+    let last = (end + 0xFFF) & !0xFFF;
+    let mut area_start = first;
+    while area_start < last && vm_area_find(space, area_start).is_some() {
+        area_start += 4096;
+    }
+    if area_start < last && !vm_area_insert(space, area_start, last, prot, VM_AREA_ANON) {
+        return false;
+    }
+    let data_end = va + data.len() as u64;
+    let mut page = first;
+    while page < last {
+        let frame = match vm_lookup_pte(space, page) {
+            Some((pte, 4096)) if vm_leaf_mapped(*pte) => {
+                // Executable if either segment is.
+                let flags = vm_prot_pte_flags(prot);
+                let nx = *pte & flags & VM_PTE_NX;
+                *pte = ((*pte | flags) & !VM_PTE_NX) | nx;
+                *pte & VM_PTE_ADDR_MASK
+            }
+            _ if page >= data_end => {
+                page += 4096;
+                continue;
+            }
+            _ => {
+                let frame = match vm_frame_alloc() {
+                    Some(frame) => frame,
+                    None => return false,
+                };
+                if !vm_map_page(space, page, frame, vm_prot_pte_flags(prot)) {
+                    vm_frame_release(frame);
+                    return false;
+                }
+                frame
+            }
+        };
+        let lo = core::cmp::max(page, va);
+        let hi = core::cmp::min(page + 4096, data_end);
+        if lo < hi {
+            core::ptr::copy_nonoverlapping(
+                data.as_ptr().add((lo - va) as usize),
+                (vm_table(frame) as *mut u8).add((lo - page) as usize),
+                (hi - lo) as usize,
+            );
+        }
+        page += 4096;
+    }
+    true
+}
+
+/// Store `bytes` at `va` in `space` through the kernel mapping, whatever
+/// the page's user permissions; unbacked pages of an area are faulted in
+/// and copy-on-write pages get a private copy first, keeping their user
+/// write access as the area has it. Used by the loader to apply
+/// relocations.
+#[cfg(feature = "go_test")]
+pub(crate) unsafe fn vm_write_user(space: usize, va: u64, bytes: &[u8]) -> bool {
+    let mut done = 0usize;
+    while done < bytes.len() {
+        let addr = va + done as u64;
+        let page = addr & !0xFFF;
+        let mapped = matches!(vm_lookup_pte(space, page), Some((pte, _)) if vm_leaf_mapped(*pte));
+        if !mapped && !vm_fault_in(space, page, false, false) {
+            return false;
+        }
+        let (mut pte, mut size) = match vm_lookup_pte(space, page) {
+            Some(found) => found,
+            None => return false,
+        };
+        if *pte & VM_PTE_PRESENT == 0 {
+            return false;
+        }
+        if *pte & VM_PTE_COW != 0 {
+            // Same rule as the write-fault path.
+            let writable = match vm_area_find(space, page) {
+                Some(idx) => VM_SPACES[space].areas[idx].prot & VM_PROT_WRITE != 0,
+                None => true,
+            };
+            let broken = if size == VM_LARGE_PAGE {
+                vm_break_cow_large(space, addr, pte, writable)
+            } else {
+                vm_break_cow(space, page, pte, writable)
+            };
+            if !broken {
+                return false;
+            }
+            // A large leaf may have been split to copy just this page.
+            (pte, size) = match vm_lookup_pte(space, page) {
+                Some(found) => found,
+                None => return false,
+            };
+        }
+        let offset = addr & (size - 1);
+        let n = core::cmp::min(bytes.len() - done, (size - offset) as usize);
+        let n = core::cmp::min(n, (4096 - (addr & 0xFFF)) as usize);
+        core::ptr::copy_nonoverlapping(
+            bytes.as_ptr().add(done),
+            (vm_table(*pte & VM_PTE_ADDR_MASK) as *mut u8).add(offset as usize),
+            n,
+        );
+        done += n;
+    }
+    true
+}
+
+/// Map the frames of an SHM object contiguously, at the hint when it is free
+/// or wherever `vm_find_free` puts it. Each mapping holds its own frame
+/// references, so it outlives the handle it was made through.
+///
+/// `frame_size` is 4 KiB, or 2 MiB for an object of large frames. Like a big
+/// anonymous region, such an object starts on a 2 MiB boundary unless the
+/// hint says otherwise, and each frame that lands on one is mapped with a
+/// large leaf; a frame that does not is mapped page by page.
+pub(crate) unsafe fn vm_shm_map(
+    space: usize,
+    hint: u64,
+    frames: &[u64],
+    frame_size: u64,
+    writable: bool,
+) -> Option<u64> {
+    if !vm_space_ok(space) || frames.is_empty() {
+        return None;
+    }
+    let len = (frames.len() as u64) * frame_size;
+    let start = vm_find_free(space, hint, len, frame_size)?;
+    let prot = if writable { VM_PROT_READ | VM_PROT_WRITE } else { VM_PROT_READ };
+    if !vm_area_insert(space, start, start + len, prot, VM_AREA_SHM) {
+        return None;
+    }
+    let flags = vm_prot_pte_flags(prot) | VM_PTE_SHARED;
+    for (i, frame) in frames.iter().enumerate() {
+        let va = start + (i as u64) * frame_size;
+        if frame_size == VM_LARGE_PAGE && va & (VM_LARGE_PAGE - 1) == 0 {
+            if !vm_map_large(space, va, *frame, flags) {
+                vm_munmap(space, start, len);
+                return None;
+            }
+            vm_leaf_ref(*frame, VM_LARGE_PAGE);
+            continue;
+        }
+        for offset in (0..frame_size).step_by(4096) {
+            if !vm_map_page(space, va + offset, *frame + offset, flags) {
+                vm_munmap(space, start, len);
+                return None;
+            }
+            vm_frame_ref(*frame + offset);
+        }
+    }
+    Some(start)
+}
+
+/// Drop the whole SHM mapping that starts at `start`.
+pub(crate) unsafe fn vm_shm_unmap(space: usize, start: u64) -> bool {
+    if !vm_space_ok(space) {
+        return false;
+    }
+    let idx = match vm_area_find(space, start) {
+        Some(idx) => idx,
+        None => return false,
+    };
+    let area = VM_SPACES[space].areas[idx];
+    if area.kind != VM_AREA_SHM || area.start != start {
+        return false;
+    }
+    vm_munmap(space, area.start, area.end - area.start)
+}
@@ -0,0 +1,74 @@
+// Deferred work for the Go lane: a FIFO queue per kernel worker thread,
+// filled from interrupt and syscall context and drained by the worker.
+//
+// Queueing a job that is still pending coalesces with it, so a source that
+// re-arms on every tick never floods its worker.
+
+use crate::*;
+
+const WORKQ_CAPACITY: usize = 8;
+
+#[derive(Clone, Copy)]
+pub(crate) struct Work {
+    pub(crate) func: unsafe fn(u64),
+    pub(crate) arg: u64,
+}
+
+impl Work {
+    fn same_job(&self, other: &Work) -> bool {
+        self.func as usize == other.func as usize && self.arg == other.arg
+    }
+}
+
+#[derive(Clone, Copy)]
+struct WorkQueue {
+    items: [Option<Work>; WORKQ_CAPACITY],
+    head: usize,
+    len: usize,
+}
+
+impl WorkQueue {
+    const EMPTY: Self = Self { items: [None; WORKQ_CAPACITY], head: 0, len: 0 };
+}
+
+static mut WORKQ: [WorkQueue; R4_MAX_KWORKERS] = [WorkQueue::EMPTY; R4_MAX_KWORKERS];
+
+/// Drop every pending job.
+pub(crate) unsafe fn workq_reset() {
+    WORKQ = [WorkQueue::EMPTY; R4_MAX_KWORKERS];
+}
+
+/// Append `work` to `worker`'s queue. True if it is queued, including when
+/// the same job was already pending; false when the queue is full.
+pub(crate) unsafe fn workq_push(worker: usize, work: Work) -> bool {
+    let queue = &mut WORKQ[worker];
+    for i in 0..queue.len {
+        if let Some(pending) = queue.items[(queue.head + i) % WORKQ_CAPACITY] {
+            if pending.same_job(&work) {
+                return true;
+            }
+        }
+    }
+    if queue.len == WORKQ_CAPACITY {
+        return false;
+    }
+    queue.items[(queue.head + queue.len) % WORKQ_CAPACITY] = Some(work);
+    queue.len += 1;
+    true
+}
+
+/// Take the oldest job on `worker`'s queue.
+pub(crate) unsafe fn workq_pop(worker: usize) -> Option<Work> {
+    let queue = &mut WORKQ[worker];
+    if queue.len == 0 {
+        return None;
+    }
+    let work = queue.items[queue.head].take();
+    queue.head = (queue.head + 1) % WORKQ_CAPACITY;
+    queue.len -= 1;
+    work
+}
+
+pub(crate) unsafe fn workq_pending(worker: usize) -> bool {
+    WORKQ[worker].len != 0
+}
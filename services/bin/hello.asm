@@ -0,0 +1,62 @@
+bits 64
+default rel
+
+; /bin/hello: the Go lane's built-in spawn target. Prints "SPAWN: child "
+; and its first argument, then exits.
+
+%define SYS_DEBUG_WRITE 0
+%define SYS_THREAD_EXIT 2
+
+global _start
+
+section .text
+_start:
+    ; The entry rsp points at argc, followed by the argv pointers.
+    cmp  qword [rsp], 2
+    jb   no_arg
+    mov  rbx, [rsp + 16]
+
+    xor  r12d, r12d
+arg_len:
+    cmp  byte [rbx + r12], 0
+    je   print
+    inc  r12
+    jmp  arg_len
+
+print:
+    lea  rdi, [rel msg_child]
+    mov  esi, msg_child_end - msg_child
+    mov  eax, SYS_DEBUG_WRITE
+    int  0x80
+
+    mov  rdi, rbx
+    mov  rsi, r12
+    mov  eax, SYS_DEBUG_WRITE
+    int  0x80
+
+    lea  rdi, [rel msg_newline]
+    mov  esi, 1
+    mov  eax, SYS_DEBUG_WRITE
+    int  0x80
+    jmp  exit
+
+no_arg:
+    lea  rdi, [rel msg_no_arg]
+    mov  esi, msg_no_arg_end - msg_no_arg
+    mov  eax, SYS_DEBUG_WRITE
+    int  0x80
+
+exit:
+    mov  eax, SYS_THREAD_EXIT
+    int  0x80
+    jmp  exit
+
+section .rodata
+msg_child:
+    db "SPAWN: child "
+msg_child_end:
+msg_newline:
+    db 10
+msg_no_arg:
+    db "SPAWN: child no arg", 10
+msg_no_arg_end:
@@ -0,0 +1,1208 @@
+bits 64
+default rel
+
+; x1-mem-probe: exercises the R4 memory model from user space and reports
+; each check as a serial marker. A failed check prints "X1MEM: fail" and
+; stops QEMU.
+
+%define SYS_DEBUG_WRITE 0
+%define SYS_THREAD_EXIT 2
+%define SYS_SHM_CREATE 6
+%define SYS_SHM_MAP 7
+%define SYS_IPC_RECV 9
+%define SYS_IPC_ENDPOINT_CREATE 17
+%define SYS_WAIT 22
+%define SYS_PROC_INFO 28
+%define SYS_ISOLATION_CONFIG 41
+%define SYS_SHM_UNMAP 42
+%define SYS_FORK 43
+%define SYS_MMAP 46
+%define SYS_MUNMAP 47
+%define SYS_MPROTECT 48
+%define SYS_SHM_SEND 49
+%define SYS_MEM_NOTIFY 50
+%define SYS_ARCH_PRCTL 51
+%define SYS_QEMU_EXIT 98
+
+%define PAGE_SIZE 4096
+%define COW_PAGES 3
+%define COW_BEFORE 0x11
+%define COW_CHILD 0x22
+%define COW_PARENT 0x33
+
+%define PROT_READ 1
+%define PROT_WRITE 2
+%define PROT_EXEC 4
+%define OPCODE_RET 0xC3
+; Exit status of a task killed by an unhandled user fault.
+%define FAULT_STATUS 1
+; Half of the 256 KiB user stack limit.
+%define STACK_GROW_PAGES 32
+%define MAP_STACK 0x100
+%define STACK_GUARD 0x10000
+%define GUARD_STACK_BYTES 4 * PAGE_SIZE
+; More than the default frame pool and the large frames together.
+%define OOM_MAP_BYTES 0x2000000
+%define OOM_STATUS 137
+%define SPACE_PARENT 0x77
+%define SPACE_CHILD 0x88
+%define DEMAND_PAGES 16
+%define LARGE_PAGE 0x200000
+%define LARGE_MAP_BYTES 2 * LARGE_PAGE
+%define LARGE_UNITS 512
+%define LARGE_PARENT 0xAA
+%define LARGE_CHILD 0xBB
+%define SHM_BYTES 2 * PAGE_SIZE
+%define SHM_RIGHT_READ 1
+%define SHM_MAP_READONLY 1
+%define SHM_FIRST 0x5151
+%define SHM_SECOND 0x5252
+%define SHM_CHILD 0x5353
+%define ERR_MEM_LIMIT -2
+%define LIMIT_HARD_PAGES 4
+%define LIMIT_SOFT_PAGES 2
+%define SOFT_TOUCH_PAGES LIMIT_SOFT_PAGES + 2
+%define MEM_EVENT_BYTES 32
+%define MEM_EVENT_SOFT_LIMIT 3
+; fd 13, sockets 16, endpoints 16: the most each may be set to.
+%define ISOLATION_LIMITS 0x10100D
+%define ISOLATION_CONFIG_MEM_SIZE 40
+; ET_DYN images load at or above this base on the R4 lanes.
+%define PIE_BASE 0x2000000000
+%define ARCH_SET_GS 0x1001
+%define ARCH_SET_FS 0x1002
+%define ARCH_GET_FS 0x1003
+%define ARCH_GET_GS 0x1004
+%define ARCH_UNKNOWN 0x1005
+%define USER_VA_LIMIT 0x800000000000
+%define TLS_FIRST 0x7151
+%define TLS_SECOND 0x7252
+%define GS_WORD 0x6353
+
+%define PROC_INFO_MEM_SIZE 168
+%define PROC_INFO_PARENT 1
+%define PROC_INFO_DOMAIN 13
+%define PROC_INFO_CAPS 14
+%define PROC_INFO_RESIDENT 17
+%define PROC_INFO_RESERVED 18
+%define PROC_INFO_HARD 19
+%define PROC_INFO_SOFT 20
+
+global _start
+
+section .text
+_start:
+    lea  rdi, [rel msg_start]
+    mov  esi, msg_start_end - msg_start
+    xor  eax, eax
+    int  0x80
+
+    ; Fork: back every page of the buffer before the fork, then let both
+    ; sides write. Each must keep seeing only its own stores.
+    lea  rbx, [rel cow_buf]
+    xor  ecx, ecx
+cow_fill:
+    mov  qword [rbx], COW_BEFORE
+    add  rbx, PAGE_SIZE
+    inc  ecx
+    cmp  ecx, COW_PAGES
+    jb   cow_fill
+
+    mov  eax, SYS_FORK
+    int  0x80
+    cmp  rax, -1
+    je   fail
+    test rax, rax
+    jz   cow_child
+    mov  [child_tid], rax
+
+    mov  qword [cow_buf], COW_PARENT
+
+    mov  rdi, [child_tid]
+    lea  rsi, [rel wait_status]
+    xor  edx, edx
+    mov  eax, SYS_WAIT
+    int  0x80
+    cmp  rax, qword [child_tid]
+    jne  fail
+    cmp  qword [wait_status], 0
+    jne  fail
+
+    cmp  qword [cow_buf], COW_PARENT
+    jne  fail
+    cmp  qword [cow_buf + PAGE_SIZE], COW_BEFORE
+    jne  fail
+    cmp  qword [cow_buf + 2 * PAGE_SIZE], COW_BEFORE
+    jne  fail
+
+    lea  rdi, [rel msg_cow_ok]
+    mov  esi, msg_cow_ok_end - msg_cow_ok
+    xor  eax, eax
+    int  0x80
+
+    ; Two anonymous pages: write both, drop write on the first, run code
+    ; only once the second is made executable, then unmap them.
+    xor  edi, edi
+    mov  esi, 2 * PAGE_SIZE
+    mov  edx, PROT_READ | PROT_WRITE
+    mov  eax, SYS_MMAP
+    int  0x80
+    test rax, rax
+    js   fail
+    mov  [map_addr], rax
+    mov  qword [rax], 0x44
+    mov  qword [rax + PAGE_SIZE], 0x55
+    cmp  qword [rax], 0x44
+    jne  fail
+    cmp  qword [rax + PAGE_SIZE], 0x55
+    jne  fail
+
+    mov  rdi, [map_addr]
+    mov  esi, PAGE_SIZE
+    mov  edx, PROT_READ
+    mov  eax, SYS_MPROTECT
+    int  0x80
+    test rax, rax
+    jnz  fail
+    lea  rdi, [rel probe_write_first]
+    call expect_kill
+    cmp  rax, FAULT_STATUS
+    jne  fail
+    mov  rax, [map_addr]
+    cmp  qword [rax], 0x44
+    jne  fail
+    ; Only the first page changed protection.
+    mov  byte [rax + PAGE_SIZE], OPCODE_RET
+
+    lea  rdi, [rel probe_exec_second]
+    call expect_kill
+    cmp  rax, FAULT_STATUS
+    jne  fail
+    mov  rdi, [map_addr]
+    add  rdi, PAGE_SIZE
+    mov  esi, PAGE_SIZE
+    mov  edx, PROT_READ | PROT_EXEC
+    mov  eax, SYS_MPROTECT
+    int  0x80
+    test rax, rax
+    jnz  fail
+    call probe_exec_second
+
+    mov  rdi, [map_addr]
+    mov  esi, 2 * PAGE_SIZE
+    mov  eax, SYS_MUNMAP
+    int  0x80
+    test rax, rax
+    jnz  fail
+    lea  rdi, [rel probe_read_first]
+    call expect_kill
+    cmp  rax, FAULT_STATUS
+    jne  fail
+
+    lea  rdi, [rel msg_mmap_ok]
+    mov  esi, msg_mmap_ok_end - msg_mmap_ok
+    xor  eax, eax
+    int  0x80
+
+    ; The stack grows on demand well past its first page, but running into
+    ; the guard gap below its limit kills the task.
+    mov  rbx, rsp
+    mov  ecx, STACK_GROW_PAGES
+stack_grow:
+    sub  rsp, PAGE_SIZE
+    mov  [rsp], rcx
+    dec  ecx
+    jnz  stack_grow
+    mov  rsp, rbx
+    lea  rdi, [rel probe_stack_overflow]
+    call expect_kill
+    cmp  rax, FAULT_STATUS
+    jne  fail
+
+    ; A stack's guard gap only goes away with the whole stack: unmapping the
+    ; gap alone, or with the stack's lowest page, fails.
+    xor  edi, edi
+    mov  esi, GUARD_STACK_BYTES
+    mov  edx, PROT_READ | PROT_WRITE | MAP_STACK
+    mov  eax, SYS_MMAP
+    int  0x80
+    test rax, rax
+    js   fail
+    mov  rbx, rax
+    lea  rdi, [rbx - STACK_GUARD]
+    mov  esi, STACK_GUARD
+    mov  eax, SYS_MUNMAP
+    int  0x80
+    test rax, rax
+    jns  fail
+    lea  rdi, [rbx - STACK_GUARD]
+    mov  esi, STACK_GUARD + PAGE_SIZE
+    mov  eax, SYS_MUNMAP
+    int  0x80
+    test rax, rax
+    jns  fail
+    lea  rdi, [rbx - STACK_GUARD]
+    mov  esi, STACK_GUARD + GUARD_STACK_BYTES
+    mov  eax, SYS_MUNMAP
+    int  0x80
+    test rax, rax
+    jnz  fail
+
+    lea  rdi, [rel msg_stack_ok]
+    mov  esi, msg_stack_ok_end - msg_stack_ok
+    xor  eax, eax
+    int  0x80
+
+    ; A child that keeps touching fresh pages drains the frame pool and, as
+    ; the task with the most resident pages, is the OOM victim.
+    lea  rdi, [rel probe_oom]
+    call expect_kill
+    cmp  rax, OOM_STATUS
+    jne  fail
+
+    lea  rdi, [rel msg_oom_ok]
+    mov  esi, msg_oom_ok_end - msg_oom_ok
+    xor  eax, eax
+    int  0x80
+
+    ; Each task has its own page tables: a child that rewrites and unmaps
+    ; an inherited mapping leaves the parent's mapping and contents alone.
+    xor  edi, edi
+    mov  esi, PAGE_SIZE
+    mov  edx, PROT_READ | PROT_WRITE
+    mov  eax, SYS_MMAP
+    int  0x80
+    test rax, rax
+    js   fail
+    mov  [map_addr], rax
+    mov  qword [rax], SPACE_PARENT
+
+    mov  eax, SYS_FORK
+    int  0x80
+    cmp  rax, -1
+    je   fail
+    test rax, rax
+    jz   space_child
+    mov  rdi, rax
+    call reap
+    mov  rax, [map_addr]
+    cmp  qword [rax], SPACE_PARENT
+    jne  fail
+    mov  rdi, [map_addr]
+    mov  esi, PAGE_SIZE
+    mov  eax, SYS_MUNMAP
+    int  0x80
+    test rax, rax
+    jnz  fail
+
+    lea  rdi, [rel msg_space_ok]
+    mov  esi, msg_space_ok_end - msg_space_ok
+    xor  eax, eax
+    int  0x80
+
+    ; Demand paging: reserving an area backs nothing, and the first write
+    ; or read of a page backs just that page, zeroed.
+    call find_self
+    call read_self_info
+    mov  rax, [proc_info + PROC_INFO_RESIDENT * 8]
+    mov  [resident_mark], rax
+    mov  rax, [proc_info + PROC_INFO_RESERVED * 8]
+    mov  [reserved_mark], rax
+
+    xor  edi, edi
+    mov  esi, DEMAND_PAGES * PAGE_SIZE
+    mov  edx, PROT_READ | PROT_WRITE
+    mov  eax, SYS_MMAP
+    int  0x80
+    test rax, rax
+    js   fail
+    mov  [map_addr], rax
+    call read_self_info
+    mov  rax, [resident_mark]
+    cmp  [proc_info + PROC_INFO_RESIDENT * 8], rax
+    jne  fail
+    mov  rax, [reserved_mark]
+    add  rax, DEMAND_PAGES
+    cmp  [proc_info + PROC_INFO_RESERVED * 8], rax
+    jne  fail
+
+    mov  rax, [map_addr]
+    mov  qword [rax + 5 * PAGE_SIZE], 0x99
+    call read_self_info
+    mov  rax, [resident_mark]
+    inc  rax
+    cmp  [proc_info + PROC_INFO_RESIDENT * 8], rax
+    jne  fail
+    mov  rax, [map_addr]
+    cmp  qword [rax + 9 * PAGE_SIZE], 0
+    jne  fail
+    call read_self_info
+    mov  rax, [resident_mark]
+    add  rax, 2
+    cmp  [proc_info + PROC_INFO_RESIDENT * 8], rax
+    jne  fail
+
+    mov  rdi, [map_addr]
+    mov  esi, DEMAND_PAGES * PAGE_SIZE
+    mov  eax, SYS_MUNMAP
+    int  0x80
+    test rax, rax
+    jnz  fail
+    call read_self_info
+    mov  rax, [resident_mark]
+    cmp  [proc_info + PROC_INFO_RESIDENT * 8], rax
+    jne  fail
+    mov  rax, [reserved_mark]
+    cmp  [proc_info + PROC_INFO_RESERVED * 8], rax
+    jne  fail
+
+    lea  rdi, [rel msg_demand_ok]
+    mov  esi, msg_demand_ok_end - msg_demand_ok
+    xor  eax, eax
+    int  0x80
+
+    ; Large pages: a big mapping starts on a 2 MiB boundary and one touch
+    ; backs the whole block. A forked child's write copies it, and
+    ; mprotect of one page splits it.
+    xor  edi, edi
+    mov  esi, LARGE_MAP_BYTES
+    mov  edx, PROT_READ | PROT_WRITE
+    mov  eax, SYS_MMAP
+    int  0x80
+    test rax, rax
+    js   fail
+    test eax, LARGE_PAGE - 1
+    jnz  fail
+    mov  [map_addr], rax
+    mov  qword [rax], LARGE_PARENT
+    call read_self_info
+    mov  rax, [resident_mark]
+    add  rax, LARGE_UNITS
+    cmp  [proc_info + PROC_INFO_RESIDENT * 8], rax
+    jne  fail
+
+    mov  eax, SYS_FORK
+    int  0x80
+    cmp  rax, -1
+    je   fail
+    test rax, rax
+    jz   large_child
+    mov  rdi, rax
+    call reap
+    mov  rax, [map_addr]
+    cmp  qword [rax], LARGE_PARENT
+    jne  fail
+
+    mov  rdi, [map_addr]
+    mov  esi, PAGE_SIZE
+    mov  edx, PROT_READ
+    mov  eax, SYS_MPROTECT
+    int  0x80
+    test rax, rax
+    jnz  fail
+    mov  rax, [map_addr]
+    mov  qword [rax + PAGE_SIZE], LARGE_PARENT
+    cmp  qword [rax], LARGE_PARENT
+    jne  fail
+
+    mov  rdi, [map_addr]
+    mov  esi, LARGE_MAP_BYTES
+    mov  eax, SYS_MUNMAP
+    int  0x80
+    test rax, rax
+    jnz  fail
+    call read_self_info
+    mov  rax, [resident_mark]
+    cmp  [proc_info + PROC_INFO_RESIDENT * 8], rax
+    jne  fail
+    mov  rax, [reserved_mark]
+    cmp  [proc_info + PROC_INFO_RESERVED * 8], rax
+    jne  fail
+
+    lea  rdi, [rel msg_large_ok]
+    mov  esi, msg_large_ok_end - msg_large_ok
+    xor  eax, eax
+    int  0x80
+
+    ; SHM: a handle sent over an endpoint arrives as a new handle with only
+    ; the rights passed on. A read-only handle neither maps writable nor
+    ; can be sent on, and both views, a forked child's included, see the
+    ; same pages.
+    mov  edi, SHM_BYTES
+    mov  eax, SYS_SHM_CREATE
+    int  0x80
+    test rax, rax
+    js   fail
+    mov  [shm_handle], rax
+    mov  rdi, rax
+    xor  esi, esi
+    xor  edx, edx
+    mov  eax, SYS_SHM_MAP
+    int  0x80
+    test rax, rax
+    js   fail
+    mov  [shm_addr], rax
+    mov  qword [rax], SHM_FIRST
+
+    mov  eax, SYS_IPC_ENDPOINT_CREATE
+    int  0x80
+    test rax, rax
+    js   fail
+    mov  [shm_ep], rax
+    mov  rdi, rax
+    mov  rsi, [shm_handle]
+    mov  edx, SHM_RIGHT_READ
+    mov  eax, SYS_SHM_SEND
+    int  0x80
+    test rax, rax
+    jnz  fail
+    mov  rdi, [shm_ep]
+    lea  rsi, [rel shm_msg]
+    mov  edx, 8
+    mov  eax, SYS_IPC_RECV
+    int  0x80
+    cmp  rax, 8
+    jne  fail
+
+    mov  rdi, [shm_msg]
+    xor  esi, esi
+    xor  edx, edx
+    mov  eax, SYS_SHM_MAP
+    int  0x80
+    cmp  rax, -1
+    jne  fail
+    mov  rdi, [shm_msg]
+    xor  esi, esi
+    mov  edx, SHM_MAP_READONLY
+    mov  eax, SYS_SHM_MAP
+    int  0x80
+    test rax, rax
+    js   fail
+    mov  [shm_view], rax
+    cmp  qword [rax], SHM_FIRST
+    jne  fail
+    mov  rcx, [shm_addr]
+    mov  qword [rcx], SHM_SECOND
+    cmp  qword [rax], SHM_SECOND
+    jne  fail
+
+    mov  rdi, [shm_ep]
+    mov  rsi, [shm_msg]
+    mov  edx, SHM_RIGHT_READ
+    mov  eax, SYS_SHM_SEND
+    int  0x80
+    cmp  rax, -1
+    jne  fail
+
+    mov  eax, SYS_FORK
+    int  0x80
+    cmp  rax, -1
+    je   fail
+    test rax, rax
+    jz   shm_child
+    mov  rdi, rax
+    call reap
+    mov  rax, [shm_view]
+    cmp  qword [rax], SHM_CHILD
+    jne  fail
+
+    mov  rdi, [shm_view]
+    mov  eax, SYS_SHM_UNMAP
+    int  0x80
+    test rax, rax
+    jnz  fail
+    mov  rdi, [shm_addr]
+    mov  eax, SYS_SHM_UNMAP
+    int  0x80
+    test rax, rax
+    jnz  fail
+
+    lea  rdi, [rel msg_shm_ok]
+    mov  esi, msg_shm_ok_end - msg_shm_ok
+    xor  eax, eax
+    int  0x80
+
+    ; A 2 MiB SHM object is backed by a large frame: each view starts on a
+    ; 2 MiB boundary, is mapped up front, and shares the block.
+    call read_self_info
+    mov  rax, [proc_info + PROC_INFO_RESIDENT * 8]
+    mov  [resident_mark], rax
+    mov  edi, LARGE_PAGE
+    mov  eax, SYS_SHM_CREATE
+    int  0x80
+    test rax, rax
+    js   fail
+    mov  [shm_handle], rax
+    mov  rdi, rax
+    xor  esi, esi
+    xor  edx, edx
+    mov  eax, SYS_SHM_MAP
+    int  0x80
+    test rax, rax
+    js   fail
+    test eax, LARGE_PAGE - 1
+    jnz  fail
+    mov  [shm_addr], rax
+    call read_self_info
+    mov  rax, [resident_mark]
+    add  rax, LARGE_UNITS
+    cmp  [proc_info + PROC_INFO_RESIDENT * 8], rax
+    jne  fail
+
+    mov  rdi, [shm_handle]
+    xor  esi, esi
+    mov  edx, SHM_MAP_READONLY
+    mov  eax, SYS_SHM_MAP
+    int  0x80
+    test rax, rax
+    js   fail
+    test eax, LARGE_PAGE - 1
+    jnz  fail
+    mov  [shm_view], rax
+    mov  rcx, [shm_addr]
+    mov  qword [rcx + LARGE_PAGE - 8], SHM_FIRST
+    cmp  qword [rax + LARGE_PAGE - 8], SHM_FIRST
+    jne  fail
+
+    mov  rdi, [shm_view]
+    mov  eax, SYS_SHM_UNMAP
+    int  0x80
+    test rax, rax
+    jnz  fail
+    mov  rdi, [shm_addr]
+    mov  eax, SYS_SHM_UNMAP
+    int  0x80
+    test rax, rax
+    jnz  fail
+    call read_self_info
+    mov  rax, [resident_mark]
+    cmp  [proc_info + PROC_INFO_RESIDENT * 8], rax
+    jne  fail
+
+    lea  rdi, [rel msg_shm_large_ok]
+    mov  esi, msg_shm_large_ok_end - msg_shm_large_ok
+    xor  eax, eax
+    int  0x80
+
+    ; User copies: a copy-out backs a reserved page on demand, and fails
+    ; with -1 on a read-only or unmapped page without killing the task.
+    xor  edi, edi
+    mov  esi, 2 * PAGE_SIZE
+    mov  edx, PROT_READ | PROT_WRITE
+    mov  eax, SYS_MMAP
+    int  0x80
+    test rax, rax
+    js   fail
+    mov  [map_addr], rax
+    mov  rdi, [self_id]
+    mov  rsi, rax
+    mov  edx, PROC_INFO_MEM_SIZE
+    mov  eax, SYS_PROC_INFO
+    int  0x80
+    test rax, rax
+    jnz  fail
+    mov  rax, [map_addr]
+    mov  rcx, [self_id]
+    cmp  [rax], rcx
+    jne  fail
+
+    mov  rdi, [map_addr]
+    add  rdi, PAGE_SIZE
+    mov  esi, PAGE_SIZE
+    mov  edx, PROT_READ
+    mov  eax, SYS_MPROTECT
+    int  0x80
+    test rax, rax
+    jnz  fail
+    mov  rdi, [self_id]
+    mov  rsi, [map_addr]
+    add  rsi, PAGE_SIZE
+    mov  edx, PROC_INFO_MEM_SIZE
+    mov  eax, SYS_PROC_INFO
+    int  0x80
+    cmp  rax, -1
+    jne  fail
+
+    mov  rdi, [map_addr]
+    mov  esi, 2 * PAGE_SIZE
+    mov  eax, SYS_MUNMAP
+    int  0x80
+    test rax, rax
+    jnz  fail
+    mov  rdi, [self_id]
+    mov  rsi, [map_addr]
+    mov  edx, PROC_INFO_MEM_SIZE
+    mov  eax, SYS_PROC_INFO
+    int  0x80
+    cmp  rax, -1
+    jne  fail
+
+    lea  rdi, [rel msg_usercopy_ok]
+    mov  esi, msg_usercopy_ok_end - msg_usercopy_ok
+    xor  eax, eax
+    int  0x80
+
+    ; Memory limits, set by a forked child on itself so the probe keeps
+    ; none.
+    mov  eax, SYS_FORK
+    int  0x80
+    cmp  rax, -1
+    je   fail
+    test rax, rax
+    jz   limits_child
+    mov  rdi, rax
+    call reap
+
+    lea  rdi, [rel msg_limits_ok]
+    mov  esi, msg_limits_ok_end - msg_limits_ok
+    xor  eax, eax
+    int  0x80
+
+    ; Memory supervisor: the probe registers an endpoint of its own and may
+    ; register it again, but a forked child, which does not own it, cannot
+    ; take the registration over with an endpoint of its own.
+    mov  eax, SYS_IPC_ENDPOINT_CREATE
+    int  0x80
+    test rax, rax
+    js   fail
+    mov  [notify_ep], rax
+    mov  rdi, rax
+    mov  eax, SYS_MEM_NOTIFY
+    int  0x80
+    test rax, rax
+    jnz  fail
+    mov  eax, SYS_FORK
+    int  0x80
+    cmp  rax, -1
+    je   fail
+    test rax, rax
+    jz   notify_child
+    mov  rdi, rax
+    call reap
+    mov  rdi, [notify_ep]
+    mov  eax, SYS_MEM_NOTIFY
+    int  0x80
+    test rax, rax
+    jnz  fail
+
+    lea  rdi, [rel msg_notify_ok]
+    mov  esi, msg_notify_ok_end - msg_notify_ok
+    xor  eax, eax
+    int  0x80
+
+    ; Soft limit: a forked child sets one just above its resident pages and
+    ; touches past it. The supervisor endpoint gets a soft-limit event
+    ; naming the child.
+    mov  eax, SYS_FORK
+    int  0x80
+    cmp  rax, -1
+    je   fail
+    test rax, rax
+    jz   soft_child
+    mov  rdi, rax
+    call reap
+    mov  rdi, [notify_ep]
+    lea  rsi, [rel notify_msg]
+    mov  edx, MEM_EVENT_BYTES
+    mov  eax, SYS_IPC_RECV
+    int  0x80
+    cmp  rax, MEM_EVENT_BYTES
+    jne  fail
+    cmp  qword [notify_msg], MEM_EVENT_SOFT_LIMIT
+    jne  fail
+    mov  rax, [child_tid]
+    cmp  [notify_msg + 24], rax
+    jne  fail
+
+    lea  rdi, [rel msg_soft_ok]
+    mov  esi, msg_soft_ok_end - msg_soft_ok
+    xor  eax, eax
+    int  0x80
+
+    ; The probe is a PIE: it runs above the ET_DYN base, and the loader's
+    ; RELATIVE relocation points pie_anchor at where it actually landed.
+    lea  rax, [rel pie_anchor]
+    cmp  [pie_anchor], rax
+    jne  fail
+    mov  rcx, PIE_BASE
+    cmp  rax, rcx
+    jb   fail
+
+    lea  rdi, [rel msg_pie_ok]
+    mov  esi, msg_pie_ok_end - msg_pie_ok
+    xor  eax, eax
+    int  0x80
+
+    ; TLS: the loader points FS at a variant II block, the PT_TLS template
+    ; right below the thread pointer and the pointer's own address at it.
+    ; GS can be set and read back; a non-user base or unknown code fails.
+    mov  edi, ARCH_GET_FS
+    lea  rsi, [rel tls_tp]
+    mov  eax, SYS_ARCH_PRCTL
+    int  0x80
+    test rax, rax
+    jnz  fail
+    mov  rax, [tls_tp]
+    test rax, rax
+    jz   fail
+    cmp  [rax], rax
+    jne  fail
+    cmp  qword [rax - 16], TLS_FIRST
+    jne  fail
+    cmp  qword [rax - 8], TLS_SECOND
+    jne  fail
+    xor  edx, edx
+    mov  rcx, [fs:rdx]
+    cmp  rcx, rax
+    jne  fail
+
+    mov  qword [gs_block], GS_WORD
+    mov  edi, ARCH_SET_GS
+    lea  rsi, [rel gs_block]
+    mov  eax, SYS_ARCH_PRCTL
+    int  0x80
+    test rax, rax
+    jnz  fail
+    mov  edi, ARCH_GET_GS
+    lea  rsi, [rel tls_tp]
+    mov  eax, SYS_ARCH_PRCTL
+    int  0x80
+    test rax, rax
+    jnz  fail
+    lea  rax, [rel gs_block]
+    cmp  [tls_tp], rax
+    jne  fail
+    xor  edx, edx
+    cmp  qword [gs:rdx], GS_WORD
+    jne  fail
+
+    mov  edi, ARCH_SET_FS
+    mov  rsi, USER_VA_LIMIT
+    mov  eax, SYS_ARCH_PRCTL
+    int  0x80
+    cmp  rax, -1
+    jne  fail
+    mov  edi, ARCH_UNKNOWN
+    lea  rsi, [rel tls_tp]
+    mov  eax, SYS_ARCH_PRCTL
+    int  0x80
+    cmp  rax, -1
+    jne  fail
+
+    lea  rdi, [rel msg_tls_ok]
+    mov  esi, msg_tls_ok_end - msg_tls_ok
+    xor  eax, eax
+    int  0x80
+
+    ; Image segments keep their ELF permissions: a store into .text and a
+    ; call into .data each kill a forked child. .bss is demand-zero, so a
+    ; first read of an untouched page backs just that page.
+    lea  rdi, [rel probe_write_text]
+    call expect_kill
+    cmp  rax, FAULT_STATUS
+    jne  fail
+    lea  rdi, [rel probe_exec_data]
+    call expect_kill
+    cmp  rax, FAULT_STATUS
+    jne  fail
+
+    call read_self_info
+    mov  rax, [proc_info + PROC_INFO_RESIDENT * 8]
+    mov  [resident_mark], rax
+    cmp  qword [bss_page], 0
+    jne  fail
+    call read_self_info
+    mov  rax, [resident_mark]
+    inc  rax
+    cmp  [proc_info + PROC_INFO_RESIDENT * 8], rax
+    jne  fail
+
+    lea  rdi, [rel msg_image_ok]
+    mov  esi, msg_image_ok_end - msg_image_ok
+    xor  eax, eax
+    int  0x80
+
+    lea  rdi, [rel msg_done]
+    mov  esi, msg_done_end - msg_done
+    xor  eax, eax
+    int  0x80
+
+    mov  eax, SYS_THREAD_EXIT
+    int  0x80
+
+hang:
+    hlt
+    jmp  hang
+
+; Fork a child that calls the routine in rdi, which must get the child
+; killed. Returns the child's wait status in rax.
+expect_kill:
+    mov  [probe_fn], rdi
+    mov  eax, SYS_FORK
+    int  0x80
+    cmp  rax, -1
+    je   fail
+    test rax, rax
+    jz   expect_kill_child
+    mov  [child_tid], rax
+
+    mov  rdi, rax
+    lea  rsi, [rel wait_status]
+    xor  edx, edx
+    mov  eax, SYS_WAIT
+    int  0x80
+    cmp  rax, qword [child_tid]
+    jne  fail
+    mov  rax, [wait_status]
+    ret
+
+expect_kill_child:
+    call [probe_fn]
+    jmp  fail
+
+; Wait for the child in rdi, which must exit with status 0.
+reap:
+    mov  [child_tid], rdi
+    lea  rsi, [rel wait_status]
+    xor  edx, edx
+    mov  eax, SYS_WAIT
+    int  0x80
+    cmp  rax, qword [child_tid]
+    jne  fail
+    cmp  qword [wait_status], 0
+    jne  fail
+    ret
+
+; Store this task's id in self_id: it is the parent word of a child's
+; proc_info.
+find_self:
+    mov  eax, SYS_FORK
+    int  0x80
+    cmp  rax, -1
+    je   fail
+    test rax, rax
+    jz   exit_child
+    mov  [child_tid], rax
+    mov  rdi, rax
+    lea  rsi, [rel proc_info]
+    mov  edx, PROC_INFO_MEM_SIZE
+    mov  eax, SYS_PROC_INFO
+    int  0x80
+    test rax, rax
+    jnz  fail
+    mov  rax, [proc_info + PROC_INFO_PARENT * 8]
+    mov  [self_id], rax
+    mov  rdi, [child_tid]
+    jmp  reap
+
+; Fill proc_info with this task's words, memory usage included.
+read_self_info:
+    mov  rdi, [self_id]
+    lea  rsi, [rel proc_info]
+    mov  edx, PROC_INFO_MEM_SIZE
+    mov  eax, SYS_PROC_INFO
+    int  0x80
+    test rax, rax
+    jnz  fail
+    ret
+
+probe_write_text:
+    lea  rax, [rel _start]
+    mov  byte [rax], OPCODE_RET
+    ret
+
+probe_exec_data:
+    lea  rax, [rel data_ret]
+    call rax
+    ret
+
+probe_write_first:
+    mov  rax, [map_addr]
+    mov  qword [rax], 0x66
+    ret
+
+probe_exec_second:
+    mov  rax, [map_addr]
+    add  rax, PAGE_SIZE
+    call rax
+    ret
+
+probe_read_first:
+    mov  rax, [map_addr]
+    mov  rax, [rax]
+    ret
+
+probe_stack_overflow:
+    sub  rsp, PAGE_SIZE
+    mov  qword [rsp], 0
+    jmp  probe_stack_overflow
+
+probe_oom:
+    xor  edi, edi
+    mov  esi, OOM_MAP_BYTES
+    mov  edx, PROT_READ | PROT_WRITE
+    mov  eax, SYS_MMAP
+    int  0x80
+    test rax, rax
+    js   fail
+    lea  rcx, [rax + OOM_MAP_BYTES]
+probe_oom_touch:
+    mov  qword [rax], 1
+    add  rax, PAGE_SIZE
+    cmp  rax, rcx
+    jb   probe_oom_touch
+    ret
+
+; The child sees the pre-fork contents, then overwrites every page.
+cow_child:
+    lea  rbx, [rel cow_buf]
+    xor  ecx, ecx
+cow_child_page:
+    cmp  qword [rbx], COW_BEFORE
+    jne  fail
+    mov  qword [rbx], COW_CHILD
+    cmp  qword [rbx], COW_CHILD
+    jne  fail
+    add  rbx, PAGE_SIZE
+    inc  ecx
+    cmp  ecx, COW_PAGES
+    jb   cow_child_page
+    mov  eax, SYS_THREAD_EXIT
+    int  0x80
+    jmp  hang
+
+exit_child:
+    mov  eax, SYS_THREAD_EXIT
+    int  0x80
+    jmp  hang
+
+space_child:
+    mov  rax, [map_addr]
+    mov  qword [rax], SPACE_CHILD
+    mov  rdi, rax
+    mov  esi, PAGE_SIZE
+    mov  eax, SYS_MUNMAP
+    int  0x80
+    test rax, rax
+    jnz  fail
+    mov  eax, SYS_THREAD_EXIT
+    int  0x80
+    jmp  hang
+
+; The child's write to the shared block must not reach the parent.
+large_child:
+    mov  rax, [map_addr]
+    cmp  qword [rax], LARGE_PARENT
+    jne  fail
+    mov  qword [rax], LARGE_CHILD
+    cmp  qword [rax], LARGE_CHILD
+    jne  fail
+    mov  eax, SYS_THREAD_EXIT
+    int  0x80
+    jmp  hang
+
+; Cap the reservation a few pages above its current size. mmap past the
+; hard limit fails with ERR_MEM_LIMIT, and a hard limit below the
+; reservation or a soft one above the hard one is refused.
+limits_child:
+    call find_self
+    call read_self_info
+    mov  rax, [proc_info + PROC_INFO_DOMAIN * 8]
+    mov  [limits_cfg], rax
+    mov  rax, [proc_info + PROC_INFO_CAPS * 8]
+    mov  [limits_cfg + 8], rax
+    mov  qword [limits_cfg + 16], ISOLATION_LIMITS
+    mov  rax, [proc_info + PROC_INFO_RESERVED * 8]
+    lea  rcx, [rax + LIMIT_HARD_PAGES]
+    mov  [limits_cfg + 24], rcx
+    lea  rcx, [rax + LIMIT_SOFT_PAGES]
+    mov  [limits_cfg + 32], rcx
+    call set_limits
+    test rax, rax
+    jnz  fail
+    call read_self_info
+    mov  rax, [limits_cfg + 24]
+    cmp  [proc_info + PROC_INFO_HARD * 8], rax
+    jne  fail
+    mov  rax, [limits_cfg + 32]
+    cmp  [proc_info + PROC_INFO_SOFT * 8], rax
+    jne  fail
+
+    xor  edi, edi
+    mov  esi, LIMIT_HARD_PAGES * PAGE_SIZE
+    mov  edx, PROT_READ | PROT_WRITE
+    mov  eax, SYS_MMAP
+    int  0x80
+    test rax, rax
+    js   fail
+    xor  edi, edi
+    mov  esi, PAGE_SIZE
+    mov  edx, PROT_READ | PROT_WRITE
+    mov  eax, SYS_MMAP
+    int  0x80
+    cmp  rax, ERR_MEM_LIMIT
+    jne  fail
+
+    dec  qword [limits_cfg + 24]
+    call set_limits
+    cmp  rax, -1
+    jne  fail
+    inc  qword [limits_cfg + 24]
+    mov  rax, [limits_cfg + 24]
+    inc  rax
+    mov  [limits_cfg + 32], rax
+    call set_limits
+    cmp  rax, -1
+    jne  fail
+
+    mov  eax, SYS_THREAD_EXIT
+    int  0x80
+    jmp  hang
+
+set_limits:
+    mov  rdi, [self_id]
+    lea  rsi, [rel limits_cfg]
+    mov  edx, ISOLATION_CONFIG_MEM_SIZE
+    mov  eax, SYS_ISOLATION_CONFIG
+    int  0x80
+    ret
+
+notify_child:
+    mov  eax, SYS_IPC_ENDPOINT_CREATE
+    int  0x80
+    test rax, rax
+    js   fail
+    mov  rdi, rax
+    mov  eax, SYS_MEM_NOTIFY
+    int  0x80
+    cmp  rax, -1
+    jne  fail
+    mov  eax, SYS_THREAD_EXIT
+    int  0x80
+    jmp  hang
+
+; No hard limit, and a soft one two pages above the resident count.
+soft_child:
+    call find_self
+    call read_self_info
+    mov  rax, [proc_info + PROC_INFO_DOMAIN * 8]
+    mov  [limits_cfg], rax
+    mov  rax, [proc_info + PROC_INFO_CAPS * 8]
+    mov  [limits_cfg + 8], rax
+    mov  qword [limits_cfg + 16], ISOLATION_LIMITS
+    mov  qword [limits_cfg + 24], 0
+    mov  rax, [proc_info + PROC_INFO_RESIDENT * 8]
+    add  rax, LIMIT_SOFT_PAGES
+    mov  [limits_cfg + 32], rax
+    call set_limits
+    test rax, rax
+    jnz  fail
+
+    xor  edi, edi
+    mov  esi, SOFT_TOUCH_PAGES * PAGE_SIZE
+    mov  edx, PROT_READ | PROT_WRITE
+    mov  eax, SYS_MMAP
+    int  0x80
+    test rax, rax
+    js   fail
+    lea  rcx, [rax + SOFT_TOUCH_PAGES * PAGE_SIZE]
+soft_child_touch:
+    mov  qword [rax], 1
+    add  rax, PAGE_SIZE
+    cmp  rax, rcx
+    jb   soft_child_touch
+    mov  eax, SYS_THREAD_EXIT
+    int  0x80
+    jmp  hang
+
+; The child's store through the inherited writable mapping is shared.
+shm_child:
+    mov  rax, [shm_addr]
+    mov  qword [rax], SHM_CHILD
+    mov  eax, SYS_THREAD_EXIT
+    int  0x80
+    jmp  hang
+
+fail:
+    lea  rdi, [rel msg_fail]
+    mov  esi, msg_fail_end - msg_fail
+    xor  eax, eax
+    int  0x80
+
+    mov  edi, 0x33
+    mov  eax, SYS_QEMU_EXIT
+    int  0x80
+    jmp  hang
+
+section .data
+msg_start:       db "X1MEM: start", 10
+msg_start_end:
+msg_cow_ok:      db "X1MEM: cow ok", 10
+msg_cow_ok_end:
+msg_mmap_ok:     db "X1MEM: mmap ok", 10
+msg_mmap_ok_end:
+msg_stack_ok:    db "X1MEM: stack ok", 10
+msg_stack_ok_end:
+msg_oom_ok:      db "X1MEM: oom ok", 10
+msg_oom_ok_end:
+msg_space_ok:    db "X1MEM: space ok", 10
+msg_space_ok_end:
+msg_demand_ok:   db "X1MEM: demand ok", 10
+msg_demand_ok_end:
+msg_large_ok:    db "X1MEM: large ok", 10
+msg_large_ok_end:
+msg_shm_ok:      db "X1MEM: shm ok", 10
+msg_shm_ok_end:
+msg_shm_large_ok: db "X1MEM: shm large ok", 10
+msg_shm_large_ok_end:
+msg_usercopy_ok: db "X1MEM: usercopy ok", 10
+msg_usercopy_ok_end:
+msg_limits_ok:   db "X1MEM: limits ok", 10
+msg_limits_ok_end:
+msg_notify_ok:   db "X1MEM: notify ok", 10
+msg_notify_ok_end:
+msg_soft_ok:     db "X1MEM: soft ok", 10
+msg_soft_ok_end:
+msg_pie_ok:      db "X1MEM: pie ok", 10
+msg_pie_ok_end:
+msg_tls_ok:      db "X1MEM: tls ok", 10
+msg_tls_ok_end:
+msg_image_ok:    db "X1MEM: image ok", 10
+msg_image_ok_end:
+msg_done:        db "X1MEM: done", 10
+msg_done_end:
+msg_fail:        db "X1MEM: fail", 10
+msg_fail_end:
+; Holds its own address once the loader has applied the relocation.
+align 8
+pie_anchor:      dq pie_anchor
+; A return instruction in a segment mapped without execute permission.
+data_ret:        db OPCODE_RET
+
+; The PT_TLS template the loader copies below the thread pointer.
+section .tdata progbits alloc write noexec tls align=16
+    dq TLS_FIRST, TLS_SECOND
+
+section .bss align=4096
+cow_buf:         resb COW_PAGES * PAGE_SIZE
+child_tid:       resq 1
+wait_status:     resq 1
+probe_fn:        resq 1
+map_addr:        resq 1
+self_id:         resq 1
+resident_mark:   resq 1
+reserved_mark:   resq 1
+shm_handle:      resq 1
+shm_addr:        resq 1
+shm_view:        resq 1
+shm_ep:          resq 1
+shm_msg:         resq 1
+limits_cfg:      resb ISOLATION_CONFIG_MEM_SIZE
+notify_ep:       resq 1
+notify_msg:      resb MEM_EVENT_BYTES
+tls_tp:          resq 1
+gs_block:        resq 1
+proc_info:       resb PROC_INFO_MEM_SIZE
+; Never touched before the image check reads it.
+alignb 4096
+bss_page:        resb PAGE_SIZE
@@ -15,11 +15,14 @@ default rel
 %define SYS_SOCKET_CLOSE 38
 %define SYS_NET_IF_CONFIG 39
 %define SYS_NET_ROUTE_ADD 40
-%define SYS_FORK_DEFERRED 43
-%define SYS_CLONE_DEFERRED 44
+%define SYS_FORK 43
+%define SYS_CLONE 44
 %define SYS_EPOLL_DEFERRED 45
 %define SYS_QEMU_EXIT 98
 
+%define CLONE_VM 1
+%define CLONE_ARG 0x5A
+
 %define NET_AF_INET6 10
 %define SOCKET_STREAM 1
 
@@ -190,15 +193,72 @@ _start:
     xor  eax, eax
     int  0x80
 
-    mov  eax, SYS_FORK_DEFERRED
+    mov  eax, SYS_FORK
+    int  0x80
+    cmp  rax, -1
+    je   fail
+    test rax, rax
+    jz   fork_child
+    mov  [child_tid], rax
+
+    mov  rdi, rax
+    lea  rsi, [rel wait_status]
+    xor  edx, edx
+    mov  eax, SYS_WAIT
+    int  0x80
+    cmp  rax, qword [child_tid]
+    jne  fail
+    cmp  qword [wait_status], 0
+    jne  fail
+    ; The child's store went to its own copy of the page.
+    cmp  qword [fork_marker], 0
+    jne  fail
+
+    lea  rdi, [rel msg_fork_ok]
+    mov  esi, msg_fork_ok_end - msg_fork_ok
+    xor  eax, eax
+    int  0x80
+
+    ; A stack top or join word in read-only text is refused.
+    lea  rdi, [rel clone_ro_stack_args]
+    mov  esi, clone_ro_stack_args_end - clone_ro_stack_args
+    mov  eax, SYS_CLONE
+    int  0x80
+    cmp  rax, -1
+    jne  fail
+    lea  rdi, [rel clone_ro_join_args]
+    mov  esi, clone_ro_join_args_end - clone_ro_join_args
+    mov  eax, SYS_CLONE
     int  0x80
     cmp  rax, -1
     jne  fail
 
-    mov  eax, SYS_CLONE_DEFERRED
+    lea  rdi, [rel clone_args]
+    mov  esi, clone_args_end - clone_args
+    mov  eax, SYS_CLONE
     int  0x80
     cmp  rax, -1
+    je   fail
+    mov  [child_tid], rax
+
+    mov  rdi, rax
+    lea  rsi, [rel wait_status]
+    xor  edx, edx
+    mov  eax, SYS_WAIT
+    int  0x80
+    cmp  rax, qword [child_tid]
+    jne  fail
+    ; The thread stored its argument in shared memory and its join word was
+    ; cleared on exit.
+    cmp  qword [clone_marker], CLONE_ARG
     jne  fail
+    cmp  qword [clone_join], 0
+    jne  fail
+
+    lea  rdi, [rel msg_clone_ok]
+    mov  esi, msg_clone_ok_end - msg_clone_ok
+    xor  eax, eax
+    int  0x80
 
     mov  eax, SYS_EPOLL_DEFERRED
     int  0x80
@@ -226,6 +286,19 @@ child_entry:
     int  0x80
     jmp  hang
 
+align 16
+clone_entry:
+    mov  [clone_marker], rdi
+    mov  eax, SYS_THREAD_EXIT
+    int  0x80
+    jmp  hang
+
+fork_child:
+    mov  qword [fork_marker], 1
+    mov  eax, SYS_THREAD_EXIT
+    int  0x80
+    jmp  hang
+
 fail:
     lea  rdi, [rel msg_fail]
     mov  esi, msg_fail_end - msg_fail
@@ -246,6 +319,10 @@ msg_wait_ok:     db "X1PROC: wait ok", 10
 msg_wait_ok_end:
 msg_sock_ok:     db "X1SOCK: ok", 10
 msg_sock_ok_end:
+msg_fork_ok:     db "X1FORK: ok", 10
+msg_fork_ok_end:
+msg_clone_ok:    db "X1CLONE: ok", 10
+msg_clone_ok_end:
 msg_defer_ok:    db "X1DEFER: ok", 10
 msg_defer_ok_end:
 msg_fail:        db "X1PROC: fail", 10
@@ -260,6 +337,35 @@ loopback_cfg:
     dq 128
     db 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1
 
+align 8
+clone_args:
+    dq CLONE_VM
+    dq clone_entry
+    dq CLONE_ARG
+    dq clone_stack_top
+    dq 0
+    dq clone_join
+clone_args_end:
+clone_join:      dq 1
+
+clone_ro_stack_args:
+    dq CLONE_VM
+    dq clone_entry
+    dq CLONE_ARG
+    dq clone_entry
+    dq 0
+    dq 0
+clone_ro_stack_args_end:
+
+clone_ro_join_args:
+    dq CLONE_VM
+    dq clone_entry
+    dq CLONE_ARG
+    dq clone_stack_top
+    dq 0
+    dq clone_entry
+clone_ro_join_args_end:
+
 listen_addr:
     dq NET_AF_INET6
     dq 5050
@@ -268,9 +374,14 @@ listen_addr:
 section .bss
 child_tid:       resq 1
 wait_status:     resq 1
+fork_marker:     resq 1
+clone_marker:    resq 1
 server_socket:   resq 1
 client_socket:   resq 1
 accepted_socket: resq 1
 peer_len:        resq 1
 peer_addr:       resb 32
 recv_buf:        resb 32
+alignb 16
+clone_stack:     resb 512
+clone_stack_top:
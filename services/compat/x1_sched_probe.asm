@@ -0,0 +1,1010 @@
+bits 64
+default rel
+
+; x1-sched-probe: exercises R4 process creation and scheduling from user
+; space and reports each check as a serial marker. A failed check prints
+; "X1SCHED: fail" and stops QEMU.
+
+%define SYS_DEBUG_WRITE 0
+%define SYS_THREAD_EXIT 2
+%define SYS_WAIT 22
+%define SYS_PROC_INFO 28
+%define SYS_SCHED_SET 29
+%define SYS_FORK 43
+%define SYS_CLONE 44
+%define SYS_SCHED_NICE 53
+%define SYS_SPAWN 52
+%define SYS_CLOCK_NOW 54
+%define SYS_NANOSLEEP 55
+%define SYS_WAIT_UNTIL 57
+%define SYS_TIMER_CREATE 59
+%define SYS_TIMER_WAIT 60
+%define SYS_TIMER_CLOSE 61
+%define SYS_SYSINFO 62
+%define SYS_QEMU_EXIT 98
+
+%define SPAWN_ARGS_SIZE 88
+%define ERR_MEM_LIMIT -2
+
+%define CLONE_VM 1
+%define CLONE_SETTLS 4
+%define CLONE_DETACHED 8
+%define CLONE_UNKNOWN 0x10
+%define CLONE_ARGS_SIZE 48
+%define CLONE_ARG 0x5A
+%define JOIN_TIMEOUT_MS 1000
+%define NS_PER_MS 1000000
+; The spinning thread must get this far while the probe itself spins.
+%define SPIN_TARGET 1000
+%define SPIN_TIMEOUT_MS 2000
+%define SPIN_CLOCK_MASK 0xFFFF
+
+%define SCHED_CLASS_BEST_EFFORT 0
+%define SCHED_CLASS_CRITICAL 1
+%define SCHED_CLASS_DEADLINE 2
+%define ERR_SCHED_CAPACITY -2
+%define SLEEPER_MS 300
+%define PROC_INFO_DEADLINE_SIZE 224
+%define PROC_INFO_CLASS 3
+%define PROC_INFO_DL_RUNTIME 24
+%define PROC_INFO_DL_PERIOD 25
+%define PROC_INFO_SCHED_SIZE 192
+%define PROC_INFO_NICE 21
+%define PROC_INFO_CPU_TIME 22
+%define PROC_INFO_AFFINITY_SIZE 248
+%define PROC_INFO_PARENT 1
+%define PROC_INFO_AFFINITY 30
+; Kernel workers take the slots above the lane's default rugo.max_tasks and
+; stay on the boot CPU.
+%define MAX_TASKS 6
+%define KWORKERS 2
+%define BOOT_CPU_MASK 1
+%define TASK_SLOT_MASK 0xFFFF
+%define SCHED_SET_AFFINITY 0x80
+; The lane boots one CPU, so CPU 1 is not online.
+%define OFFLINE_CPU_MASK 2
+%define NICE_LOW 5
+%define NICE_OUT_OF_RANGE 20
+; Both spinners outlive the sample taken NICE_SAMPLE_MS after they start.
+%define NICE_SPIN_MS 300
+%define NICE_SAMPLE_MS 200
+
+%define SLEEP_ABSOLUTE 1
+%define ERR_TIMED_OUT -3
+%define SLEEP_MS 50
+%define TIMER_FIRST_MS 20
+%define TIMER_PERIOD_MS 10
+%define WAIT_TIMEOUT_MS 30
+
+%define SYSINFO_SIZE 64
+%define SYSINFO_UPTIME 0
+%define SYSINFO_USER 2
+%define SYSINFO_KERNEL 3
+%define SYSINFO_LOAD1 4
+%define SYSINFO_RUNNABLE 7
+; Past the next 5 s load-average sample from anywhere in the boot.
+%define LOAD_TIMEOUT_MS 6000
+
+%define AT_PAGESZ 6
+%define AT_ENTRY 9
+%define AT_UID 11
+%define AT_HWCAP 16
+%define AT_CLKTCK 17
+%define AT_RANDOM 25
+%define AT_EXECFN 31
+%define AUXV_REQUIRED (1 << AT_PAGESZ) | (1 << AT_ENTRY) | (1 << AT_UID) | (1 << AT_HWCAP) | (1 << AT_CLKTCK) | (1 << AT_RANDOM) | (1 << AT_EXECFN)
+%define CLKTCK 100
+%define PAGE_SIZE 4096
+
+global _start
+
+section .text
+_start:
+    mov  [startup_sp], rsp
+    lea  rdi, [rel msg_start]
+    mov  esi, msg_start_end - msg_start
+    xor  eax, eax
+    int  0x80
+
+    ; Spawn /bin/hello with an argument and reap it; an unknown path and an
+    ; image larger than its memory limit are refused.
+    lea  rdi, [rel spawn_hello]
+    mov  esi, SPAWN_ARGS_SIZE
+    mov  eax, SYS_SPAWN
+    int  0x80
+    test rax, rax
+    js   fail
+    mov  [child_tid], rax
+
+    mov  rdi, rax
+    lea  rsi, [rel wait_status]
+    xor  edx, edx
+    mov  eax, SYS_WAIT
+    int  0x80
+    cmp  rax, qword [child_tid]
+    jne  fail
+    cmp  qword [wait_status], 0
+    jne  fail
+
+    lea  rdi, [rel spawn_missing]
+    mov  esi, SPAWN_ARGS_SIZE
+    mov  eax, SYS_SPAWN
+    int  0x80
+    cmp  rax, -1
+    jne  fail
+
+    lea  rdi, [rel spawn_tiny]
+    mov  esi, SPAWN_ARGS_SIZE
+    mov  eax, SYS_SPAWN
+    int  0x80
+    cmp  rax, ERR_MEM_LIMIT
+    jne  fail
+
+    lea  rdi, [rel msg_spawn_ok]
+    mov  esi, msg_spawn_ok_end - msg_spawn_ok
+    xor  eax, eax
+    int  0x80
+
+    ; Preemption: a thread in the same space spins without syscalls while
+    ; this task spins waiting for its counter. On one CPU that only
+    ; finishes if the timer tick preempts both of them.
+    mov  eax, SYS_CLOCK_NOW
+    int  0x80
+    add  rax, SPIN_TIMEOUT_MS * NS_PER_MS
+    mov  [spin_deadline], rax
+
+    lea  rdi, [rel clone_args]
+    mov  esi, clone_args_end - clone_args
+    mov  eax, SYS_CLONE
+    int  0x80
+    test rax, rax
+    js   fail
+    mov  [child_tid], rax
+
+    xor  ebx, ebx
+spin_wait:
+    cmp  qword [spin_count], SPIN_TARGET
+    jae  spin_done
+    inc  rbx
+    test rbx, SPIN_CLOCK_MASK
+    jnz  spin_wait
+    mov  eax, SYS_CLOCK_NOW
+    int  0x80
+    cmp  rax, [spin_deadline]
+    jb   spin_wait
+    jmp  fail
+spin_done:
+    mov  qword [spin_stop], 1
+
+    mov  rdi, [child_tid]
+    lea  rsi, [rel wait_status]
+    xor  edx, edx
+    mov  eax, SYS_WAIT
+    int  0x80
+    cmp  rax, qword [child_tid]
+    jne  fail
+
+    lea  rdi, [rel msg_preempt_ok]
+    mov  esi, msg_preempt_ok_end - msg_preempt_ok
+    xor  eax, eax
+    int  0x80
+
+    ; Deadline class: reservations are admitted up to 90% of the CPU, and
+    ; leaving the class hands the bandwidth back.
+    call fork_sleeper
+    mov  [sleeper_a], rax
+    call fork_sleeper
+    mov  [sleeper_b], rax
+
+    mov  rdi, [sleeper_a]
+    mov  esi, SCHED_CLASS_DEADLINE
+    lea  rdx, [rel dl_small]
+    mov  eax, SYS_SCHED_SET
+    int  0x80
+    test rax, rax
+    jnz  fail
+    mov  rdi, [sleeper_b]
+    mov  esi, SCHED_CLASS_DEADLINE
+    lea  rdx, [rel dl_large]
+    mov  eax, SYS_SCHED_SET
+    int  0x80
+    cmp  rax, ERR_SCHED_CAPACITY
+    jne  fail
+    mov  rdi, [sleeper_b]
+    mov  esi, SCHED_CLASS_DEADLINE
+    lea  rdx, [rel dl_invalid]
+    mov  eax, SYS_SCHED_SET
+    int  0x80
+    cmp  rax, -1
+    jne  fail
+
+    mov  rdi, [sleeper_a]
+    mov  esi, SCHED_CLASS_BEST_EFFORT
+    xor  edx, edx
+    mov  eax, SYS_SCHED_SET
+    int  0x80
+    test rax, rax
+    jnz  fail
+    mov  rdi, [sleeper_b]
+    mov  esi, SCHED_CLASS_DEADLINE
+    lea  rdx, [rel dl_fits]
+    mov  eax, SYS_SCHED_SET
+    int  0x80
+    test rax, rax
+    jnz  fail
+
+    mov  rdi, [sleeper_b]
+    lea  rsi, [rel proc_info]
+    mov  edx, PROC_INFO_DEADLINE_SIZE
+    mov  eax, SYS_PROC_INFO
+    int  0x80
+    test rax, rax
+    jnz  fail
+    cmp  qword [proc_info + PROC_INFO_CLASS * 8], SCHED_CLASS_DEADLINE
+    jne  fail
+    mov  rax, [dl_fits]
+    cmp  [proc_info + PROC_INFO_DL_RUNTIME * 8], rax
+    jne  fail
+    mov  rax, [dl_fits + 8]
+    cmp  [proc_info + PROC_INFO_DL_PERIOD * 8], rax
+    jne  fail
+
+    mov  rdi, [sleeper_a]
+    call reap
+    mov  rdi, [sleeper_b]
+    call reap
+
+    lea  rdi, [rel msg_deadline_ok]
+    mov  esi, msg_deadline_ok_end - msg_deadline_ok
+    xor  eax, eax
+    int  0x80
+
+    ; Timers: relative and absolute sleeps last at least as asked.
+    mov  eax, SYS_CLOCK_NOW
+    int  0x80
+    mov  [time_mark], rax
+    mov  edi, SLEEP_MS * NS_PER_MS
+    xor  esi, esi
+    mov  eax, SYS_NANOSLEEP
+    int  0x80
+    test rax, rax
+    jnz  fail
+    mov  eax, SYS_CLOCK_NOW
+    int  0x80
+    sub  rax, [time_mark]
+    cmp  rax, SLEEP_MS * NS_PER_MS
+    jb   fail
+
+    mov  eax, SYS_CLOCK_NOW
+    int  0x80
+    add  rax, SLEEP_MS * NS_PER_MS
+    mov  [time_mark], rax
+    mov  rdi, rax
+    mov  esi, SLEEP_ABSOLUTE
+    mov  eax, SYS_NANOSLEEP
+    int  0x80
+    test rax, rax
+    jnz  fail
+    mov  eax, SYS_CLOCK_NOW
+    int  0x80
+    cmp  rax, [time_mark]
+    jb   fail
+
+    ; A periodic handle fires again after its first expiry until closed; a
+    ; one-shot handle is spent after one.
+    mov  eax, SYS_CLOCK_NOW
+    int  0x80
+    lea  rdi, [rax + TIMER_FIRST_MS * NS_PER_MS]
+    mov  esi, TIMER_PERIOD_MS * NS_PER_MS
+    mov  eax, SYS_TIMER_CREATE
+    int  0x80
+    test rax, rax
+    js   fail
+    mov  [timer_handle], rax
+    mov  rdi, rax
+    mov  eax, SYS_TIMER_WAIT
+    int  0x80
+    test rax, rax
+    jz   fail
+    js   fail
+    mov  rdi, [timer_handle]
+    mov  eax, SYS_TIMER_WAIT
+    int  0x80
+    test rax, rax
+    jz   fail
+    js   fail
+    mov  rdi, [timer_handle]
+    mov  eax, SYS_TIMER_CLOSE
+    int  0x80
+    test rax, rax
+    jnz  fail
+    mov  rdi, [timer_handle]
+    mov  eax, SYS_TIMER_WAIT
+    int  0x80
+    cmp  rax, -1
+    jne  fail
+
+    mov  eax, SYS_CLOCK_NOW
+    int  0x80
+    lea  rdi, [rax + TIMER_FIRST_MS * NS_PER_MS]
+    xor  esi, esi
+    mov  eax, SYS_TIMER_CREATE
+    int  0x80
+    test rax, rax
+    js   fail
+    mov  [timer_handle], rax
+    mov  rdi, rax
+    mov  eax, SYS_TIMER_WAIT
+    int  0x80
+    cmp  rax, 1
+    jne  fail
+    mov  rdi, [timer_handle]
+    mov  eax, SYS_TIMER_WAIT
+    int  0x80
+    cmp  rax, -1
+    jne  fail
+    mov  rdi, [timer_handle]
+    mov  eax, SYS_TIMER_CLOSE
+    int  0x80
+    test rax, rax
+    jnz  fail
+
+    ; A wait with a deadline gives up before the sleeping child exits.
+    call fork_sleeper
+    mov  [sleeper_a], rax
+    mov  eax, SYS_CLOCK_NOW
+    int  0x80
+    add  rax, WAIT_TIMEOUT_MS * NS_PER_MS
+    mov  [time_mark], rax
+    mov  r10, rax
+    mov  rdi, [sleeper_a]
+    lea  rsi, [rel wait_status]
+    xor  edx, edx
+    mov  eax, SYS_WAIT_UNTIL
+    int  0x80
+    cmp  rax, ERR_TIMED_OUT
+    jne  fail
+    mov  eax, SYS_CLOCK_NOW
+    int  0x80
+    cmp  rax, [time_mark]
+    jb   fail
+    mov  rdi, [sleeper_a]
+    call reap
+
+    lea  rdi, [rel msg_timers_ok]
+    mov  esi, msg_timers_ok_end - msg_timers_ok
+    xor  eax, eax
+    int  0x80
+
+    ; sysinfo: the probe has run in user and kernel mode and is runnable
+    ; itself; staying busy across a load sample raises the 1 minute load.
+    lea  rdi, [rel sysinfo]
+    mov  esi, SYSINFO_SIZE - 8
+    mov  eax, SYS_SYSINFO
+    int  0x80
+    cmp  rax, -1
+    jne  fail
+    call read_sysinfo
+    cmp  qword [sysinfo + SYSINFO_UPTIME * 8], 0
+    je   fail
+    cmp  qword [sysinfo + SYSINFO_USER * 8], 0
+    je   fail
+    cmp  qword [sysinfo + SYSINFO_KERNEL * 8], 0
+    je   fail
+    cmp  qword [sysinfo + SYSINFO_RUNNABLE * 8], 1
+    jb   fail
+
+    mov  eax, SYS_CLOCK_NOW
+    int  0x80
+    mov  rcx, LOAD_TIMEOUT_MS * NS_PER_MS
+    add  rax, rcx
+    mov  [time_mark], rax
+load_wait:
+    call read_sysinfo
+    cmp  qword [sysinfo + SYSINFO_LOAD1 * 8], 0
+    jne  load_done
+    mov  eax, SYS_CLOCK_NOW
+    int  0x80
+    cmp  rax, [time_mark]
+    jb   load_wait
+    jmp  fail
+load_done:
+
+    lea  rdi, [rel msg_sysinfo_ok]
+    mov  esi, msg_sysinfo_ok_end - msg_sysinfo_ok
+    xor  eax, eax
+    int  0x80
+
+    ; clone: unknown flags and a misaligned stack are refused. A detached
+    ; thread gets its argument and its own FS base, clears its join word
+    ; when it exits, and cannot be waited for.
+    lea  rdi, [rel clone_bad_flags]
+    mov  esi, CLONE_ARGS_SIZE
+    mov  eax, SYS_CLONE
+    int  0x80
+    cmp  rax, -1
+    jne  fail
+    lea  rdi, [rel clone_bad_stack]
+    mov  esi, CLONE_ARGS_SIZE
+    mov  eax, SYS_CLONE
+    int  0x80
+    cmp  rax, -1
+    jne  fail
+
+    lea  rdi, [rel clone_detached]
+    mov  esi, CLONE_ARGS_SIZE
+    mov  eax, SYS_CLONE
+    int  0x80
+    test rax, rax
+    js   fail
+    mov  [child_tid], rax
+
+    mov  eax, SYS_CLOCK_NOW
+    int  0x80
+    add  rax, JOIN_TIMEOUT_MS * NS_PER_MS
+    mov  [time_mark], rax
+join_wait:
+    cmp  qword [detached_join], 0
+    je   join_done
+    mov  edi, NS_PER_MS
+    xor  esi, esi
+    mov  eax, SYS_NANOSLEEP
+    int  0x80
+    mov  eax, SYS_CLOCK_NOW
+    int  0x80
+    cmp  rax, [time_mark]
+    jb   join_wait
+    jmp  fail
+join_done:
+    cmp  qword [detached_arg], CLONE_ARG
+    jne  fail
+    lea  rax, [rel clone_tls]
+    cmp  [detached_fs], rax
+    jne  fail
+    mov  rdi, [child_tid]
+    lea  rsi, [rel wait_status]
+    xor  edx, edx
+    mov  eax, SYS_WAIT
+    int  0x80
+    cmp  rax, -1
+    jne  fail
+
+    lea  rdi, [rel msg_clone_ok]
+    mov  esi, msg_clone_ok_end - msg_clone_ok
+    xor  eax, eax
+    int  0x80
+
+    ; Startup stack: argc and argv as launched, envp, then an aux vector
+    ; carrying each baseline key below with a sane value.
+    mov  rbx, [startup_sp]
+    cmp  qword [rbx], 1
+    jne  fail
+    cmp  qword [rbx + 16], 0
+    jne  fail
+    mov  rdi, [rbx + 8]
+    call check_app_name
+    add  rbx, 24
+auxv_skip_envp:
+    mov  rax, [rbx]
+    add  rbx, 8
+    test rax, rax
+    jnz  auxv_skip_envp
+
+    xor  r12d, r12d
+auxv_next:
+    mov  rax, [rbx]
+    mov  rcx, [rbx + 8]
+    add  rbx, 16
+    test rax, rax
+    jz   auxv_end
+    cmp  rax, AT_PAGESZ
+    je   auxv_pagesz
+    cmp  rax, AT_ENTRY
+    je   auxv_entry
+    cmp  rax, AT_UID
+    je   auxv_uid
+    cmp  rax, AT_HWCAP
+    je   auxv_nonzero
+    cmp  rax, AT_CLKTCK
+    je   auxv_clktck
+    cmp  rax, AT_RANDOM
+    je   auxv_nonzero
+    cmp  rax, AT_EXECFN
+    je   auxv_execfn
+    jmp  auxv_next
+auxv_pagesz:
+    cmp  rcx, PAGE_SIZE
+    jne  fail
+    jmp  auxv_seen
+auxv_entry:
+    lea  rdx, [rel _start]
+    cmp  rcx, rdx
+    jne  fail
+    jmp  auxv_seen
+auxv_uid:
+    test rcx, rcx
+    jnz  fail
+    jmp  auxv_seen
+auxv_nonzero:
+    test rcx, rcx
+    jz   fail
+    jmp  auxv_seen
+auxv_clktck:
+    cmp  rcx, CLKTCK
+    jne  fail
+    jmp  auxv_seen
+auxv_execfn:
+    mov  rdi, rcx
+    call check_app_name
+    mov  eax, AT_EXECFN
+auxv_seen:
+    bts  r12d, eax
+    jmp  auxv_next
+auxv_end:
+    cmp  r12d, AUXV_REQUIRED
+    jne  fail
+
+    lea  rdi, [rel msg_auxv_ok]
+    mov  esi, msg_auxv_ok_end - msg_auxv_ok
+    xor  eax, eax
+    int  0x80
+
+    ; Nice: two best-effort children spin while this task sleeps; the one
+    ; at nice 5 gets the smaller share of CPU time. Nice values outside
+    ; -20..19 are refused.
+    mov  eax, SYS_CLOCK_NOW
+    int  0x80
+    mov  [time_mark], rax
+    add  rax, NICE_SPIN_MS * NS_PER_MS
+    mov  [spin_deadline], rax
+    call fork_spinner
+    mov  [sleeper_a], rax
+    call fork_spinner
+    mov  [sleeper_b], rax
+
+    mov  rdi, [sleeper_b]
+    mov  esi, NICE_OUT_OF_RANGE
+    mov  eax, SYS_SCHED_NICE
+    int  0x80
+    cmp  rax, -1
+    jne  fail
+    mov  rdi, [sleeper_b]
+    mov  esi, NICE_LOW
+    mov  eax, SYS_SCHED_NICE
+    int  0x80
+    test rax, rax
+    jnz  fail
+
+    mov  rdi, [time_mark]
+    add  rdi, NICE_SAMPLE_MS * NS_PER_MS
+    mov  esi, SLEEP_ABSOLUTE
+    mov  eax, SYS_NANOSLEEP
+    int  0x80
+    test rax, rax
+    jnz  fail
+
+    mov  rdi, [sleeper_b]
+    lea  rsi, [rel proc_info]
+    mov  edx, PROC_INFO_SCHED_SIZE
+    mov  eax, SYS_PROC_INFO
+    int  0x80
+    test rax, rax
+    jnz  fail
+    cmp  qword [proc_info + PROC_INFO_NICE * 8], NICE_LOW
+    jne  fail
+    mov  rbx, [proc_info + PROC_INFO_CPU_TIME * 8]
+    test rbx, rbx
+    jz   fail
+
+    mov  rdi, [sleeper_a]
+    lea  rsi, [rel proc_info]
+    mov  edx, PROC_INFO_SCHED_SIZE
+    mov  eax, SYS_PROC_INFO
+    int  0x80
+    test rax, rax
+    jnz  fail
+    cmp  qword [proc_info + PROC_INFO_NICE * 8], 0
+    jne  fail
+    cmp  [proc_info + PROC_INFO_CPU_TIME * 8], rbx
+    jbe  fail
+
+    mov  rdi, [sleeper_a]
+    call reap
+    mov  rdi, [sleeper_b]
+    call reap
+
+    lea  rdi, [rel msg_nice_ok]
+    mov  esi, msg_nice_ok_end - msg_nice_ok
+    xor  eax, eax
+    int  0x80
+
+    ; Kernel workers: each worker slot is its own parent, runs in the
+    ; critical class on the boot CPU and takes nice changes like any task;
+    ; the slot past the last worker is no task.
+    mov  ebx, MAX_TASKS
+worker_next:
+    mov  rdi, rbx
+    lea  rsi, [rel proc_info]
+    mov  edx, PROC_INFO_AFFINITY_SIZE
+    mov  eax, SYS_PROC_INFO
+    int  0x80
+    test rax, rax
+    jnz  fail
+    cmp  [proc_info], rbx
+    jne  fail
+    cmp  [proc_info + PROC_INFO_PARENT * 8], rbx
+    jne  fail
+    cmp  qword [proc_info + PROC_INFO_CLASS * 8], SCHED_CLASS_CRITICAL
+    jne  fail
+    cmp  qword [proc_info + PROC_INFO_AFFINITY * 8], BOOT_CPU_MASK
+    jne  fail
+    mov  rdi, rbx
+    xor  esi, esi
+    mov  eax, SYS_SCHED_NICE
+    int  0x80
+    test rax, rax
+    jnz  fail
+    inc  ebx
+    cmp  ebx, MAX_TASKS + KWORKERS
+    jb   worker_next
+
+    mov  rdi, rbx
+    lea  rsi, [rel proc_info]
+    mov  edx, PROC_INFO_AFFINITY_SIZE
+    mov  eax, SYS_PROC_INFO
+    int  0x80
+    cmp  rax, -1
+    jne  fail
+
+    lea  rdi, [rel msg_workers_ok]
+    mov  esi, msg_workers_ok_end - msg_workers_ok
+    xor  eax, eax
+    int  0x80
+
+    ; Task ids: a reaped child's slot goes to the next fork under a new id,
+    ; and the old id no longer names a task. The probe and its children fit
+    ; in MAX_TASKS slots, and the fork after that is refused.
+    mov  qword [spin_deadline], 0
+    call fork_spinner
+    mov  [sleeper_a], rax
+    mov  rdi, rax
+    call reap
+    call fork_spinner
+    mov  [sleeper_b], rax
+    mov  rdi, rax
+    call reap
+    mov  rax, [sleeper_a]
+    cmp  rax, [sleeper_b]
+    je   fail
+    xor  rax, [sleeper_b]
+    test rax, TASK_SLOT_MASK
+    jnz  fail
+
+    mov  rdi, [sleeper_a]
+    lea  rsi, [rel proc_info]
+    mov  edx, PROC_INFO_AFFINITY_SIZE
+    mov  eax, SYS_PROC_INFO
+    int  0x80
+    cmp  rax, -1
+    jne  fail
+    mov  rdi, [sleeper_a]
+    lea  rsi, [rel wait_status]
+    xor  edx, edx
+    mov  eax, SYS_WAIT
+    int  0x80
+    cmp  rax, -1
+    jne  fail
+
+    xor  ebx, ebx
+ids_fill:
+    mov  eax, SYS_FORK
+    int  0x80
+    test rax, rax
+    js   ids_full
+    jz   sleeper_child
+    lea  rcx, [rel ids_children]
+    mov  [rcx + rbx * 8], rax
+    inc  ebx
+    cmp  ebx, MAX_TASKS
+    jb   ids_fill
+    jmp  fail
+ids_full:
+    cmp  ebx, MAX_TASKS - 1
+    jne  fail
+ids_reap:
+    dec  ebx
+    lea  rcx, [rel ids_children]
+    mov  rdi, [rcx + rbx * 8]
+    call reap
+    test ebx, ebx
+    jnz  ids_reap
+
+    lea  rdi, [rel msg_ids_ok]
+    mov  esi, msg_ids_ok_end - msg_ids_ok
+    xor  eax, eax
+    int  0x80
+
+    ; Affinity: a child inherits the mask its parent had at fork, and a
+    ; mask must name an online CPU. The first child's parent word is this
+    ; task's own id.
+    call fork_sleeper
+    mov  [sleeper_a], rax
+    mov  rdi, rax
+    lea  rsi, [rel proc_info]
+    mov  edx, PROC_INFO_AFFINITY_SIZE
+    mov  eax, SYS_PROC_INFO
+    int  0x80
+    test rax, rax
+    jnz  fail
+    cmp  qword [proc_info + PROC_INFO_AFFINITY * 8], -1
+    jne  fail
+    mov  rbx, [proc_info + PROC_INFO_PARENT * 8]
+
+    mov  rdi, rbx
+    mov  esi, SCHED_SET_AFFINITY
+    mov  edx, BOOT_CPU_MASK
+    mov  eax, SYS_SCHED_SET
+    int  0x80
+    test rax, rax
+    jnz  fail
+    call fork_sleeper
+    mov  [sleeper_b], rax
+    mov  rdi, rax
+    lea  rsi, [rel proc_info]
+    mov  edx, PROC_INFO_AFFINITY_SIZE
+    mov  eax, SYS_PROC_INFO
+    int  0x80
+    test rax, rax
+    jnz  fail
+    cmp  qword [proc_info + PROC_INFO_AFFINITY * 8], BOOT_CPU_MASK
+    jne  fail
+
+    mov  rdi, [sleeper_b]
+    mov  esi, SCHED_SET_AFFINITY
+    mov  edx, OFFLINE_CPU_MASK
+    mov  eax, SYS_SCHED_SET
+    int  0x80
+    cmp  rax, -1
+    jne  fail
+    mov  rdi, [sleeper_b]
+    mov  esi, SCHED_SET_AFFINITY
+    xor  edx, edx
+    mov  eax, SYS_SCHED_SET
+    int  0x80
+    cmp  rax, -1
+    jne  fail
+
+    mov  rdi, rbx
+    mov  esi, SCHED_SET_AFFINITY
+    mov  rdx, -1
+    mov  eax, SYS_SCHED_SET
+    int  0x80
+    test rax, rax
+    jnz  fail
+    mov  rdi, rbx
+    lea  rsi, [rel proc_info]
+    mov  edx, PROC_INFO_AFFINITY_SIZE
+    mov  eax, SYS_PROC_INFO
+    int  0x80
+    test rax, rax
+    jnz  fail
+    cmp  qword [proc_info + PROC_INFO_AFFINITY * 8], -1
+    jne  fail
+
+    mov  rdi, [sleeper_a]
+    call reap
+    mov  rdi, [sleeper_b]
+    call reap
+
+    lea  rdi, [rel msg_affinity_ok]
+    mov  esi, msg_affinity_ok_end - msg_affinity_ok
+    xor  eax, eax
+    int  0x80
+
+    lea  rdi, [rel msg_done]
+    mov  esi, msg_done_end - msg_done
+    xor  eax, eax
+    int  0x80
+
+    mov  eax, SYS_THREAD_EXIT
+    int  0x80
+
+hang:
+    hlt
+    jmp  hang
+
+; Fork a child that sleeps for SLEEPER_MS and exits. Returns its tid.
+fork_sleeper:
+    mov  eax, SYS_FORK
+    int  0x80
+    test rax, rax
+    js   fail
+    jz   sleeper_child
+    ret
+
+sleeper_child:
+    mov  edi, SLEEPER_MS * NS_PER_MS
+    xor  esi, esi
+    mov  eax, SYS_NANOSLEEP
+    int  0x80
+    mov  eax, SYS_THREAD_EXIT
+    int  0x80
+    jmp  hang
+
+; Fork a child that busy-polls the clock until spin_deadline and exits.
+; Returns its tid.
+fork_spinner:
+    mov  eax, SYS_FORK
+    int  0x80
+    test rax, rax
+    js   fail
+    jz   spinner_child
+    ret
+
+spinner_child:
+    mov  eax, SYS_CLOCK_NOW
+    int  0x80
+    cmp  rax, [spin_deadline]
+    jb   spinner_child
+    mov  eax, SYS_THREAD_EXIT
+    int  0x80
+    jmp  hang
+
+; Wait for the child in rdi, which must exit with status 0.
+reap:
+    mov  [child_tid], rdi
+    lea  rsi, [rel wait_status]
+    xor  edx, edx
+    mov  eax, SYS_WAIT
+    int  0x80
+    cmp  rax, qword [child_tid]
+    jne  fail
+    cmp  qword [wait_status], 0
+    jne  fail
+    ret
+
+read_sysinfo:
+    lea  rdi, [rel sysinfo]
+    mov  esi, SYSINFO_SIZE
+    mov  eax, SYS_SYSINFO
+    int  0x80
+    test rax, rax
+    jnz  fail
+    ret
+
+spin_entry:
+    inc  qword [spin_count]
+    cmp  qword [spin_stop], 0
+    je   spin_entry
+    mov  eax, SYS_THREAD_EXIT
+    int  0x80
+    jmp  hang
+
+; The NUL-terminated string at rdi must be the launch name.
+check_app_name:
+    lea  rsi, [rel app_name]
+check_app_name_byte:
+    mov  al, [rdi]
+    cmp  al, [rsi]
+    jne  fail
+    inc  rdi
+    inc  rsi
+    test al, al
+    jnz  check_app_name_byte
+    ret
+
+; The detached thread: record the argument and the word at FS base 0.
+detached_entry:
+    mov  [detached_arg], rdi
+    xor  edx, edx
+    mov  rax, [fs:rdx]
+    mov  [detached_fs], rax
+    mov  eax, SYS_THREAD_EXIT
+    int  0x80
+    jmp  hang
+
+fail:
+    lea  rdi, [rel msg_fail]
+    mov  esi, msg_fail_end - msg_fail
+    xor  eax, eax
+    int  0x80
+
+    mov  edi, 0x33
+    mov  eax, SYS_QEMU_EXIT
+    int  0x80
+    jmp  hang
+
+section .data
+msg_start:       db "X1SCHED: start", 10
+msg_start_end:
+msg_spawn_ok:    db "X1SCHED: spawn ok", 10
+msg_spawn_ok_end:
+msg_preempt_ok:  db "X1SCHED: preempt ok", 10
+msg_preempt_ok_end:
+msg_deadline_ok: db "X1SCHED: deadline ok", 10
+msg_deadline_ok_end:
+msg_timers_ok:   db "X1SCHED: timers ok", 10
+msg_timers_ok_end:
+msg_sysinfo_ok:  db "X1SCHED: sysinfo ok", 10
+msg_sysinfo_ok_end:
+msg_clone_ok:    db "X1SCHED: clone ok", 10
+msg_clone_ok_end:
+msg_auxv_ok:     db "X1SCHED: auxv ok", 10
+msg_auxv_ok_end:
+msg_nice_ok:     db "X1SCHED: nice ok", 10
+msg_nice_ok_end:
+msg_workers_ok:  db "X1SCHED: workers ok", 10
+msg_workers_ok_end:
+msg_ids_ok:      db "X1SCHED: ids ok", 10
+msg_ids_ok_end:
+msg_affinity_ok: db "X1SCHED: affinity ok", 10
+msg_affinity_ok_end:
+msg_done:        db "X1SCHED: done", 10
+msg_done_end:
+msg_fail:        db "X1SCHED: fail", 10
+msg_fail_end:
+app_name:        db "x1-sched-probe", 0
+
+path_hello:      db "/bin/hello"
+path_hello_end:
+path_missing:    db "/bin/missing"
+path_missing_end:
+arg_hello:       db "hello", 0
+arg_probe:       db "probe", 0
+
+align 8
+spawn_argv:      dq arg_hello, arg_probe
+
+; Spawn argument blocks: path, path_len, argv, argc, envp, envc, then the
+; isolation config (domain, flags, limits, hard and soft page limits).
+spawn_hello:
+    dq path_hello, path_hello_end - path_hello, spawn_argv, 2, 0, 0
+    dq 0, 0, 0, 0, 0
+spawn_missing:
+    dq path_missing, path_missing_end - path_missing, spawn_argv, 2, 0, 0
+    dq 0, 0, 0, 0, 0
+spawn_tiny:
+    dq path_hello, path_hello_end - path_hello, spawn_argv, 2, 0, 0
+    dq 0, 0, 0, 1, 0
+
+; Clone arguments: flags, entry, argument, stack top, TLS, join word.
+clone_args:
+    dq CLONE_VM, spin_entry, 0, clone_stack_top, 0, clone_join
+clone_args_end:
+clone_join:      dq 1
+clone_bad_flags:
+    dq CLONE_VM | CLONE_UNKNOWN, spin_entry, 0, clone_stack_top, 0, 0
+clone_bad_stack:
+    dq CLONE_VM, spin_entry, 0, clone_stack_top - 8, 0, 0
+clone_detached:
+    dq CLONE_VM | CLONE_SETTLS | CLONE_DETACHED, detached_entry, CLONE_ARG
+    dq clone_stack_top, clone_tls, detached_join
+detached_join:   dq 1
+; The detached thread's TLS: its first word holds its own address.
+clone_tls:       dq clone_tls
+
+; Deadline reservations: runtime and period in microseconds.
+dl_small:        dq 10000, 100000
+dl_large:        dq 90000, 100000
+dl_invalid:      dq 20000, 10000
+dl_fits:         dq 80000, 100000
+
+section .bss
+child_tid:       resq 1
+wait_status:     resq 1
+spin_deadline:   resq 1
+spin_count:      resq 1
+spin_stop:       resq 1
+sleeper_a:       resq 1
+sleeper_b:       resq 1
+proc_info:       resb PROC_INFO_AFFINITY_SIZE
+time_mark:       resq 1
+timer_handle:    resq 1
+sysinfo:         resb SYSINFO_SIZE
+startup_sp:      resq 1
+detached_arg:    resq 1
+detached_fs:     resq 1
+ids_children:    resq MAX_TASKS
+alignb 16
+clone_stack:     resb 512
+clone_stack_top:
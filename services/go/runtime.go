@@ -48,7 +48,8 @@ const (
 )
 
 const stateUnset = 0xFF
-const taskUnset = 0xFF
+// Kernel task ids are generation-tagged and never reach the top bit.
+const taskUnset = ^uintptr(0)
 
 const (
 	requiredOptional = iota
@@ -97,7 +98,7 @@ var (
 	shellComplete   uintptr
 
 	serviceStates = [serviceCount]byte{stateUnset, stateUnset, stateUnset, stateUnset}
-	serviceTasks  = [serviceCount]byte{taskUnset, taskUnset, taskUnset, taskUnset}
+	serviceTasks  = [serviceCount]uintptr{taskUnset, taskUnset, taskUnset, taskUnset}
 
 	serviceResults  [serviceCount]byte
 	serviceRestarts [serviceCount]byte
@@ -176,10 +177,17 @@ var serviceManifest = [...]serviceSpec{
 
 var restartBackoffYields = [...]uintptr{1, 2, 4}
 
+var (
+	execHelloPath = [...]byte{'/', 'b', 'i', 'n', '/', 'h', 'e', 'l', 'l', 'o'}
+	execHelloArg0 = [...]byte{'h', 'e', 'l', 'l', 'o', 0}
+	execHelloArg1 = [...]byte{'o', 'k', 0}
+)
+
 var (
 	msgGoInitStart       = [...]byte{'G', 'O', 'I', 'N', 'I', 'T', ':', ' ', 's', 't', 'a', 'r', 't', '\n'}
 	msgGoInitBootstrap   = [...]byte{'G', 'O', 'I', 'N', 'I', 'T', ':', ' ', 'b', 'o', 'o', 't', 's', 't', 'r', 'a', 'p', '\n'}
 	msgGoInitSpawn       = [...]byte{'G', 'O', 'I', 'N', 'I', 'T', ':', ' ', 's', 'v', 'c', 'm', 'g', 'r', ' ', 'u', 'p', '\n'}
+	msgGoInitExec        = [...]byte{'G', 'O', 'I', 'N', 'I', 'T', ':', ' ', 's', 'p', 'a', 'w', 'n', ' ', 'o', 'k', '\n'}
 	msgGoInitOperational = [...]byte{'G', 'O', 'I', 'N', 'I', 'T', ':', ' ', 'o', 'p', 'e', 'r', 'a', 't', 'i', 'o', 'n', 'a', 'l', '\n'}
 	msgGoInitResult      = [...]byte{'G', 'O', 'I', 'N', 'I', 'T', ':', ' ', 'r', 'e', 's', 'u', 'l', 't', ' '}
 	msgGoInitReady       = [...]byte{'G', 'O', 'I', 'N', 'I', 'T', ':', ' ', 'r', 'e', 'a', 'd', 'y', '\n'}
@@ -199,6 +207,7 @@ var (
 	msgServicePrefix = [...]byte{'S', 'V', 'C', ':', ' '}
 	msgProcPrefix    = [...]byte{'P', 'R', 'O', 'C', ':', ' '}
 	msgTaskPrefix    = [...]byte{'T', 'A', 'S', 'K', ':', ' '}
+	msgSysPrefix     = [...]byte{'S', 'Y', 'S', ':', ' '}
 	msgMetricStarts  = [...]byte{'s', '='}
 	msgMetricRest    = [...]byte{'r', '='}
 	msgMetricFail    = [...]byte{'f', '='}
@@ -218,6 +227,11 @@ var (
 	msgMetricCap     = [...]byte{'c', 'a', 'p', '='}
 	msgMetricFd      = [...]byte{'f', 'd', '='}
 	msgMetricSock    = [...]byte{'s', 'o', 'c', 'k', '='}
+	msgMetricUser    = [...]byte{'u', 's', 'r', '='}
+	msgMetricSys     = [...]byte{'s', 'y', 's', '='}
+	msgMetricUp      = [...]byte{'u', 'p', '='}
+	msgMetricIdle    = [...]byte{'i', 'd', 'l', 'e', '='}
+	msgMetricLoad    = [...]byte{'l', 'o', 'a', 'd', '='}
 	msgMetricSvc     = [...]byte{'s', 'v', 'c', '='}
 	msgMetricRole    = [...]byte{'r', 'o', 'l', 'e', '='}
 	msgMetricPhase   = [...]byte{'p', 'h', 'a', 's', 'e', '='}
@@ -227,6 +241,7 @@ var (
 	msgMetricResult  = [...]byte{'r', 'e', 's', '='}
 	msgSpace         = [...]byte{' '}
 	msgComma         = [...]byte{','}
+	msgDot           = [...]byte{'.'}
 	msgSlash         = [...]byte{'/'}
 	msgNewline       = [...]byte{'\n'}
 
@@ -244,6 +259,7 @@ var (
 	msgTaskStateDead    = [...]byte{'d', 'e', 'a', 'd'}
 	msgClassCritical    = [...]byte{'c', 'r', 'i', 't', 'i', 'c', 'a', 'l'}
 	msgClassBestEffort  = [...]byte{'b', 'e', 's', 't', '-', 'e', 'f', 'f', 'o', 'r', 't'}
+	msgClassDeadline    = [...]byte{'d', 'e', 'a', 'd', 'l', 'i', 'n', 'e'}
 	msgNeedRequired     = [...]byte{'r', 'e', 'q', 'u', 'i', 'r', 'e', 'd'}
 	msgNeedOptional     = [...]byte{'o', 'p', 't', 'i', 'o', 'n', 'a', 'l'}
 	msgPolicyNever      = [...]byte{'n', 'e', 'v', 'e', 'r'}
@@ -280,6 +296,10 @@ func bootRuntime() {
 
 	log(msgGoInitBootstrap[:])
 
+	if !execProbe() {
+		fail(msgGoInitErr[:])
+	}
+
 	var order [serviceCount]byte
 	if !buildStartPlan(&order) {
 		fail(msgGoInitErr[:])
@@ -296,6 +316,30 @@ func bootRuntime() {
 	fail(msgGoInitErr[:])
 }
 
+// execProbe starts /bin/hello from its path in a task of its own and reaps
+// it before any service runs, so the manager's wait-any never sees it.
+func execProbe() bool {
+	argv := [...]*byte{&execHelloArg0[0], &execHelloArg1[0]}
+	args := spawnArgs{
+		Path:     &execHelloPath[0],
+		PathLen:  uintptr(len(execHelloPath)),
+		Argv:     &argv[0],
+		Argc:     uintptr(len(argv)),
+		DomainID: 5,
+	}
+	tid := sysSpawn(&args)
+	if tid == sysErr || tid == sysErr-1 {
+		return false
+	}
+
+	var status uintptr
+	if sysWait(tid, &status, 0) != tid || status != 0 {
+		return false
+	}
+	log(msgGoInitExec[:])
+	return true
+}
+
 func serviceManagerMain(order [serviceCount]byte) byte {
 	log(msgSvcMgrStart[:])
 
@@ -526,7 +570,7 @@ func launchService(serviceID uintptr) bool {
 			setServiceState(serviceID, stateFailed)
 			serviceTasks[serviceID] = taskUnset
 		} else {
-			serviceTasks[serviceID] = byte(tid)
+			serviceTasks[serviceID] = tid
 			if !applyServiceIsolation(serviceID, tid) {
 				fail(msgSvcMgrErr[:])
 			}
@@ -623,7 +667,7 @@ func verifyReapedServiceCleanup(serviceID uintptr, tid uintptr) bool {
 func serviceByTask(tid uintptr) uintptr {
 	var serviceID uintptr
 	for serviceID = 0; serviceID < serviceCount; serviceID++ {
-		if serviceTasks[serviceID] == byte(tid) {
+		if serviceTasks[serviceID] == tid {
 			return serviceID
 		}
 	}
@@ -879,6 +923,9 @@ func schedClassLabel(class uintptr) []byte {
 	if class == schedClassCritical {
 		return msgClassCritical[:]
 	}
+	if class == schedClassDeadline {
+		return msgClassDeadline[:]
+	}
 	return msgClassBestEffort[:]
 }
 
@@ -1056,6 +1103,22 @@ func logUint(value uintptr) {
 	log(buf[i:])
 }
 
+// logLoad prints a 16.16 fixed-point load average with two decimals.
+func logLoad(load uint64) {
+	whole := load >> 16
+	hundredths := ((load&0xFFFF)*100 + 0x8000) >> 16
+	if hundredths == 100 {
+		whole++
+		hundredths = 0
+	}
+	logUint(uintptr(whole))
+	log(msgDot[:])
+	if hundredths < 10 {
+		logUint(0)
+	}
+	logUint(uintptr(hundredths))
+}
+
 func logServiceSnapshot(serviceID uintptr) {
 	log(msgProcPrefix[:])
 	log(serviceManifest[serviceID].name)
@@ -1090,7 +1153,7 @@ func logKernelTaskSnapshot(serviceID uintptr) bool {
 	}
 
 	var info taskInfo
-	if sysProcInfo(uintptr(tid), &info) == sysErr {
+	if sysProcInfo(tid, &info) == sysErr {
 		return false
 	}
 
@@ -1138,6 +1201,44 @@ func logKernelTaskSnapshot(serviceID uintptr) bool {
 	log(msgSpace[:])
 	log(msgMetricSock[:])
 	logUint(uintptr(info.SocketCount))
+	log(msgSpace[:])
+	log(msgMetricUser[:])
+	logUint(uintptr(info.UserTimeUS))
+	log(msgSpace[:])
+	log(msgMetricSys[:])
+	logUint(uintptr(info.SysTimeUS))
+	log(msgNewline[:])
+	return true
+}
+
+func logSystemSnapshot() bool {
+	var info systemInfo
+	if sysSysInfo(&info) == sysErr {
+		return false
+	}
+
+	log(msgSysPrefix[:])
+	log(msgMetricUp[:])
+	logUint(uintptr(info.UptimeUS))
+	log(msgSpace[:])
+	log(msgMetricIdle[:])
+	logUint(uintptr(info.IdleUS))
+	log(msgSpace[:])
+	log(msgMetricUser[:])
+	logUint(uintptr(info.UserUS))
+	log(msgSpace[:])
+	log(msgMetricSys[:])
+	logUint(uintptr(info.KernelUS))
+	log(msgSpace[:])
+	log(msgMetricLoad[:])
+	logLoad(info.Load1)
+	log(msgComma[:])
+	logLoad(info.Load5)
+	log(msgComma[:])
+	logLoad(info.Load15)
+	log(msgSpace[:])
+	log(msgMetricRun[:])
+	logUint(uintptr(info.Runnable))
 	log(msgNewline[:])
 	return true
 }
@@ -156,6 +156,10 @@ func diagServiceMain() {
 			markServiceFailed(serviceDiag)
 			fail(msgDiagSvcErr[:])
 		}
+		if !logSystemSnapshot() {
+			markServiceFailed(serviceDiag)
+			fail(msgDiagSvcErr[:])
+		}
 
 		if sysIpcSend(uintptr(req[1]), &replyOK[0], uintptr(len(replyOK))) == sysErr {
 			markServiceFailed(serviceDiag)
@@ -505,7 +509,7 @@ func loadServiceTaskInfo(serviceID uintptr, info *taskInfo) bool {
 	if serviceTasks[serviceID] == taskUnset {
 		return false
 	}
-	return sysProcInfo(uintptr(serviceTasks[serviceID]), info) != sysErr
+	return sysProcInfo(serviceTasks[serviceID], info) != sysErr
 }
 
 func prepareRuntimeListenAddr(port uint64, logConfig bool) (socketAddr, bool) {
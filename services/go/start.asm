@@ -101,6 +101,18 @@ main.sysSchedSetRaw:
     int  0x80
     ret
 
+global main.sysSchedSetDeadlineRaw
+main.sysSchedSetDeadlineRaw:
+    mov  eax, 29
+    int  0x80
+    ret
+
+global main.sysSchedSetAffinityRaw
+main.sysSchedSetAffinityRaw:
+    mov  eax, 29
+    int  0x80
+    ret
+
 global main.sysFsyncRaw
 main.sysFsyncRaw:
     mov  eax, 30
@@ -173,6 +185,75 @@ main.sysIsolationConfigRaw:
     int  0x80
     ret
 
+global main.sysSpawnRaw
+main.sysSpawnRaw:
+    mov  eax, 52
+    int  0x80
+    ret
+
+global main.sysSchedNiceRaw
+main.sysSchedNiceRaw:
+    mov  eax, 53
+    int  0x80
+    ret
+
+global main.sysClockNow
+main.sysClockNow:
+    mov  eax, 54
+    int  0x80
+    ret
+
+global main.sysNanosleep
+main.sysNanosleep:
+    mov  eax, 55
+    int  0x80
+    ret
+
+; The fourth argument arrives in rcx and goes to the kernel in r10.
+global main.sysIpcRecvUntil
+main.sysIpcRecvUntil:
+    mov  r10, rcx
+    mov  eax, 56
+    int  0x80
+    ret
+
+global main.sysWaitUntil
+main.sysWaitUntil:
+    mov  r10, rcx
+    mov  eax, 57
+    int  0x80
+    ret
+
+global main.sysPollUntil
+main.sysPollUntil:
+    mov  eax, 58
+    int  0x80
+    ret
+
+global main.sysTimerCreate
+main.sysTimerCreate:
+    mov  eax, 59
+    int  0x80
+    ret
+
+global main.sysTimerWait
+main.sysTimerWait:
+    mov  eax, 60
+    int  0x80
+    ret
+
+global main.sysTimerClose
+main.sysTimerClose:
+    mov  eax, 61
+    int  0x80
+    ret
+
+global main.sysSysInfoRaw
+main.sysSysInfoRaw:
+    mov  eax, 62
+    int  0x80
+    ret
+
 global main.sysSvcRegister
 main.sysSvcRegister:
     mov  eax, 11
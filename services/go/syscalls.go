@@ -13,8 +13,13 @@ const (
 const (
 	schedClassBestEffort = iota
 	schedClassCritical
+	schedClassDeadline
 )
 
+// schedSetAffinity is the sys_sched_set selector that takes a CPU mask in
+// place of a class.
+const schedSetAffinity = 0x80
+
 const (
 	openReadOnly = iota
 	openWriteOnly
@@ -33,6 +38,7 @@ const (
 const (
 	taskCapStorage = 1 << iota
 	taskCapNetwork
+	taskCapSpawn
 )
 
 type taskInfo struct {
@@ -53,6 +59,40 @@ type taskInfo struct {
 	CapabilityFlags uint64
 	FdCount         uint64
 	SocketCount     uint64
+	MemResident     uint64
+	MemReserved     uint64
+	MemHardLimit    uint64
+	MemSoftLimit    uint64
+	Nice            int64
+	CPUTimeUS       uint64
+	VRuntime        uint64
+	DlRuntimeUS     uint64
+	DlPeriodUS      uint64
+	DlOverruns      uint64
+	DlMisses        uint64
+	UserTimeUS      uint64
+	SysTimeUS       uint64
+	Affinity        uint64
+}
+
+// systemInfo is the sys_sysinfo result: times in microseconds, load
+// averages in 16.16 fixed point.
+type systemInfo struct {
+	UptimeUS uint64
+	IdleUS   uint64
+	UserUS   uint64
+	KernelUS uint64
+	Load1    uint64
+	Load5    uint64
+	Load15   uint64
+	Runnable uint64
+}
+
+// schedReservation is the sys_sched_set argument block for the deadline
+// class: runtime_us of CPU every period_us, both multiples of 10 ms.
+type schedReservation struct {
+	RuntimeUS uint64
+	PeriodUS  uint64
 }
 
 type socketAddr struct {
@@ -73,6 +113,22 @@ type isolationConfig struct {
 	Limits          uint64
 }
 
+// spawnArgs is the argument block of sys_spawn: the program path, argv and
+// envp arrays of NUL-terminated strings, then the child's isolation config.
+type spawnArgs struct {
+	Path            *byte
+	PathLen         uintptr
+	Argv            **byte
+	Argc            uintptr
+	Envp            **byte
+	Envc            uintptr
+	DomainID        uint64
+	CapabilityFlags uint64
+	Limits          uint64
+	MemHardPages    uint64
+	MemSoftPages    uint64
+}
+
 // sysDebugWrite invokes syscall 0 (sys_debug_write).
 func sysDebugWrite(buf *byte, n uintptr) uintptr
 
@@ -121,6 +177,14 @@ func sysIpcEndpointCreate() uintptr
 // sysSchedSetRaw invokes syscall 29 (sys_sched_set).
 func sysSchedSetRaw(tid uintptr, class uintptr) uintptr
 
+// sysSchedSetDeadlineRaw invokes syscall 29 (sys_sched_set) with a
+// reservation block.
+func sysSchedSetDeadlineRaw(tid uintptr, class uintptr, params *byte) uintptr
+
+// sysSchedSetAffinityRaw invokes syscall 29 (sys_sched_set) with the
+// affinity selector and a CPU mask.
+func sysSchedSetAffinityRaw(tid uintptr, selector uintptr, mask uintptr) uintptr
+
 // sysProcInfoRaw invokes syscall 28 (sys_proc_info).
 func sysProcInfoRaw(tid uintptr, buf *byte, n uintptr) uintptr
 
@@ -160,10 +224,65 @@ func sysNetRouteAddRaw(ifIndex uintptr, cfg *byte, n uintptr) uintptr
 // sysIsolationConfigRaw invokes syscall 41 (sys_isolation_config).
 func sysIsolationConfigRaw(tid uintptr, cfg *byte, n uintptr) uintptr
 
+// sysSpawnRaw invokes syscall 52 (sys_spawn).
+func sysSpawnRaw(args *byte, n uintptr) uintptr
+
+// sysSchedNiceRaw invokes syscall 53 (sys_sched_nice).
+func sysSchedNiceRaw(tid uintptr, nice uintptr) uintptr
+
+// sysClockNow invokes syscall 54 (sys_clock_now): nanoseconds on the
+// monotonic clock.
+func sysClockNow() uintptr
+
+// sysNanosleep invokes syscall 55 (sys_nanosleep).
+func sysNanosleep(ns uintptr, flags uintptr) uintptr
+
+// sysIpcRecvUntil invokes syscall 56 (sys_ipc_recv_until).
+func sysIpcRecvUntil(ep uintptr, buf *byte, cap uintptr, deadline uintptr) uintptr
+
+// sysWaitUntil invokes syscall 57 (sys_wait_until).
+func sysWaitUntil(pid uintptr, status *uintptr, options uintptr, deadline uintptr) uintptr
+
+// sysPollUntil invokes syscall 58 (sys_poll_until).
+func sysPollUntil(fds *byte, nfds uintptr, deadline uintptr) uintptr
+
+// sysTimerCreate invokes syscall 59 (sys_timer_create).
+func sysTimerCreate(deadline uintptr, period uintptr) uintptr
+
+// sysTimerWait invokes syscall 60 (sys_timer_wait).
+func sysTimerWait(handle uintptr) uintptr
+
+// sysTimerClose invokes syscall 61 (sys_timer_close).
+func sysTimerClose(handle uintptr) uintptr
+
+// sysSysInfoRaw invokes syscall 62 (sys_sysinfo).
+func sysSysInfoRaw(buf *byte, n uintptr) uintptr
+
 func sysSchedSet(tid uintptr, class uintptr) uintptr {
 	return sysSchedSetRaw(tid, class)
 }
 
+// sysSchedSetDeadline moves a task into the deadline class. It returns -2
+// when admission control refuses the reservation.
+func sysSchedSetDeadline(tid uintptr, res *schedReservation) uintptr {
+	return sysSchedSetDeadlineRaw(tid, schedClassDeadline, (*byte)(unsafe.Pointer(res)))
+}
+
+// sysSchedSetAffinity restricts a task to the CPUs in mask, one bit per CPU.
+func sysSchedSetAffinity(tid uintptr, mask uintptr) uintptr {
+	return sysSchedSetAffinityRaw(tid, schedSetAffinity, mask)
+}
+
+// sysSleep blocks the caller for at least ns nanoseconds.
+func sysSleep(ns uintptr) uintptr {
+	return sysNanosleep(ns, 0)
+}
+
+// sysSchedNice sets a best-effort task's nice level (-20..19).
+func sysSchedNice(tid uintptr, nice int) uintptr {
+	return sysSchedNiceRaw(tid, uintptr(nice))
+}
+
 func sysOpen(path *byte, flags uintptr, mode uintptr) uintptr {
 	return sysOpenRaw(path, flags, mode)
 }
@@ -188,6 +307,10 @@ func sysProcInfo(tid uintptr, info *taskInfo) uintptr {
 	)
 }
 
+func sysSysInfo(info *systemInfo) uintptr {
+	return sysSysInfoRaw((*byte)(unsafe.Pointer(info)), unsafe.Sizeof(*info))
+}
+
 func sysFsync(fd uintptr) uintptr {
 	return sysFsyncRaw(fd)
 }
@@ -264,6 +387,13 @@ func sysIsolationConfig(tid uintptr, cfg *isolationConfig) uintptr {
 	)
 }
 
+func sysSpawn(args *spawnArgs) uintptr {
+	return sysSpawnRaw(
+		(*byte)(unsafe.Pointer(args)),
+		unsafe.Sizeof(*args),
+	)
+}
+
 // sysSpawnEntry returns the user-mode trampoline for spawned threads.
 func sysSpawnEntry() uintptr
 
@@ -57,3 +57,43 @@ def test_loader_doc_and_kernel_contract(read_repo_file):
     assert "AT_ENTRY" in process_doc
     assert "fn elf_v1_validate_image" in kernel_src
     assert "ELF_V1_MAX_PHNUM" in kernel_src
+
+
+def test_loader_pie_doc(read_repo_file):
+    process_doc = read_repo_file("docs/abi/process_thread_model_v1.md")
+
+    assert "Position-independent images (`ET_DYN`)" in process_doc
+    assert "`R_X86_64_RELATIVE`" in process_doc
+
+
+def test_loader_pie_runtime(qemu_serial_compat_real):
+    """x1-mem-probe is a PIE: it loads above the ET_DYN base with its relocation applied."""
+    out = qemu_serial_compat_real.stdout
+
+    pos = -1
+    for marker in ["X1APP: launch x1-mem-probe", "ELF: sig ok", "X1MEM: start", "X1MEM: pie ok", "X1MEM: done"]:
+        pos = out.find(marker, pos + 1)
+        assert pos != -1, f"missing marker {marker!r}. Got:\n{out}"
+    assert "ELF: unsupported relocation" not in out
+    assert "X1MEM: fail" not in out
+
+
+def test_loader_segment_mapping_doc(read_repo_file):
+    process_doc = read_repo_file("docs/abi/process_thread_model_v1.md")
+
+    assert "### Segment mapping" in process_doc
+    assert "No fixed image ceiling applies." in process_doc
+
+
+def test_loader_maps_segments_runtime(qemu_serial_compat_real):
+    """Image segments keep their ELF permissions and .bss is demand-zero."""
+    out = qemu_serial_compat_real.stdout
+
+    pos = -1
+    for marker in ["X1MEM: tls ok", "X1MEM: image ok", "X1MEM: done"]:
+        pos = out.find(marker, pos + 1)
+        assert pos != -1, f"missing marker {marker!r}. Got:\n{out}"
+    # The store into .text and the call into .data: each kills its child.
+    segment = out[out.index("X1MEM: tls ok"):out.index("X1MEM: image ok")]
+    assert segment.count("USERPF: ") == 2, segment
+    assert "X1MEM: fail" not in out
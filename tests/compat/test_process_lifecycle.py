@@ -42,3 +42,40 @@ def test_exit_wait_status_contract():
     pid2, status2 = model.waitpid(-1)
     assert pid2 == -1
     assert status2 is None
+
+
+def test_startup_stack_auxv_keys_and_doc():
+    model = ProcessModel()
+    assert model.execve(["/bin/demo"], ["A=1"]) == 0
+    keys = [key for key, _ in model.startup_contract()["auxv"]]
+    for key in ["AT_RANDOM", "AT_HWCAP", "AT_CLKTCK", "AT_UID", "AT_EXECFN"]:
+        assert key in keys
+
+    root = Path(__file__).resolve().parents[2]
+    doc = (root / "docs" / "abi" / "process_thread_model_v1.md").read_text(encoding="utf-8")
+
+    assert "### Initial stack layout" in doc
+
+
+def test_startup_stack_auxv_runtime(qemu_serial_compat_real):
+    """x1-sched-probe finds its argv, envp and the baseline aux vector keys."""
+    out = qemu_serial_compat_real.stdout
+
+    pos = -1
+    for marker in ["X1APP: launch x1-sched-probe", "X1SCHED: clone ok", "X1SCHED: auxv ok", "X1SCHED: done"]:
+        pos = out.find(marker, pos + 1)
+        assert pos != -1, f"missing marker {marker!r}. Got:\n{out}"
+    assert "X1APP: startup stack fail" not in out
+    assert "X1SCHED: fail" not in out
+
+
+def test_spawn_from_path_runtime(qemu_serial_compat_real):
+    """spawn starts /bin/hello with its argument, wait reaps it, and bad spawns are refused."""
+    out = qemu_serial_compat_real.stdout
+
+    pos = -1
+    for marker in ["X1SCHED: start", "SPAWN: child probe", "X1SCHED: spawn ok", "X1SCHED: done"]:
+        pos = out.find(marker, pos + 1)
+        assert pos != -1, f"missing marker {marker!r}. Got:\n{out}"
+    assert out.count("SPAWN: child probe") == 1
+    assert "X1SCHED: fail" not in out
@@ -38,8 +38,8 @@ def test_runtime_corpus_doc_declares_required_tokens():
         "`x1-cli-file`",
         "`x1-proc-sock`",
         "`sys_thread_spawn` + `sys_wait`",
-        "`fork` syscall slot `43` returns `-1`",
-        "`clone` syscall slot `44` returns `-1`",
+        "`fork` syscall slot `43` is no longer deferred",
+        "`clone` syscall slot `44` is no longer deferred",
         "`epoll` syscall slot `45` returns `-1`",
         "`PKG: elf ok`",
     ]:
@@ -14,17 +14,33 @@ def test_runtime_corpus_executes_real_elf_apps(qemu_serial_compat_real):
     _assert_in_order(
         out,
         [
+            "SIGN: policy enforce",
+            "SIGN: trust root module",
             "X1APP: launch x1-cli-file",
+            "ELF: sig ok",
             "X1CLI: start",
             "X1CLI: file ok",
             "X1APP: done x1-cli-file",
             "X1APP: launch x1-proc-sock",
+            "ELF: sig ok",
             "X1PROC: start",
             "X1PROC: child ok",
             "X1PROC: wait ok",
             "X1SOCK: ok",
+            "X1FORK: ok",
+            "X1CLONE: ok",
             "X1DEFER: ok",
             "X1APP: done x1-proc-sock",
+            "X1APP: launch x1-mem-probe",
+            "ELF: sig ok",
+            "X1MEM: start",
+            "X1MEM: done",
+            "X1APP: done x1-mem-probe",
+            "X1APP: launch x1-sched-probe",
+            "ELF: sig ok",
+            "X1SCHED: start",
+            "X1SCHED: done",
+            "X1APP: done x1-sched-probe",
             "X1: suite ok",
             "RUGO: halt ok",
         ],
@@ -33,7 +49,10 @@ def test_runtime_corpus_executes_real_elf_apps(qemu_serial_compat_real):
     for marker in [
         "X1CLI: fail",
         "X1PROC: fail",
+        "X1MEM: fail",
+        "X1SCHED: fail",
         "X1APP: load fail",
+        "ELF: rejected by signature policy",
         "R4: deadlock",
     ]:
         assert marker not in out, f"unexpected failure marker {marker!r}. Got:\n{out}"
@@ -0,0 +1,49 @@
+"""Thread creation (`clone`) contract checks."""
+
+
+def test_clone_doc_and_syscall_table(read_repo_file):
+    doc = read_repo_file("docs/abi/process_thread_model_v1.md")
+    syscall_doc = read_repo_file("docs/abi/syscall_v1.md")
+
+    assert "## Thread creation (`clone`)" in doc
+    for flag in ["CLONE_VM", "CLONE_FILES", "CLONE_SETTLS", "CLONE_DETACHED"]:
+        assert f"`{flag}`" in doc
+    assert "| 43 | `sys_fork` |" in syscall_doc
+    assert "| 44 | `sys_clone` |" in syscall_doc
+
+
+def test_clone_runtime(qemu_serial_compat_real):
+    """Threads share the caller's space; flags, TLS and join words behave as documented."""
+    out = qemu_serial_compat_real.stdout
+
+    pos = -1
+    for marker in [
+        "X1PROC: start",
+        "X1CLONE: ok",
+        "X1SCHED: sysinfo ok",
+        "X1SCHED: clone ok",
+        "X1SCHED: done",
+    ]:
+        pos = out.find(marker, pos + 1)
+        assert pos != -1, f"missing marker {marker!r}. Got:\n{out}"
+    assert "X1PROC: fail" not in out
+    assert "X1SCHED: fail" not in out
+
+
+def test_tls_and_arch_prctl_doc(read_repo_file):
+    doc = read_repo_file("docs/abi/process_thread_model_v1.md")
+    syscall_doc = read_repo_file("docs/abi/syscall_v1.md")
+
+    assert "## Thread-local storage and FS/GS base" in doc
+    assert "| 51 | `sys_arch_prctl` |" in syscall_doc
+
+
+def test_tls_and_arch_prctl_runtime(qemu_serial_compat_real):
+    """The loader builds the initial TLS block; FS and GS bases round-trip."""
+    out = qemu_serial_compat_real.stdout
+
+    pos = -1
+    for marker in ["X1MEM: start", "X1MEM: pie ok", "X1MEM: tls ok", "X1MEM: done"]:
+        pos = out.find(marker, pos + 1)
+        assert pos != -1, f"missing marker {marker!r}. Got:\n{out}"
+    assert "X1MEM: fail" not in out
@@ -96,6 +96,14 @@ class ProcessModel:
             ("AT_PHNUM", 1),
             ("AT_PAGESZ", 4096),
             ("AT_ENTRY", 0x400000),
+            ("AT_HWCAP", 0),
+            ("AT_CLKTCK", 100),
+            ("AT_UID", 0),
+            ("AT_EUID", 0),
+            ("AT_GID", 0),
+            ("AT_EGID", 0),
+            ("AT_RANDOM", 0),
+            ("AT_EXECFN", 0),
             ("AT_NULL", 0),
         ]
         return {"argv": argv, "envp": envp, "auxv": auxv}
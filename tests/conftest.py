@@ -63,6 +63,7 @@ PKG_REPO_V1_PATH = os.path.join(REPO_ROOT, "out", "repo-v1.json")
 PKG_BOOTSTRAP_V1_TOOL = os.path.join(REPO_ROOT, "tools", "pkg_bootstrap_v1.py")
 ISO_NET_PATH = os.path.join(REPO_ROOT, "out", "os-net.iso")
 ISO_GO_PATH = os.path.join(REPO_ROOT, "out", "os-go.iso")
+ISO_GO_MAX_TASKS_PATH = os.path.join(REPO_ROOT, "out", "os-go-max-tasks.iso")
 ISO_GO_NATIVE_PATH = os.path.join(REPO_ROOT, "out", "os-go-native.iso")
 ISO_COMPAT_REAL_PATH = os.path.join(REPO_ROOT, "out", "os-compat-real.iso")
 ISO_GO_STD_PATH = os.path.join(REPO_ROOT, "out", "os-go-std.iso")
@@ -70,6 +71,7 @@ ISO_SEC_RIGHTS_PATH = os.path.join(REPO_ROOT, "out", "os-sec-rights.iso")
 ISO_SEC_FILTER_PATH = os.path.join(REPO_ROOT, "out", "os-sec-filter.iso")
 QEMU_TIMEOUT = 10  # seconds
 NET_TIMEOUT = 15   # longer timeout for networking
+COMPAT_REAL_TIMEOUT = 30  # the corpus probes wait out a 5 s load-average sample
 
 
 def _resolve_qemu_bin():
@@ -99,7 +101,7 @@ def _resolve_qemu_bin():
 QEMU_BIN = _resolve_qemu_bin()
 
 
-def _boot_iso(iso_path, machine="q35"):
+def _boot_iso(iso_path, machine="q35", smp=1, timeout=QEMU_TIMEOUT):
     """Boot an ISO in QEMU headless and return the CompletedProcess."""
     assert os.path.isfile(iso_path), f"ISO not found: {iso_path}"
     if not QEMU_BIN:
@@ -111,6 +113,7 @@ def _boot_iso(iso_path, machine="q35"):
                 QEMU_BIN,
                 "-machine", machine,
                 "-cpu", "qemu64",
+                "-smp", str(smp),
                 "-m", "128",
                 "-serial", "stdio",
                 "-display", "none",
@@ -120,11 +123,11 @@ def _boot_iso(iso_path, machine="q35"):
             ],
             capture_output=True,
             text=True,
-            timeout=QEMU_TIMEOUT,
+            timeout=timeout,
         )
     except subprocess.TimeoutExpired as exc:
         stdout = exc.stdout.decode("utf-8", errors="replace") if exc.stdout else ""
-        pytest.fail(f"QEMU timed out ({QEMU_TIMEOUT}s). Captured serial:\n{stdout}")
+        pytest.fail(f"QEMU timed out ({timeout}s). Captured serial:\n{stdout}")
 
     return result
 
@@ -758,12 +761,28 @@ def qemu_serial_go():
     return _boot_iso(ISO_GO_PATH)
 
 
+@pytest.fixture
+def qemu_serial_go_max_tasks():
+    """Boot the G1 image with `rugo.max_tasks` set past the task limit cap."""
+    if not os.path.isfile(ISO_GO_MAX_TASKS_PATH):
+        pytest.skip(f"ISO not built: {ISO_GO_MAX_TASKS_PATH}")
+    return _boot_iso(ISO_GO_MAX_TASKS_PATH)
+
+
+@pytest.fixture
+def qemu_serial_go_smp():
+    """Boot the G1 TinyGo user-space test OS image on two CPUs."""
+    if not os.path.isfile(ISO_GO_PATH):
+        pytest.skip(f"ISO not built: {ISO_GO_PATH}")
+    return _boot_iso(ISO_GO_PATH, smp=2)
+
+
 @pytest.fixture
 def qemu_serial_compat_real():
     """Boot the runtime-backed compatibility suite image."""
     if not os.path.isfile(ISO_COMPAT_REAL_PATH):
         pytest.skip(f"ISO not built: {ISO_COMPAT_REAL_PATH}")
-    return _boot_iso(ISO_COMPAT_REAL_PATH)
+    return _boot_iso(ISO_COMPAT_REAL_PATH, timeout=COMPAT_REAL_TIMEOUT)
 
 
 @pytest.fixture
@@ -21,6 +21,7 @@ def test_go_userspace_bootstrap(qemu_serial_go):
             "RUGO: boot ok",
             "GOINIT: start",
             "GOINIT: bootstrap",
+            "GOINIT: spawn ok",
             "GOINIT: svcmgr up",
             "GOSVCM: start",
             "SVC: timesvc declared",
@@ -76,3 +77,23 @@ def test_go_userspace_bootstrap(qemu_serial_go):
             f"Did not expect '{error_marker}' in serial output.\n"
             f"Full output:\n{serial}"
         )
+
+
+def test_go_boot_spawns_program_from_path(qemu_serial_go):
+    """Go init must start /bin/hello in a fresh task and reap it."""
+    serial = qemu_serial_go.stdout
+
+    _find_in_order(
+        serial,
+        [
+            "SIGN: policy enforce",
+            "GOINIT: bootstrap",
+            "ELF: sig ok",
+            "SPAWN: child ok",
+            "GOINIT: spawn ok",
+            "GOINIT: svcmgr up",
+        ],
+    )
+    assert serial.count("SPAWN: child ") == 1
+    assert "SPAWN: child no arg" not in serial
+    assert "ELF: rejected by signature policy" not in serial
@@ -0,0 +1,147 @@
+"""Signed package verification: tooling roundtrip and kernel loader checks."""
+
+import struct
+import sys
+from pathlib import Path
+
+
+REPO_ROOT = Path(__file__).resolve().parents[2]
+sys.path.append(str(REPO_ROOT / "tools"))
+
+import ed25519_v1  # noqa: E402
+import mkfs  # noqa: E402
+import pkg_bootstrap_v1 as pkgv1  # noqa: E402
+
+
+def _read(relpath):
+    return (REPO_ROOT / relpath).read_text(encoding="utf-8")
+
+
+def test_rfc8032_test_vector_1():
+    seed = bytes.fromhex(
+        "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60"
+    )
+    expected_pub = bytes.fromhex(
+        "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a"
+    )
+    expected_sig = bytes.fromhex(
+        "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e06522490155"
+        "5fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b"
+    )
+    assert ed25519_v1.public_key(seed) == expected_pub
+    assert ed25519_v1.sign(b"", seed) == expected_sig
+    assert ed25519_v1.verify(expected_pub, b"", expected_sig) is True
+
+
+def test_signed_pkg_trailer_verifies_and_detects_tampering():
+    pkg = mkfs.build_pkg("hello", mkfs.HELLO_BINARY)
+    bin_size = struct.unpack("<I", pkg[4:8])[0]
+    assert len(pkg) == 64 + bin_size + 64
+
+    pub = ed25519_v1.public_key()
+    body, sig = pkg[: 64 + bin_size], pkg[64 + bin_size :]
+    assert ed25519_v1.verify(pub, body, sig) is True
+
+    tampered = bytearray(body)
+    tampered[64] ^= 0x01
+    assert ed25519_v1.verify(pub, bytes(tampered), sig) is False
+
+    unsigned = mkfs.build_pkg("hello", mkfs.HELLO_BINARY, signed=False)
+    assert unsigned == body
+
+
+def test_bootstrap_bridge_signs_runtime_pkg():
+    payload = pkgv1.build_debug_write_app("APP: signed bridge\n")
+    blob = pkgv1.build_pkg_v0("external-hello", payload)
+    assert len(blob) == 64 + len(payload) + 64
+    assert ed25519_v1.verify(
+        ed25519_v1.public_key(), blob[:-64], blob[-64:]
+    ) is True
+    assert len(pkgv1.build_pkg_v0("external-hello", payload, seed=None)) == (
+        64 + len(payload)
+    )
+
+
+def test_signed_image_trailer_verifies_and_detects_tampering():
+    image = b"\x7fELF" + bytes(range(60)) * 3
+    signed = ed25519_v1.sign_image(image)
+    assert signed[: len(image)] == image
+    assert signed.endswith(ed25519_v1.IMAGE_TRAILER_MAGIC)
+
+    pub = ed25519_v1.public_key()
+    sig = signed[len(image) : len(image) + 64]
+    assert ed25519_v1.verify(pub, image, sig) is True
+    assert ed25519_v1.verify(pub, image[:-1] + b"\x00", sig) is False
+
+
+def _assert_in_order(out, markers):
+    pos = -1
+    for marker in markers:
+        pos = out.find(marker, pos + 1)
+        assert pos != -1, f"missing marker {marker!r}. Got:\n{out}"
+
+
+def test_trust_root_comes_from_boot_module_runtime(qemu_serial_compat_real):
+    """The kernel enforces by default and takes its only trust root from the boot module."""
+    out = qemu_serial_compat_real.stdout
+
+    _assert_in_order(
+        out,
+        [
+            "SIGN: policy enforce",
+            "SIGN: trust root module",
+            "SIGN: WARNING: trust root is the public development key",
+            "X1APP: launch x1-cli-file",
+        ],
+    )
+    assert "SIGN: WARNING: no trust root" not in out
+    assert "SIGN: bad trust root" not in out
+
+
+def test_pkg_signature_checked_before_run_runtime(qemu_serial_fs):
+    """The package signature verifies after its hash and before the app runs."""
+    out = qemu_serial_fs.stdout
+
+    _assert_in_order(
+        out,
+        ["SIGN: policy enforce", "SIGN: trust root module", "PKG: hash ok", "PKG: sig ok"],
+    )
+    assert "PKG: rejected by signature policy" not in out
+
+
+def test_elf_signature_checked_before_each_app_runtime(qemu_serial_compat_real):
+    """Every compat app and spawned image verifies before any of it runs."""
+    out = qemu_serial_compat_real.stdout
+
+    markers = []
+    for app, start in [
+        ("x1-cli-file", "X1CLI: start"),
+        ("x1-proc-sock", "X1PROC: start"),
+        ("x1-mem-probe", "X1MEM: start"),
+        ("x1-sched-probe", "X1SCHED: start"),
+    ]:
+        markers += [f"X1APP: launch {app}", "ELF: sig ok", start]
+    markers += ["ELF: sig ok", "SPAWN: child probe"]
+    _assert_in_order(out, markers)
+    assert "ELF: rejected by signature policy" not in out
+    assert "ELF: unsigned" not in out
+    assert "ELF: sig bad" not in out
+
+
+def test_image_signing_doc_covers_policy_and_trust_root():
+    doc = _read("docs/security/image_signing_v1.md")
+    for token in [
+        "`rugo.sigpolicy=`",
+        "`enforce` (default)",
+        "`warn`",
+        "`off`",
+        "module_string: rugo.trust_root",
+        "`PKG: sig ok`",
+        "`PKG: sig bad`",
+        "`PKG: unsigned`",
+        "`ELF: sig ok`",
+        "`RUGOSIG1`",
+        "`rugo.trust_root=<64 hex digits>`",
+        "The kernel has no built-in trust root.",
+    ]:
+        assert token in doc, token
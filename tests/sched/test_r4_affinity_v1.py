@@ -0,0 +1,31 @@
+"""R4 CPU affinity masks set through sys_sched_set and honoured by the picker."""
+
+from pathlib import Path
+
+
+ROOT = Path(__file__).resolve().parents[2]
+
+
+def _read(relpath: str) -> str:
+    return (ROOT / relpath).read_text(encoding="utf-8")
+
+
+def test_affinity_runtime(qemu_serial_compat_real):
+    """Affinity masks are inherited at fork and must name an online CPU."""
+    out = qemu_serial_compat_real.stdout
+
+    assert "SMP: cpus=1" in out
+    pos = -1
+    for marker in ["X1SCHED: start", "X1SCHED: ids ok", "X1SCHED: affinity ok", "X1SCHED: done"]:
+        pos = out.find(marker, pos + 1)
+        assert pos != -1, f"missing marker {marker!r}. Got:\n{out}"
+    assert "X1SCHED: fail" not in out
+
+
+def test_affinity_and_smp_status_are_documented():
+    doc = _read("docs/abi/process_thread_model_v1.md")
+    assert "## CPU affinity" in doc
+    assert "### SMP" in doc
+    assert "`SMP: cpus=N`" in doc
+    assert "Kernel workers stay on the boot CPU (bit `0`)." in doc
+    assert "selector `0x80` sets the CPU affinity mask" in _read("docs/abi/syscall_v1.md")
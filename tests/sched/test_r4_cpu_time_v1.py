@@ -0,0 +1,48 @@
+"""R4 CPU time accounting: TSC-charged user/kernel/idle time and load averages."""
+
+import re
+from pathlib import Path
+
+
+ROOT = Path(__file__).resolve().parents[2]
+
+
+def _read(relpath: str) -> str:
+    return (ROOT / relpath).read_text(encoding="utf-8")
+
+
+def test_sysinfo_reports_time_and_load_runtime(qemu_serial_compat_real):
+    """sysinfo reports charged time, and a busy task raises the load average."""
+    out = qemu_serial_compat_real.stdout
+
+    pos = -1
+    for marker in ["X1SCHED: start", "X1SCHED: sysinfo ok", "X1SCHED: done"]:
+        pos = out.find(marker, pos + 1)
+        assert pos != -1, f"missing marker {marker!r}. Got:\n{out}"
+    assert "X1SCHED: fail" not in out
+
+
+def test_go_diag_logs_measured_cpu_time(qemu_serial_go):
+    """The diag service's boot snapshot shows charged user time and a runnable task."""
+    out = qemu_serial_go.stdout
+
+    match = re.search(
+        r"^SYS: up=(\d+) idle=(\d+) usr=(\d+) sys=(\d+) "
+        r"load=(\d+\.\d\d),(\d+\.\d\d),(\d+\.\d\d) run=(\d+)$",
+        out,
+        re.MULTILINE,
+    )
+    assert match, f"missing SYS snapshot. Got:\n{out}"
+    up, idle, usr, sys_us = (int(match.group(i)) for i in range(1, 5))
+    assert up > 0
+    assert usr > 0
+    assert sys_us > 0
+    assert idle + usr + sys_us <= up * 2, match.group(0)
+    assert int(match.group(8)) >= 1
+
+
+def test_cpu_time_is_documented():
+    doc = _read("docs/abi/process_thread_model_v1.md")
+    assert "## CPU time accounting" in doc
+    assert "`sys_proc_info` with a 240-byte buffer" in doc
+    assert "| 62 | `sys_sysinfo` |" in _read("docs/abi/syscall_v1.md")
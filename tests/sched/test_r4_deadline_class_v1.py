@@ -0,0 +1,35 @@
+"""R4 deadline class: reservations, admission control and overrun accounting."""
+
+from pathlib import Path
+
+
+ROOT = Path(__file__).resolve().parents[2]
+
+
+def _read(relpath: str) -> str:
+    return (ROOT / relpath).read_text(encoding="utf-8")
+
+
+def test_deadline_admission_and_release_runtime(qemu_serial_compat_real):
+    """Reservations are admitted up to capacity and a class change frees them."""
+    out = qemu_serial_compat_real.stdout
+
+    pos = -1
+    for marker in ["X1SCHED: start", "X1SCHED: deadline ok", "X1SCHED: done"]:
+        pos = out.find(marker, pos + 1)
+        assert pos != -1, f"missing marker {marker!r}. Got:\n{out}"
+    assert "X1SCHED: fail" not in out
+
+
+def test_deadline_doc_covers_admission_and_accounting():
+    doc = _read("docs/abi/process_thread_model_v1.md")
+    syscall_doc = _read("docs/abi/syscall_v1.md")
+    for token in [
+        "## Deadline class",
+        "**Admission control:**",
+        "would pass 90%",
+        "An overrun is counted",
+        "`sys_proc_info` with a 224-byte buffer",
+    ]:
+        assert token in doc, token
+    assert "`2=deadline`" in syscall_doc
@@ -0,0 +1,35 @@
+"""R4 class policy: strict critical priority and nice-weighted fair sharing."""
+
+from pathlib import Path
+
+
+ROOT = Path(__file__).resolve().parents[2]
+
+
+def _read(relpath: str) -> str:
+    return (ROOT / relpath).read_text(encoding="utf-8")
+
+
+def test_nice_weighted_sharing_runtime(qemu_serial_compat_real):
+    """A niced spinner is charged less CPU time and bad nice values are refused."""
+    out = qemu_serial_compat_real.stdout
+
+    pos = -1
+    for marker in ["X1SCHED: start", "X1SCHED: auxv ok", "X1SCHED: nice ok", "X1SCHED: done"]:
+        pos = out.find(marker, pos + 1)
+        assert pos != -1, f"missing marker {marker!r}. Got:\n{out}"
+    assert "X1SCHED: fail" not in out
+
+
+def test_policy_doc_covers_classes_nice_and_starvation():
+    doc = _read("docs/abi/process_thread_model_v1.md")
+    syscall_doc = _read("docs/abi/syscall_v1.md")
+    for token in [
+        "## Scheduling policy",
+        "**Critical (`1`)** tasks have strict priority",
+        "`sys_sched_nice` (53)",
+        "**Starvation protection:**",
+        "`sys_proc_info` with a 192-byte buffer",
+    ]:
+        assert token in doc, token
+    assert "| 53 | `sys_sched_nice` |" in syscall_doc
@@ -0,0 +1,28 @@
+"""R4 kernel workers: ring-0 worker threads scheduled with user tasks on the Go lane."""
+
+from pathlib import Path
+
+
+ROOT = Path(__file__).resolve().parents[2]
+
+
+def _read(relpath: str) -> str:
+    return (ROOT / relpath).read_text(encoding="utf-8")
+
+
+def test_kernel_workers_runtime(qemu_serial_compat_real):
+    """Worker slots sit above the user task limit as critical tasks on the boot CPU."""
+    out = qemu_serial_compat_real.stdout
+
+    assert "LIMITS: tasks=6 threads=" in out
+    pos = -1
+    for marker in ["X1SCHED: start", "X1SCHED: nice ok", "X1SCHED: workers ok", "X1SCHED: done"]:
+        pos = out.find(marker, pos + 1)
+        assert pos != -1, f"missing marker {marker!r}. Got:\n{out}"
+    assert "X1SCHED: fail" not in out
+
+
+def test_kernel_workers_are_documented():
+    doc = _read("docs/abi/process_thread_model_v1.md")
+    assert "## Kernel workers" in doc
+    assert "ring-0\n  `int 0x80`" in doc
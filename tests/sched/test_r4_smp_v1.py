@@ -0,0 +1,22 @@
+"""R4 SMP acceptance: the Go lane starts the APs and finishes its boot on two CPUs."""
+
+
+def _find_in_order(serial: str, markers: list[str]) -> None:
+    pos = -1
+    for marker in markers:
+        pos = serial.find(marker, pos + 1)
+        assert pos != -1, f"Missing '{marker}' in serial output.\nFull output:\n{serial}"
+
+
+def test_r4_smp_brings_up_the_second_cpu(qemu_serial_go_smp):
+    serial = qemu_serial_go_smp.stdout
+
+    _find_in_order(serial, ["SMP: cpu 1 online", "SMP: cpus=2", "GOINIT: ready"])
+    assert "RUGO: panic" not in serial, f"Kernel panicked on two CPUs.\nFull output:\n{serial}"
+
+
+def test_r4_smp_single_cpu_boot_reports_one_cpu(qemu_serial_go):
+    serial = qemu_serial_go.stdout
+
+    _find_in_order(serial, ["SMP: cpus=1", "GOINIT: ready"])
+    assert "SMP: cpu 1 online" not in serial
@@ -0,0 +1,45 @@
+"""R4 task table: boot-sized task slots, boot limits and generation-tagged ids."""
+
+from pathlib import Path
+
+
+ROOT = Path(__file__).resolve().parents[2]
+
+
+def _read(relpath: str) -> str:
+    return (ROOT / relpath).read_text(encoding="utf-8")
+
+
+def test_task_ids_and_task_limit_runtime(qemu_serial_compat_real):
+    """Reused slots get fresh ids, stale ids are refused and the task limit holds."""
+    out = qemu_serial_compat_real.stdout
+
+    assert "LIMITS: tasks=6 threads=" in out
+    pos = -1
+    for marker in ["X1SCHED: start", "X1SCHED: workers ok", "X1SCHED: ids ok", "X1SCHED: done"]:
+        pos = out.find(marker, pos + 1)
+        assert pos != -1, f"missing marker {marker!r}. Got:\n{out}"
+    assert "X1SCHED: fail" not in out
+
+
+def test_go_lane_boots_at_the_task_limit_cap(qemu_serial_go_max_tasks):
+    """A `rugo.max_tasks` past the cap is clamped and the Go lane still boots."""
+    out = qemu_serial_go_max_tasks.stdout
+
+    assert "LIMITS: tasks=64 threads=" in out
+    pos = -1
+    for marker in ["RUGO: boot ok", "GOINIT: start", "GOINIT: ready", "RUGO: halt ok"]:
+        pos = out.find(marker, pos + 1)
+        assert pos != -1, f"missing marker {marker!r}. Got:\n{out}"
+    assert "GO: space map fail" not in out
+    assert "R4: task table alloc fail" not in out
+
+
+def test_task_table_is_documented():
+    doc = _read("docs/abi/process_thread_model_v1.md")
+    assert "## Task table and task ids" in doc
+    assert "`rugo.max_tasks=N`" in doc
+    assert "`rugo.max_threads=N`" in doc
+    assert "`rugo.kobj_frames=N`" in doc
+    assert "`rugo.m3_threads=N`" in doc
+    assert "their tids are the task limit (storage flusher) and one above it" in doc
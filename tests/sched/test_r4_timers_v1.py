@@ -0,0 +1,36 @@
+"""R4 kernel timers: sleep, deadline timeouts and timer handles on the Go lane."""
+
+from pathlib import Path
+
+
+ROOT = Path(__file__).resolve().parents[2]
+
+
+def _read(relpath: str) -> str:
+    return (ROOT / relpath).read_text(encoding="utf-8")
+
+
+def test_sleeps_timer_handles_and_wait_deadlines_runtime(qemu_serial_compat_real):
+    """Sleeps last as asked, timer handles fire, and a wait deadline times out."""
+    out = qemu_serial_compat_real.stdout
+
+    pos = -1
+    for marker in ["X1SCHED: start", "X1SCHED: timers ok", "X1SCHED: done"]:
+        pos = out.find(marker, pos + 1)
+        assert pos != -1, f"missing marker {marker!r}. Got:\n{out}"
+    assert "X1SCHED: fail" not in out
+
+
+def test_timer_docs_cover_clock_timeouts_and_handles():
+    doc = _read("docs/abi/process_thread_model_v1.md")
+    syscall_doc = _read("docs/abi/syscall_v1.md")
+    for token in [
+        "## Timers and timeouts",
+        "`sys_clock_now` (54)",
+        "`sys_timer_wait` (60) returns the number of expirations",
+        "**Idle:**",
+    ]:
+        assert token in doc, token
+    for row in range(54, 62):
+        assert f"| {row} | `sys_" in syscall_doc, row
+    assert "`R4_ERR_TIMED_OUT`" in syscall_doc
@@ -0,0 +1,190 @@
+"""Per-process address space contract checks."""
+
+from pathlib import Path
+
+
+ROOT = Path(__file__).resolve().parents[2]
+
+
+def _read(relpath: str) -> str:
+    return (ROOT / relpath).read_text(encoding="utf-8")
+
+
+def test_address_space_model_doc():
+    doc = _read("docs/abi/address_space_model_v1.md")
+
+    assert "Model identifier: `rugo.address_space_model.v1`" in doc
+    assert "## Per-process page tables" in doc
+    assert "## Cross-space copies" in doc
+
+
+def test_demand_paging_doc():
+    doc = _read("docs/abi/address_space_model_v1.md")
+
+    assert "## Demand paging" in doc
+
+
+def _find_in_order(serial: str, markers: list[str]) -> None:
+    pos = -1
+    for marker in markers:
+        pos = serial.find(marker, pos + 1)
+        assert pos != -1, f"Missing '{marker}' in serial output.\nFull output:\n{serial}"
+
+
+def test_fork_copy_on_write_runtime(qemu_serial_compat_real):
+    """A forked child and its parent each keep their own copy of shared pages."""
+    serial = qemu_serial_compat_real.stdout
+
+    _find_in_order(serial, ["X1APP: launch x1-mem-probe", "X1MEM: start", "X1MEM: cow ok", "X1MEM: done"])
+    assert "X1MEM: fail" not in serial
+
+
+def test_mmap_munmap_mprotect_runtime(qemu_serial_compat_real):
+    """mprotect and munmap change what a mapping allows, and PROT_EXEC is enforced."""
+    serial = qemu_serial_compat_real.stdout
+
+    _find_in_order(serial, ["X1MEM: cow ok", "X1MEM: mmap ok", "X1MEM: done"])
+    # A write to the read-only page, a call into the page without PROT_EXEC
+    # and a read after munmap: each kills its probe child.
+    segment = serial[serial.index("X1MEM: cow ok"):serial.index("X1MEM: mmap ok")]
+    assert segment.count("USERPF: ") == 3, segment
+    assert "X1MEM: fail" not in serial
+
+
+def test_growable_stack_guard_runtime(qemu_serial_compat_real):
+    """The stack grows on demand, an overflow into the guard gap kills the task, and the gap only goes away with its stack."""
+    serial = qemu_serial_compat_real.stdout
+
+    _find_in_order(serial, ["X1MEM: mmap ok", "USER: stack overflow", "X1MEM: stack ok", "X1MEM: done"])
+    assert serial.count("USER: stack overflow") == 1
+    assert "X1MEM: fail" not in serial
+
+
+def test_private_address_spaces_runtime(qemu_serial_compat_real):
+    """A child that rewrites and unmaps an inherited mapping leaves the parent's intact."""
+    serial = qemu_serial_compat_real.stdout
+
+    _find_in_order(serial, ["X1MEM: oom ok", "X1MEM: space ok", "X1MEM: done"])
+    assert "X1MEM: fail" not in serial
+
+
+def test_demand_paging_runtime(qemu_serial_compat_real):
+    """mmap reserves without backing; each first touch backs exactly one page."""
+    serial = qemu_serial_compat_real.stdout
+
+    _find_in_order(serial, ["X1MEM: space ok", "X1MEM: demand ok", "X1MEM: done"])
+    segment = serial[serial.index("X1MEM: space ok"):serial.index("X1MEM: demand ok")]
+    assert "USERPF: " not in segment, segment
+    assert "X1MEM: fail" not in serial
+
+
+def test_large_pages_doc():
+    doc = _read("docs/abi/address_space_model_v1.md")
+
+    assert "## Large pages" in doc
+
+
+def test_large_pages_runtime(qemu_serial_compat_real):
+    """Big mappings get 2 MiB leaves that fork copies and mprotect splits."""
+    serial = qemu_serial_compat_real.stdout
+
+    _find_in_order(serial, ["X1MEM: demand ok", "X1MEM: large ok", "X1MEM: done"])
+    assert "VM: no large frames" not in serial
+    segment = serial[serial.index("X1MEM: demand ok"):serial.index("X1MEM: large ok")]
+    assert "USERPF: " not in segment, segment
+    assert "X1MEM: fail" not in serial
+
+
+def test_shm_transfer_doc():
+    doc = _read("docs/abi/syscall_v0.md")
+    syscall_doc = _read("docs/abi/syscall_v1.md")
+
+    assert "### Transfer" in doc
+    assert "| 49 | `sys_shm_send` |" in syscall_doc
+
+
+def test_shm_transfer_runtime(qemu_serial_compat_real):
+    """A sent SHM handle keeps only the passed rights and shares the sender's pages."""
+    serial = qemu_serial_compat_real.stdout
+
+    _find_in_order(serial, ["X1MEM: large ok", "X1MEM: shm ok", "X1MEM: done"])
+    segment = serial[serial.index("X1MEM: large ok"):serial.index("X1MEM: shm ok")]
+    assert "USERPF: " not in segment, segment
+    assert "X1MEM: fail" not in serial
+
+
+def test_shm_large_pages_runtime(qemu_serial_compat_real):
+    """A 2 MiB SHM object maps on a 2 MiB boundary with its block resident up front."""
+    serial = qemu_serial_compat_real.stdout
+
+    _find_in_order(serial, ["X1MEM: shm ok", "X1MEM: shm large ok", "X1MEM: done"])
+    assert "VM: no large frames" not in serial
+    segment = serial[serial.index("X1MEM: shm ok"):serial.index("X1MEM: shm large ok")]
+    assert "USERPF: " not in segment, segment
+    assert "X1MEM: fail" not in serial
+
+
+def test_user_copy_exception_table_doc():
+    doc = _read("docs/abi/syscall_v0.md")
+    memory_src = _read("kernel_rs/src/memory.rs")
+
+    assert "kernel exception table" in doc
+    assert "Debug builds cross-check every successful copy" in doc
+    assert "user_pages_mapped(user_ptr, len, USER_PERM_READ)" in memory_src
+
+
+def test_user_copy_runtime(qemu_serial_compat_real):
+    """Copy-outs back reserved pages and fail cleanly on read-only or unmapped ones."""
+    serial = qemu_serial_compat_real.stdout
+
+    _find_in_order(serial, ["X1MEM: shm large ok", "X1MEM: usercopy ok", "X1MEM: done"])
+    segment = serial[serial.index("X1MEM: shm large ok"):serial.index("X1MEM: usercopy ok")]
+    assert "USERPF: " not in segment, segment
+    assert "RUGO: panic" not in segment, segment
+    assert "X1MEM: fail" not in serial
+
+
+def test_memory_accounting_and_limits_doc():
+    doc = _read("docs/abi/address_space_model_v1.md")
+    syscall_doc = _read("docs/abi/syscall_v1.md")
+
+    assert "## Memory accounting and limits" in doc
+    assert "`R4_ERR_MEM_LIMIT`" in syscall_doc
+
+
+def test_memory_limits_runtime(qemu_serial_compat_real):
+    """Hard limits cap reservations with R4_ERR_MEM_LIMIT; bad limits are refused."""
+    serial = qemu_serial_compat_real.stdout
+
+    _find_in_order(serial, ["X1MEM: usercopy ok", "X1MEM: limits ok", "X1MEM: done"])
+    segment = serial[serial.index("X1MEM: usercopy ok"):serial.index("X1MEM: limits ok")]
+    assert "USERPF: " not in segment, segment
+    assert "X1MEM: fail" not in serial
+
+
+def test_mem_supervisor_runtime(qemu_serial_compat_real):
+    """Only the owner of the registered supervisor endpoint may replace it."""
+    serial = qemu_serial_compat_real.stdout
+
+    _find_in_order(serial, ["X1MEM: limits ok", "X1MEM: notify ok", "X1MEM: done"])
+    assert "X1MEM: fail" not in serial
+
+
+def test_soft_limit_event_runtime(qemu_serial_compat_real):
+    """Passing a soft limit posts a soft-limit event to the memory supervisor."""
+    serial = qemu_serial_compat_real.stdout
+
+    _find_in_order(serial, ["X1MEM: notify ok", "X1MEM: soft ok", "X1MEM: done"])
+    segment = serial[serial.index("X1MEM: notify ok"):serial.index("X1MEM: soft ok")]
+    assert "USERPF: " not in segment, segment
+    assert "X1MEM: fail" not in serial
+
+
+def test_oom_kill_runtime(qemu_serial_compat_real):
+    """Draining the frame pool kills the task with the most resident pages."""
+    serial = qemu_serial_compat_real.stdout
+
+    _find_in_order(serial, ["X1MEM: stack ok", "OOM: level=2", "X1MEM: oom ok", "X1MEM: done"])
+    segment = serial[serial.index("X1MEM: stack ok"):serial.index("X1MEM: oom ok")]
+    assert "victim=none" not in segment, segment
+    assert "X1MEM: fail" not in serial
@@ -0,0 +1,191 @@
+#!/usr/bin/env python3
+"""Pure-Python Ed25519 (RFC 8032) for signing packages and images.
+
+The kernel loader verifies package and image signatures against a trust
+root (see docs/security/image_signing_v1.md). CI signs with a deterministic
+development key so images stay reproducible; real deployments pass their
+own 32-byte seed.
+
+Usage:
+  python tools/ed25519_v1.py pubkey [--seed-hex HEX] [--out ROOTFILE]
+  python tools/ed25519_v1.py sign FILE [--seed-hex HEX] [--out SIGFILE]
+  python tools/ed25519_v1.py sign-image FILE [--seed-hex HEX] [--out SIGNED]
+"""
+
+from __future__ import annotations
+
+import argparse
+import hashlib
+import sys
+
+P = 2**255 - 19
+L = 2**252 + 27742317777372353535851937790883648493
+D = (-121665 * pow(121666, P - 2, P)) % P
+SQRT_M1 = pow(2, (P - 1) // 4, P)
+
+# Seed of the development signing key. It is public, so the kernel warns
+# when its public half (`SIGN_DEV_TRUST_ROOT`) is the configured trust root.
+DEV_SEED = hashlib.sha256(b"rugo image signing dev key v1").digest()
+
+# Executable images carry their signature in a trailer: signature || magic.
+IMAGE_TRAILER_MAGIC = b"RUGOSIG1"
+
+
+def _recover_x(y, sign):
+    if y >= P:
+        return None
+    x2 = (y * y - 1) * pow(D * y * y + 1, P - 2, P)
+    if x2 == 0:
+        return None if sign else 0
+    x = pow(x2, (P + 3) // 8, P)
+    if (x * x - x2) % P != 0:
+        x = x * SQRT_M1 % P
+    if (x * x - x2) % P != 0:
+        return None
+    if (x & 1) != sign:
+        x = P - x
+    return x
+
+
+_GY = 4 * pow(5, P - 2, P) % P
+_GX = _recover_x(_GY, 0)
+G = (_GX, _GY, 1, _GX * _GY % P)
+IDENTITY = (0, 1, 1, 0)
+
+
+def _point_add(p, q):
+    a = (p[1] - p[0]) * (q[1] - q[0]) % P
+    b = (p[1] + p[0]) * (q[1] + q[0]) % P
+    c = 2 * p[3] * q[3] * D % P
+    d = 2 * p[2] * q[2] % P
+    e, f, g, h = b - a, d - c, d + c, b + a
+    return (e * f % P, g * h % P, f * g % P, e * h % P)
+
+
+def _point_mul(s, p):
+    q = IDENTITY
+    while s > 0:
+        if s & 1:
+            q = _point_add(q, p)
+        p = _point_add(p, p)
+        s >>= 1
+    return q
+
+
+def _point_equal(p, q):
+    if (p[0] * q[2] - q[0] * p[2]) % P != 0:
+        return False
+    return (p[1] * q[2] - q[1] * p[2]) % P == 0
+
+
+def _point_compress(p):
+    zinv = pow(p[2], P - 2, P)
+    x = p[0] * zinv % P
+    y = p[1] * zinv % P
+    return int.to_bytes(y | ((x & 1) << 255), 32, "little")
+
+
+def _point_decompress(data):
+    if len(data) != 32:
+        return None
+    y = int.from_bytes(data, "little")
+    sign = y >> 255
+    y &= (1 << 255) - 1
+    x = _recover_x(y, sign)
+    if x is None:
+        return None
+    return (x, y, 1, x * y % P)
+
+
+def _sha512_int(data):
+    return int.from_bytes(hashlib.sha512(data).digest(), "little")
+
+
+def _expand_seed(seed):
+    if len(seed) != 32:
+        raise ValueError("Ed25519 seed must be 32 bytes")
+    h = hashlib.sha512(seed).digest()
+    a = int.from_bytes(h[:32], "little")
+    a &= (1 << 254) - 8
+    a |= 1 << 254
+    return a, h[32:]
+
+
+def public_key(seed=DEV_SEED):
+    a, _ = _expand_seed(seed)
+    return _point_compress(_point_mul(a, G))
+
+
+def sign(message, seed=DEV_SEED):
+    a, prefix = _expand_seed(seed)
+    pub = _point_compress(_point_mul(a, G))
+    r = _sha512_int(prefix + message) % L
+    big_r = _point_compress(_point_mul(r, G))
+    h = _sha512_int(big_r + pub + message) % L
+    s = (r + h * a) % L
+    return big_r + int.to_bytes(s, 32, "little")
+
+
+def verify(pub, message, signature):
+    if len(pub) != 32 or len(signature) != 64:
+        return False
+    a = _point_decompress(pub)
+    r = _point_decompress(signature[:32])
+    if a is None or r is None:
+        return False
+    s = int.from_bytes(signature[32:], "little")
+    if s >= L:
+        return False
+    h = _sha512_int(signature[:32] + pub + message) % L
+    return _point_equal(_point_mul(s, G), _point_add(r, _point_mul(h, a)))
+
+
+def sign_image(image, seed=DEV_SEED):
+    """Return `image` with a signature trailer the kernel loader checks."""
+    return image + sign(image, seed) + IMAGE_TRAILER_MAGIC
+
+
+def _parse_args():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    sub = parser.add_subparsers(dest="cmd", required=True)
+    pub = sub.add_parser("pubkey", help="print the public key as hex")
+    pub.add_argument("--seed-hex", default=DEV_SEED.hex())
+    pub.add_argument("--out", default=None, help="write the raw 32 bytes instead")
+    sig = sub.add_parser("sign", help="write a detached 64-byte signature")
+    sig.add_argument("path")
+    sig.add_argument("--seed-hex", default=DEV_SEED.hex())
+    sig.add_argument("--out", default=None)
+    img = sub.add_parser("sign-image", help="append a signature trailer to an image")
+    img.add_argument("path")
+    img.add_argument("--seed-hex", default=DEV_SEED.hex())
+    img.add_argument("--out", required=True)
+    return parser.parse_args()
+
+
+def main():
+    args = _parse_args()
+    seed = bytes.fromhex(args.seed_hex)
+    if args.cmd == "pubkey":
+        if args.out is None:
+            print(public_key(seed).hex())
+            return 0
+        with open(args.out, "wb") as f:
+            f.write(public_key(seed))
+        print(f"==> trust root -> {args.out}")
+        return 0
+    with open(args.path, "rb") as f:
+        data = f.read()
+    if args.cmd == "sign-image":
+        out = args.out
+        signed = sign_image(data, seed)
+    else:
+        out = args.out or args.path + ".sig"
+        signed = sign(data, seed)
+    with open(out, "wb") as f:
+        f.write(signed)
+    print(f"==> signed {args.path} -> {out}")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(main())
@@ -12,6 +12,8 @@ Package format v0 (hello.pkg):
   Offset 8:  name      (24 bytes, NUL-padded)
   Offset 32: sha256    (32 bytes, payload hash)
   Offset 64: binary payload
+  Trailer:   Ed25519 signature (64 bytes) over header || payload, omitted
+             with --unsigned (see docs/security/image_signing_v1.md)
 """
 
 import hashlib
@@ -19,6 +21,8 @@ import os
 import struct
 import sys
 
+import ed25519_v1
+
 # SimpleFS magic: "SFS1" as little-endian u32
 SFS_MAGIC = 0x53465331
 
@@ -48,23 +52,25 @@ HELLO_BINARY = bytes([
 ])
 
 
-def build_pkg(name, binary):
-    """Build a PKG v0 package: 64-byte header + binary payload."""
+def build_pkg(name, binary, signed=True):
+    """Build a PKG v0 package: 64-byte header + binary payload + signature."""
     header = struct.pack('<II', PKG_MAGIC, len(binary))
     name_bytes = name.encode('ascii')[:24].ljust(24, b'\x00')
     header += name_bytes
     header += hashlib.sha256(binary).digest()
     assert len(header) == 64
-    return header + binary
+    if not signed:
+        return header + binary
+    return header + binary + ed25519_v1.sign(header + binary)
 
 
-def build_disk_image(output_path, corrupt_superblock_magic=False):
+def build_disk_image(output_path, corrupt_superblock_magic=False, signed=True):
     """Create a 1 MiB SimpleFS v0 disk image."""
     disk_size = 1024 * 1024  # 1 MiB
     disk = bytearray(disk_size)
 
     # Build hello.pkg
-    hello_pkg = build_pkg("hello", HELLO_BINARY)
+    hello_pkg = build_pkg("hello", HELLO_BINARY, signed=signed)
 
     # Layout: sector 0 = superblock, sector 1 = file table, sector 2+ = data
     file_count = 1
@@ -96,19 +102,25 @@ def build_disk_image(output_path, corrupt_superblock_magic=False):
     print(f"    SimpleFS: {file_count} file(s), data_start={data_start}, "
           f"next_free={next_free}")
     print(f"    hello.pkg: {len(hello_pkg)} bytes "
-          f"(header=64, binary={len(HELLO_BINARY)})")
+          f"(header=64, binary={len(HELLO_BINARY)}, "
+          f"signed={'yes' if signed else 'no'})")
 
 
 if __name__ == '__main__':
     output = 'out/fs-test.img'
     corrupt_superblock_magic = False
+    signed = True
     for arg in sys.argv[1:]:
         if arg == '--corrupt-superblock-magic':
             corrupt_superblock_magic = True
+        elif arg == '--unsigned':
+            signed = False
         elif output == 'out/fs-test.img':
             output = arg
         else:
             raise SystemExit(
-                'Usage: mkfs.py [output_path] [--corrupt-superblock-magic]'
+                'Usage: mkfs.py [output_path] [--corrupt-superblock-magic] '
+                '[--unsigned]'
             )
-    build_disk_image(output, corrupt_superblock_magic=corrupt_superblock_magic)
+    build_disk_image(output, corrupt_superblock_magic=corrupt_superblock_magic,
+                     signed=signed)
@@ -59,6 +59,16 @@ mkdir -p "$ISO_ROOT/boot/limine"
 
 cp "$OUT/$KERNEL_ELF"                       "$ISO_ROOT/boot/kernel.elf"
 cp "$ROOT/boot/limine.conf"                "$ISO_ROOT/boot/limine/limine.conf"
+# Optional boot-time knobs for the kernel entry: a command line (for example
+# `rugo.sigpolicy=warn`) and a 32-byte Ed25519 trust root module.
+if [ -n "${KERNEL_CMDLINE:-}" ]; then
+    printf '    cmdline: %s\n' "$KERNEL_CMDLINE" >> "$ISO_ROOT/boot/limine/limine.conf"
+fi
+if [ -n "${TRUST_ROOT:-}" ]; then
+    cp "$TRUST_ROOT" "$ISO_ROOT/boot/trust_root.bin"
+    printf '    module_path: boot():/boot/trust_root.bin\n    module_string: rugo.trust_root\n' \
+        >> "$ISO_ROOT/boot/limine/limine.conf"
+fi
 cp "$VENDOR_LIMINE/limine-bios.sys"        "$ISO_ROOT/boot/limine/"
 cp "$VENDOR_LIMINE/limine-bios-cd.bin"     "$ISO_ROOT/boot/limine/"
 XORRISO_DATE_ARGS=()
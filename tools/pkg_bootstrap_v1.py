@@ -15,6 +15,8 @@ import os
 import struct
 from datetime import datetime, timezone
 
+import ed25519_v1
+
 
 PKG_V1_MAGIC = b"RPKGV1\x00\x00"
 PKG_V0_MAGIC = 0x01474B50  # "PKG\x01"
@@ -191,13 +193,19 @@ def verify_repo_metadata(signed_doc, key):
     return hmac.compare_digest(signature.get("sig_hex", ""), expected)
 
 
-def build_pkg_v0(name, payload):
-    """Build runtime-compatible PKG v0 blob (64-byte header + payload)."""
+def build_pkg_v0(name, payload, seed=ed25519_v1.DEV_SEED):
+    """Build runtime-compatible PKG v0 blob (64-byte header + payload).
+
+    The blob ends with an Ed25519 signature over header || payload made with
+    `seed`; pass `seed=None` for an unsigned blob.
+    """
     payload = bytes(payload)
     header = struct.pack("<II", PKG_V0_MAGIC, len(payload))
     header += name.encode("ascii")[:24].ljust(24, b"\x00")
     header += hashlib.sha256(payload).digest()
-    return header + payload
+    if seed is None:
+        return header + payload
+    return header + payload + ed25519_v1.sign(header + payload, seed)
 
 
 def build_simplefs_image(output_path, files):